@@ -0,0 +1,161 @@
+use crate::BigBed;
+use crate::error::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Read, Seek};
+
+/// a difference between the chromosomes of two assemblies
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChromMismatch {
+    /// a chromosome that only this file has
+    OnlyInSelf{name: String, size: u32},
+    /// a chromosome that only the other file has
+    OnlyInOther{name: String, size: u32},
+    /// a chromosome that both files have, with a different size in each
+    SizeDiffers{name: String, size: u32, other_size: u32},
+}
+
+impl fmt::Display for ChromMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChromMismatch::OnlyInSelf{name, size} => write!(f, "only in first: {} ({})", name, size),
+            ChromMismatch::OnlyInOther{name, size} => write!(f, "only in second: {} ({})", name, size),
+            ChromMismatch::SizeDiffers{name, size, other_size} => write!(f, "size differs: {} ({} vs {})", name, size, other_size),
+        }
+    }
+}
+
+/// the result of comparing the chromosomes of two assemblies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compatibility {
+    /// the number of chromosomes with the same name and size in both
+    pub matching: usize,
+    /// the differences, in the order of the first assembly and then the second
+    pub mismatches: Vec<ChromMismatch>,
+}
+
+impl Compatibility {
+    /// whether the two assemblies could be the same
+    /// (chromosomes missing from one side are allowed, since a BigBed only lists
+    /// the chromosomes it has records on, but they must share at least one chromosome
+    /// and no chromosome may differ in size)
+    pub fn is_compatible(&self) -> bool {
+        self.matching > 0 && !self.mismatches.iter().any(|mismatch| {
+            matches!(mismatch, ChromMismatch::SizeDiffers{..})
+        })
+    }
+
+    pub fn size_mismatches(&self) -> usize {
+        self.mismatches.iter().filter(|mismatch| matches!(mismatch, ChromMismatch::SizeDiffers{..})).count()
+    }
+}
+
+/// compare two lists of (name, size) pairs
+pub fn compare_chroms(chroms: &[(String, u32)], other: &[(String, u32)]) -> Compatibility {
+    let other_sizes: HashMap<&str, u32> = other.iter().map(|(name, size)| (name.as_str(), *size)).collect();
+    let names: HashMap<&str, u32> = chroms.iter().map(|(name, size)| (name.as_str(), *size)).collect();
+    let mut matching = 0;
+    let mut mismatches = Vec::new();
+    for (name, size) in chroms {
+        match other_sizes.get(name.as_str()) {
+            Some(&other_size) if other_size == *size => matching += 1,
+            Some(&other_size) => mismatches.push(ChromMismatch::SizeDiffers{
+                name: name.clone(), size: *size, other_size
+            }),
+            None => mismatches.push(ChromMismatch::OnlyInSelf{name: name.clone(), size: *size}),
+        }
+    }
+    for (name, size) in other {
+        if !names.contains_key(name.as_str()) {
+            mismatches.push(ChromMismatch::OnlyInOther{name: name.clone(), size: *size});
+        }
+    }
+    Compatibility{matching, mismatches}
+}
+
+/// read a chrom.sizes file (a chromosome name and size on each line, separated by whitespace)
+/// blank lines and lines starting with '#' are skipped
+pub fn read_chrom_sizes<R: BufRead>(reader: R) -> Result<Vec<(String, u32)>, Error> {
+    let mut chroms = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue
+        }
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next().and_then(|size| size.parse::<u32>().ok())) {
+            (Some(name), Some(size)) => chroms.push((String::from(name), size)),
+            _ => return Err(Error::BadField("chrom.sizes", line)),
+        }
+    }
+    Ok(chroms)
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// the (name, size) of each chromosome in the file, in name order
+    pub fn chrom_sizes(&mut self) -> Result<Vec<(String, u32)>, Error> {
+        Ok(self.chrom_list()?.iter().map(|chrom| (String::from(chrom.name()), chrom.size())).collect())
+    }
+
+    /// compare the chromosome names and sizes of this file with another
+    /// (e.g. to catch files made against different assemblies)
+    pub fn compatible_with<U: Read + Seek>(&mut self, other: &mut BigBed<U>) -> Result<Compatibility, Error> {
+        Ok(compare_chroms(&self.chrom_sizes()?, &other.chrom_sizes()?))
+    }
+
+    /// compare the chromosome names and sizes of this file with a list of
+    /// (name, size) pairs, such as one from read_chrom_sizes
+    pub fn compatible_with_sizes(&mut self, sizes: &[(String, u32)]) -> Result<Compatibility, Error> {
+        Ok(compare_chroms(&self.chrom_sizes()?, sizes))
+    }
+}
+
+#[cfg(test)]
+mod test_assembly {
+    use super::*;
+    use crate::bb_from_file;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn sizes_from_file(filename: &str) -> Vec<(String, u32)> {
+        read_chrom_sizes(BufReader::new(File::open(filename).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_read_chrom_sizes() {
+        let sizes = read_chrom_sizes("# comment\nchr1\t100\n\nchr2 200\n".as_bytes()).unwrap();
+        assert_eq!(sizes, vec![(String::from("chr1"), 100), (String::from("chr2"), 200)]);
+        assert!(read_chrom_sizes("chr1\n".as_bytes()).is_err());
+        assert!(read_chrom_sizes("chr1\tlong\n".as_bytes()).is_err());
+        assert_eq!(sizes_from_file("test/chrom.sizes/bed12.chrom.sizes").len(), 3);
+    }
+
+    #[test]
+    fn test_compatible_with() {
+        let mut long = bb_from_file("test/bigbeds/long.bb");
+        let mut long_unc = bb_from_file("test/bigbeds/long-unc.bb");
+        let same = long.compatible_with(&mut long_unc).unwrap();
+        assert!(same.is_compatible());
+        assert!(same.mismatches.is_empty());
+
+        // hg38 lists many more chromosomes than the file has records on
+        let hg38 = long.compatible_with_sizes(&sizes_from_file("test/chrom.sizes/hg38.chrom.sizes")).unwrap();
+        assert!(hg38.is_compatible());
+        assert!(hg38.mismatches.iter().all(|mismatch| matches!(mismatch, ChromMismatch::OnlyInOther{..})));
+
+        let mut mm10 = bb_from_file("test/bigbeds/mm10.bb");
+        let mixup = long.compatible_with(&mut mm10).unwrap();
+        assert!(!mixup.is_compatible());
+        assert!(mixup.size_mismatches() > 0);
+        assert!(mixup.mismatches.contains(&ChromMismatch::SizeDiffers{
+            name: String::from("chr1"), size: 248956422, other_size: 195471971
+        }));
+
+        // the same assembly with different chromosome names shares nothing
+        let mut tair10 = bb_from_file("test/bigbeds/tair10.bb");
+        let nochr = tair10.compatible_with_sizes(&sizes_from_file("test/chrom.sizes/tair10-nochr.chrom.sizes")).unwrap();
+        assert_eq!(nochr.matching, 0);
+        assert!(!nochr.is_compatible());
+        assert_eq!(ChromMismatch::OnlyInOther{name: String::from("1"), size: 30427671}.to_string(), "only in second: 1 (30427671)");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{BigBed, ByteReader, Chrom, Chroms};
+use crate::bptree::BPlusTree;
+use crate::error::Error;
+use crate::zoom::Summary;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// the signature of a BigBed file (as a big-endian number)
+pub const BIGBED_SIG: [u8; 4] = [0x87, 0x89, 0xF2, 0xEB];
+/// the signature of a BigWig file (as a big-endian number)
+pub const BIGWIG_SIG: [u8; 4] = [0x88, 0x8F, 0xFC, 0x26];
+// the first bytes of a gzip stream
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// the kind of a file, as told by its signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    BigBed,
+    BigWig,
+    /// neither a BigBed nor a BigWig (including files too short to have a signature)
+    Unknown,
+}
+
+/// tell a BigBed from a BigWig by the signature at the current position of the reader
+/// (in either byte order), leaving the reader where it was
+pub fn sniff<R: Read + Seek>(reader: &mut R) -> Result<FileKind, Error> {
+    let position = reader.stream_position()?;
+    let mut buff = [0; 4];
+    let mut length = 0;
+    // read what there is of the signature (a short file is just Unknown)
+    while length < buff.len() {
+        match reader.read(&mut buff[length..])? {
+            0 => break,
+            read => length += read,
+        }
+    }
+    reader.seek(SeekFrom::Start(position))?;
+    let matches = |magic: [u8; 4]| length == 4 && (buff == magic || buff.iter().eq(magic.iter().rev()));
+    Ok(if matches(BIGBED_SIG) {
+        FileKind::BigBed
+    } else if matches(BIGWIG_SIG) {
+        FileKind::BigWig
+    } else {
+        FileKind::Unknown
+    })
+}
+
+/// the header of one zoom level of a bbi file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomLevel {
+    /// the number of bases summarized by each record (at most)
+    pub reduction_level: u32,
+    pub reserved: u32,
+    /// the offset of the zoom data (starting with its record count)
+    pub data_offset: u64,
+    /// the offset of the R-tree indexing the zoom data
+    pub index_offset: u64,
+}
+
+/// the fixed header at the start of every bbi file (BigBed or BigWig),
+/// followed by the list of zoom levels
+#[derive(Debug, Clone, PartialEq)]
+pub struct BbiHeader {
+    /// the signature of the file (as a big-endian number, whatever the byte order of the file)
+    pub magic: [u8; 4],
+    pub big_endian: bool,
+    pub version: u16,
+    pub zoom_levels: u16,
+    pub chrom_tree_offset: u64,
+    pub unzoomed_data_offset: u64,
+    pub unzoomed_index_offset: u64,
+    /// the number of columns (always 0 for BigWig files)
+    pub field_count: u16,
+    pub defined_field_count: u16,
+    /// the offset of the autoSql definition (0 if there is none)
+    pub as_offset: u64,
+    /// the offset of the summary of the whole file (0 if there is none)
+    pub total_summary_offset: u64,
+    /// the size of the largest uncompressed data block (0 if the data is not compressed)
+    pub uncompress_buf_size: u32,
+    /// the offset of the extension header (0 if there is none)
+    pub extension_offset: u64,
+    pub level_list: Vec<ZoomLevel>,
+}
+
+impl BbiHeader {
+    /// read the header at the current position of the reader, which must start with
+    /// the signature `magic` (in either byte order)
+    pub fn read<R: Read>(reader: &mut R, magic: [u8; 4]) -> Result<BbiHeader, Error> {
+        let mut buff = [0; 4];
+        reader.read_exact(&mut buff)?;
+        let big_endian =
+            if buff == magic {
+                true
+            } else if buff.iter().eq(magic.iter().rev()) {
+                false
+            } else if buff[..2] == GZIP_MAGIC {
+                return Err(Error::Gzipped);
+            } else {
+                return Err(Error::BadSig{expected: magic, received: buff});
+            };
+        let version = reader.read_u16(big_endian);
+        let zoom_levels = reader.read_u16(big_endian);
+        let chrom_tree_offset = reader.read_u64(big_endian);
+        let unzoomed_data_offset = reader.read_u64(big_endian);
+        let unzoomed_index_offset = reader.read_u64(big_endian);
+        let field_count = reader.read_u16(big_endian);
+        let defined_field_count = reader.read_u16(big_endian);
+        let as_offset = reader.read_u64(big_endian);
+        let total_summary_offset = reader.read_u64(big_endian);
+        let uncompress_buf_size = reader.read_u32(big_endian);
+        let extension_offset = reader.read_u64(big_endian);
+
+        let mut level_list: Vec<ZoomLevel> = Vec::with_capacity(usize::from(zoom_levels));
+        for _ in 0..usize::from(zoom_levels) {
+            level_list.push(ZoomLevel{
+                reduction_level: reader.read_u32(big_endian),
+                reserved: reader.read_u32(big_endian),
+                data_offset: reader.read_u64(big_endian),
+                index_offset: reader.read_u64(big_endian)
+            })
+        }
+
+        Ok(BbiHeader{
+            magic, big_endian, version, zoom_levels, chrom_tree_offset,
+            unzoomed_data_offset, unzoomed_index_offset, field_count,
+            defined_field_count, as_offset, total_summary_offset,
+            uncompress_buf_size, extension_offset, level_list,
+        })
+    }
+
+    /// read the summary of the whole file (None if the file has none)
+    /// for BigBed files, the summarized value is the coverage depth at each base
+    pub fn total_summary<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<Summary>, Error> {
+        if self.total_summary_offset == 0 {
+            return Ok(None)
+        }
+        reader.seek(SeekFrom::Start(self.total_summary_offset))?;
+        let valid_count = reader.read_u64(self.big_endian);
+        let mut read_f64 = || f64::from_bits(reader.read_u64(self.big_endian));
+        Ok(Some(Summary{valid_count, min: read_f64(), max: read_f64(), sum: read_f64(), sum_squares: read_f64()}))
+    }
+}
+
+/// a BigWig file, read as far as the parts it shares with BigBed files
+/// (its header, chromosomes and total summary)
+#[derive(Debug)]
+pub struct BigWig<T: Read + Seek> {
+    reader: T,
+    header: BbiHeader,
+    chrom_bpt: BPlusTree,
+}
+
+impl<T: Read + Seek> BigWig<T> {
+    pub fn from_file(mut reader: T) -> Result<BigWig<T>, Error> {
+        let header = BbiHeader::read(&mut reader, BIGWIG_SIG)?;
+        reader.seek(SeekFrom::Start(header.chrom_tree_offset))?;
+        let chrom_bpt = BPlusTree::with_reader(&mut reader)?;
+        Ok(BigWig{reader, header, chrom_bpt})
+    }
+
+    pub fn header(&self) -> &BbiHeader {
+        &self.header
+    }
+
+    /// iterate over the chromosomes in the file (in name order)
+    pub fn chroms(&mut self) -> Chroms<'_, T> {
+        let big_endian = self.chrom_bpt.big_endian;
+        Chroms{items: self.chrom_bpt.items(&mut self.reader), big_endian}
+    }
+
+    pub fn chrom_list(&mut self) -> Result<Vec<Chrom>, Error> {
+        self.chroms().collect()
+    }
+
+    /// the summary of the values over the whole file (None if the file has none)
+    pub fn total_summary(&mut self) -> Result<Option<Summary>, Error> {
+        self.header.total_summary(&mut self.reader)
+    }
+}
+
+/// a BigBed or BigWig file, for tools that take either
+#[derive(Debug)]
+pub enum BbiFile<T: Read + Seek> {
+    BigBed(BigBed<T>),
+    BigWig(BigWig<T>),
+}
+
+impl<T: Read + Seek> BbiFile<T> {
+    /// read a BigBed or BigWig, as told by its signature
+    /// (anything else fails as a BigBed would, e.g. with Error::Gzipped for gzipped files)
+    pub fn from_reader(mut reader: T) -> Result<BbiFile<T>, Error> {
+        match sniff(&mut reader)? {
+            FileKind::BigWig => Ok(BbiFile::BigWig(BigWig::from_file(reader)?)),
+            FileKind::BigBed | FileKind::Unknown => Ok(BbiFile::BigBed(BigBed::from_file(reader)?)),
+        }
+    }
+
+    pub fn kind(&self) -> FileKind {
+        match self {
+            BbiFile::BigBed(_) => FileKind::BigBed,
+            BbiFile::BigWig(_) => FileKind::BigWig,
+        }
+    }
+
+    pub fn header(&self) -> BbiHeader {
+        match self {
+            BbiFile::BigBed(bigbed) => bigbed.header(),
+            BbiFile::BigWig(bigwig) => bigwig.header().clone(),
+        }
+    }
+
+    pub fn chrom_list(&mut self) -> Result<Vec<Chrom>, Error> {
+        match self {
+            BbiFile::BigBed(bigbed) => bigbed.chrom_list(),
+            BbiFile::BigWig(bigwig) => bigwig.chrom_list(),
+        }
+    }
+
+    /// the summary of the whole file (for BigBed files, of the coverage depth)
+    pub fn total_summary(&mut self) -> Result<Option<Summary>, Error> {
+        match self {
+            BbiFile::BigBed(bigbed) => bigbed.total_summary(),
+            BbiFile::BigWig(bigwig) => bigwig.total_summary(),
+        }
+    }
+}
+
+/// open a BigBed or BigWig file
+pub fn open<P: AsRef<Path>>(path: P) -> Result<BbiFile<File>, Error> {
+    BbiFile::from_reader(File::open(path)?)
+}
+
+#[cfg(test)]
+mod test_bbi {
+    use super::*;
+    use crate::BigBed;
+    use std::fs::File;
+
+    #[test]
+    fn test_sniff() {
+        let mut file = File::open("test/bigbeds/long.bb").unwrap();
+        assert_eq!(sniff(&mut file).unwrap(), FileKind::BigBed);
+        // the reader is left where it was
+        assert_eq!(file.stream_position().unwrap(), 0);
+        assert!(BigBed::from_file(file).is_ok());
+
+        let mut wig = std::io::Cursor::new(vec![0x26, 0xFC, 0x8F, 0x88, 4, 0]);
+        assert_eq!(sniff(&mut wig).unwrap(), FileKind::BigWig);
+        let mut wig = std::io::Cursor::new(BIGWIG_SIG.to_vec());
+        assert_eq!(sniff(&mut wig).unwrap(), FileKind::BigWig);
+
+        let mut png = File::open("test/notbed.png").unwrap();
+        assert_eq!(sniff(&mut png).unwrap(), FileKind::Unknown);
+        let mut short = std::io::Cursor::new(vec![0xEB, 0xF2]);
+        assert_eq!(sniff(&mut short).unwrap(), FileKind::Unknown);
+        // sniffing from part of the way through a reader
+        let mut bytes = vec![0; 10];
+        bytes.extend_from_slice(&std::fs::read("test/bigbeds/one.bb").unwrap());
+        let mut offset = std::io::Cursor::new(bytes);
+        offset.seek(SeekFrom::Start(10)).unwrap();
+        assert_eq!(sniff(&mut offset).unwrap(), FileKind::BigBed);
+        assert_eq!(offset.position(), 10);
+    }
+
+    // a BigBed relabelled as a BigWig, which shares everything that BigWig reads
+    fn fake_bigwig(filename: &str) -> std::io::Cursor<Vec<u8>> {
+        let mut bytes = std::fs::read(filename).unwrap();
+        let mut sig = BIGWIG_SIG;
+        sig.reverse();
+        bytes[..4].copy_from_slice(&sig);
+        std::io::Cursor::new(bytes)
+    }
+
+    #[test]
+    fn test_open() {
+        let mut bigbed = open("test/bigbeds/long.bb").unwrap();
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        assert_eq!(bigbed.kind(), FileKind::BigBed);
+        assert_eq!(bigbed.header(), bb.header());
+        assert_eq!(bigbed.chrom_list().unwrap(), bb.chrom_list().unwrap());
+        assert_eq!(bigbed.total_summary().unwrap(), bb.total_summary().unwrap());
+
+        let mut bigwig = BbiFile::from_reader(fake_bigwig("test/bigbeds/long.bb")).unwrap();
+        assert_eq!(bigwig.kind(), FileKind::BigWig);
+        assert_eq!(bigwig.header().magic, BIGWIG_SIG);
+        assert_eq!(bigwig.header().level_list, bb.level_list);
+        assert_eq!(bigwig.chrom_list().unwrap(), bb.chrom_list().unwrap());
+        assert_eq!(bigwig.total_summary().unwrap(), bb.total_summary().unwrap());
+
+        assert!(matches!(open("test/notbed.png"), Err(Error::BadSig{..})));
+        assert!(open("test/bigbeds/nope.bb").is_err());
+    }
+
+    #[test]
+    fn test_read_header() {
+        let mut file = File::open("test/bigbeds/long.bb").unwrap();
+        let header = BbiHeader::read(&mut file, BIGBED_SIG).unwrap();
+        let bb = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        assert_eq!(header.magic, BIGBED_SIG);
+        assert_eq!(header.big_endian, bb.big_endian);
+        assert_eq!((header.version, header.zoom_levels), (bb.version, bb.zoom_levels));
+        assert_eq!(header.unzoomed_index_offset, bb.unzoomed_index_offset);
+        assert_eq!(header.total_summary_offset, bb.total_summary_offset);
+        assert_eq!(header.level_list, bb.level_list);
+        assert_eq!(bb.header(), header);
+
+        let mut file = File::open("test/bigbeds/long.bb").unwrap();
+        assert!(matches!(BbiHeader::read(&mut file, BIGWIG_SIG), Err(Error::BadSig{..})));
+    }
+
+    #[test]
+    fn test_total_summary() {
+        let mut file = File::open("test/bigbeds/one.bb").unwrap();
+        let header = BbiHeader::read(&mut file, BIGBED_SIG).unwrap();
+        let summary = header.total_summary(&mut file).unwrap().unwrap();
+        // the coverage of a single record
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/one.bb").unwrap()).unwrap();
+        let line = &bb.query("chr7", 0, u32::MAX, 0).unwrap()[0];
+        assert_eq!(summary.valid_count, u64::from(line.end - line.start));
+        assert_eq!((summary.min, summary.max), (1.0, 1.0));
+        assert_eq!(bb.total_summary().unwrap(), Some(summary));
+
+        let no_summary = BbiHeader{total_summary_offset: 0, ..header};
+        assert_eq!(no_summary.total_summary(&mut file).unwrap(), None);
+    }
+}
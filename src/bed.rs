@@ -0,0 +1,804 @@
+use crate::{BedLine, BigBed};
+use crate::error::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::str::FromStr;
+
+/// the strand of a BED record (column 6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strand {
+    Forward,
+    Reverse,
+    Unknown,
+}
+
+impl Strand {
+    /// the opposite strand (Unknown stays Unknown)
+    pub fn flip(self) -> Strand {
+        match self {
+            Strand::Forward => Strand::Reverse,
+            Strand::Reverse => Strand::Forward,
+            Strand::Unknown => Strand::Unknown,
+        }
+    }
+
+    /// the direction of transcription: 1 for Forward, -1 for Reverse, 0 for Unknown
+    pub fn sign(self) -> i64 {
+        match self {
+            Strand::Forward => 1,
+            Strand::Reverse => -1,
+            Strand::Unknown => 0,
+        }
+    }
+
+    /// multiply two strands, e.g. to find the strand of a feature relative to another
+    /// (Reverse * Reverse = Forward, anything * Unknown = Unknown)
+    pub fn relative_to(self, other: Strand) -> Strand {
+        match self.sign() * other.sign() {
+            1 => Strand::Forward,
+            -1 => Strand::Reverse,
+            _ => Strand::Unknown,
+        }
+    }
+
+    pub fn as_char(self) -> char {
+        match self {
+            Strand::Forward => '+',
+            Strand::Reverse => '-',
+            Strand::Unknown => '.',
+        }
+    }
+}
+
+impl FromStr for Strand {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Strand, Error> {
+        match s {
+            "+" => Ok(Strand::Forward),
+            "-" => Ok(Strand::Reverse),
+            "." => Ok(Strand::Unknown),
+            _ => Err(Error::BadField("strand", s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// an itemRgb color (column 9)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb{r, g, b}
+    }
+
+    /// unpack a color stored as a single 0xRRGGBB integer
+    pub fn from_u32(value: u32) -> Rgb {
+        Rgb{r: (value >> 16) as u8, g: (value >> 8) as u8, b: value as u8}
+    }
+
+    /// pack this color into a single 0xRRGGBB integer
+    pub fn to_u32(self) -> u32 {
+        (u32::from(self.r) << 16) | (u32::from(self.g) << 8) | u32::from(self.b)
+    }
+
+    /// format this color as a hex string (e.g. "#ff0000")
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl From<(u8, u8, u8)> for Rgb {
+    fn from((r, g, b): (u8, u8, u8)) -> Rgb {
+        Rgb{r, g, b}
+    }
+}
+
+impl From<Rgb> for (u8, u8, u8) {
+    fn from(color: Rgb) -> (u8, u8, u8) {
+        (color.r, color.g, color.b)
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = Error;
+
+    /// accepts both "r,g,b" and single-integer (e.g. "0" or "16711680") encodings
+    fn from_str(s: &str) -> Result<Rgb, Error> {
+        let bad_value = || Error::BadField("itemRgb", s.to_owned());
+        let value = s.trim();
+        if !value.contains(',') {
+            return value.parse::<u32>()
+                .ok()
+                .filter(|packed| *packed <= 0xFF_FF_FF)
+                .map(Rgb::from_u32)
+                .ok_or_else(bad_value)
+        }
+        let mut channels = [0u8; 3];
+        let mut parts = value.split(',');
+        for channel in channels.iter_mut() {
+            *channel = parts.next()
+                .and_then(|part| part.trim().parse::<u8>().ok())
+                .ok_or_else(bad_value)?;
+        }
+        // tolerate a single trailing comma, as some writers emit "255,0,0,"
+        match (parts.next(), parts.next()) {
+            (None, _) | (Some(""), None) => Ok(Rgb::new(channels[0], channels[1], channels[2])),
+            _ => Err(bad_value()),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.r, self.g, self.b)
+    }
+}
+
+// positions of the optional BED columns within `rest`
+// (the first three columns are stored separately)
+const NAME: usize = 0;
+const SCORE: usize = 1;
+const STRAND: usize = 2;
+const THICK_START: usize = 3;
+const THICK_END: usize = 4;
+const ITEM_RGB: usize = 5;
+const BLOCK_COUNT: usize = 6;
+const BLOCK_SIZES: usize = 7;
+const BLOCK_STARTS: usize = 8;
+
+pub(crate) fn parse_score(value: &str) -> Result<u16, Error> {
+    value.parse::<u16>().map_err(|_| Error::BadField("score", value.to_owned()))
+}
+
+fn parse_u32(field: &'static str, value: &str) -> Result<u32, Error> {
+    value.parse::<u32>().map_err(|_| Error::BadField(field, value.to_owned()))
+}
+
+// parse a comma-separated list of numbers (a trailing comma is allowed)
+fn parse_u32_list(field: &'static str, value: &str) -> Result<Vec<u32>, Error> {
+    let trimmed = value.strip_suffix(',').unwrap_or(value);
+    if trimmed.is_empty() {
+        return Ok(Vec::new())
+    }
+    trimmed.split(',')
+        .map(|part| part.trim().parse::<u32>().map_err(|_| Error::BadField(field, value.to_owned())))
+        .collect()
+}
+
+/// the standard BED columns of a record, read by position as in a plain bedN file
+/// (these don't know how many standard columns the record's file defines, so in a bedN+M
+/// file a custom column is read as if it were standard, e.g. the fifth column of a bed3+3;
+/// use BedRecord, from BigBed::to_record, to read the columns according to the file)
+impl BedLine {
+    /// get the Nth column after chromEnd (0 is the name column), if present
+    pub(crate) fn rest_field(&self, index: usize) -> Option<&str> {
+        self.rest.as_ref().and_then(|rest| rest.split('\t').nth(index))
+    }
+
+    // a standard column after chromEnd, if a file with `defined_field_count` standard columns
+    // defines it and the record has it (as BedRecord reads it, without splitting every column)
+    fn standard_field(&self, index: usize, defined_field_count: u16) -> Option<&str> {
+        if index + 3 < usize::from(defined_field_count) { self.rest_field(index) } else { None }
+    }
+
+    /// the score column, if the record's file (with `defined_field_count` standard columns)
+    /// defines one and the record has it
+    pub fn defined_score(&self, defined_field_count: u16) -> Result<Option<u16>, Error> {
+        self.standard_field(SCORE, defined_field_count).map(parse_score).transpose()
+    }
+
+    /// the strand column, if the record's file (with `defined_field_count` standard columns)
+    /// defines one and the record has it
+    pub fn defined_strand(&self, defined_field_count: u16) -> Result<Option<Strand>, Error> {
+        self.standard_field(STRAND, defined_field_count).map(str::parse).transpose()
+    }
+
+    /// the name column (column 4), if present
+    pub fn name(&self) -> Option<&str> {
+        self.rest_field(NAME)
+    }
+
+    /// the score column (column 5), if present
+    pub fn score(&self) -> Result<Option<u16>, Error> {
+        self.rest_field(SCORE).map(parse_score).transpose()
+    }
+
+    /// the strand column (column 6), if present
+    pub fn strand(&self) -> Result<Option<Strand>, Error> {
+        self.rest_field(STRAND).map(str::parse).transpose()
+    }
+
+    /// the thickStart column (column 7), if present
+    pub fn thick_start(&self) -> Result<Option<u32>, Error> {
+        self.rest_field(THICK_START).map(|value| parse_u32("thickStart", value)).transpose()
+    }
+
+    /// the thickEnd column (column 8), if present
+    pub fn thick_end(&self) -> Result<Option<u32>, Error> {
+        self.rest_field(THICK_END).map(|value| parse_u32("thickEnd", value)).transpose()
+    }
+
+    /// the itemRgb column (column 9), if present
+    pub fn item_rgb(&self) -> Result<Option<Rgb>, Error> {
+        self.rest_field(ITEM_RGB).map(str::parse).transpose()
+    }
+
+    /// the blockCount column (column 10), if present
+    pub fn block_count(&self) -> Result<Option<u32>, Error> {
+        self.rest_field(BLOCK_COUNT).map(|value| parse_u32("blockCount", value)).transpose()
+    }
+
+    /// the blockSizes column (column 11), if present
+    pub fn block_sizes(&self) -> Result<Option<Vec<u32>>, Error> {
+        self.rest_field(BLOCK_SIZES).map(|value| parse_u32_list("blockSizes", value)).transpose()
+    }
+
+    /// the blockStarts column (column 12, relative to chromStart), if present
+    pub fn block_starts(&self) -> Result<Option<Vec<u32>>, Error> {
+        self.rest_field(BLOCK_STARTS).map(|value| parse_u32_list("blockStarts", value)).transpose()
+    }
+
+    /// the blocks (e.g. exons) of this record as absolute (start, end) pairs, if present
+    /// the blocks are checked to be consistent with blockCount, to be in order and
+    /// non-overlapping, and to span exactly from chromStart to chromEnd
+    pub fn blocks(&self) -> Result<Option<Vec<(u32, u32)>>, Error> {
+        match (self.block_count()?, self.block_sizes()?, self.block_starts()?) {
+            (Some(count), Some(sizes), Some(starts)) => check_blocks(self.start, self.end, count, &sizes, &starts).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+// turn the blockCount, blockSizes and blockStarts of a record into absolute (start, end) pairs,
+// checking that they are consistent, in order and non-overlapping, and span the record exactly
+fn check_blocks(start: u32, end: u32, count: u32, sizes: &[u32], starts: &[u32]) -> Result<Vec<(u32, u32)>, Error> {
+    let count = count as usize;
+    if sizes.len() != count {
+        return Err(Error::BadBlock{block: sizes.len().min(count), reason: "blockSizes does not match blockCount"})
+    }
+    if starts.len() != count {
+        return Err(Error::BadBlock{block: starts.len().min(count), reason: "blockStarts does not match blockCount"})
+    }
+    let length = end.saturating_sub(start);
+    let mut blocks = Vec::with_capacity(count);
+    let mut prev_end = 0;
+    for (block, (&rel_start, &size)) in starts.iter().zip(sizes.iter()).enumerate() {
+        if block == 0 && rel_start != 0 {
+            return Err(Error::BadBlock{block, reason: "first block must start at chromStart"})
+        }
+        if rel_start < prev_end {
+            return Err(Error::BadBlock{block, reason: "block overlaps or precedes the previous block"})
+        }
+        let rel_end = match rel_start.checked_add(size) {
+            Some(rel_end) if rel_end <= length => rel_end,
+            _ => return Err(Error::BadBlock{block, reason: "block extends past chromEnd"}),
+        };
+        if block + 1 == count && rel_end != length {
+            return Err(Error::BadBlock{block, reason: "last block must end at chromEnd"})
+        }
+        blocks.push((start + rel_start, start + rel_end));
+        prev_end = rel_end;
+    }
+    Ok(blocks)
+}
+
+/// a record with its columns split once, read according to the number of standard BED
+/// columns in its file (defined_field_count), so that custom columns are never mistaken
+/// for standard ones (e.g. the signalValue of a bed6+4 narrowPeak is not a thickStart)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BedRecord {
+    pub chrom_id: u32,
+    pub start: u32,
+    pub end: u32,
+    defined_field_count: u16,
+    // the columns after chromEnd
+    fields: Vec<String>,
+}
+
+impl BedRecord {
+    /// split the columns of a record from a file with `defined_field_count` standard columns
+    pub fn new(line: &BedLine, defined_field_count: u16) -> BedRecord {
+        BedRecord{
+            chrom_id: line.chrom_id,
+            start: line.start,
+            end: line.end,
+            defined_field_count,
+            fields: line.rest().map(|rest| rest.split('\t').map(str::to_owned).collect()).unwrap_or_default(),
+        }
+    }
+
+    /// the number of standard columns in the record's file
+    pub fn defined_field_count(&self) -> u16 {
+        self.defined_field_count
+    }
+
+    /// get the Nth column after chromEnd (0 is the name column), standard or not, if present
+    pub fn field(&self, index: usize) -> Option<&str> {
+        self.fields.get(index).map(String::as_str)
+    }
+
+    /// every column after chromEnd
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// the columns after the standard ones (e.g. the four extra columns of a bed6+4)
+    pub fn extra_fields(&self) -> &[String] {
+        let defined = usize::from(self.defined_field_count.saturating_sub(3)).min(self.fields.len());
+        &self.fields[defined..]
+    }
+
+    // a standard column after chromEnd, if the file defines it and the record has it
+    fn standard(&self, index: usize) -> Option<&str> {
+        if index + 3 < usize::from(self.defined_field_count) { self.field(index) } else { None }
+    }
+
+    /// the name column (column 4), if present
+    pub fn name(&self) -> Option<&str> {
+        self.standard(NAME)
+    }
+
+    /// the score column (column 5), if present
+    pub fn score(&self) -> Result<Option<u16>, Error> {
+        self.standard(SCORE).map(parse_score).transpose()
+    }
+
+    /// the strand column (column 6), if present
+    pub fn strand(&self) -> Result<Option<Strand>, Error> {
+        self.standard(STRAND).map(str::parse).transpose()
+    }
+
+    /// the thickStart column (column 7), if present
+    pub fn thick_start(&self) -> Result<Option<u32>, Error> {
+        self.standard(THICK_START).map(|value| parse_u32("thickStart", value)).transpose()
+    }
+
+    /// the thickEnd column (column 8), if present
+    pub fn thick_end(&self) -> Result<Option<u32>, Error> {
+        self.standard(THICK_END).map(|value| parse_u32("thickEnd", value)).transpose()
+    }
+
+    /// the itemRgb column (column 9), if present
+    pub fn item_rgb(&self) -> Result<Option<Rgb>, Error> {
+        self.standard(ITEM_RGB).map(str::parse).transpose()
+    }
+
+    /// the blockCount column (column 10), if present
+    pub fn block_count(&self) -> Result<Option<u32>, Error> {
+        self.standard(BLOCK_COUNT).map(|value| parse_u32("blockCount", value)).transpose()
+    }
+
+    /// the blockSizes column (column 11), if present
+    pub fn block_sizes(&self) -> Result<Option<Vec<u32>>, Error> {
+        self.standard(BLOCK_SIZES).map(|value| parse_u32_list("blockSizes", value)).transpose()
+    }
+
+    /// the blockStarts column (column 12, relative to chromStart), if present
+    pub fn block_starts(&self) -> Result<Option<Vec<u32>>, Error> {
+        self.standard(BLOCK_STARTS).map(|value| parse_u32_list("blockStarts", value)).transpose()
+    }
+
+    /// the blocks of this record as absolute (start, end) pairs, if present (see BedLine::blocks)
+    pub fn blocks(&self) -> Result<Option<Vec<(u32, u32)>>, Error> {
+        match (self.block_count()?, self.block_sizes()?, self.block_starts()?) {
+            (Some(count), Some(sizes), Some(starts)) => check_blocks(self.start, self.end, count, &sizes, &starts).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl From<BedRecord> for BedLine {
+    fn from(record: BedRecord) -> BedLine {
+        let rest = if record.fields.is_empty() { None } else { Some(record.fields.join("\t")) };
+        BedLine{chrom_id: record.chrom_id, start: record.start, end: record.end, rest}
+    }
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// split the columns of a record from this file (see BedRecord)
+    pub fn to_record(&self, line: &BedLine) -> BedRecord {
+        BedRecord::new(line, self.defined_field_count)
+    }
+
+    /// like query, but with the columns of each record split according to the file
+    pub fn query_records(&mut self, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedRecord>, Error> {
+        let lines = self.query(chrom, start, end, max_items)?;
+        Ok(lines.iter().map(|line| self.to_record(line)).collect())
+    }
+}
+
+// the blockSizes and blockStarts of absolute (start, end) blocks, checked as they are when read
+fn block_columns(start: u32, end: u32, blocks: &[(u32, u32)]) -> Result<(Vec<u32>, Vec<u32>), Error> {
+    let mut sizes = Vec::with_capacity(blocks.len());
+    let mut starts = Vec::with_capacity(blocks.len());
+    for (block, &(block_start, block_end)) in blocks.iter().enumerate() {
+        match (block_end.checked_sub(block_start), block_start.checked_sub(start)) {
+            (None, _) => return Err(Error::BadBlock{block, reason: "block ends before it starts"}),
+            (_, None) => return Err(Error::BadBlock{block, reason: "first block must start at chromStart"}),
+            (Some(size), Some(rel_start)) => {
+                sizes.push(size);
+                starts.push(rel_start);
+            }
+        }
+    }
+    check_blocks(start, end, blocks.len() as u32, &sizes, &starts)?;
+    Ok((sizes, starts))
+}
+
+/// a fluent builder for BedLines with optional BED columns
+/// unset columns that precede a set column are filled with the usual defaults
+/// (e.g. setting only the strand produces a name of "." and a score of 0)
+#[derive(Debug, Clone)]
+pub struct BedLineBuilder {
+    chrom_id: u32,
+    start: u32,
+    end: u32,
+    name: Option<String>,
+    score: Option<u16>,
+    strand: Option<Strand>,
+    thick: Option<(u32, u32)>,
+    item_rgb: Option<Rgb>,
+    blocks: Option<Vec<(u32, u32)>>,
+    extra: Vec<String>,
+}
+
+impl BedLineBuilder {
+    pub fn name(mut self, name: &str) -> BedLineBuilder {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    pub fn score(mut self, score: u16) -> BedLineBuilder {
+        self.score = Some(score);
+        self
+    }
+
+    pub fn strand(mut self, strand: Strand) -> BedLineBuilder {
+        self.strand = Some(strand);
+        self
+    }
+
+    pub fn thick(mut self, thick_start: u32, thick_end: u32) -> BedLineBuilder {
+        self.thick = Some((thick_start, thick_end));
+        self
+    }
+
+    pub fn item_rgb(mut self, color: Rgb) -> BedLineBuilder {
+        self.item_rgb = Some(color);
+        self
+    }
+
+    /// set the blocks (e.g. exons) as absolute (start, end) pairs
+    pub fn blocks(mut self, blocks: &[(u32, u32)]) -> BedLineBuilder {
+        self.blocks = Some(blocks.to_vec());
+        self
+    }
+
+    /// append a custom (non-standard) column after the standard ones
+    pub fn extra(mut self, value: &str) -> BedLineBuilder {
+        self.extra.push(value.to_owned());
+        self
+    }
+
+    /// fails with BadBlock if the blocks don't cover the record the way a reader expects
+    /// (in order, without overlaps, from chromStart to chromEnd; see BedLine::blocks)
+    pub fn build(self) -> Result<BedLine, Error> {
+        // find the last standard column that has been set
+        let last = if self.blocks.is_some() {
+            BLOCK_STARTS
+        } else if self.item_rgb.is_some() {
+            ITEM_RGB
+        } else if self.thick.is_some() {
+            THICK_END
+        } else if self.strand.is_some() {
+            STRAND
+        } else if self.score.is_some() {
+            SCORE
+        } else if self.name.is_some() {
+            NAME
+        } else {
+            0
+        };
+        let has_standard = self.name.is_some() || last > NAME;
+        let mut fields: Vec<String> = Vec::new();
+        let (start, end) = (self.start, self.end);
+        if has_standard {
+            let (thick_start, thick_end) = self.thick.unwrap_or((start, end));
+            let (block_sizes, block_starts) = match &self.blocks {
+                Some(blocks) => block_columns(start, end, blocks)?,
+                None => (vec![end.saturating_sub(start)], vec![0]),
+            };
+            let standard = [
+                self.name.unwrap_or_else(|| String::from(".")),
+                self.score.unwrap_or(0).to_string(),
+                self.strand.unwrap_or(Strand::Unknown).to_string(),
+                thick_start.to_string(),
+                thick_end.to_string(),
+                self.item_rgb.map(|color| color.to_string()).unwrap_or_else(|| String::from("0")),
+                block_sizes.len().to_string(),
+                block_sizes.iter().map(|size| format!("{},", size)).collect(),
+                block_starts.iter().map(|block_start| format!("{},", block_start)).collect(),
+            ];
+            fields.extend(standard.iter().take(last + 1).cloned());
+        }
+        fields.extend(self.extra);
+        let rest = if fields.is_empty() {
+            None
+        } else {
+            Some(fields.join("\t"))
+        };
+        Ok(BedLine{chrom_id: self.chrom_id, start, end, rest})
+    }
+}
+
+impl BedLine {
+    /// create a BED3 record
+    pub fn new(chrom_id: u32, start: u32, end: u32) -> BedLine {
+        BedLine{chrom_id, start, end, rest: None}
+    }
+
+    /// create a BED3 record with raw (tab-separated) extra columns
+    pub fn with_rest(chrom_id: u32, start: u32, end: u32, rest: &str) -> BedLine {
+        BedLine{chrom_id, start, end, rest: Some(rest.to_owned())}
+    }
+
+    /// start building a record with optional BED columns
+    pub fn builder(chrom_id: u32, start: u32, end: u32) -> BedLineBuilder {
+        BedLineBuilder{
+            chrom_id, start, end,
+            name: None, score: None, strand: None, thick: None,
+            item_rgb: None, blocks: None, extra: Vec::new(),
+        }
+    }
+}
+
+/// group records by their name column, for files that lack an extra name index
+/// records without a name are skipped
+pub fn group_by_name<I: IntoIterator<Item = BedLine>>(lines: I) -> HashMap<String, Vec<BedLine>> {
+    let mut groups: HashMap<String, Vec<BedLine>> = HashMap::new();
+    for line in lines {
+        if let Some(name) = line.name() {
+            groups.entry(name.to_owned()).or_default().push(line);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test_bed {
+    use super::*;
+
+    fn line(rest: &str) -> BedLine {
+        BedLine{chrom_id: 0, start: 100, end: 200, rest: Some(rest.to_owned())}
+    }
+
+    #[test]
+    fn test_typed_columns() {
+        let bed = line("GENE1\t960\t-\t110\t190\t255,0,0\t1\t100,\t0,");
+        assert_eq!(bed.score(), Ok(Some(960)));
+        assert_eq!(bed.strand(), Ok(Some(Strand::Reverse)));
+        assert_eq!(bed.thick_start(), Ok(Some(110)));
+        assert_eq!(bed.thick_end(), Ok(Some(190)));
+        assert_eq!(bed.item_rgb(), Ok(Some(Rgb::new(255, 0, 0))));
+        assert_eq!(line("GENE1\t0\t.\t100\t100\t0").item_rgb(), Ok(Some(Rgb::new(0, 0, 0))));
+        assert_eq!(line("GENE1\t0\t.").strand(), Ok(Some(Strand::Unknown)));
+    }
+
+    #[test]
+    fn test_strand() {
+        assert_eq!("+".parse::<Strand>(), Ok(Strand::Forward));
+        assert_eq!("-".parse::<Strand>(), Ok(Strand::Reverse));
+        assert_eq!(".".parse::<Strand>(), Ok(Strand::Unknown));
+        assert_eq!("forward".parse::<Strand>(), Err(Error::BadField("strand", String::from("forward"))));
+        for strand in &[Strand::Forward, Strand::Reverse, Strand::Unknown] {
+            // display and parsing should round-trip
+            assert_eq!(strand.to_string().parse::<Strand>(), Ok(*strand));
+            assert_eq!(strand.flip().flip(), *strand);
+        }
+        assert_eq!(Strand::Forward.flip(), Strand::Reverse);
+        assert_eq!(Strand::Unknown.flip(), Strand::Unknown);
+        assert_eq!(Strand::Reverse.relative_to(Strand::Reverse), Strand::Forward);
+        assert_eq!(Strand::Forward.relative_to(Strand::Reverse), Strand::Reverse);
+        assert_eq!(Strand::Forward.relative_to(Strand::Unknown), Strand::Unknown);
+        assert_eq!(Strand::Reverse.sign(), -1);
+    }
+
+    #[test]
+    fn test_rgb() {
+        assert_eq!("255,0,0".parse::<Rgb>(), Ok(Rgb::new(255, 0, 0)));
+        assert_eq!("12, 34, 56".parse::<Rgb>(), Ok(Rgb::new(12, 34, 56)));
+        assert_eq!("0,0,255,".parse::<Rgb>(), Ok(Rgb::new(0, 0, 255)));
+        assert_eq!("0".parse::<Rgb>(), Ok(Rgb::new(0, 0, 0)));
+        assert_eq!("16711680".parse::<Rgb>(), Ok(Rgb::new(255, 0, 0)));
+        assert_eq!("16777216".parse::<Rgb>(), Err(Error::BadField("itemRgb", String::from("16777216"))));
+        assert_eq!("red".parse::<Rgb>(), Err(Error::BadField("itemRgb", String::from("red"))));
+        assert_eq!("1,2,,".parse::<Rgb>(), Err(Error::BadField("itemRgb", String::from("1,2,,"))));
+        let color = Rgb::new(18, 52, 86);
+        assert_eq!(color.to_string(), "18,52,86");
+        assert_eq!(color.to_hex(), "#123456");
+        assert_eq!(Rgb::from_u32(color.to_u32()), color);
+        assert_eq!(<(u8, u8, u8)>::from(color), (18, 52, 86));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let bed = line("GENE1\t960\t-\t110\t190\t0\t3\t10,20,30,\t0,40,70,");
+        assert_eq!(bed.block_count(), Ok(Some(3)));
+        assert_eq!(bed.block_sizes(), Ok(Some(vec![10, 20, 30])));
+        assert_eq!(bed.block_starts(), Ok(Some(vec![0, 40, 70])));
+        assert_eq!(bed.blocks(), Ok(Some(vec![(100, 110), (140, 160), (170, 200)])));
+        // trailing commas are optional
+        let bed = line("GENE1\t960\t-\t110\t190\t0\t1\t100\t0");
+        assert_eq!(bed.blocks(), Ok(Some(vec![(100, 200)])));
+        // no block columns
+        assert_eq!(line("GENE1\t960\t-").blocks(), Ok(None));
+    }
+
+    #[test]
+    fn test_malformed_blocks() {
+        let bad = |blocks: &str| line(&format!("GENE1\t0\t+\t100\t100\t0\t{}", blocks)).blocks();
+        assert_eq!(bad("2\t10,x,\t0,90,"), Err(Error::BadField("blockSizes", String::from("10,x,"))));
+        assert_eq!(bad("3\t10,10,\t0,90,"), Err(Error::BadBlock{block: 2, reason: "blockSizes does not match blockCount"}));
+        assert_eq!(bad("2\t10,10,\t0,"), Err(Error::BadBlock{block: 1, reason: "blockStarts does not match blockCount"}));
+        assert_eq!(bad("2\t10,10,\t5,90,"), Err(Error::BadBlock{block: 0, reason: "first block must start at chromStart"}));
+        assert_eq!(bad("2\t50,50,\t0,40,"), Err(Error::BadBlock{block: 1, reason: "block overlaps or precedes the previous block"}));
+        assert_eq!(bad("2\t10,20,\t0,90,"), Err(Error::BadBlock{block: 1, reason: "block extends past chromEnd"}));
+        assert_eq!(bad("2\t10,5,\t0,90,"), Err(Error::BadBlock{block: 1, reason: "last block must end at chromEnd"}));
+    }
+
+    #[test]
+    fn test_group_by_name() {
+        assert_eq!(line("GENE1\t960").name(), Some("GENE1"));
+        let lines = vec![
+            line("GENE1\t1"),
+            line("GENE2\t2"),
+            line("GENE1\t3"),
+            BedLine{chrom_id: 0, start: 0, end: 10, rest: None},
+        ];
+        let groups = group_by_name(lines);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups["GENE1"], vec![line("GENE1\t1"), line("GENE1\t3")]);
+        assert_eq!(groups["GENE2"], vec![line("GENE2\t2")]);
+    }
+
+    #[test]
+    fn test_builder() {
+        assert_eq!(BedLine::new(1, 10, 20), BedLine{chrom_id: 1, start: 10, end: 20, rest: None});
+        assert_eq!(BedLine::builder(1, 10, 20).build().unwrap(), BedLine::new(1, 10, 20));
+        assert_eq!(BedLine::builder(0, 100, 200).name("GENE1").build().unwrap(), line("GENE1"));
+        // preceding columns get defaults
+        assert_eq!(BedLine::builder(0, 100, 200).strand(Strand::Reverse).build().unwrap(), line(".\t0\t-"));
+        let bed = BedLine::builder(0, 100, 200)
+            .name("GENE1")
+            .score(960)
+            .strand(Strand::Reverse)
+            .thick(110, 190)
+            .item_rgb(Rgb::new(255, 0, 0))
+            .blocks(&[(100, 110), (140, 160), (170, 200)])
+            .build().unwrap();
+        assert_eq!(bed, line("GENE1\t960\t-\t110\t190\t255,0,0\t3\t10,20,30,\t0,40,70,"));
+        assert_eq!(bed.blocks(), Ok(Some(vec![(100, 110), (140, 160), (170, 200)])));
+        // blocks that a reader would reject are refused
+        assert_eq!(BedLine::builder(0, 100, 200).blocks(&[(90, 200)]).build(), Err(Error::BadBlock{block: 0, reason: "first block must start at chromStart"}));
+        assert_eq!(BedLine::builder(0, 100, 200).blocks(&[(100, 150), (180, 170)]).build(), Err(Error::BadBlock{block: 1, reason: "block ends before it starts"}));
+        assert_eq!(BedLine::builder(0, 100, 200).blocks(&[(100, 150), (140, 200)]).build(), Err(Error::BadBlock{block: 1, reason: "block overlaps or precedes the previous block"}));
+        assert_eq!(BedLine::builder(0, 100, 200).blocks(&[(100, 150)]).build(), Err(Error::BadBlock{block: 0, reason: "last block must end at chromEnd"}));
+        // custom columns come after the last standard column
+        let bed = BedLine::builder(0, 100, 200).name("peak1").score(5).strand(Strand::Unknown).extra("3.5").extra("12").build().unwrap();
+        assert_eq!(bed, line("peak1\t5\t.\t3.5\t12"));
+        assert_eq!(BedLine::with_rest(0, 100, 200, "a\tb"), line("a\tb"));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut lines = vec![
+            BedLine::new(1, 5, 10), BedLine::with_rest(0, 100, 200, "b"), BedLine::new(0, 100, 150),
+            BedLine::with_rest(0, 100, 200, "a"), BedLine::new(0, 100, 150),
+        ];
+        lines.sort();
+        lines.dedup();
+        assert_eq!(lines, vec![
+            BedLine::new(0, 100, 150), BedLine::with_rest(0, 100, 200, "a"),
+            BedLine::with_rest(0, 100, 200, "b"), BedLine::new(1, 5, 10),
+        ]);
+        assert!(BedLine::new(0, 100, 150) < BedLine::new(0, 101, 110));
+    }
+
+    #[test]
+    fn test_interval_methods() {
+        let bed = BedLine::new(0, 100, 200);
+        assert_eq!((bed.len(), bed.is_empty()), (100, false));
+        assert!(BedLine::new(0, 100, 100).is_empty());
+        assert!(bed.contains(100) && bed.contains(199));
+        assert!(!bed.contains(99) && !bed.contains(200));
+        assert!(bed.overlaps(&BedLine::new(0, 199, 300)) && bed.overlaps(&BedLine::new(0, 120, 130)));
+        // touching records, and records on other chromosomes, don't overlap
+        assert!(!bed.overlaps(&BedLine::new(0, 200, 300)) && !bed.overlaps(&BedLine::new(1, 100, 200)));
+    }
+
+    #[test]
+    fn test_missing_columns() {
+        let bed = BedLine{chrom_id: 0, start: 100, end: 200, rest: None};
+        assert_eq!(bed.score(), Ok(None));
+        assert_eq!(bed.strand(), Ok(None));
+        let bed = line("GENE1\t960");
+        assert_eq!(bed.score(), Ok(Some(960)));
+        assert_eq!(bed.strand(), Ok(None));
+        assert_eq!(bed.item_rgb(), Ok(None));
+    }
+
+    #[test]
+    fn test_malformed_columns() {
+        let bed = line("GENE1\thigh\t*\t-5\tx\t255,0");
+        assert_eq!(bed.score(), Err(Error::BadField("score", String::from("high"))));
+        assert_eq!(bed.strand(), Err(Error::BadField("strand", String::from("*"))));
+        assert_eq!(bed.thick_start(), Err(Error::BadField("thickStart", String::from("-5"))));
+        assert_eq!(bed.thick_end(), Err(Error::BadField("thickEnd", String::from("x"))));
+        assert_eq!(bed.item_rgb(), Err(Error::BadField("itemRgb", String::from("255,0"))));
+        assert_eq!(line("a\t0\t+\t0\t0\t1,2,3,4").item_rgb(), Err(Error::BadField("itemRgb", String::from("1,2,3,4"))));
+        assert_eq!(line("a\t0\t+\t0\t0\t256,0,0").item_rgb(), Err(Error::BadField("itemRgb", String::from("256,0,0"))));
+    }
+
+    #[test]
+    fn test_bed_record() {
+        let bed = line("GENE1\t960\t-\t110\t190\t255,0,0\t2\t30,50,\t0,50,\textra");
+        let record = BedRecord::new(&bed, 12);
+        assert_eq!(record.name(), Some("GENE1"));
+        assert_eq!(record.score(), bed.score());
+        assert_eq!(record.strand(), Ok(Some(Strand::Reverse)));
+        assert_eq!((record.thick_start(), record.thick_end()), (Ok(Some(110)), Ok(Some(190))));
+        assert_eq!(record.item_rgb(), Ok(Some(Rgb::new(255, 0, 0))));
+        assert_eq!(record.blocks(), Ok(Some(vec![(100, 130), (150, 200)])));
+        assert_eq!(record.extra_fields(), &[String::from("extra")]);
+        assert_eq!(record.field(9), Some("extra"));
+        assert_eq!(BedLine::from(record), bed);
+
+        // the custom columns of a bed6+4 are not read as standard ones
+        let peak = BedRecord::new(&line("peak1\t518\t.\t35.77\t39.95\t32.45\t662"), 6);
+        assert_eq!(peak.strand(), Ok(Some(Strand::Unknown)));
+        assert_eq!(peak.thick_start(), Ok(None));
+        assert_eq!(peak.extra_fields(), &["35.77", "39.95", "32.45", "662"]);
+        assert_eq!(peak.field(3), Some("35.77"));
+
+        let bed3 = BedRecord::new(&BedLine::new(0, 10, 20), 3);
+        assert_eq!((bed3.name(), bed3.score()), (None, Ok(None)));
+        assert!(bed3.fields().is_empty() && bed3.extra_fields().is_empty());
+        assert_eq!(BedLine::from(bed3), BedLine::new(0, 10, 20));
+        assert_eq!(BedRecord::new(&line("a\thigh"), 5).score(), Err(Error::BadField("score", String::from("high"))));
+    }
+
+    #[test]
+    fn test_defined_columns() {
+        // the score and strand of a bed6, and custom columns in the same places in a bed3+3
+        let bed = line("peak1\t3\t+");
+        assert_eq!((bed.defined_score(6), bed.defined_strand(6)), (Ok(Some(3)), Ok(Some(Strand::Forward))));
+        assert_eq!((bed.defined_score(5), bed.defined_strand(5)), (Ok(Some(3)), Ok(None)));
+        assert_eq!((bed.defined_score(3), bed.defined_strand(3)), (Ok(None), Ok(None)));
+        assert_eq!(line("peak1\tnot a score").defined_score(4), Ok(None));
+    }
+
+    #[test]
+    fn test_query_records() {
+        let mut bb = BigBed::from_file(std::fs::File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let records = bb.query_records("chr1", 0, 20000, 1).unwrap();
+        assert_eq!(records[0].name(), Some("peak1"));
+        assert_eq!(records[0].thick_start(), Ok(None));
+        assert_eq!(records[0].extra_fields().len(), 4);
+        let mut bed12 = BigBed::from_file(std::fs::File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        let lines = bed12.query("chr1", 0, 400000, 0).unwrap();
+        let records = bed12.query_records("chr1", 0, 400000, 0).unwrap();
+        assert_eq!(records.iter().map(BedRecord::blocks).collect::<Vec<_>>(), lines.iter().map(BedLine::blocks).collect::<Vec<_>>());
+    }
+}
@@ -1,16 +1,34 @@
-#[macro_use]
 extern crate clap;
 extern crate bigbed;
-mod error;
 
-use clap::{App, Arg, crate_version};
-use crate::bigbed::BigBed;
-use crate::bigbed::error::Error::*;
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, crate_version};
+use crate::bigbed::{natural_cmp, BigBed, BedFormatter, BedLine, Chrom, Strand};
+use crate::bigbed::assembly::{read_chrom_sizes, ChromMismatch};
+use crate::bigbed::bbi::{BbiFile, FileKind};
+use crate::bigbed::format::chrom_file_name;
+use crate::bigbed::metrics::{Metrics, MeteredReader};
+use crate::bigbed::reader::{BufferedReader, OpenOptions, Spooled, DEFAULT_SPOOL_LIMIT};
+use crate::bigbed::region::{is_bed_header, parse_bed_region, GenomicRange};
+use crate::bigbed::repair::{repair, RepairOptions};
+use crate::bigbed::error::Error::{self, *};
+use crate::bigbed::serve::StaticServer;
+use crate::bigbed::sink::{BufferedWriter, OutputOptions};
+use crate::bigbed::tabix::{BgzfWriter, TabixWriter};
+use crate::bigbed::writer::{BigBedWriter, WriterOptions};
+use crate::bigbed::intervals::{clip, merge_intervals, subtract, IntersectMode, IntersectOptions, Intersection};
+use crate::bigbed::zoom::RegionSummary;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::Path;
+use flate2::Compression;
+use regex::Regex;
+use flate2::write::GzEncoder;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
 use std::process::exit;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-// a simple function that performs all the necessary error checking 
+// a simple function that performs all the necessary error checking
 // for the 32-bit unsigned flags: start, stop, max
 fn parse_u32_parameter(input: Option<&str>, flag: &str) -> Option<u32> {
     match input {
@@ -18,9 +36,9 @@ fn parse_u32_parameter(input: Option<&str>, flag: &str) -> Option<u32> {
         Some(value) => {
             match value.parse::<u32>() {
                 Ok(num) => Some(num),
-                Err(msg) => {
+                Err(_) => {
                     eprintln!("Invalid value for {}: '{}'", flag, value);
-                    eprintln!("(Expected a number between 0 and {})", u32::max_value());
+                    eprintln!("(Expected a number between 0 and {})", u32::MAX);
                     exit(1);
                 }
             }
@@ -28,14 +46,1499 @@ fn parse_u32_parameter(input: Option<&str>, flag: &str) -> Option<u32> {
     }
 }
 
+// determine if we should use stdout or create a new file
+fn open_output(name: Option<&str>) -> BufferedWriter<Box<dyn Write>> {
+    open_output_with(name, &OutputOptions::new())
+}
+
+// like open_output, buffering the output with `options`
+fn open_output_with(name: Option<&str>, options: &OutputOptions) -> BufferedWriter<Box<dyn Write>> {
+    options.buffered(
+        match name {
+            None => Box::new(io::stdout()),
+            Some(name) => {
+                match File::create(name) {
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        exit(1);
+                    },
+                    Ok(file) => {
+                        Box::new(file)
+                    }
+                }
+            }
+        }
+    )
+}
+
+// try to open a BigBed file ('-' for stdin), exiting with a helpful message if we cannot
+fn open_bigbed(filename: &str) -> BigBed<BufferedReader<Spooled>> {
+    open_bigbed_with(filename, |file| OpenOptions::new().from_reader(file))
+}
+
+// like open_bigbed, but with `open` reading the BigBed from the file
+fn open_bigbed_with<T, F>(filename: &str, open: F) -> BigBed<T>
+    where T: Read + Seek, F: FnOnce(Spooled) -> Result<BigBed<T>, Error> {
+    // attempt to create a BigBed from the file
+    match open(open_spooled(filename)) {
+        Ok(bigbed) => {
+            for warning in bigbed.warnings() {
+                eprintln!("Warning: {} ('{}')", warning, filename);
+            }
+            bigbed
+        }
+        Err(err) => exit_open_error(filename, err),
+    }
+}
+
+// try to open a BigBed or BigWig file ('-' for stdin)
+fn open_bbi(filename: &str) -> BbiFile<Spooled> {
+    match BbiFile::from_reader(open_spooled(filename)) {
+        Ok(BbiFile::BigBed(bigbed)) => {
+            for warning in bigbed.warnings() {
+                eprintln!("Warning: {} ('{}')", warning, filename);
+            }
+            BbiFile::BigBed(bigbed)
+        }
+        Ok(bigwig) => bigwig,
+        Err(err) => exit_open_error(filename, err),
+    }
+}
+
+// open a file to read a BigBed (or BigWig) from, exiting if we cannot
+// stdin can't be seeked, so it is read in full first (into memory, or a temporary file if large)
+fn open_spooled(filename: &str) -> Spooled {
+    let file = if filename == "-" {
+        Spooled::new(io::stdin().lock(), DEFAULT_SPOOL_LIMIT)
+    } else {
+        File::open(filename).map(Spooled::file)
+    };
+    match file {
+        Ok(file) => file,
+        // notify the user if we cannot exist
+        Err(err) => {
+            eprintln!("{}", err);
+            // make it really obvious that the provided file could not be opened
+            eprintln!("Could not open file: {}", filename);
+            exit(1);
+        }
+    }
+}
+
+// if a bigbed cannot be created, let the user know why
+fn exit_open_error(filename: &str, err: Error) -> ! {
+    // provide helpful follow-ups on specific errors
+    match err {
+        IOError(_) => {
+            eprintln!("Could not open file '{}' due to the following error:\n{}.", filename, err);
+        }
+        BadSig{..} => {
+            eprintln!("{}", err);
+            eprintln!("Is '{}' a BigBed file?", filename);
+        }
+        _ => report_error(err),
+    }
+    exit(1);
+}
+
+// report an error that occurred while reading a BigBed
+fn report_error(err: Error) {
+    eprintln!("{}", err);
+    // provide helpful follow-ups on specific errors
+    if let Some(hint) = err.hint() {
+        eprintln!("{}", hint);
+    }
+}
+
+// produces the records to write for a chromosome (chrom, start, end, max_items)
+type Fetch<T> = fn(&mut BigBed<T>, &str, u32, u32, u32) -> Result<Vec<BedLine>, Error>;
+
+fn query_merged<T: Read + Seek>(bigbed: &mut BigBed<T>, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedLine>, Error> {
+    bigbed.query(chrom, start, end, max_items).map(|lines| merge_intervals(&lines))
+}
+
+// the filters used by write_bed
+struct Filters<'a> {
+    // chromosome names or wildcard patterns (any of which may match), and a regex to match names against
+    chroms: Vec<&'a str>,
+    chrom_regex: Option<Regex>,
+    // only write the chromosomes with these names
+    chrom_names: Option<HashSet<String>>,
+    start: Option<u32>,
+    end: Option<u32>,
+    // full regions to write instead of the chromosomes above, in the order given
+    regions: Vec<GenomicRange>,
+    max_items: Option<u32>,
+    // regions to remove from the output (by chromosome name), and whether to truncate
+    // records overlapping them rather than dropping them
+    exclude: Option<HashMap<String, Vec<(u32, u32)>>>,
+    truncate: bool,
+    // write chromosomes in natural order (chr1, chr2, ..., chr10) instead of name order
+    natural_order: bool,
+    // truncate records to the start and end
+    clip: bool,
+}
+
+impl<'a> Filters<'a> {
+    // the chromosomes to write, in order
+    // (a --chr name is resolved like a query's, so '1' finds 'chr1' or 'Chr1')
+    fn chroms<T: Read + Seek>(&self, bigbed: &mut BigBed<T>) -> Result<Vec<Chrom>, Error> {
+        let mut chroms = if self.chroms.is_empty() {
+            bigbed.chrom_list()?
+        } else {
+            bigbed.chroms_matching_any(&self.chroms)?
+        };
+        if self.natural_order {
+            chroms.sort_by(|a, b| natural_cmp(a.name(), b.name()));
+        }
+        Ok(chroms)
+    }
+
+    // the parts of the file to write as (chromosome, start, end), in order: each of the
+    // chromosomes between --start and --end, or else each --region (skipping those on
+    // chromosomes that are not in the file)
+    fn spans<T: Read + Seek>(&self, bigbed: &mut BigBed<T>) -> Result<Vec<(Chrom, u32, u32)>, Error> {
+        if self.regions.is_empty() {
+            return Ok(self.chroms(bigbed)?.into_iter().map(|chrom_data| {
+                let end = self.end.unwrap_or_else(|| chrom_data.size());
+                (chrom_data, self.start.unwrap_or(0), end)
+            }).collect())
+        }
+        let mut spans = Vec::new();
+        for region in &self.regions {
+            match bigbed.resolve_chrom(&region.chrom) {
+                Ok(chrom_data) => spans.push((chrom_data, region.start, region.end)),
+                Err(BadChrom(_)) | Err(BadKey(..)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(spans)
+    }
+
+    // the records to write for part of a chromosome, or None if it is filtered out
+    fn fetch<T: Read + Seek>(&self, bigbed: &mut BigBed<T>, chrom_data: &Chrom, start: u32, end: u32, fetch: Fetch<T>) -> Result<Option<Vec<BedLine>>, Error> {
+        if let Some(regex) = &self.chrom_regex {
+            if !regex.is_match(chrom_data.name()) {
+                return Ok(None)
+            }
+        }
+        if let Some(names) = &self.chrom_names {
+            if !names.contains(chrom_data.name()) {
+                return Ok(None)
+            }
+        }
+        let mut lines = fetch(bigbed, chrom_data.name(), start, end, self.max_items.unwrap_or(0))?;
+        if self.clip {
+            lines = lines.iter().map(|line| clip(line, start, end)).collect();
+        }
+        match self.exclude.as_ref().and_then(|exclude| exclude.get(chrom_data.name())) {
+            Some(regions) => {
+                let regions: Vec<BedLine> = regions.iter().map(|&(start, end)| BedLine::new(chrom_data.id(), start, end)).collect();
+                Ok(Some(subtract(&lines, &regions, self.truncate)))
+            }
+            None => Ok(Some(lines)),
+        }
+    }
+}
+
+// write the records of each chromosome (using the same filters as write_bed),
+// with `fetch` producing the records to write for a chromosome
+fn write_per_chrom<T: Read + Seek>(bigbed: &mut BigBed<T>, filters: &Filters, formatter: &BedFormatter, mut output: impl Write, fetch: Fetch<T>) -> Result<(), Error> {
+    for (chrom_data, start, end) in filters.spans(bigbed)? {
+        let lines = match filters.fetch(bigbed, &chrom_data, start, end, fetch)? {
+            Some(lines) => lines,
+            None => continue,
+        };
+        for line in lines {
+            formatter.write(&mut output, chrom_data.raw_name(), &line)?;
+        }
+    }
+    Ok(())
+}
+
+// like write_per_chrom, but forward and reverse strand records go to separate outputs
+// records without a strand are skipped; returns how many were
+fn write_by_strand<T: Read + Seek>(bigbed: &mut BigBed<T>, filters: &Filters, formatter: &BedFormatter, mut plus: impl Write, mut minus: impl Write, fetch: Fetch<T>) -> Result<u64, Error> {
+    // a bed3+3 has a sixth column, but it is a custom one
+    if bigbed.defined_field_count < 6 {
+        return Err(Misc("File has no strand column"))
+    }
+    let mut skipped = 0;
+    for (chrom_data, start, end) in filters.spans(bigbed)? {
+        let lines = match filters.fetch(bigbed, &chrom_data, start, end, fetch)? {
+            Some(lines) => lines,
+            None => continue,
+        };
+        for line in lines {
+            match line.defined_strand(bigbed.defined_field_count)? {
+                Some(Strand::Forward) => formatter.write(&mut plus, chrom_data.raw_name(), &line)?,
+                Some(Strand::Reverse) => formatter.write(&mut minus, chrom_data.raw_name(), &line)?,
+                _ => skipped += 1,
+            }
+        }
+    }
+    plus.flush()?;
+    minus.flush()?;
+    Ok(skipped)
+}
+
+// the path for one strand's records: out.bed becomes out.plus.bed (or out.minus.bed)
+fn strand_path(output: &str, strand: &str) -> String {
+    match output.strip_suffix(".bed") {
+        Some(stem) => format!("{}.{}.bed", stem, strand),
+        None => format!("{}.{}", output, strand),
+    }
+}
+
+// like write_per_chrom, but each chromosome with records is written to its own file in `dir`
+// (each starting with `header`, if given)
+fn write_split<T: Read + Seek>(bigbed: &mut BigBed<T>, filters: &Filters, formatter: &BedFormatter, header: Option<&str>, dir: &str, gzip: bool, fetch: Fetch<T>) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    let mut used_names = HashSet::new();
+    for (chrom_data, start, end) in filters.spans(bigbed)? {
+        let lines = match filters.fetch(bigbed, &chrom_data, start, end, fetch)? {
+            Some(lines) if !lines.is_empty() => lines,
+            _ => continue,
+        };
+        // different names may be sanitized to the same file name
+        let mut file_name = chrom_file_name(chrom_data.name());
+        if !used_names.insert(file_name.clone()) {
+            file_name = format!("{}_{}", file_name, chrom_data.id());
+            used_names.insert(file_name.clone());
+        }
+        let extension = if gzip { "bed.gz" } else { "bed" };
+        let file = File::create(Path::new(dir).join(format!("{}.{}", file_name, extension)))?;
+        let mut output: Box<dyn Write> = if gzip {
+            Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Box::new(BufWriter::new(file))
+        };
+        if let Some(header) = header {
+            writeln!(output, "{}", header)?;
+        }
+        for line in lines {
+            formatter.write(&mut output, chrom_data.raw_name(), &line)?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// read the regions of a BED file, grouped by chromosome
+fn read_regions(filename: &str) -> HashMap<String, Vec<(u32, u32)>> {
+    let mut regions: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+    for (number, line) in open_input(filename).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        };
+        if is_bed_header(&line) {
+            continue
+        }
+        match parse_bed_region(&line) {
+            Some((chrom, start, end)) => regions.entry(chrom.to_owned()).or_default().push((start, end)),
+            None => {
+                eprintln!("Invalid BED line {} in '{}': '{}'", number + 1, filename, line);
+                exit(1);
+            }
+        }
+    }
+    regions
+}
+
+// the regions given with --region (chr1:1501-1879) and --regions-bed, in that order, exiting if any are invalid
+fn read_region_args(matches: &ArgMatches) -> Vec<GenomicRange> {
+    let mut regions = Vec::new();
+    for text in matches.values_of("region").into_iter().flatten() {
+        match text.parse() {
+            Ok(region) => regions.push(region),
+            Err(_) => {
+                eprintln!("Invalid value for --region: '{}'", text);
+                eprintln!("(Expected chrom:start-end, with 1-based positions like chr1:1501-1879)");
+                exit(1);
+            }
+        }
+    }
+    if let Some(filename) = matches.value_of("regions_bed") {
+        for (number, line) in open_input(filename).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            };
+            if is_bed_header(&line) {
+                continue
+            }
+            match parse_bed_region(&line) {
+                Some((chrom, start, end)) => regions.push(GenomicRange::new(chrom, start, end)),
+                None => {
+                    eprintln!("Invalid BED line {} in '{}': '{}'", number + 1, filename, line);
+                    exit(1);
+                }
+            }
+        }
+    }
+    regions
+}
+
+// read a file of chromosome names, one per line (only the first column is used,
+// so a chrom.sizes file also works); blank lines and '#' comments are skipped
+fn read_chrom_names(filename: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for line in open_input(filename).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        };
+        if line.starts_with('#') {
+            continue
+        }
+        if let Some(name) = line.split_whitespace().next() {
+            names.insert(name.to_owned());
+        }
+    }
+    names
+}
+
+// parse a score range given as "min:max"
+fn parse_score_range(range: &str) -> Option<(f64, f64)> {
+    let mut bounds = range.splitn(2, ':');
+    let min: f64 = bounds.next()?.trim().parse().ok()?;
+    let max: f64 = bounds.next()?.trim().parse().ok()?;
+    if min.is_finite() && max.is_finite() && min <= max {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+// convert a BigBed to a BED (the default behavior)
+fn run_convert(matches: &ArgMatches) {
+    let filters = Filters{
+        chroms: matches.values_of("chr").map(|values| values.collect()).unwrap_or_default(),
+        chrom_regex: matches.value_of("chr_regex").map(|pattern| match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                eprintln!("Invalid value for --chr-regex: '{}'", pattern);
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }),
+        chrom_names: matches.value_of("chrom_list").map(read_chrom_names),
+        start: parse_u32_parameter(matches.value_of("start"), "--start"),
+        end: parse_u32_parameter(matches.value_of("end"), "--end"),
+        regions: read_region_args(matches),
+        max_items: parse_u32_parameter(matches.value_of("max_items"), "--max"),
+        exclude: matches.value_of("exclude").map(read_regions),
+        truncate: matches.is_present("truncate"),
+        natural_order: matches.is_present("natural_sort"),
+        clip: matches.is_present("clip"),
+    };
+
+    let mut formatter = match matches.value_of("format") {
+        Some("csv") => BedFormatter::csv(),
+        _ => BedFormatter::default(),
+    };
+    if let Some(delimiter) = matches.value_of("delimiter") {
+        // allow the escaped form of a tab, which is awkward to type
+        let delimiter = if delimiter == "\\t" { "\t" } else { delimiter };
+        if delimiter.is_empty() {
+            eprintln!("Invalid value for --delimiter: ''");
+            exit(1);
+        }
+        formatter = formatter.delimiter(delimiter).quote_fields(true);
+    }
+    formatter = formatter.raw_chrom_names(matches.is_present("raw_chrom_names"));
+    if let Some(range) = matches.value_of("rescale_score") {
+        match parse_score_range(range) {
+            Some((min, max)) => formatter = formatter.rescale_score(min, max),
+            None => {
+                eprintln!("Invalid value for --rescale-score: '{}'", range);
+                eprintln!("(Expected min:max, with min <= max)");
+                exit(1);
+            }
+        }
+    }
+
+    if matches.is_present("names_only") {
+        formatter = formatter.only_column(4);
+    } else if let Some(column) = parse_u32_parameter(matches.value_of("only_column"), "--only-column") {
+        if column == 0 {
+            eprintln!("Invalid value for --only-column: '0'");
+            eprintln!("(Columns are counted from 1)");
+            exit(1);
+        }
+        formatter = formatter.only_column(column as usize);
+    }
+
+    // this will always work, since input is required arg
+    let mut bigbed = open_bigbed(matches.value_of("input.bb").unwrap());
+    // the whole file is checked before anything is written, so that an unsorted file
+    // never leaves a partial output behind
+    if matches.is_present("sorted") {
+        match find_unsorted(&mut bigbed) {
+            Ok(None) => {}
+            Ok(Some(description)) => {
+                eprintln!("{}", description);
+                eprintln!("'{}' is not sorted", matches.value_of("input.bb").unwrap());
+                exit(1);
+            }
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        }
+    }
+    let opening_warnings = bigbed.warnings().len();
+    formatter = match formatter.defined_field_count(bigbed.defined_field_count) {
+        Ok(formatter) => formatter,
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    };
+    if let Some(precision) = parse_u32_parameter(matches.value_of("float_precision"), "--float-precision") {
+        // only round the columns that the autoSql definition says are floating-point
+        let columns = match bigbed.float_columns() {
+            Ok(columns) => columns,
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        };
+        if columns.is_empty() {
+            eprintln!("No float or double columns in the autoSql definition; --float-precision has no effect");
+        }
+        formatter = formatter.precision(precision as usize).float_columns(&columns);
+    }
+    let mut selected_names = None;
+    if let Some(spec) = matches.value_of("select") {
+        let selected = match bigbed.select_columns(spec) {
+            Ok(selected) => selected,
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        };
+        let columns: Vec<usize> = selected.iter().map(|column| column.index).collect();
+        formatter = formatter.select(&columns);
+        selected_names = Some(selected.into_iter().map(|column| column.name).collect::<Vec<String>>());
+    }
+    // the header names the columns of the file (or the selected columns, with their new names)
+    let header = if matches.is_present("header") {
+        let names = match selected_names {
+            Some(names) => names,
+            None => match bigbed.field_names() {
+                // merged records only have the first three columns
+                Ok(mut names) if matches.is_present("merge") => {
+                    names.truncate(3);
+                    formatter.column_names(&names)
+                }
+                Ok(names) => formatter.column_names(&names),
+                Err(err) => {
+                    report_error(err);
+                    exit(1);
+                }
+            }
+        };
+        Some(formatter.header(&names))
+    } else {
+        None
+    };
+    let fetch: Fetch<_> = if matches.is_present("merge") {
+        query_merged
+    } else if matches.is_present("dedup") {
+        BigBed::query_unique
+    } else {
+        BigBed::query
+    };
+    let mut output_options = OutputOptions::new();
+    if let Some(size) = parse_u32_parameter(matches.value_of("buffer_size"), "--buffer-size") {
+        output_options = output_options.buffer_size(size as usize);
+    }
+    if let Some(lines) = parse_u32_parameter(matches.value_of("flush_lines"), "--flush-lines") {
+        output_options = output_options.flush_lines(u64::from(lines));
+    }
+    if let Some(seconds) = parse_u32_parameter(matches.value_of("flush_interval"), "--flush-interval") {
+        output_options = output_options.flush_interval(Duration::from_secs(u64::from(seconds)));
+    }
+    // attempt to convert BigBed to a BED using the provided parameters
+    let result = if let Some(dir) = matches.value_of("split_by_chrom") {
+        write_split(&mut bigbed, &filters, &formatter, header.as_deref(), dir, matches.is_present("gzip"), fetch)
+    } else if matches.is_present("split_strand") {
+        // output.bed is required with --split-strand
+        let output = matches.value_of("output.bed").unwrap();
+        let mut plus = open_output_with(Some(&strand_path(output, "plus")), &output_options);
+        let mut minus = open_output_with(Some(&strand_path(output, "minus")), &output_options);
+        if let Some(header) = &header {
+            if let Err(err) = writeln!(plus, "{}", header).and_then(|_| writeln!(minus, "{}", header)) {
+                report_error(err.into());
+                exit(1);
+            }
+        }
+        write_by_strand(&mut bigbed, &filters, &formatter, plus, minus, fetch).map(|skipped| {
+            if skipped > 0 {
+                eprintln!("Skipped {} records without a strand", skipped);
+            }
+        })
+    } else {
+        let mut output = open_output_with(matches.value_of("output.bed"), &output_options);
+        let mut write_records = |output: &mut dyn Write| {
+            if let Some(header) = &header {
+                writeln!(output, "{}", header)?;
+            }
+            if matches.is_present("merge") || matches.is_present("dedup") || filters.exclude.is_some()
+                || filters.chrom_regex.is_some() || filters.chrom_names.is_some() || filters.natural_order || filters.clip {
+                write_per_chrom(&mut bigbed, &filters, &formatter, output, fetch)
+            } else if !filters.regions.is_empty() {
+                bigbed.write_bed_regions(&filters.regions, filters.max_items, &formatter, output)
+            } else {
+                bigbed.write_bed_chroms(&filters.chroms, filters.start, filters.end, filters.max_items, &formatter, output)
+            }
+        };
+        if matches.is_present("tabix") {
+            // the index goes next to the output (output.bed is required with --tabix)
+            let mut writer = TabixWriter::new(output);
+            write_records(&mut writer).and_then(|_| {
+                let (_, index) = writer.finish()?;
+                let index_file = File::create(format!("{}.tbi", matches.value_of("output.bed").unwrap()))?;
+                index.write(BufWriter::new(index_file))?;
+                Ok(())
+            })
+        } else if matches.is_present("bgzip") {
+            let mut writer = BgzfWriter::new(output);
+            write_records(&mut writer).and_then(|_| writer.finish().map(|_| ()).map_err(Error::from))
+        } else {
+            write_records(&mut output)
+        }
+    };
+    // data-quality problems found in the records (the warnings from opening the file were already shown)
+    for warning in bigbed.take_warnings().iter().skip(opening_warnings) {
+        eprintln!("Warning: {}", warning);
+    }
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// report the overlaps between two BigBed files
+fn run_intersect(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output"));
+    let mut a = open_bigbed(matches.value_of("a.bb").unwrap());
+    let mut b = open_bigbed(matches.value_of("b.bb").unwrap());
+    let options = IntersectOptions{
+        mode: if matches.is_present("counts") { IntersectMode::Counts } else { IntersectMode::Pairs },
+        min_overlap: parse_u32_parameter(matches.value_of("min_overlap"), "--min-overlap").unwrap_or(1),
+        report_unmatched: matches.is_present("unmatched"),
+    };
+    let formatter = BedFormatter::default();
+    let result = a.intersect(&mut b, &options).and_then(|results| {
+        for result in results {
+            let line = match result {
+                Intersection::Pair{chrom, a, b} => {
+                    format!("{}\t{}\n", formatter.format(&chrom, &a), formatter.format(&chrom, &b))
+                }
+                Intersection::Count{chrom, a, hits} => {
+                    format!("{}\t{}\n", formatter.format(&chrom, &a), hits)
+                }
+            };
+            output.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// pick a random subset of the records in a BigBed file
+fn run_sample(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output.bed"));
+    let mut bigbed = open_bigbed(matches.value_of("input.bb").unwrap());
+    let count = parse_u32_parameter(matches.value_of("count"), "-n").unwrap();
+    let seed = match matches.value_of("seed").map(str::parse::<u64>) {
+        None => 0,
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => {
+            eprintln!("Invalid value for --seed: '{}'", matches.value_of("seed").unwrap());
+            exit(1);
+        }
+    };
+    let result = bigbed.sample(count as usize, seed).and_then(|lines| {
+        for line in lines {
+            output.write_all(format!("{}\n", line).as_bytes())?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// the outcome of one of validate's checks
+struct Check {
+    name: &'static str,
+    // the number of records or zoom levels checked
+    checked: u64,
+    // each problem found, described for people and as a JSON object
+    problems: Vec<(String, String)>,
+    // the error that stopped the check, if any
+    error: Option<Error>,
+}
+
+impl Check {
+    fn new(name: &'static str) -> Check {
+        Check{name, checked: 0, problems: Vec::new(), error: None}
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.error, self.problems.is_empty()) {
+            (Some(_), _) => "error",
+            (None, true) => "pass",
+            (None, false) => "fail",
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let problems: Vec<&str> = self.problems.iter().map(|(_, json)| json.as_str()).collect();
+        let error = match &self.error {
+            Some(err) => format!(", \"error\": {}", json_string(&err.to_string())),
+            None => String::new(),
+        };
+        format!("{{\"name\": {}, \"status\": \"{}\", \"checked\": {}, \"problem_count\": {}, \"problems\": [{}]{}}}",
+                json_string(self.name), self.status(), self.checked, self.problems.len(), problems.join(", "), error)
+    }
+}
+
+// quote and escape a string for JSON
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// a number for JSON, which has no NaN or infinity
+fn json_number(value: f64) -> String {
+    if value.is_finite() { value.to_string() } else { String::from("null") }
+}
+
+// read every record, checking the coordinates against the chromosome sizes
+fn check_records<T: Read + Seek>(bigbed: &mut BigBed<T>) -> Check {
+    let mut check = Check::new("records");
+    let result = bigbed.check_records().and_then(|(count, invalid)| {
+        check.checked = count;
+        let chroms = bigbed.chrom_list()?;
+        for record in invalid {
+            let chrom = chroms.iter().find(|chrom| chrom.id() == record.chrom_id)
+                .map(|chrom| chrom.name().to_owned())
+                .unwrap_or_else(|| format!("chrom #{}", record.chrom_id));
+            let size = record.chrom_size.map_or_else(|| String::from("unknown"), |size| size.to_string());
+            check.problems.push((
+                format!("Invalid record: {}\t{}\t{} (chromosome size {}, in block at offset {})",
+                        chrom, record.start, record.end, size, record.block_offset),
+                format!("{{\"chrom\": {}, \"start\": {}, \"end\": {}, \"chrom_size\": {}, \"block_offset\": {}}}",
+                        json_string(&chrom), record.start, record.end,
+                        record.chrom_size.map_or_else(|| String::from("null"), |size| size.to_string()), record.block_offset),
+            ));
+        }
+        Ok(())
+    });
+    check.error = result.err();
+    check
+}
+
+// recompute a sample of the zoom records of each level from the records
+fn check_zoom_levels<T: Read + Seek>(bigbed: &mut BigBed<T>, samples: u32) -> Check {
+    let mut check = Check::new("zoom_levels");
+    match bigbed.verify_zoom_levels(samples as usize, 0) {
+        Ok(mismatches) => {
+            check.checked = bigbed.level_list.len() as u64;
+            for mismatch in mismatches {
+                let stored = &mismatch.stored;
+                let computed = match &mismatch.computed {
+                    Some(summary) => format!("{{\"valid_count\": {}, \"min\": {}, \"max\": {}, \"sum\": {}, \"sum_squares\": {}}}",
+                                             summary.valid_count, json_number(summary.min), json_number(summary.max),
+                                             json_number(summary.sum), json_number(summary.sum_squares)),
+                    None => String::from("null"),
+                };
+                check.problems.push((
+                    format!("Zoom level {} does not match the records: {:?} (expected {:?})",
+                            mismatch.reduction_level, mismatch.stored, mismatch.computed),
+                    format!("{{\"reduction_level\": {}, \"chrom_id\": {}, \"start\": {}, \"end\": {}, \"stored\": {{\"valid_count\": {}, \"min\": {}, \"max\": {}, \"sum\": {}, \"sum_squares\": {}}}, \"computed\": {}}}",
+                            mismatch.reduction_level, stored.chrom_id, stored.start, stored.end, stored.valid_count,
+                            json_number(stored.min.into()), json_number(stored.max.into()),
+                            json_number(stored.sum.into()), json_number(stored.sum_squares.into()), computed),
+                ));
+            }
+        }
+        Err(err) => check.error = Some(err),
+    }
+    check
+}
+
+// check that a BigBed file can be read, and optionally that its zoom levels match its records
+// with --json, a report of each check is printed instead (and still exits with 1 on any problem)
+fn run_validate(matches: &ArgMatches) {
+    let filename = matches.value_of("input.bb").unwrap();
+    let json = matches.is_present("json");
+    let samples = parse_u32_parameter(matches.value_of("samples"), "--samples").unwrap_or(100);
+    let mut bigbed = if json {
+        match OpenOptions::new().from_reader(open_spooled(filename)) {
+            Ok(bigbed) => bigbed,
+            Err(err) => {
+                let mut open = Check::new("open");
+                open.error = Some(err);
+                print_validate_json(filename, &[], &[open]);
+                exit(1);
+            }
+        }
+    } else {
+        open_bigbed(filename)
+    };
+    let mut checks = vec![check_records(&mut bigbed)];
+    // the zoom levels are only worth checking if the records can be read
+    if matches.is_present("deep") && checks[0].error.is_none() {
+        checks.push(check_zoom_levels(&mut bigbed, samples));
+    }
+    let problems: usize = checks.iter().map(|check| check.problems.len()).sum();
+    let failed = problems > 0 || checks.iter().any(|check| check.error.is_some());
+
+    if json {
+        let warnings: Vec<String> = bigbed.warnings().iter().map(|warning| warning.to_string()).collect();
+        print_validate_json(filename, &warnings, &checks);
+        if failed {
+            exit(1);
+        }
+        return
+    }
+    for check in checks {
+        for (text, _) in &check.problems {
+            println!("{}", text);
+        }
+        if let Some(err) = check.error {
+            report_error(err);
+            exit(1);
+        }
+        match check.name {
+            "records" => println!("Read {} records", check.checked),
+            _ => println!("Checked {} zoom levels", check.checked),
+        }
+    }
+    if failed {
+        eprintln!("Found {} problems in '{}'", problems, filename);
+        exit(1);
+    }
+    println!("'{}' is valid", filename);
+}
+
+// print the report of validate --json
+fn print_validate_json(filename: &str, warnings: &[String], checks: &[Check]) {
+    let valid = checks.iter().all(|check| check.status() == "pass");
+    let records = checks.iter().find(|check| check.name == "records").map_or(0, |check| check.checked);
+    let problems: usize = checks.iter().map(|check| check.problems.len()).sum();
+    let warnings: Vec<String> = warnings.iter().map(|warning| json_string(warning)).collect();
+    let checks: Vec<String> = checks.iter().map(Check::to_json).collect();
+    println!("{{\"file\": {}, \"valid\": {}, \"records\": {}, \"problem_count\": {}, \"warnings\": [{}], \"checks\": [{}]}}",
+             json_string(filename), valid, records, problems, warnings.join(", "), checks.join(", "));
+}
+
+// open a text file for reading ('-' for stdin), exiting with a helpful message if we cannot
+fn open_input(name: &str) -> Box<dyn BufRead> {
+    match name {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        filename => match File::open(filename) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("{}", err);
+                eprintln!("Could not open file: {}", filename);
+                exit(1);
+            }
+        }
+    }
+}
+
+// append the overlap count and overlapping names from a BigBed to each line of a BED file
+fn run_annotate(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output"));
+    let mut bigbed = open_bigbed(matches.value_of("track.bb").unwrap());
+    let input = open_input(matches.value_of("regions.bed").unwrap());
+    for (number, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        };
+        // pass headers and blank lines through unchanged
+        if is_bed_header(&line) {
+            if let Err(err) = writeln!(output, "{}", line) {
+                report_error(err.into());
+                exit(1);
+            }
+            continue
+        }
+        let (chrom, start, end) = match parse_bed_region(&line) {
+            Some(region) => region,
+            None => {
+                eprintln!("Invalid BED line {}: '{}'", number + 1, line);
+                exit(1);
+            }
+        };
+        let hits = match bigbed.query(chrom, start, end, 0) {
+            Ok(hits) => hits,
+            // regions on chromosomes that are not in the track have no overlaps
+            Err(BadChrom(_)) | Err(BadKey(_, _)) => Vec::new(),
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        };
+        let names: Vec<&str> = hits.iter().filter_map(BedLine::name).collect();
+        let names = if names.is_empty() { String::from(".") } else { names.join(",") };
+        if let Err(err) = writeln!(output, "{}\t{}\t{}", line, hits.len(), names) {
+            report_error(err.into());
+            exit(1);
+        }
+    }
+}
+
+// report statistics of the BigBed records overlapping each region of a BED file, as a TSV
+fn run_region_summary(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output"));
+    let mut bigbed = open_bigbed(matches.value_of("track.bb").unwrap());
+    let input = open_input(matches.value_of("regions.bed").unwrap());
+    let result = writeln!(output, "#name\tsize\tcount\tcovered\tmean_score").map_err(Error::from).and_then(|_| {
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            if is_bed_header(&line) {
+                continue
+            }
+            let (chrom, start, end) = match parse_bed_region(&line) {
+                Some(region) => region,
+                None => {
+                    eprintln!("Invalid BED line {}: '{}'", number + 1, line);
+                    exit(1);
+                }
+            };
+            // use the name column if there is one
+            let name = match line.split('\t').nth(3) {
+                Some(name) => name.to_owned(),
+                None => format!("{}:{}-{}", chrom, start, end),
+            };
+            let summary = match bigbed.region_summary(chrom, start, end) {
+                Ok(summary) => summary,
+                // regions on chromosomes that are not in the track have no overlaps
+                Err(BadChrom(_)) | Err(BadKey(_, _)) => RegionSummary{count: 0, covered: 0, mean_score: None},
+                Err(err) => return Err(err),
+            };
+            let mean_score = match summary.mean_score {
+                Some(mean) => format!("{:.3}", mean),
+                None => String::from("NA"),
+            };
+            writeln!(output, "{}\t{}\t{}\t{}\t{}", name, end.saturating_sub(start), summary.count, summary.covered, mean_score)?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// describe the first record of a BigBed file that is out of order, if any
+fn find_unsorted<T: Read + Seek>(bigbed: &mut BigBed<T>) -> Result<Option<String>, Error> {
+    let unsorted = match bigbed.verify_sorted()? {
+        Some(unsorted) => unsorted,
+        None => return Ok(None),
+    };
+    // report the chromosomes by name
+    let chroms = bigbed.chrom_list()?;
+    let name = |chrom_id: u32| chroms.iter()
+        .find(|chrom| chrom.id() == chrom_id)
+        .map(|chrom| chrom.name().to_owned())
+        .unwrap_or_else(|| format!("chrom #{}", chrom_id));
+    Ok(Some(format!("Record {}:{} (in block at offset {}) comes after {}:{}",
+                    name(unsorted.record.0), unsorted.record.1, unsorted.block_offset,
+                    name(unsorted.previous.0), unsorted.previous.1)))
+}
+
+// check that the records of a BigBed file are sorted
+fn run_check_sorted(matches: &ArgMatches) {
+    let filename = matches.value_of("input.bb").unwrap();
+    let mut bigbed = open_bigbed(filename);
+    match find_unsorted(&mut bigbed) {
+        Ok(None) => println!("'{}' is sorted", filename),
+        Ok(Some(description)) => {
+            println!("{}", description);
+            eprintln!("'{}' is not sorted", filename);
+            exit(1);
+        }
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// print a digest of the records of each BigBed file
+fn run_fingerprint(matches: &ArgMatches) {
+    for filename in matches.values_of("input.bb").unwrap() {
+        let mut bigbed = open_bigbed(filename);
+        match bigbed.fingerprint() {
+            Ok(fingerprint) => println!("{}\t{}\t{}", fingerprint, fingerprint.records, filename),
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        }
+    }
+}
+
+// describe the header of a BigBed or BigWig file
+fn run_info(matches: &ArgMatches) {
+    let mut file = open_bbi(matches.value_of("input").unwrap());
+    let header = file.header();
+    let chroms = match file.chrom_list() {
+        Ok(chroms) => chroms,
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    };
+    let kind = if file.kind() == FileKind::BigWig { "bigWig" } else { "bigBed" };
+    println!("type\t{}", kind);
+    println!("version\t{}", header.version);
+    println!("compressed\t{}", if header.uncompress_buf_size > 0 { "yes" } else { "no" });
+    println!("big_endian\t{}", if header.big_endian { "yes" } else { "no" });
+    println!("field_count\t{}", header.field_count);
+    println!("defined_field_count\t{}", header.defined_field_count);
+    println!("chrom_count\t{}", chroms.len());
+    println!("bases_in_chroms\t{}", chroms.iter().map(|chrom| u64::from(chrom.size())).sum::<u64>());
+    println!("zoom_levels\t{}", header.zoom_levels);
+    for level in &header.level_list {
+        println!("zoom_reduction\t{}", level.reduction_level);
+    }
+}
+
+// list the chromosomes of a BigBed or BigWig file (like a chrom.sizes file)
+fn run_chroms(matches: &ArgMatches) {
+    let mut file = open_bbi(matches.value_of("input").unwrap());
+    match file.chrom_list() {
+        Ok(chroms) => {
+            for chrom in chroms {
+                println!("{}\t{}", chrom.name(), chrom.size());
+            }
+        }
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// print the summary of the whole of a BigBed (of its coverage depth) or BigWig file
+fn run_summary(matches: &ArgMatches) {
+    let mut file = open_bbi(matches.value_of("input").unwrap());
+    match file.total_summary() {
+        Ok(Some(summary)) => {
+            println!("bases_covered\t{}", summary.valid_count);
+            println!("min\t{}", summary.min);
+            println!("max\t{}", summary.max);
+            println!("mean\t{}", summary.mean());
+        }
+        Ok(None) => {
+            eprintln!("The file has no summary");
+            exit(1);
+        }
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// report the Jaccard similarity of two BigBed files (like bedtools jaccard)
+fn run_jaccard(matches: &ArgMatches) {
+    let mut a = open_bigbed(matches.value_of("a.bb").unwrap());
+    let mut b = open_bigbed(matches.value_of("b.bb").unwrap());
+    match a.jaccard(&mut b) {
+        Ok(result) => {
+            println!("intersection\tunion\tjaccard\tn_intersections");
+            println!("{}\t{}\t{}\t{}", result.intersection, result.union, result.jaccard(), result.intersections);
+        }
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// time queries over a set of regions, to compare reader settings on real data
+fn run_bench(matches: &ArgMatches) {
+    let iterations = parse_u32_parameter(matches.value_of("iterations"), "--iterations").unwrap_or(1).max(1);
+    let mut options = OpenOptions::new();
+    if let Some(size) = parse_u32_parameter(matches.value_of("buffer_size"), "--buffer-size") {
+        options = options.buffer_size(size as usize);
+    }
+    if matches.is_present("no_seek_within_buffer") {
+        options = options.seek_within_buffer(false);
+    }
+    let metrics = Metrics::new();
+    let mut bigbed = open_bigbed_with(matches.value_of("input.bb").unwrap(), |file| {
+        options.from_reader(MeteredReader::new(file, Arc::clone(&metrics)))
+    });
+    bigbed.register_metrics(Arc::clone(&metrics));
+
+    let result = bigbed.chrom_list().and_then(|chroms| {
+        // query the given regions (on chromosomes in the file), or else every chromosome
+        let mut regions: Vec<(String, u32, u32)> = Vec::new();
+        let mut skipped = 0;
+        match matches.value_of("regions") {
+            Some(filename) => {
+                let mut by_chrom: Vec<_> = read_regions(filename).into_iter().collect();
+                by_chrom.sort_by(|a, b| a.0.cmp(&b.0));
+                for (chrom, ranges) in by_chrom {
+                    // chromosomes are resolved like a query's, as in write_bed_regions
+                    let chrom = match bigbed.resolve_chrom(&chrom) {
+                        Ok(chrom_data) => chrom_data.name().to_owned(),
+                        Err(Error::BadChrom(_)) | Err(Error::BadKey(..)) => {
+                            skipped += ranges.len();
+                            continue
+                        }
+                        Err(err) => return Err(err),
+                    };
+                    regions.extend(ranges.into_iter().map(|(start, end)| (chrom.clone(), start, end)));
+                }
+            }
+            None => regions.extend(chroms.iter().map(|chrom| (chrom.name().to_owned(), 0, chrom.size()))),
+        }
+
+        // don't count opening the file
+        metrics.reset();
+        let mut records: u64 = 0;
+        let started = Instant::now();
+        for _ in 0..iterations {
+            for (chrom, start, end) in &regions {
+                records += bigbed.query(chrom, *start, *end, 0)?.len() as u64;
+            }
+        }
+        let seconds = started.elapsed().as_secs_f64();
+
+        let queries = regions.len() as u64 * u64::from(iterations);
+        println!("queries\t{}", queries);
+        if skipped > 0 {
+            println!("skipped_regions\t{}", skipped);
+        }
+        println!("records\t{}", records);
+        println!("seconds\t{:.6}", seconds);
+        if seconds > 0.0 {
+            println!("queries_per_second\t{:.1}", queries as f64 / seconds);
+            println!("records_per_second\t{:.1}", records as f64 / seconds);
+        }
+        println!("blocks_read\t{}", metrics.blocks_read());
+        println!("blocks_decompressed\t{}", metrics.blocks_decompressed());
+        println!("seeks\t{}", metrics.seeks());
+        println!("bytes_read\t{}", metrics.bytes_read());
+        if let Some(ratio) = metrics.cache_hit_ratio() {
+            println!("cache_hit_ratio\t{:.3}", ratio);
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// rebuild the indexes of a damaged BigBed file from its data section
+fn run_repair(matches: &ArgMatches) {
+    let mut options = RepairOptions::new();
+    if let Some(size) = parse_u32_parameter(matches.value_of("block_size"), "--block-size") {
+        options = options.block_size(size);
+    }
+    if let Some(items) = parse_u32_parameter(matches.value_of("items_per_slot"), "--items-per-slot") {
+        options = options.items_per_slot(items);
+    }
+    let input_name = matches.value_of("input.bb").unwrap();
+    let output_name = matches.value_of("output.bb").unwrap();
+    let input = match File::open(input_name) {
+        Ok(file) => BufReader::new(file),
+        Err(err) => {
+            eprintln!("Could not open '{}': {}", input_name, err);
+            exit(1);
+        }
+    };
+    let output = match File::create(output_name) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Could not create '{}': {}", output_name, err);
+            exit(1);
+        }
+    };
+    match repair(input, output, &options) {
+        Ok(report) => {
+            for warning in &report.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            println!("Wrote {} records in {} blocks, with {} zoom levels", report.records, report.blocks, report.zoom_levels);
+        }
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// write a BigBed file from a sorted BED file (like bedToBigBed)
+fn run_build(matches: &ArgMatches) {
+    let mut options = WriterOptions::new().compress(!matches.is_present("unc"));
+    if let Some(size) = parse_u32_parameter(matches.value_of("block_size"), "--block-size") {
+        options = options.block_size(size);
+    }
+    if let Some(items) = parse_u32_parameter(matches.value_of("items_per_slot"), "--items-per-slot") {
+        options = options.items_per_slot(items);
+    }
+    if let Some(bed_type) = matches.value_of("type") {
+        // bedN or bedN+M (or bedN+), where N is the number of standard columns
+        let count = bed_type.strip_prefix("bed")
+            .map(|count| count.split('+').next().unwrap_or(""))
+            .and_then(|count| count.parse::<u16>().ok());
+        match count {
+            Some(count) => options = options.defined_field_count(count),
+            None => {
+                eprintln!("Invalid value for --type: '{}'", bed_type);
+                eprintln!("(Expected bedN or bedN+M, e.g. bed6+4)");
+                exit(1);
+            }
+        }
+    }
+    if let Some(filename) = matches.value_of("as") {
+        match fs::read_to_string(filename) {
+            Ok(text) => options = options.autosql(&text),
+            Err(err) => {
+                eprintln!("Could not read '{}': {}", filename, err);
+                exit(1);
+            }
+        }
+    }
+    let sizes_name = matches.value_of("chrom.sizes").unwrap();
+    let sizes = match File::open(sizes_name).map_err(Error::from).and_then(|file| read_chrom_sizes(BufReader::new(file))) {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            eprintln!("Could not read '{}': {}", sizes_name, err);
+            exit(1);
+        }
+    };
+    let input_name = matches.value_of("input.bed").unwrap();
+    let input: Box<dyn BufRead> = if input_name == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        match File::open(input_name) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Could not open '{}': {}", input_name, err);
+                exit(1);
+            }
+        }
+    };
+    let output_name = matches.value_of("output.bb").unwrap();
+    let output = match File::create(output_name) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Could not create '{}': {}", output_name, err);
+            exit(1);
+        }
+    };
+    let result = BigBedWriter::with_options(output, &sizes, options).and_then(|mut writer| {
+        let records = writer.add_bed(input)?;
+        writer.finish()?;
+        Ok(records)
+    });
+    match result {
+        Ok(records) => println!("Wrote {} records", records),
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
+// report statistics of the records of a BigBed file
+fn run_stats(matches: &ArgMatches) {
+    if !matches.is_present("lengths") && !matches.is_present("blocks") {
+        eprintln!("Choose the statistics to report (--lengths and/or --blocks)");
+        exit(1);
+    }
+    let mut output = open_output(matches.value_of("output"));
+    let mut bigbed = open_bigbed(matches.value_of("input.bb").unwrap());
+    if matches.is_present("blocks") {
+        let result = bigbed.block_stats().and_then(|stats| {
+            writeln!(output, "blocks\t{}", stats.blocks)?;
+            writeln!(output, "mean_compressed_size\t{:.2}", stats.mean_compressed_size())?;
+            writeln!(output, "mean_uncompressed_size\t{:.2}", stats.mean_uncompressed_size())?;
+            writeln!(output, "compression_ratio\t{:.2}", stats.compression_ratio())?;
+            writeln!(output, "mean_items_per_slot\t{:.2}", stats.mean_items())?;
+            writeln!(output, "max_items_per_slot\t{}", stats.max_items)?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            report_error(err);
+            exit(1);
+        }
+        if !matches.is_present("lengths") {
+            return
+        }
+    }
+    let region = matches.value_of("chr").map(|chrom| {
+        let start = parse_u32_parameter(matches.value_of("start"), "--start").unwrap_or(0);
+        let end = parse_u32_parameter(matches.value_of("end"), "--end").unwrap_or(u32::MAX);
+        (chrom, start, end)
+    });
+    let result = bigbed.length_stats(region).and_then(|stats| {
+        let stats = match stats {
+            Some(stats) => stats,
+            None => {
+                writeln!(output, "records\t0")?;
+                return Ok(())
+            }
+        };
+        writeln!(output, "records\t{}", stats.count)?;
+        writeln!(output, "min_length\t{}", stats.min)?;
+        writeln!(output, "median_length\t{}", stats.median)?;
+        writeln!(output, "mean_length\t{:.2}", stats.mean)?;
+        writeln!(output, "max_length\t{}", stats.max)?;
+        writeln!(output, "# length\trecords")?;
+        for (lower, count) in stats.histogram {
+            // each bin runs up to (but not including) twice its lower bound
+            if lower <= 1 {
+                writeln!(output, "{}\t{}", lower, count)?;
+            } else {
+                writeln!(output, "{}-{}\t{}", lower, u64::from(lower) * 2 - 1, count)?;
+            }
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// report the number of blocks and records on each chromosome
+fn run_counts_by_chrom(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output"));
+    let mut bigbed = open_bigbed(matches.value_of("input.bb").unwrap());
+    let blocks_only = matches.is_present("blocks_only");
+    let result = if blocks_only { bigbed.block_counts_by_chrom() } else { bigbed.counts_by_chrom() };
+    let result = result.and_then(|mut counts| {
+        if matches.is_present("natural_sort") {
+            counts.sort_by(|a, b| natural_cmp(&a.chrom, &b.chrom));
+        }
+        for count in counts {
+            match count.records {
+                Some(records) => writeln!(output, "{}\t{}\t{}\t{}", count.chrom, count.size, count.blocks, records)?,
+                None => writeln!(output, "{}\t{}\t{}", count.chrom, count.size, count.blocks)?,
+            }
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+// compare the chromosomes of a BigBed with another BigBed or a chrom.sizes file
+fn run_compatible(matches: &ArgMatches) {
+    let a_name = matches.value_of("a.bb").unwrap();
+    let b_name = matches.value_of("other").unwrap();
+    let mut a = open_bigbed(a_name);
+    // a file without the BigBed signature is read as a chrom.sizes file ("-" reads one from stdin),
+    // but any other problem opening a BigBed is reported
+    let b = if b_name == "-" {
+        None
+    } else {
+        match File::open(b_name).map_err(Error::from).and_then(BigBed::from_file) {
+            Ok(b) => Some(b),
+            Err(Error::BadSig{..}) => None,
+            Err(err) => {
+                report_error(err);
+                exit(1);
+            }
+        }
+    };
+    let result = match b {
+        Some(mut b) => a.compatible_with(&mut b),
+        None => read_chrom_sizes(open_input(b_name)).and_then(|sizes| a.compatible_with_sizes(&sizes)),
+    };
+    let compatibility = match result {
+        Ok(compatibility) => compatibility,
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    };
+    // chromosomes missing from one side are common, so only list them on request
+    let all = matches.is_present("all");
+    for mismatch in &compatibility.mismatches {
+        if all || matches!(mismatch, ChromMismatch::SizeDiffers{..}) {
+            println!("{}", mismatch);
+        }
+    }
+    println!("{} matching, {} with different sizes, {} only in one file",
+        compatibility.matching, compatibility.size_mismatches(),
+        compatibility.mismatches.len() - compatibility.size_mismatches());
+    if compatibility.is_compatible() {
+        println!("'{}' and '{}' are compatible", a_name, b_name);
+    } else {
+        println!("'{}' and '{}' are not compatible", a_name, b_name);
+        exit(1);
+    }
+}
+
+// compare the schemas of two BigBed files
+fn run_schema_diff(matches: &ArgMatches) {
+    let a_name = matches.value_of("a.bb").unwrap();
+    let b_name = matches.value_of("b.bb").unwrap();
+    let mut a = open_bigbed(a_name);
+    let mut b = open_bigbed(b_name);
+    let diff = match a.schema_diff(&mut b) {
+        Ok(diff) => diff,
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    };
+    if diff.is_empty() {
+        println!("'{}' and '{}' have the same schema", a_name, b_name);
+        return
+    }
+    if diff.bed_types.0 != diff.bed_types.1 {
+        println!("bed type: {} -> {}", diff.bed_types.0, diff.bed_types.1);
+    }
+    if diff.field_counts.0 != diff.field_counts.1 {
+        println!("field count: {} -> {}", diff.field_counts.0, diff.field_counts.1);
+    }
+    if diff.defined_field_counts.0 != diff.defined_field_counts.1 {
+        println!("defined field count: {} -> {}", diff.defined_field_counts.0, diff.defined_field_counts.1);
+    }
+    for change in &diff.changes {
+        println!("{}", change);
+    }
+    // like diff, exit with an error if the schemas differ
+    exit(1);
+}
+
+// write the records with the given names, using the name index
+fn run_names(matches: &ArgMatches) {
+    let mut output = open_output(matches.value_of("output"));
+    let mut bigbed = open_bigbed(matches.value_of("track.bb").unwrap());
+    let mut names: Vec<String> = matches.values_of("names").map(|names| names.map(String::from).collect()).unwrap_or_default();
+    if let Some(filename) = matches.value_of("names_file") {
+        for line in open_input(filename).lines() {
+            match line {
+                Ok(line) => {
+                    // one name per line, ignoring blank lines
+                    let name = line.trim();
+                    if !name.is_empty() {
+                        names.push(name.to_owned());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        }
+    }
+    let result = bigbed.find_by_names(&names).and_then(|lines| {
+        for line in lines {
+            writeln!(output, "{}", line)?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        report_error(err);
+        exit(1);
+    }
+}
+
+fn run_serve(matches: &ArgMatches) {
+    if !matches.is_present("static") {
+        eprintln!("Only static file serving is supported (use --static)");
+        exit(1);
+    }
+    let root = matches.value_of("dir").unwrap_or(".");
+    if !Path::new(root).is_dir() {
+        eprintln!("Not a directory: {}", root);
+        exit(1);
+    }
+    let mut server = StaticServer::new(root);
+    if let Some(max_age) = parse_u32_parameter(matches.value_of("max_age"), "max-age") {
+        server = server.max_age(max_age);
+    }
+    let address = format!("{}:{}", matches.value_of("bind").unwrap_or("127.0.0.1"), matches.value_of("port").unwrap_or("8080"));
+    eprintln!("Serving {} at http://{}/", root, address);
+    if let Err(err) = server.serve(address) {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn run_sqlite(matches: &ArgMatches) {
+    let mut bigbed = open_bigbed(matches.value_of("input.bb").unwrap());
+    let region = matches.value_of("chr").map(|chrom| {
+        let start = parse_u32_parameter(matches.value_of("start"), "start").unwrap_or(0);
+        let end = parse_u32_parameter(matches.value_of("end"), "end").unwrap_or(u32::MAX);
+        (chrom, start, end)
+    });
+    let result = rusqlite::Connection::open(matches.value_of("output.db").unwrap())
+        .map_err(Error::from)
+        .and_then(|mut conn| bigbed.export_sqlite(&mut conn, matches.value_of("table").unwrap_or("bed"), region));
+    match result {
+        Ok(count) => eprintln!("Wrote {} records", count),
+        Err(err) => {
+            report_error(err);
+            exit(1);
+        }
+    }
+}
+
 fn main() {
     // create a simple command line parser
-    let matches = App::new("rbb")
+    // (the subcommands are only used if they appear before any other argument)
+    let app = App::new("rbb")
         .about("Convert BigBed files to BED files")
         .version(crate_version!())
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("input.bb")
-                .help("BigBed file to convert")
+                .help("BigBed file to convert (or - to read it from stdin)")
                 .index(1)
                 .required(true)
         )
@@ -46,10 +1549,42 @@ fn main() {
         )
         .arg(
             Arg::with_name("chr")
-                .help("if set, restrict output to given chromosome")
+                .help("if set, restrict output to given chromosome (or chromosomes matching a pattern like 'chr*_random'); repeat it or separate names with commas for several")
                 .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
                 .long("chr")
         )
+        .arg(
+            Arg::with_name("region")
+                .help("if set, restrict output to this region (chrom:start-end, 1-based like chr1:1501-1879); repeat it for several, which are written in the order given")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("region")
+                .conflicts_with_all(&["chr", "start", "end", "split_by_chrom"])
+        )
+        .arg(
+            Arg::with_name("regions_bed")
+                .help("if set, restrict output to the regions in this BED file, written in the order of the file")
+                .takes_value(true)
+                .value_name("regions.bed")
+                .long("regions-bed")
+                .conflicts_with_all(&["chr", "start", "end", "split_by_chrom"])
+        )
+        .arg(
+            Arg::with_name("chr_regex")
+                .help("if set, restrict output to chromosomes whose names match this regular expression")
+                .takes_value(true)
+                .long("chr-regex")
+        )
+        .arg(
+            Arg::with_name("chrom_list")
+                .help("if set, restrict output to the chromosomes named in this file (one per line, e.g. the primary assembly)")
+                .takes_value(true)
+                .long("chrom-list")
+        )
         .arg(
             Arg::with_name("start")
                 .help("if set, restrict output to only that over start")
@@ -62,82 +1597,750 @@ fn main() {
                 .takes_value(true)
                 .long("end")
         )
+        .arg(
+            Arg::with_name("merge")
+                .help("if set, merge overlapping intervals before output")
+                .long("merge")
+        )
+        .arg(
+            Arg::with_name("dedup")
+                .help("if set, drop exact duplicate records")
+                .long("dedup")
+        )
         .arg(
             Arg::with_name("max_items")
-                .help("if set, restrict output to first N items (per chromosome)")
+                .help("if set, restrict output to first N items (per chromosome, or per region with --region)")
                 .takes_value(true)
                 .long("max")
         )
-        .get_matches();
-    
-    // determine if we should use stdout or create a new file
-    let output: BufWriter<Box<dyn Write>> = BufWriter::new(
-        match matches.value_of("output.bed") {
-            None => Box::new(io::stdout()),
-            Some(name) => {
-                match File::create(name) {
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        exit(1);
-                    },
-                    Ok(file) => {
-                        Box::new(file)
-                    }
-                }
-            }
-        }
+        .arg(
+            Arg::with_name("exclude")
+                .help("if set, drop records overlapping the regions in this BED file (e.g. a blacklist)")
+                .takes_value(true)
+                .value_name("exclude.bed")
+                .long("exclude")
+        )
+        .arg(
+            Arg::with_name("truncate")
+                .help("if set with --exclude, trim records to the parts outside the excluded regions instead")
+                .long("truncate")
+                .requires("exclude")
+        )
+        .arg(
+            Arg::with_name("split_by_chrom")
+                .help("if set, write one BED file per chromosome into this directory (instead of output.bed)")
+                .takes_value(true)
+                .value_name("out_dir")
+                .long("split-by-chrom")
+                .conflicts_with("output.bed")
+        )
+        .arg(
+            Arg::with_name("gzip")
+                .help("if set with --split-by-chrom, gzip each BED file")
+                .long("gzip")
+                .requires("split_by_chrom")
+        )
+        .arg(
+            Arg::with_name("rescale_score")
+                .help("if set, linearly rescale scores from min:max to the 0-1000 range (clamping outliers)")
+                .takes_value(true)
+                .value_name("min:max")
+                .long("rescale-score")
+                .allow_hyphen_values(true)
+        )
+        .arg(
+            Arg::with_name("clip")
+                .help("if set, truncate records to --start and --end instead of writing them whole")
+                .long("clip")
+        )
+        .arg(
+            Arg::with_name("natural_sort")
+                .help("if set, write chromosomes in natural order (chr1, chr2, ..., chr10) instead of name order")
+                .long("natural-sort")
+        )
+        .arg(
+            Arg::with_name("raw_chrom_names")
+                .help("if set, write chromosome names as stored in the file's index, including any null padding")
+                .long("raw-chrom-names")
+        )
+        .arg(
+            Arg::with_name("float_precision")
+                .help("if set, round the float and double columns of the autoSql definition to this many decimal places")
+                .takes_value(true)
+                .long("float-precision")
+        )
+        .arg(
+            Arg::with_name("names_only")
+                .help("if set, only output the name column (records without a name are skipped)")
+                .long("names-only")
+        )
+        .arg(
+            Arg::with_name("only_column")
+                .help("if set, only output column N, counting from 1 (records without it are skipped)")
+                .takes_value(true)
+                .value_name("N")
+                .long("only-column")
+                .conflicts_with("names_only")
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("output format: bed (tab-separated, the default) or csv (comma-separated, with quoting)")
+                .takes_value(true)
+                .possible_values(&["bed", "csv"])
+                .long("format")
+        )
+        .arg(
+            Arg::with_name("delimiter")
+                .help("if set, separate columns with this string instead of a tab, quoting fields that contain it")
+                .takes_value(true)
+                .long("delimiter")
+        )
+        .arg(
+            Arg::with_name("select")
+                .help("if set, only output these columns, in this order, chosen by field name (e.g. 'name,score:peakScore,signalValue', where 'field:new_name' renames a field)")
+                .takes_value(true)
+                .long("select")
+                .conflicts_with_all(&["names_only", "only_column"])
+        )
+        .arg(
+            Arg::with_name("header")
+                .help("if set, start the output with a header line naming the columns (from the autoSql definition, or the selected columns with --select)")
+                .long("header")
+        )
+        .arg(
+            Arg::with_name("split_strand")
+                .help("if set, write forward and reverse strand records to output.plus.bed and output.minus.bed")
+                .long("split-strand")
+                .requires("output.bed")
+                .conflicts_with_all(&["split_by_chrom", "merge", "bgzip"])
+        )
+        .arg(
+            Arg::with_name("bgzip")
+                .help("if set, compress the output with BGZF (like bgzip)")
+                .long("bgzip")
+                .conflicts_with("split_by_chrom")
+        )
+        .arg(
+            Arg::with_name("tabix")
+                .help("if set with --bgzip, also write a tabix index (output.bed.tbi)")
+                .long("tabix")
+                .requires("bgzip")
+                .requires("output.bed")
+        )
+        .arg(
+            Arg::with_name("buffer_size")
+                .help("size of the output buffer in bytes (default: 65536)")
+                .takes_value(true)
+                .long("buffer-size")
+        )
+        .arg(
+            Arg::with_name("flush_lines")
+                .help("if set, flush the output after every N lines (e.g. to follow a slow export through a pipe)")
+                .takes_value(true)
+                .value_name("N")
+                .long("flush-lines")
+        )
+        .arg(
+            Arg::with_name("flush_interval")
+                .help("if set, flush the output at the end of a line once this many seconds have passed since the last flush")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .long("flush-interval")
+        )
+        .arg(
+            Arg::with_name("sorted")
+                .help("if set, check that the records in the file are sorted (as tabix indexing needs) before writing any, and fail if not")
+                .long("sorted")
+        )
+        .subcommand(
+            SubCommand::with_name("intersect")
+                .about("Report the overlaps between the records of two BigBed files")
+                .arg(
+                    Arg::with_name("a.bb")
+                        .help("BigBed file whose records are reported")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("b.bb")
+                        .help("BigBed file to intersect with")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output file")
+                        .index(3)
+                )
+                .arg(
+                    Arg::with_name("counts")
+                        .help("if set, report each record of a.bb with its number of overlaps")
+                        .long("counts")
+                )
+                .arg(
+                    Arg::with_name("unmatched")
+                        .help("if set with --counts, also report records with no overlaps")
+                        .long("unmatched")
+                )
+                .arg(
+                    Arg::with_name("min_overlap")
+                        .help("minimum number of overlapping bases (default: 1)")
+                        .takes_value(true)
+                        .long("min-overlap")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sample")
+                .about("Pick a random subset of the records in a BigBed file")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to sample from")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output.bed")
+                        .help("Path for output BED file")
+                        .index(2)
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .help("number of records to pick")
+                        .takes_value(true)
+                        .short("n")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .help("seed for the random number generator (default: 0)")
+                        .takes_value(true)
+                        .long("seed")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("annotate")
+                .about("Append the number and names of overlapping BigBed records to each line of a BED file")
+                .arg(
+                    Arg::with_name("regions.bed")
+                        .help("BED file to annotate (use '-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("track.bb")
+                        .help("BigBed file to annotate with")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output BED file")
+                        .index(3)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("region-summary")
+                .about("Report the count, covered bases and mean score of the BigBed records overlapping each line of a BED file")
+                .arg(
+                    Arg::with_name("regions.bed")
+                        .help("BED file of regions (use '-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("track.bb")
+                        .help("BigBed file to summarize")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output TSV file")
+                        .index(3)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("check-sorted")
+                .about("Check that the records of a BigBed file are sorted by chromosome and start")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to check")
+                        .index(1)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("fingerprint")
+                .about("Print a digest of the records of BigBed files, which only depends on their content")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file(s) to fingerprint")
+                        .index(1)
+                        .multiple(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Describe the header of a BigBed or BigWig file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("BigBed or BigWig file ('-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("chroms")
+                .about("List the chromosomes (and sizes) of a BigBed or BigWig file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("BigBed or BigWig file ('-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Print the summary of a whole BigBed (of its coverage depth) or BigWig file")
+                .arg(
+                    Arg::with_name("input")
+                        .help("BigBed or BigWig file ('-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("jaccard")
+                .about("Compare the bases covered by two BigBed files (intersection / union)")
+                .arg(
+                    Arg::with_name("a.bb")
+                        .help("first BigBed file")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("b.bb")
+                        .help("second BigBed file")
+                        .index(2)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Time queries of a BigBed file, reporting throughput and I/O")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to query")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("regions")
+                        .help("BED file of regions to query (default: every chromosome)")
+                        .takes_value(true)
+                        .value_name("regions.bed")
+                        .long("regions")
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .help("number of times to query the regions (default: 1)")
+                        .takes_value(true)
+                        .value_name("N")
+                        .long("iterations")
+                )
+                .arg(
+                    Arg::with_name("buffer_size")
+                        .help("size of the read buffer in bytes (default: 65536)")
+                        .takes_value(true)
+                        .long("buffer-size")
+                )
+                .arg(
+                    Arg::with_name("no_seek_within_buffer")
+                        .help("if set, discard the read buffer on every seek")
+                        .long("no-seek-within-buffer")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Rebuild the indexes and zoom levels of a damaged BigBed file from its data section")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("damaged BigBed file (its header must be intact)")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output.bb")
+                        .help("where to write the repaired file")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("block_size")
+                        .help("number of children per node of the new indexes (default: 256)")
+                        .takes_value(true)
+                        .long("block-size")
+                )
+                .arg(
+                    Arg::with_name("items_per_slot")
+                        .help("number of records per data block (default: 512)")
+                        .takes_value(true)
+                        .long("items-per-slot")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Write a BigBed file from a BED file sorted with sort -k1,1 -k2,2n (like bedToBigBed)")
+                .arg(
+                    Arg::with_name("input.bed")
+                        .help("sorted BED file ('-' for stdin)")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("chrom.sizes")
+                        .help("the name and size of each chromosome")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output.bb")
+                        .help("where to write the BigBed file")
+                        .index(3)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("as")
+                        .help("autoSql file describing the columns")
+                        .takes_value(true)
+                        .long("as")
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .help("the number of standard BED columns, as bedN or bedN+M (default: all, up to 12)")
+                        .takes_value(true)
+                        .long("type")
+                )
+                .arg(
+                    Arg::with_name("unc")
+                        .help("don't compress the data blocks")
+                        .long("unc")
+                )
+                .arg(
+                    Arg::with_name("block_size")
+                        .help("number of children per node of the indexes (default: 256)")
+                        .takes_value(true)
+                        .long("block-size")
+                )
+                .arg(
+                    Arg::with_name("items_per_slot")
+                        .help("number of records per data block (default: 512)")
+                        .takes_value(true)
+                        .long("items-per-slot")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Print statistics of the records of a BigBed file")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to summarize")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("lengths")
+                        .help("report the min/median/mean/max record length and a histogram of lengths")
+                        .long("lengths")
+                )
+                .arg(
+                    Arg::with_name("blocks")
+                        .help("report the number of data blocks, their mean sizes and records per block (ignores --chr)")
+                        .long("blocks")
+                )
+                .arg(
+                    Arg::with_name("chr")
+                        .help("if set, only use records on this chromosome")
+                        .takes_value(true)
+                        .long("chr")
+                )
+                .arg(
+                    Arg::with_name("start")
+                        .help("if set with --chr, only use records after start")
+                        .takes_value(true)
+                        .long("start")
+                        .requires("chr")
+                )
+                .arg(
+                    Arg::with_name("end")
+                        .help("if set with --chr, only use records before end")
+                        .takes_value(true)
+                        .long("end")
+                        .requires("chr")
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output file")
+                        .takes_value(true)
+                        .short("o")
+                        .long("output")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("counts-by-chrom")
+                .about("Print the size, number of data blocks and number of records of each chromosome")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to count")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("blocks_only")
+                        .help("if set, only count blocks, using the index alone (no data is read)")
+                        .long("blocks-only")
+                )
+                .arg(
+                    Arg::with_name("natural_sort")
+                        .help("if set, list chromosomes in natural order (chr1, chr2, ..., chr10) instead of name order")
+                        .long("natural-sort")
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output file")
+                        .takes_value(true)
+                        .short("o")
+                        .long("output")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("schema-diff")
+                .about("Compare the bed types and fields of two BigBed files")
+                .arg(
+                    Arg::with_name("a.bb")
+                        .help("old BigBed file")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("b.bb")
+                        .help("new BigBed file")
+                        .index(2)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("compatible")
+                .about("Check that two files use the same chromosome names and sizes")
+                .arg(
+                    Arg::with_name("a.bb")
+                        .help("BigBed file")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("other")
+                        .help("BigBed or chrom.sizes file to compare against")
+                        .index(2)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("also list chromosomes that are only in one file")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("names")
+                .about("Write the records with the given names, using the file's name index")
+                .arg(
+                    Arg::with_name("track.bb")
+                        .help("BigBed file with an index on the name column")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("names")
+                        .help("names to look up")
+                        .index(2)
+                        .multiple(true)
+                )
+                .arg(
+                    Arg::with_name("names_file")
+                        .help("file of names to look up, one per line (use '-' for stdin)")
+                        .takes_value(true)
+                        .long("names-file")
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Path for output BED file")
+                        .takes_value(true)
+                        .short("o")
+                        .long("output")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Check that a BigBed file can be read")
+                .arg(
+                    Arg::with_name("input.bb")
+                        .help("BigBed file to check")
+                        .index(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("deep")
+                        .help("if set, also check the zoom levels against the records")
+                        .long("deep")
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .help("number of zoom records to check per zoom level (default: 100)")
+                        .takes_value(true)
+                        .long("samples")
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .help("if set, print a JSON report of each check (its status, counts and problems) instead")
+                        .long("json")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve the files in a directory over HTTP (with Range and CORS support, e.g. for igv.js or JBrowse)")
+                .arg(
+                    Arg::with_name("dir")
+                        .help("directory to serve (default: the current directory)")
+                        .index(1)
+                )
+                .arg(
+                    Arg::with_name("static")
+                        .help("serve the raw files, byte ranges and all")
+                        .long("static")
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .help("port to listen on (default: 8080)")
+                        .takes_value(true)
+                        .long("port")
+                )
+                .arg(
+                    Arg::with_name("bind")
+                        .help("address to listen on (default: 127.0.0.1)")
+                        .takes_value(true)
+                        .long("bind")
+                )
+                .arg(
+                    Arg::with_name("max_age")
+                        .help("if set, let clients cache files for this many seconds")
+                        .takes_value(true)
+                        .value_name("seconds")
+                        .long("max-age")
+                )
+        );
+    #[cfg(feature = "sqlite")]
+    let app = app.subcommand(
+        SubCommand::with_name("sqlite")
+            .about("Export records into a new SQLite table, with columns typed from the autoSql schema")
+            .arg(
+                Arg::with_name("input.bb")
+                    .help("BigBed file to export")
+                    .index(1)
+                    .required(true)
+            )
+            .arg(
+                Arg::with_name("output.db")
+                    .help("SQLite database to write to (created if needed)")
+                    .index(2)
+                    .required(true)
+            )
+            .arg(
+                Arg::with_name("table")
+                    .help("name of the table to create (default: bed)")
+                    .takes_value(true)
+                    .long("table")
+            )
+            .arg(
+                Arg::with_name("chr")
+                    .help("if set, only export records on this chromosome")
+                    .takes_value(true)
+                    .long("chr")
+            )
+            .arg(
+                Arg::with_name("start")
+                    .help("if set with --chr, only export records after start")
+                    .takes_value(true)
+                    .long("start")
+                    .requires("chr")
+            )
+            .arg(
+                Arg::with_name("end")
+                    .help("if set with --chr, only export records before end")
+                    .takes_value(true)
+                    .long("end")
+                    .requires("chr")
+            )
     );
-    let chrom = matches.value_of("chr");
-    let start = parse_u32_parameter(matches.value_of("start"), "--start");
-    let end = parse_u32_parameter(matches.value_of("end"), "--end");
-    let max_items = parse_u32_parameter(matches.value_of("max_items"), "--max");
+    let matches = app.get_matches();
 
-    // this will always work, since input is required arg
-    let filename = matches.value_of("input.bb").unwrap();
-    // try to open the file
-    match File::open(filename) {
-        // notify the user if we cannot exist
-        Err(err) => {
-            eprintln!("{}", err);
-            // make it really obvious that the provided file could not be opened
-            eprintln!("Could not open file: {}", filename);
-        }
-        Ok(file) => {
-            // attempt to create a BigBed from the file
-            let result = BigBed::from_file(BufReader::new(file));
-            match result {
-                Ok(mut bigbed) => {
-                    // attempt to convert BigBed to a BED using the provided parameters
-                    let result = bigbed.write_bed(chrom, start, end, max_items, output);
-                    // handle any errors
-                    if let Err(err) = result {
-                        eprintln!("{}", err);
-                        // provide helpful follow-ups on specific errors
-                        match err {
-                            BadChrom(chr) | BadKey(chr, _) => {
-                                eprintln!("This chromosome ('{}') may not be in the file.", chr);
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                // if a bigbed cannot be created, let the user know why
-                Err(err) => {
-                    // provide helpful follow-ups on specific errors
-                    match err {
-                        IOError(_) => {
-                            eprintln!("Could not open file '{}' due to the following error:\n{}.", filename, err);
-                        }
-                        BadSig{expected, received} => {
-                            eprintln!("{}", err);
-                            eprintln!("Is '{}' a BigBed file?", filename);
-                        }
-                        _ => {
-                            eprintln!("{}", err)
-                        }
-                    }
-                }
-            }
+    match matches.subcommand() {
+        ("intersect", Some(sub_matches)) => run_intersect(sub_matches),
+        ("sample", Some(sub_matches)) => run_sample(sub_matches),
+        ("validate", Some(sub_matches)) => run_validate(sub_matches),
+        ("annotate", Some(sub_matches)) => run_annotate(sub_matches),
+        ("region-summary", Some(sub_matches)) => run_region_summary(sub_matches),
+        ("check-sorted", Some(sub_matches)) => run_check_sorted(sub_matches),
+        ("fingerprint", Some(sub_matches)) => run_fingerprint(sub_matches),
+        ("info", Some(sub_matches)) => run_info(sub_matches),
+        ("chroms", Some(sub_matches)) => run_chroms(sub_matches),
+        ("summary", Some(sub_matches)) => run_summary(sub_matches),
+        ("counts-by-chrom", Some(sub_matches)) => run_counts_by_chrom(sub_matches),
+        ("stats", Some(sub_matches)) => run_stats(sub_matches),
+        ("repair", Some(sub_matches)) => run_repair(sub_matches),
+        ("build", Some(sub_matches)) => run_build(sub_matches),
+        ("jaccard", Some(sub_matches)) => run_jaccard(sub_matches),
+        ("bench", Some(sub_matches)) => run_bench(sub_matches),
+        ("schema-diff", Some(sub_matches)) => run_schema_diff(sub_matches),
+        ("compatible", Some(sub_matches)) => run_compatible(sub_matches),
+        ("names", Some(sub_matches)) => run_names(sub_matches),
+        ("serve", Some(sub_matches)) => run_serve(sub_matches),
+        #[cfg(feature = "sqlite")]
+        ("sqlite", Some(sub_matches)) => run_sqlite(sub_matches),
+        _ => run_convert(&matches),
+    }
+}
+
+#[cfg(test)]
+mod test_bin {
+    use super::*;
+    use std::io::Cursor;
+
+    // a file with the given standard columns, whose records have 6 columns
+    fn bigbed(defined_field_count: u16) -> BigBed<Cursor<Vec<u8>>> {
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(defined_field_count);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t10\t20\tpeak1\t3\t+").unwrap();
+        writer.add_line("chr1\t30\t40\tpeak2\t5\t-").unwrap();
+        BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    fn filters() -> Filters<'static> {
+        Filters{
+            chroms: Vec::new(), chrom_regex: None, chrom_names: None, start: None, end: None,
+            regions: Vec::new(), max_items: None, exclude: None, truncate: false, natural_order: false, clip: false,
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_by_strand() {
+        let fetch: Fetch<Cursor<Vec<u8>>> = |bigbed, chrom, start, end, max_items| bigbed.query(chrom, start, end, max_items);
+        let (mut plus, mut minus) = (Vec::new(), Vec::new());
+        let skipped = write_by_strand(&mut bigbed(6), &filters(), &BedFormatter::default(), &mut plus, &mut minus, fetch).unwrap();
+        assert_eq!((skipped, plus.as_slice(), minus.as_slice()), (0, &b"chr1\t10\t20\tpeak1\t3\t+\n"[..], &b"chr1\t30\t40\tpeak2\t5\t-\n"[..]));
+        // the sixth column of a bed3+3 is not a strand
+        let result = write_by_strand(&mut bigbed(3), &filters(), &BedFormatter::default(), Vec::new(), Vec::new(), fetch);
+        assert_eq!(result, Err(Misc("File has no strand column")));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{BedLine, BigBed, BlockRecords, FileOffsetSize, Inflater};
+use crate::error::Error;
+use std::io::{Read, Seek};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// the records of an uncompressed data block, which may include records from several
+/// chromosomes (see BedLine::chrom_id) and records outside a query's range
+pub fn block_lines(block: &[u8], big_endian: bool) -> Vec<BedLine> {
+    BlockRecords::new(block, big_endian).map(|record| record.to_bed_line()).collect()
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    // the data blocks that may hold records overlapping a region
+    fn region_blocks(&mut self, chrom: &str, start: u32, end: u32) -> Result<Vec<FileOffsetSize>, Error> {
+        let chrom_id = self.resolve_chrom(chrom)?.id;
+        // pad the range as query does, to include zero-length insertions
+        self.overlapping_blocks(chrom_id, start.saturating_sub(1), end.saturating_add(1))
+    }
+
+    /// hand each uncompressed data block that may hold records overlapping a region to `visit`
+    /// the blocks are not filtered: use block_lines (or parse them yourself) and check each record
+    /// returns the number of blocks visited
+    pub fn for_each_block<F>(&mut self, chrom: &str, start: u32, end: u32, mut visit: F) -> Result<usize, Error>
+        where F: FnMut(&[u8]) {
+        let blocks = self.region_blocks(chrom, start, end)?;
+        self.read_blocks(&blocks, |_, buff| {
+            visit(buff);
+            true
+        })?;
+        Ok(blocks.len())
+    }
+
+    /// like for_each_block, but the blocks are decompressed and visited on `threads` worker threads
+    /// (blocks are read from the file in order, but may be visited in any order)
+    pub fn for_each_block_parallel<F>(&mut self, chrom: &str, start: u32, end: u32, threads: usize, visit: F) -> Result<usize, Error>
+        where F: Fn(&[u8]) + Sync {
+        if threads <= 1 {
+            return self.for_each_block(chrom, start, end, visit)
+        }
+        let blocks = self.region_blocks(chrom, start, end)?;
+        let buffer_size = self.uncompress_buf_size;
+        let failure: Mutex<Option<Error>> = Mutex::new(None);
+        // bound the number of blocks waiting, so large regions are not read into memory at once
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(threads * 2);
+        let receiver = Mutex::new(receiver);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    let mut inflater = if buffer_size > 0 { Some(Inflater::new(buffer_size)) } else { None };
+                    loop {
+                        // the lock is released as soon as a block is received
+                        let compressed = match receiver.lock().unwrap().recv() {
+                            Ok(compressed) => compressed,
+                            Err(_) => break,
+                        };
+                        // after an error, keep draining the channel so the reader never blocks
+                        if failure.lock().unwrap().is_some() {
+                            continue
+                        }
+                        let result = match inflater.as_mut() {
+                            Some(inflater) => inflater.inflate(&compressed).map(&visit),
+                            None => {
+                                visit(&compressed);
+                                Ok(())
+                            }
+                        };
+                        if let Err(err) = result {
+                            failure.lock().unwrap().get_or_insert(err);
+                        }
+                    }
+                });
+            }
+            let sender = sender;
+            self.read_raw_blocks(&blocks, |_, buff| {
+                Ok(failure.lock().unwrap().is_none() && sender.send(buff.to_vec()).is_ok())
+            })
+        })?;
+        if let Some(err) = failure.into_inner().unwrap() {
+            return Err(err)
+        }
+        if let Some(metrics) = &self.metrics {
+            let count = blocks.len() as u64;
+            metrics.add_blocks(count, if buffer_size > 0 { count } else { 0 });
+        }
+        Ok(blocks.len())
+    }
+}
+
+#[cfg(test)]
+mod test_blocks {
+    use super::*;
+    use crate::bb_from_file;
+
+    // the records of the blocks that overlap the region, in order
+    fn in_range(mut lines: Vec<BedLine>, chrom_id: u32, start: u32, end: u32) -> Vec<(u32, u32, Option<String>)> {
+        lines.retain(|line| line.chrom_id == chrom_id && line.start < end && line.end > start);
+        let mut lines: Vec<_> = lines.into_iter().map(|line| (line.start, line.end, line.rest)).collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_for_each_block() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let chrom = bb.resolve_chrom("chr1").unwrap();
+        let big_endian = bb.big_endian;
+        let expected = in_range(bb.query("chr1", 0, 5_000_000, 0).unwrap(), chrom.id, 0, 5_000_000);
+        assert!(!expected.is_empty());
+
+        let mut lines = Vec::new();
+        let count = bb.for_each_block("chr1", 0, 5_000_000, |block| lines.extend(block_lines(block, big_endian))).unwrap();
+        assert!(count > 0);
+        assert_eq!(in_range(lines, chrom.id, 0, 5_000_000), expected);
+
+        for threads in &[1, 4] {
+            let lines = Mutex::new(Vec::new());
+            let parallel_count = bb.for_each_block_parallel("chr1", 0, 5_000_000, *threads, |block| {
+                lines.lock().unwrap().extend(block_lines(block, big_endian));
+            }).unwrap();
+            assert_eq!(parallel_count, count);
+            assert_eq!(in_range(lines.into_inner().unwrap(), chrom.id, 0, 5_000_000), expected);
+        }
+        assert!(bb.for_each_block("chrNope", 0, 100, |_| {}).is_err());
+    }
+}
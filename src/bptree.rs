@@ -0,0 +1,407 @@
+use crate::{ByteReader, BPT_SIG};
+use crate::error::Error;
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+
+/// the header of a B+ tree, which maps fixed-size keys to fixed-size values
+/// (e.g. the chromosome tree maps names to ids and sizes)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BPlusTree {
+    pub big_endian: bool,
+    pub block_size: u32,
+    pub key_size: usize,
+    pub val_size: usize,
+    pub item_count: u64,
+    pub(crate) root_offset: u64,
+}
+
+impl BPlusTree {
+    /// read the header of a B+ tree at the current position of the reader
+    pub fn with_reader<T: Read + Seek>(reader: &mut T) -> Result<BPlusTree, Error> {
+        // check the signature first
+        let mut buff = [0; 4];
+        reader.read_exact(&mut buff)?;
+        let big_endian =
+            if buff == BPT_SIG {
+                true
+            } else if buff.iter().eq(BPT_SIG.iter().rev()) {
+                false
+            } else {
+                return Err(Error::BadSig{expected: BPT_SIG, received: buff});
+            };
+
+        //read all the header information
+        let block_size = reader.read_u32(big_endian);
+        let key_size = reader.read_u32(big_endian).try_into()?;
+        let val_size = reader.read_u32(big_endian).try_into()?;
+        let item_count = reader.read_u64(big_endian);
+
+        // skip over the reserved region and get the root offset
+        let root_offset = reader.seek(SeekFrom::Current(8))?;
+        Ok(BPlusTree{big_endian, block_size, key_size, val_size, item_count, root_offset})
+    }
+
+    /// iterate over the (key, value) pairs of the tree in key order,
+    /// reading each node only when it is reached
+    pub fn items<'a, T: Read + Seek>(&self, reader: &'a mut T) -> BPlusTreeItems<'a, T> {
+        BPlusTreeItems{
+            tree: self.clone(),
+            reader,
+            pending: vec![self.root_offset],
+            leaf: None,
+            failed: false,
+        }
+    }
+
+    /// find the values of every item with a key (trees may hold several items with the same key,
+    /// e.g. the extra indexes of a BigBed file), in the order they are stored
+    pub fn find_all<T: Read + Seek>(&self, key: &[u8], reader: &mut T) -> Result<Vec<Vec<u8>>, Error> {
+        if key.len() > self.key_size {
+            return Err(Error::BadKey(String::from_utf8_lossy(key).into_owned(), self.key_size))
+        }
+        let mut padded_key = key.to_vec();
+        padded_key.resize(self.key_size, 0);
+
+        let mut values = Vec::new();
+        // nodes still to visit, with the next node last
+        let mut pending = vec![self.root_offset];
+        while let Some(offset) = pending.pop() {
+            reader.seek(SeekFrom::Start(offset))?;
+
+            // read block header
+            let is_leaf = reader.read_u8();
+            let _reserved = reader.read_u8();
+            let child_count = reader.read_u16(self.big_endian);
+            if is_leaf != 0 {
+                for _ in 0..child_count {
+                    let mut keybuf = vec![0; self.key_size];
+                    let mut valbuf = vec![0; self.val_size];
+                    reader.read_exact(&mut keybuf)?;
+                    reader.read_exact(&mut valbuf)?;
+                    if keybuf == padded_key {
+                        values.push(valbuf);
+                    }
+                }
+            } else {
+                let mut children = Vec::with_capacity(usize::from(child_count));
+                for _ in 0..child_count {
+                    let mut keybuf = vec![0; self.key_size];
+                    reader.read_exact(&mut keybuf)?;
+                    children.push((keybuf, reader.read_u64(self.big_endian)));
+                }
+                // each child holds the keys from its own key up to (and possibly including)
+                // the key of the next child, since equal keys may span nodes
+                let mut matching = Vec::new();
+                for (index, (first_key, child_offset)) in children.iter().enumerate() {
+                    let below_next = match children.get(index + 1) {
+                        Some((next_key, _)) => padded_key <= *next_key,
+                        None => true,
+                    };
+                    if *first_key <= padded_key && below_next {
+                        matching.push(*child_offset);
+                    }
+                }
+                pending.extend(matching.into_iter().rev());
+            }
+        }
+        Ok(values)
+    }
+
+    /// find the values for many keys at once, visiting each node of the tree at most once
+    /// the values are in the same order as `keys`; keys longer than the key size are not found
+    pub fn find_many<T: Read + Seek>(&self, keys: &[&[u8]], reader: &mut T) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut values = vec![None; keys.len()];
+        // the padded keys to look for, in order, with their positions in `keys`
+        let mut wanted: Vec<(Vec<u8>, usize)> = keys.iter().enumerate()
+            .filter(|(_, key)| key.len() <= self.key_size)
+            .map(|(index, key)| {
+                let mut padded_key = key.to_vec();
+                padded_key.resize(self.key_size, 0);
+                (padded_key, index)
+            })
+            .collect();
+        wanted.sort();
+
+        // nodes still to visit, with the keys that may be in them
+        let mut pending = vec![(self.root_offset, wanted)];
+        while let Some((offset, wanted)) = pending.pop() {
+            reader.seek(SeekFrom::Start(offset))?;
+
+            // read block header
+            let is_leaf = reader.read_u8();
+            let _reserved = reader.read_u8();
+            let child_count = reader.read_u16(self.big_endian);
+            if is_leaf != 0 {
+                for _ in 0..child_count {
+                    let mut keybuf = vec![0; self.key_size];
+                    let mut valbuf = vec![0; self.val_size];
+                    reader.read_exact(&mut keybuf)?;
+                    reader.read_exact(&mut valbuf)?;
+                    // the same key may be asked for more than once
+                    let first = wanted.partition_point(|(key, _)| *key < keybuf);
+                    for (_, index) in wanted[first..].iter().take_while(|(key, _)| *key == keybuf) {
+                        values[*index].get_or_insert_with(|| valbuf.clone());
+                    }
+                }
+            } else {
+                let mut children = Vec::with_capacity(usize::from(child_count));
+                for _ in 0..child_count {
+                    let mut keybuf = vec![0; self.key_size];
+                    reader.read_exact(&mut keybuf)?;
+                    children.push((keybuf, reader.read_u64(self.big_endian)));
+                }
+                // as in find, each key belongs to the last child whose first key is not above it
+                // (or the first child, for keys below every child)
+                let mut remaining = &wanted[..];
+                for (index, (_, child_offset)) in children.iter().enumerate() {
+                    let split = match children.get(index + 1) {
+                        Some((next_key, _)) => remaining.partition_point(|(key, _)| key < next_key),
+                        None => remaining.len(),
+                    };
+                    if split > 0 {
+                        pending.push((*child_offset, remaining[..split].to_vec()));
+                    }
+                    remaining = &remaining[split..];
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// find the value for a key, which is padded with null bytes to the key size
+    pub fn find<T: Read + Seek>(&self, key: &[u8], reader: &mut T) -> Result<Option<Vec<u8>>, Error> {
+        if key.len() > self.key_size {
+            return Err(Error::BadKey(String::from_utf8_lossy(key).into_owned(), self.key_size))
+        }
+        let mut padded_key = key.to_vec();
+        padded_key.resize(self.key_size, 0);
+
+        let mut offset = self.root_offset;
+        let mut keybuf = vec![0; self.key_size];
+        loop {
+            // move to the offset
+            reader.seek(SeekFrom::Start(offset))?;
+
+            // read block header
+            let is_leaf = reader.read_u8();
+            let _reserved = reader.read_u8();
+            let child_count = reader.read_u16(self.big_endian);
+            if is_leaf != 0 {
+                let mut valbuf = vec![0; self.val_size];
+                for _ in 0..child_count {
+                    reader.read_exact(&mut keybuf)?;
+                    reader.read_exact(&mut valbuf)?;
+                    if keybuf == padded_key {
+                        return Ok(Some(valbuf))
+                    }
+                }
+                return Ok(None)
+            }
+            if child_count == 0 {
+                return Ok(None)
+            }
+            // skip past the first key
+            reader.seek(SeekFrom::Current(self.key_size.try_into()?))?;
+            // read the offset
+            offset = reader.read_u64(self.big_endian);
+            for _ in 1..child_count {
+                reader.read_exact(&mut keybuf)?;
+                // if find a bigger key, that means we passed our good key
+                if padded_key < keybuf {
+                    break;
+                }
+                // otherwise: read the next offset and keep going
+                offset = reader.read_u64(self.big_endian);
+            }
+        }
+    }
+}
+
+/// a (key, value) pair from a B+ tree
+pub type BPlusTreeItem = (Vec<u8>, Vec<u8>);
+
+/// an iterator over the (key, value) pairs of a B+ tree
+pub struct BPlusTreeItems<'a, T: Read + Seek> {
+    tree: BPlusTree,
+    reader: &'a mut T,
+    // nodes still to visit, with the next node last
+    pending: Vec<u64>,
+    // the position of the next item in the current leaf, and the number of items left
+    leaf: Option<(u64, u16)>,
+    failed: bool,
+}
+
+impl<'a, T: Read + Seek> BPlusTreeItems<'a, T> {
+    fn next_item(&mut self) -> Result<Option<BPlusTreeItem>, Error> {
+        let big_endian = self.tree.big_endian;
+        loop {
+            if let Some((position, remaining)) = self.leaf {
+                if remaining > 0 {
+                    self.reader.seek(SeekFrom::Start(position))?;
+                    let mut key = vec![0; self.tree.key_size];
+                    let mut val = vec![0; self.tree.val_size];
+                    self.reader.read_exact(&mut key)?;
+                    self.reader.read_exact(&mut val)?;
+                    let position = position + (self.tree.key_size + self.tree.val_size) as u64;
+                    self.leaf = Some((position, remaining - 1));
+                    return Ok(Some((key, val)))
+                }
+                self.leaf = None;
+            }
+            let offset = match self.pending.pop() {
+                Some(offset) => offset,
+                None => return Ok(None),
+            };
+            self.reader.seek(SeekFrom::Start(offset))?;
+
+            // read block header
+            let is_leaf = self.reader.read_u8();
+            let _reserved = self.reader.read_u8();
+            let child_count = self.reader.read_u16(big_endian);
+            if is_leaf != 0 {
+                self.leaf = Some((offset + 4, child_count));
+            } else {
+                let mut children = Vec::with_capacity(usize::from(child_count));
+                for _ in 0..child_count {
+                    // skip over the key in each block
+                    self.reader.seek(SeekFrom::Current(self.tree.key_size.try_into()?))?;
+                    children.push(self.reader.read_u64(big_endian));
+                }
+                // visit the children in order
+                self.pending.extend(children.into_iter().rev());
+            }
+        }
+    }
+}
+
+impl<'a, T: Read + Seek> Iterator for BPlusTreeItems<'a, T> {
+    type Item = Result<BPlusTreeItem, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None
+        }
+        match self.next_item() {
+            Ok(item) => item.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_bptree {
+    use super::*;
+    use std::io::Cursor;
+
+    // build a tree in memory with two leaves under a root node
+    fn two_level_tree() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&BPT_SIG);
+        bytes.extend_from_slice(&2u32.to_be_bytes()); // block size
+        bytes.extend_from_slice(&2u32.to_be_bytes()); // key size
+        bytes.extend_from_slice(&1u32.to_be_bytes()); // val size
+        bytes.extend_from_slice(&4u64.to_be_bytes()); // item count
+        bytes.extend_from_slice(&[0; 8]);
+        // root node at 32, with two children
+        bytes.extend_from_slice(&[0, 0, 0, 2]);
+        bytes.extend_from_slice(b"aa");
+        bytes.extend_from_slice(&56u64.to_be_bytes());
+        bytes.extend_from_slice(b"c\0");
+        bytes.extend_from_slice(&66u64.to_be_bytes());
+        // leaves at 56 and 66
+        bytes.extend_from_slice(&[1, 0, 0, 2]);
+        bytes.extend_from_slice(b"aa\x01b\0\x02");
+        bytes.extend_from_slice(&[1, 0, 0, 2]);
+        bytes.extend_from_slice(b"c\0\x03dd\x04");
+        bytes
+    }
+
+    #[test]
+    fn test_items() {
+        let mut reader = Cursor::new(two_level_tree());
+        let tree = BPlusTree::with_reader(&mut reader).unwrap();
+        assert_eq!((tree.key_size, tree.val_size, tree.item_count), (2, 1, 4));
+        let items: Vec<BPlusTreeItem> = tree.items(&mut reader).map(Result::unwrap).collect();
+        assert_eq!(items, vec![
+            (b"aa".to_vec(), vec![1]),
+            (b"b\0".to_vec(), vec![2]),
+            (b"c\0".to_vec(), vec![3]),
+            (b"dd".to_vec(), vec![4]),
+        ]);
+        // the iterator is lazy
+        assert_eq!(tree.items(&mut reader).next().unwrap().unwrap(), (b"aa".to_vec(), vec![1]));
+    }
+
+    #[test]
+    fn test_find() {
+        let mut reader = Cursor::new(two_level_tree());
+        let tree = BPlusTree::with_reader(&mut reader).unwrap();
+        assert_eq!(tree.find(b"aa", &mut reader).unwrap(), Some(vec![1]));
+        assert_eq!(tree.find(b"b", &mut reader).unwrap(), Some(vec![2]));
+        assert_eq!(tree.find(b"c", &mut reader).unwrap(), Some(vec![3]));
+        assert_eq!(tree.find(b"dd", &mut reader).unwrap(), Some(vec![4]));
+        assert_eq!(tree.find(b"bb", &mut reader).unwrap(), None);
+        assert_eq!(tree.find(b"", &mut reader).unwrap(), None);
+        assert_eq!(tree.find(b"eee", &mut reader), Err(Error::BadKey(String::from("eee"), 2)));
+    }
+
+    #[test]
+    fn test_find_many() {
+        let mut reader = Cursor::new(two_level_tree());
+        let tree = BPlusTree::with_reader(&mut reader).unwrap();
+        let keys: Vec<&[u8]> = vec![b"dd", b"b", b"bb", b"aa", b"eee", b"", b"c", b"b"];
+        assert_eq!(tree.find_many(&keys, &mut reader).unwrap(), vec![
+            Some(vec![4]), Some(vec![2]), None, Some(vec![1]), None, None, Some(vec![3]), Some(vec![2]),
+        ]);
+        assert_eq!(tree.find_many(&[], &mut reader).unwrap(), Vec::<Option<Vec<u8>>>::new());
+    }
+
+    // a tree where items with the same key span several leaves
+    fn duplicate_tree() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&BPT_SIG);
+        bytes.extend_from_slice(&3u32.to_be_bytes()); // block size
+        bytes.extend_from_slice(&2u32.to_be_bytes()); // key size
+        bytes.extend_from_slice(&1u32.to_be_bytes()); // val size
+        bytes.extend_from_slice(&5u64.to_be_bytes()); // item count
+        bytes.extend_from_slice(&[0; 8]);
+        // root node at 32, with three children
+        bytes.extend_from_slice(&[0, 0, 0, 3]);
+        for (key, offset) in &[(b"aa", 66u64), (b"bb", 76), (b"bb", 86)] {
+            bytes.extend_from_slice(*key);
+            bytes.extend_from_slice(&offset.to_be_bytes());
+        }
+        // leaves at 66, 76 and 86
+        bytes.extend_from_slice(&[1, 0, 0, 2]);
+        bytes.extend_from_slice(b"aa\x01bb\x02");
+        bytes.extend_from_slice(&[1, 0, 0, 2]);
+        bytes.extend_from_slice(b"bb\x03bb\x04");
+        bytes.extend_from_slice(&[1, 0, 0, 2]);
+        bytes.extend_from_slice(b"bb\x05cc\x06");
+        bytes
+    }
+
+    #[test]
+    fn test_find_all() {
+        let mut reader = Cursor::new(duplicate_tree());
+        let tree = BPlusTree::with_reader(&mut reader).unwrap();
+        assert_eq!(tree.find_all(b"bb", &mut reader).unwrap(), vec![vec![2], vec![3], vec![4], vec![5]]);
+        assert_eq!(tree.find_all(b"aa", &mut reader).unwrap(), vec![vec![1]]);
+        assert_eq!(tree.find_all(b"cc", &mut reader).unwrap(), vec![vec![6]]);
+        assert_eq!(tree.find_all(b"a", &mut reader).unwrap(), Vec::<Vec<u8>>::new());
+        assert_eq!(tree.find_all(b"dd", &mut reader).unwrap(), Vec::<Vec<u8>>::new());
+        assert!(tree.find_all(b"eee", &mut reader).is_err());
+        let mut reader = Cursor::new(two_level_tree());
+        let tree = BPlusTree::with_reader(&mut reader).unwrap();
+        assert_eq!(tree.find_all(b"c", &mut reader).unwrap(), vec![vec![3]]);
+    }
+
+    #[test]
+    fn test_bad_sig() {
+        let mut reader = Cursor::new(vec![0; 32]);
+        assert!(BPlusTree::with_reader(&mut reader).is_err());
+    }
+}
@@ -8,19 +8,26 @@ impl PartialEq for IOErrorWrapper {
     fn eq(&self, _other: &IOErrorWrapper) -> bool {
         false
     }
-    fn ne(&self, _other: &IOErrorWrapper) -> bool {
-        true
-    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     IOError(IOErrorWrapper),
-    DecompressError,
+    /// a data block could not be decompressed (with the reason)
+    DecompressError(String),
     BadSig{expected: [u8; 4], received: [u8; 4]},
+    /// the whole file is gzip-compressed (a BigBed only compresses its data blocks)
+    Gzipped,
     BadChrom(String),
     BadKey(String, usize),
+    BadField(&'static str, String),
+    /// an exon block (blockCount, blockSizes and blockStarts) of a record is invalid
+    BadBlock{block: usize, reason: &'static str},
+    /// the data block at `offset` in the file is invalid
+    BadDataBlock{offset: u64, reason: &'static str},
     ConversionError(std::num::TryFromIntError),
+    #[cfg(feature = "sqlite")]
+    SqlError(String),
     Misc(&'static str)
 }
 
@@ -37,8 +44,8 @@ impl From<&'static str> for Error {
 }
 
 impl From<flate2::DecompressError> for Error {
-    fn from(_e: flate2::DecompressError) -> Error {
-        Error::DecompressError
+    fn from(e: flate2::DecompressError) -> Error {
+        Error::DecompressError(e.to_string())
     }
 }
 
@@ -48,17 +55,69 @@ impl From<std::num::TryFromIntError> for Error {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::SqlError(e.to_string())
+    }
+}
+
+impl Error {
+    /// a suggestion to show the user alongside the error, if there is a likely cause
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::BadChrom(chr) | Error::BadKey(chr, _) => Some(format!("This chromosome ('{}') may not be in the file.", chr)),
+            Error::Gzipped => Some(String::from(
+                "Web servers sometimes compress files they send (Content-Encoding: gzip); decompress it first (e.g. with gunzip)."
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::IOError(wrapped_io_err) => write!(f, "IOError: {}", wrapped_io_err.0),
-            Error::DecompressError => write!(f, "Decompression error!"),
+            Error::DecompressError(detail) => write!(f, "Decompression error: {}", detail),
             Error::BadSig{expected, received} => write!(f, "Bad file signature. Expected \"{:?}\", Received \"{:?}\" ", expected, received),
+            Error::Gzipped => write!(f, "The file is gzip-compressed, not a BigBed"),
             Error::BadChrom(chr) => write!(f, "Chromosome \"{}\" not found", chr),
             Error::BadKey(key, size) => write!(f, "Chromosome \"{}\" not found (Exceeds max key size: {})", key, size),
+            Error::BadField(field, value) => write!(f, "Invalid value for field \"{}\": \"{}\"", field, value),
+            Error::BadBlock{block, reason} => write!(f, "Invalid block {}: {}", block, reason),
+            Error::BadDataBlock{offset, reason} => write!(f, "Invalid data block at offset {}: {}", offset, reason),
             Error::ConversionError(convert_err) => write!(f, "{}", convert_err),
+            #[cfg(feature = "sqlite")]
+            Error::SqlError(msg) => write!(f, "SQLite error: {}", msg),
             Error::Misc(msg) => write!(f, "{}", msg),
         }
     }
-}
\ No newline at end of file
+}
+/// a problem that does not stop a file from being read
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// the file uses something newer than this crate understands (which is skipped)
+    UnsupportedFeature(String),
+    /// something repair could not recover
+    Repaired(String),
+    /// the extra columns of a record were not valid UTF-8, so the invalid bytes were replaced
+    LossyUtf8{chrom: String, start: u32, end: u32},
+    /// a record ending past the end of its chromosome was clipped to the chromosome (in lenient queries)
+    Clipped{chrom: String, start: u32, end: u32, size: u32},
+    /// a malformed record was skipped (in lenient queries)
+    Skipped{chrom: String, start: u32, end: u32, reason: String},
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnsupportedFeature(feature) => write!(f, "Unsupported feature: {}", feature),
+            Warning::Repaired(problem) => write!(f, "Not recovered: {}", problem),
+            Warning::LossyUtf8{chrom, start, end} => write!(f, "Invalid UTF-8 replaced in record {}:{}-{}", chrom, start, end),
+            Warning::Clipped{chrom, start, end, size} => write!(f, "Record {}:{}-{} clipped to the chromosome size ({})", chrom, start, end, size),
+            Warning::Skipped{chrom, start, end, reason} => write!(f, "Skipped record {}:{}-{}: {}", chrom, start, end, reason),
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{BedLine, BigBed, BlockRecords, ByteReader, FileOffsetSize, NamedBedLine};
+use crate::bptree::BPlusTree;
+use crate::error::Error;
+use crate::region::GenomicRange;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// an extra index, which maps the values of a column to the records holding them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraIndex {
+    /// the position of the indexed column (e.g. 3 for the name column)
+    pub field_id: u16,
+    pub field_name: String,
+    /// the offset of the index's B+ tree
+    pub tree_offset: u64,
+}
+
+/// how a record is represented in an extra index (see BigBed::index_entries)
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexStatus {
+    /// the value is in the index, and one of its entries points at the block holding the record
+    Found,
+    /// the record has no value in the indexed column
+    NoValue,
+    /// the value is longer than the keys of the index, so it cannot be looked up
+    TooLong{key_size: usize},
+    /// the value is in the index, but none of its entries point at the block holding the record
+    OtherBlocks(Vec<FileOffsetSize>),
+    /// the value is not in the index
+    Missing,
+}
+
+/// a record's entry in one extra index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub index: ExtraIndex,
+    /// the record's value in the indexed column (the key that a lookup would use)
+    pub value: Option<String>,
+    pub status: IndexStatus,
+}
+
+/// decode a value from an extra index tree: the offset and size of the data block
+/// holding the record (any bytes after the first 16 are ignored)
+pub fn decode_location(val: &[u8], big_endian: bool) -> Result<FileOffsetSize, Error> {
+    if val.len() < 16 {
+        return Err(Error::BadField("val_size", val.len().to_string()))
+    }
+    let read_u64 = |bytes: &[u8]| {
+        let bytes: [u8; 8] = bytes.try_into().expect("Failed to convert bytes");
+        if big_endian {u64::from_be_bytes(bytes)} else {u64::from_le_bytes(bytes)}
+    };
+    Ok(FileOffsetSize{
+        offset: read_u64(&val[0..8]).try_into()?,
+        size: read_u64(&val[8..16]).try_into()?,
+    })
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// list the extra indexes of the file (i.e. the columns that can be searched)
+    pub fn extra_indexes(&mut self) -> Result<Vec<ExtraIndex>, Error> {
+        let (count, list_offset) = match (self.extra_index_count, self.extra_index_list_offset) {
+            (Some(count), Some(list_offset)) if count > 0 => (count, list_offset),
+            _ => return Ok(Vec::new()),
+        };
+        let big_endian = self.big_endian;
+        self.reader.seek(SeekFrom::Start(list_offset))?;
+        let mut entries = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let _index_type = self.reader.read_u16(big_endian);
+            let field_count = self.reader.read_u16(big_endian);
+            let tree_offset = self.reader.read_u64(big_endian);
+            // skip over the reserved region
+            self.reader.seek(SeekFrom::Current(4))?;
+            // only single-column indexes are written in practice, so use the first column
+            let mut field_id = None;
+            for _ in 0..field_count {
+                let id = self.reader.read_u16(big_endian);
+                let _reserved = self.reader.read_u16(big_endian);
+                field_id.get_or_insert(id);
+            }
+            match field_id {
+                Some(field_id) => entries.push((field_id, tree_offset)),
+                None => return Err(Error::BadField("field_count", field_count.to_string())),
+            }
+        }
+        let names = self.field_names()?;
+        Ok(entries.into_iter().map(|(field_id, tree_offset)| {
+            let field_name = names.get(usize::from(field_id)).cloned().unwrap_or_else(|| format!("field{}", field_id + 1));
+            ExtraIndex{field_id, field_name, tree_offset}
+        }).collect())
+    }
+
+    /// find the data blocks holding the records whose value is `key`, using the extra index
+    /// tree at `tree_offset`; each block is only reported once, even if it holds several hits
+    pub fn extra_index_locations(&mut self, tree_offset: u64, key: &str) -> Result<Vec<FileOffsetSize>, Error> {
+        self.reader.seek(SeekFrom::Start(tree_offset))?;
+        let tree = BPlusTree::with_reader(&mut self.reader)?;
+        let mut locations: Vec<FileOffsetSize> = Vec::new();
+        for val in tree.find_all(key.as_bytes(), &mut self.reader)? {
+            let location = decode_location(&val, tree.big_endian)?;
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+        Ok(locations)
+    }
+
+    /// find every record whose indexed column holds one of `values`, using an extra index
+    /// the lookups are done in sorted order and each data block is read at most once,
+    /// so this is much faster than looking up many values one at a time
+    /// records are returned in file order
+    pub fn query_extra_index<S: AsRef<str>>(&mut self, index: &ExtraIndex, values: &[S]) -> Result<Vec<NamedBedLine>, Error> {
+        let mut sorted: Vec<&str> = values.iter().map(AsRef::as_ref).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let mut blocks: Vec<FileOffsetSize> = Vec::new();
+        for value in &sorted {
+            match self.extra_index_locations(index.tree_offset, value) {
+                Ok(locations) => blocks.extend(locations),
+                // values longer than the keys can't be in the index
+                Err(Error::BadKey(_, _)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        blocks.sort_by_key(|block| block.offset);
+        blocks.dedup();
+
+        let chrom_names: HashMap<u32, Arc<str>> = self.chrom_list()?.into_iter()
+            .map(|chrom| (chrom.id, Arc::from(chrom.name())))
+            .collect();
+        let wanted: HashSet<&str> = sorted.into_iter().collect();
+        let column = usize::from(index.field_id).saturating_sub(3);
+        let big_endian = self.big_endian;
+        let mut lines = Vec::new();
+        self.read_blocks(&blocks, |_, buff| {
+            for record in BlockRecords::new(buff, big_endian) {
+                let line = record.to_bed_line();
+                let matches = if index.field_id < 3 {
+                    false
+                } else {
+                    line.rest_field(column).map(|value| wanted.contains(value)).unwrap_or(false)
+                };
+                if let (true, Some(chrom)) = (matches, chrom_names.get(&line.chrom_id)) {
+                    lines.push(NamedBedLine{chrom: Arc::clone(chrom), line});
+                }
+            }
+            true
+        })?;
+        Ok(lines)
+    }
+
+    /// check how a record (e.g. one found by a coordinate query) is represented in each
+    /// extra index: which value of the record is indexed, and whether looking it up leads
+    /// back to the block holding the record (useful for debugging lookups that miss)
+    pub fn index_entries(&mut self, chrom: &str, line: &BedLine) -> Result<Vec<IndexEntry>, Error> {
+        let chrom_id = self.resolve_chrom(chrom)?.id;
+        // find the block holding the record
+        let blocks = self.overlapping_blocks(chrom_id, line.start, line.end.max(line.start + 1))?;
+        let big_endian = self.big_endian;
+        let mut home = None;
+        self.read_blocks(&blocks, |block, buff| {
+            let found = BlockRecords::new(buff, big_endian).any(|record| {
+                let record = record.to_bed_line();
+                record.chrom_id == chrom_id && record.start == line.start && record.end == line.end && record.rest == line.rest
+            });
+            if found {
+                home = Some(*block);
+            }
+            !found
+        })?;
+        let home = match home {
+            Some(home) => home,
+            None => return Err(Error::Misc("Record not found in the file")),
+        };
+
+        let mut entries = Vec::new();
+        for index in self.extra_indexes()? {
+            let value = if index.field_id < 3 {
+                None
+            } else {
+                line.rest_field(usize::from(index.field_id) - 3).map(String::from)
+            };
+            let status = match &value {
+                None => IndexStatus::NoValue,
+                Some(value) => match self.extra_index_locations(index.tree_offset, value) {
+                    Ok(locations) if locations.contains(&home) => IndexStatus::Found,
+                    Ok(locations) if locations.is_empty() => IndexStatus::Missing,
+                    Ok(locations) => IndexStatus::OtherBlocks(locations),
+                    Err(Error::BadKey(_, key_size)) => IndexStatus::TooLong{key_size},
+                    Err(err) => return Err(err),
+                },
+            };
+            entries.push(IndexEntry{index, value, status});
+        }
+        Ok(entries)
+    }
+
+    /// find every record with one of the given names, using the extra index on the name column
+    pub fn find_by_names<S: AsRef<str>>(&mut self, names: &[S]) -> Result<Vec<NamedBedLine>, Error> {
+        let index = self.extra_indexes()?.into_iter().find(|index| index.field_id == 3);
+        match index {
+            Some(index) => self.query_extra_index(&index, names),
+            None => Err(Error::Misc("File has no index on the name column")),
+        }
+    }
+
+    /// find where the records named `name` are (using the extra index on the name column),
+    /// widened by `padding` bases on each side (within the chromosome), ready to query or
+    /// show (e.g. to jump to a gene typed into a search box)
+    /// names are often not unique, so there is a range for each record, in file order
+    pub fn locate(&mut self, name: &str, padding: u32) -> Result<Vec<GenomicRange>, Error> {
+        let mut ranges = Vec::new();
+        for found in self.find_by_names(&[name])? {
+            let size = self.resolve_chrom(&found.chrom)?.size;
+            ranges.push(GenomicRange::new(&found.chrom, found.line.start, found.line.end).pad(padding, size));
+        }
+        Ok(ranges)
+    }
+}
+
+#[cfg(test)]
+mod test_extra {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::{BedLine, BlockRecords};
+
+    #[test]
+    fn test_decode_location() {
+        let mut val = Vec::new();
+        val.extend_from_slice(&1000u64.to_le_bytes());
+        val.extend_from_slice(&50u64.to_le_bytes());
+        assert_eq!(decode_location(&val, false), Ok(FileOffsetSize{offset: 1000, size: 50}));
+        val.extend_from_slice(&[0, 0]);
+        assert_eq!(decode_location(&val, false), Ok(FileOffsetSize{offset: 1000, size: 50}));
+        assert_eq!(decode_location(&val[8..], false), Err(Error::BadField("val_size", String::from("10"))));
+        let mut val = Vec::new();
+        val.extend_from_slice(&1000u64.to_be_bytes());
+        val.extend_from_slice(&50u64.to_be_bytes());
+        assert_eq!(decode_location(&val, true), Ok(FileOffsetSize{offset: 1000, size: 50}));
+    }
+
+    #[test]
+    fn test_extra_index_locations() {
+        let mut bb = bb_from_file("test/bigbeds/named.bb");
+        let tree_offset = bb.extra_indexes().unwrap()[0].tree_offset;
+
+        // gene5 appears three times, in different parts of the file
+        let locations = bb.extra_index_locations(tree_offset, "gene5").unwrap();
+        assert!(!locations.is_empty());
+        let big_endian = bb.big_endian;
+        let mut hits: Vec<BedLine> = Vec::new();
+        bb.read_blocks(&locations, |_, buff| {
+            hits.extend(BlockRecords::new(buff, big_endian).map(|record| record.to_bed_line()).filter(|line| line.name() == Some("gene5")));
+            true
+        }).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(bb.extra_index_locations(tree_offset, "gene500").unwrap().is_empty());
+        assert!(bb.extra_index_locations(tree_offset, "gene0000000").is_err());
+    }
+
+    #[test]
+    fn test_extra_indexes() {
+        let mut bb = bb_from_file("test/bigbeds/named.bb");
+        assert_eq!(bb.extra_indexes().unwrap(), vec![
+            ExtraIndex{field_id: 3, field_name: String::from("name"), tree_offset: 10512},
+        ]);
+        // files without an extension or without extra indexes
+        assert_eq!(bb_from_file("test/bigbeds/bed12.bb").extra_indexes().unwrap(), vec![]);
+        assert_eq!(bb_from_file("test/bigbeds/long.bb").extra_indexes().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_index_entries() {
+        let mut bb = bb_from_file("test/bigbeds/named.bb");
+        let chrom = bb.chrom_list().unwrap()[0].clone();
+        let line = bb.query(chrom.name(), 0, chrom.size(), 1).unwrap().remove(0);
+        let entries = bb.index_entries(chrom.name(), &line).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].index.field_name, "name");
+        assert_eq!(entries[0].value.as_deref(), line.name());
+        assert_eq!(entries[0].status, IndexStatus::Found);
+        // a record that is not in the file
+        let mut renamed = line.clone();
+        renamed.rest = line.rest.as_ref().map(|rest| rest.replacen(line.name().unwrap(), "missing", 1));
+        assert!(bb.index_entries(chrom.name(), &renamed).is_err());
+        // files without extra indexes have no entries
+        let mut bed12 = bb_from_file("test/bigbeds/bed12.bb");
+        let line = bed12.query("chr1", 0, 2_000_000, 1).unwrap().remove(0);
+        assert_eq!(bed12.index_entries("chr1", &line).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_find_by_names() {
+        let mut bb = bb_from_file("test/bigbeds/named.bb");
+        let lines = bb.find_by_names(&["gene7", "gene12", "gene7", "missing", "gene123456789012"]).unwrap();
+        let expected: Vec<String> = std::fs::read_to_string("test/beds/named.bed").unwrap()
+            .lines()
+            .filter(|line| line.split('\t').nth(3).map(|name| name == "gene7" || name == "gene12").unwrap_or(false))
+            .map(String::from)
+            .collect();
+        assert_eq!(expected.len(), 6);
+        assert_eq!(lines.iter().map(|line| line.to_string()).collect::<Vec<String>>(), expected);
+        assert_eq!(bb.find_by_names::<&str>(&[]).unwrap(), vec![]);
+        // files without a name index
+        assert!(bb_from_file("test/bigbeds/bed12.bb").find_by_names(&["GENE0001"]).is_err());
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut bb = bb_from_file("test/bigbeds/named.bb");
+        // every name is used three times
+        assert_eq!(bb.locate("gene0", 0).unwrap(), vec![
+            GenomicRange::new("chr1", 1500, 1879),
+            GenomicRange::new("chr1", 2465600, 2465882),
+            GenomicRange::new("chr2", 3500, 3917),
+        ]);
+        assert_eq!(bb.locate("gene0", 2000).unwrap()[0], GenomicRange::new("chr1", 0, 3879));
+        // the ranges lead back to the records
+        for range in bb.locate("gene5", 0).unwrap() {
+            let lines = bb.query(&range.chrom, range.start, range.end, 0).unwrap();
+            assert!(lines.iter().any(|line| line.rest_field(0) == Some("gene5") && (line.start, line.end) == (range.start, range.end)));
+        }
+        assert_eq!(bb.locate("gene5", 0).unwrap().len(), 3);
+        assert_eq!(bb.locate("missing", 100).unwrap(), vec![]);
+        assert!(bb_from_file("test/bigbeds/bed12.bb").locate("GENE0001", 0).is_err());
+    }
+}
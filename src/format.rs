@@ -0,0 +1,426 @@
+use crate::{strip_null, BedLine};
+use crate::error::Error;
+use std::io::{self, Write};
+
+/// converts BedLines to text
+/// by default this produces plain, tab-delimited BED with every column in the record
+#[derive(Debug, Clone)]
+pub struct BedFormatter {
+    columns: Option<usize>,
+    delimiter: String,
+    default_name: Option<String>,
+    default_score: Option<u16>,
+    precision: Option<usize>,
+    float_columns: Option<Vec<usize>>,
+    score_range: Option<(f64, f64)>,
+    defined_field_count: Option<u16>,
+    only_column: Option<usize>,
+    raw_chrom_names: bool,
+    select: Option<Vec<usize>>,
+    quote: bool,
+}
+
+impl Default for BedFormatter {
+    fn default() -> BedFormatter {
+        BedFormatter{
+            columns: None,
+            delimiter: String::from("\t"),
+            default_name: None,
+            default_score: None,
+            precision: None,
+            float_columns: None,
+            score_range: None,
+            defined_field_count: None,
+            only_column: None,
+            raw_chrom_names: false,
+            select: None,
+            quote: false,
+        }
+    }
+}
+
+// does this field look like a floating-point number (as opposed to an integer)?
+fn is_float(field: &str) -> bool {
+    (field.contains('.') || field.contains('e') || field.contains('E'))
+        && field.parse::<f64>().map(f64::is_finite).unwrap_or(false)
+}
+
+impl BedFormatter {
+    pub fn new() -> BedFormatter {
+        BedFormatter::default()
+    }
+
+    /// emit exactly this many columns (at least 3)
+    /// extra columns are dropped; missing name/score columns are filled
+    /// with the defaults (if set), otherwise the record is emitted as-is
+    pub fn columns(mut self, columns: usize) -> BedFormatter {
+        self.columns = Some(columns.max(3));
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: &str) -> BedFormatter {
+        self.delimiter = delimiter.to_owned();
+        self
+    }
+
+    /// put double quotes around fields containing the delimiter, a double quote or a line break
+    /// (doubling any double quotes inside them), as in CSV
+    pub fn quote_fields(mut self, quote: bool) -> BedFormatter {
+        self.quote = quote;
+        self
+    }
+
+    /// comma-separated output, quoted as in CSV (with a plain header line, see header)
+    pub fn csv() -> BedFormatter {
+        BedFormatter::new().delimiter(",").quote_fields(true)
+    }
+
+    // quote a field if needed
+    fn quoted(&self, field: String) -> String {
+        let needs_quotes = self.quote && !field.is_empty()
+            && (field.contains(self.delimiter.as_str()) || field.contains('"') || field.contains('\n') || field.contains('\r'));
+        if needs_quotes {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field
+        }
+    }
+
+    // join the fields of a line with the delimiter
+    fn join(&self, fields: Vec<String>) -> String {
+        let fields: Vec<String> = fields.into_iter().map(|field| self.quoted(field)).collect();
+        fields.join(&self.delimiter)
+    }
+
+    /// name to use for records without a name column
+    pub fn default_name(mut self, name: &str) -> BedFormatter {
+        self.default_name = Some(name.to_owned());
+        self
+    }
+
+    /// score to use for records without a score column
+    pub fn default_score(mut self, score: u16) -> BedFormatter {
+        self.default_score = Some(score);
+        self
+    }
+
+    /// round floating-point columns to this many decimal places
+    pub fn precision(mut self, precision: usize) -> BedFormatter {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// only round these columns (counting from 0, so 6 is the first extra column of a
+    /// bed6+ file), e.g. the float and double columns of the autoSql definition
+    /// (see BigBed::float_columns); any number in them is rounded, and other columns
+    /// are left exactly as stored even if they look like floating-point numbers
+    pub fn float_columns(mut self, columns: &[usize]) -> BedFormatter {
+        self.float_columns = Some(columns.to_vec());
+        self
+    }
+
+    // should this value (in the given column) be rounded?
+    fn rounds(&self, column: usize, field: &str) -> bool {
+        match &self.float_columns {
+            Some(columns) => columns.contains(&column) && field.parse::<f64>().map(f64::is_finite).unwrap_or(false),
+            None => is_float(field),
+        }
+    }
+
+    /// linearly rescale the score column from [min, max] to the 0-1000 range browsers use
+    /// (scores outside the range are clamped, and non-numeric scores are left alone)
+    pub fn rescale_score(mut self, min: f64, max: f64) -> BedFormatter {
+        self.score_range = Some((min, max));
+        self
+    }
+
+    /// the number of standard columns in the file the records come from (see
+    /// BigBed::defined_field_count), so that a custom column in the place of the score
+    /// (e.g. column 5 of a bed3+3) is never rescaled; without it, the records are taken
+    /// to have standard columns
+    /// fails if the score is already set to be rescaled (see rescale_score) but the file has
+    /// no score column
+    pub fn defined_field_count(mut self, count: u16) -> Result<BedFormatter, Error> {
+        if self.score_range.is_some() && count < 5 {
+            return Err(Error::Misc("File has no score column"))
+        }
+        self.defined_field_count = Some(count);
+        Ok(self)
+    }
+
+    // map a score into 0-1000, if rescaling is on
+    fn rescaled(&self, score: &str) -> Option<String> {
+        let (min, max) = self.score_range?;
+        let score: f64 = score.parse().ok().filter(|score: &f64| score.is_finite())?;
+        let scaled = if max > min {
+            ((score - min) / (max - min) * 1000.0).round().clamp(0.0, 1000.0)
+        } else if score >= max {
+            1000.0
+        } else {
+            0.0
+        };
+        Some((scaled as u16).to_string())
+    }
+
+    /// emit only this column (counting from 1, so 4 is the name column)
+    /// records without the column are skipped by `write`
+    pub fn only_column(mut self, column: usize) -> BedFormatter {
+        self.only_column = Some(column.max(1));
+        self
+    }
+
+    /// write chromosome names exactly as given, instead of stripping any null padding
+    /// (e.g. to keep the stored key from the chromosome tree, see Chrom::raw_name)
+    pub fn raw_chrom_names(mut self, raw: bool) -> BedFormatter {
+        self.raw_chrom_names = raw;
+        self
+    }
+
+    /// emit these columns, in this order (counting from 0, so 3 is the name column)
+    /// a record without one of the columns gets an empty field for it
+    /// (see schema::parse_selection for choosing columns by name)
+    pub fn select(mut self, columns: &[usize]) -> BedFormatter {
+        self.select = Some(columns.to_vec());
+        self
+    }
+
+    /// a header line naming the columns (without a trailing newline), e.g. "#chrom\tstart\tend"
+    /// with quoted fields, the '#' is left off, since CSV readers expect a plain header row
+    pub fn header<S: AsRef<str>>(&self, names: &[S]) -> String {
+        let names: Vec<String> = names.iter().map(|name| name.as_ref().to_owned()).collect();
+        if self.quote {
+            self.join(names)
+        } else {
+            format!("#{}", self.join(names))
+        }
+    }
+
+    /// the names of the columns this formatter emits, given the field names of the file
+    /// (see BigBed::field_names), for use in a header line
+    pub fn column_names<S: AsRef<str>>(&self, field_names: &[S]) -> Vec<String> {
+        let mut names: Vec<String> = field_names.iter().map(|name| name.as_ref().to_owned()).collect();
+        if let Some(columns) = self.columns {
+            names.truncate(columns);
+        }
+        if let Some(columns) = &self.select {
+            names = columns.iter().map(|&column| names.get(column).cloned().unwrap_or_default()).collect();
+        }
+        match self.only_column {
+            Some(column) => names.into_iter().skip(column - 1).take(1).collect(),
+            None => names,
+        }
+    }
+
+    // the formatted columns of a line, including chrom, start and end
+    fn fields(&self, chrom: &str, line: &BedLine) -> Vec<String> {
+        let mut fields: Vec<&str> = match &line.rest {
+            Some(rest) => rest.split('\t').collect(),
+            None => Vec::new(),
+        };
+        // the score column is rescaled before any defaults are filled in
+        let has_score = self.defined_field_count.is_none_or(|count| count >= 5);
+        let rescaled = fields.get(1).filter(|_| has_score).and_then(|score| self.rescaled(score));
+        if let Some(score) = &rescaled {
+            fields[1] = score;
+        }
+        // fill in the name and score columns if requested
+        let score = self.default_score.map(|score| score.to_string());
+        if let Some(columns) = self.columns {
+            let defaults = [self.default_name.as_deref(), score.as_deref()];
+            for default in defaults.iter().take(columns - 3).skip(fields.len()) {
+                match default {
+                    Some(value) => fields.push(value),
+                    None => break,
+                }
+            }
+            fields.truncate(columns - 3);
+        }
+        let chrom = if self.raw_chrom_names { chrom } else { strip_null(chrom) };
+        let mut formatted = vec![chrom.to_owned(), line.start.to_string(), line.end.to_string()];
+        for (index, field) in fields.into_iter().enumerate() {
+            match self.precision {
+                Some(precision) if self.rounds(index + 3, field) => {
+                    // this parse cannot fail, since rounds checks it
+                    let value: f64 = field.parse().unwrap();
+                    formatted.push(format!("{:.*}", precision, value));
+                }
+                _ => formatted.push(field.to_owned()),
+            }
+        }
+        match &self.select {
+            Some(columns) => columns.iter().map(|&column| formatted.get(column).cloned().unwrap_or_default()).collect(),
+            None => formatted,
+        }
+    }
+
+    /// format a single line (without a trailing newline)
+    /// with `only_column`, a record without the column gives an empty string
+    pub fn format(&self, chrom: &str, line: &BedLine) -> String {
+        let mut fields = self.fields(chrom, line);
+        match self.only_column {
+            Some(column) if column <= fields.len() => self.quoted(fields.swap_remove(column - 1)),
+            Some(_) => String::new(),
+            None => self.join(fields),
+        }
+    }
+
+    /// write a single line (with a trailing newline)
+    pub fn write<W: Write>(&self, output: &mut W, chrom: &str, line: &BedLine) -> io::Result<()> {
+        let mut fields = self.fields(chrom, line);
+        let mut text = match self.only_column {
+            Some(column) if column <= fields.len() => self.quoted(fields.swap_remove(column - 1)),
+            Some(_) => return Ok(()),
+            None => self.join(fields),
+        };
+        text.push('\n');
+        output.write_all(text.as_bytes())
+    }
+}
+
+/// a file name for a chromosome's records (e.g. when splitting output by chromosome)
+/// characters other than letters, digits, '.', '-' and '_' are replaced with '_'
+pub fn chrom_file_name(chrom: &str) -> String {
+    let name: String = chrom.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    // avoid hidden files and names like '.' or '..'
+    if name.is_empty() || name.starts_with('.') {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod test_format {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let formatter = BedFormatter::default();
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 10, 20)), "chr1\t10\t20");
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 10, 20, "a\t5")), "chr1\t10\t20\ta\t5");
+        let mut output: Vec<u8> = Vec::new();
+        formatter.write(&mut output, "chr2", &BedLine::new(0, 1, 2)).unwrap();
+        assert_eq!(output, b"chr2\t1\t2\n");
+    }
+
+    #[test]
+    fn test_columns() {
+        let line = BedLine::with_rest(0, 10, 20, "a\t5\t+\t10\t20");
+        assert_eq!(BedFormatter::new().columns(3).format("chr1", &line), "chr1\t10\t20");
+        assert_eq!(BedFormatter::new().columns(1).format("chr1", &line), "chr1\t10\t20");
+        assert_eq!(BedFormatter::new().columns(6).format("chr1", &line), "chr1\t10\t20\ta\t5\t+");
+        // missing columns are filled with defaults
+        let formatter = BedFormatter::new().columns(6).default_name(".").default_score(0);
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 10, 20)), "chr1\t10\t20\t.\t0");
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 10, 20, "x")), "chr1\t10\t20\tx\t0");
+        // without a name default, the score default cannot be used
+        let formatter = BedFormatter::new().columns(5).default_score(0);
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 10, 20)), "chr1\t10\t20");
+    }
+
+    #[test]
+    fn test_delimiter_and_precision() {
+        let line = BedLine::with_rest(0, 10, 20, "a\t5\t.\t3.14159\t1e-3\t12");
+        let formatter = BedFormatter::new().delimiter(",");
+        assert_eq!(formatter.format("chr1", &line), "chr1,10,20,a,5,.,3.14159,1e-3,12");
+        let formatter = BedFormatter::new().precision(2);
+        assert_eq!(formatter.format("chr1", &line), "chr1\t10\t20\ta\t5\t.\t3.14\t0.00\t12");
+        // only the given columns are rounded, including integers in them
+        let formatter = BedFormatter::new().precision(2).float_columns(&[6, 8]);
+        assert_eq!(formatter.format("chr1", &line), "chr1\t10\t20\ta\t5\t.\t3.14\t1e-3\t12.00");
+        let line = BedLine::with_rest(0, 10, 20, "1.5\t5\t.\tNA");
+        assert_eq!(formatter.format("chr1", &line), "chr1\t10\t20\t1.5\t5\t.\tNA");
+    }
+
+    #[test]
+    fn test_csv() {
+        let line = BedLine::with_rest(0, 10, 20, "a,b\t5\t+\t\"quoted\" name\t1,2,3,");
+        assert_eq!(BedFormatter::csv().format("chr1", &line), "chr1,10,20,\"a,b\",5,+,\"\"\"quoted\"\" name\",\"1,2,3,\"");
+        // quoting only depends on the delimiter in use
+        let formatter = BedFormatter::new().delimiter(";").quote_fields(true);
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 10, 20, "a,b\tc;d")), "chr1;10;20;a,b;\"c;d\"");
+        assert_eq!(BedFormatter::csv().only_column(4).format("chr1", &line), "\"a,b\"");
+        // without quoting, fields are written as they are
+        assert_eq!(BedFormatter::new().delimiter(",").format("chr1", &line), "chr1,10,20,a,b,5,+,\"quoted\" name,1,2,3,");
+        assert_eq!(BedFormatter::csv().header(&["chrom", "start"]), "chrom,start");
+    }
+
+    #[test]
+    fn test_rescale_score() {
+        let formatter = BedFormatter::new().rescale_score(10.0, 20.0);
+        let format = |rest: &str| formatter.format("chr1", &BedLine::with_rest(0, 10, 20, rest));
+        assert_eq!(format("a\t15\t+"), "chr1\t10\t20\ta\t500\t+");
+        assert_eq!(format("a\t12.5"), "chr1\t10\t20\ta\t250");
+        assert_eq!(format("a\t5"), "chr1\t10\t20\ta\t0");
+        assert_eq!(format("a\t99"), "chr1\t10\t20\ta\t1000");
+        assert_eq!(format("a\t."), "chr1\t10\t20\ta\t.");
+        assert_eq!(format("a"), "chr1\t10\t20\ta");
+        // default scores are not rescaled
+        let formatter = formatter.columns(5).default_score(7);
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 10, 20, "a")), "chr1\t10\t20\ta\t7");
+        // a single-value range
+        let formatter = BedFormatter::new().rescale_score(3.0, 3.0);
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 1, 2, "a\t3")), "chr1\t1\t2\ta\t1000");
+        assert_eq!(formatter.format("chr1", &BedLine::with_rest(0, 1, 2, "a\t2")), "chr1\t1\t2\ta\t0");
+        // column 5 of a bed3+3 is a custom one, not a score
+        let line = BedLine::with_rest(0, 10, 20, "peak1\t3\t+");
+        let formatter = BedFormatter::new().rescale_score(0.0, 10.0);
+        assert_eq!(formatter.clone().defined_field_count(3).unwrap_err(), Error::Misc("File has no score column"));
+        assert_eq!(formatter.clone().defined_field_count(6).unwrap().format("chr1", &line), "chr1\t10\t20\tpeak1\t300\t+");
+        let formatter = BedFormatter::new().defined_field_count(3).unwrap().rescale_score(0.0, 10.0);
+        assert_eq!(formatter.format("chr1", &line), "chr1\t10\t20\tpeak1\t3\t+");
+    }
+
+    #[test]
+    fn test_only_column() {
+        let line = BedLine::with_rest(0, 10, 20, "geneA\t5\t+\t0.123");
+        assert_eq!(BedFormatter::new().only_column(4).format("chr1", &line), "geneA");
+        assert_eq!(BedFormatter::new().only_column(1).format("chr1", &line), "chr1");
+        assert_eq!(BedFormatter::new().only_column(2).format("chr1", &line), "10");
+        assert_eq!(BedFormatter::new().only_column(7).precision(1).format("chr1", &line), "0.1");
+        // records without the column are skipped
+        let formatter = BedFormatter::new().only_column(4);
+        let mut output: Vec<u8> = Vec::new();
+        formatter.write(&mut output, "chr1", &BedLine::new(0, 1, 2)).unwrap();
+        formatter.write(&mut output, "chr1", &line).unwrap();
+        assert_eq!(output, b"geneA\n");
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 1, 2)), "");
+        // unless a default fills it in
+        let formatter = formatter.columns(4).default_name(".");
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 1, 2)), ".");
+    }
+
+    #[test]
+    fn test_select() {
+        let line = BedLine::with_rest(0, 10, 20, "a\t5\t+");
+        let formatter = BedFormatter::new().select(&[3, 0, 4]);
+        assert_eq!(formatter.format("chr1", &line), "a\tchr1\t5");
+        assert_eq!(formatter.format("chr1", &BedLine::new(0, 10, 20)), "\tchr1\t");
+        assert_eq!(BedFormatter::new().select(&[1, 1]).delimiter(",").format("chr1", &line), "10,10");
+        assert_eq!(formatter.header(&["name", "chrom", "score"]), "#name\tchrom\tscore");
+        let fields = ["chrom", "chromStart", "chromEnd", "name", "score"];
+        assert_eq!(formatter.column_names(&fields), vec!["name", "chrom", "score"]);
+        assert_eq!(BedFormatter::new().column_names(&fields), fields);
+        assert_eq!(BedFormatter::new().columns(4).column_names(&fields), &fields[..4]);
+        assert_eq!(BedFormatter::new().only_column(4).column_names(&fields), vec!["name"]);
+    }
+
+    #[test]
+    fn test_raw_chrom_names() {
+        let line = BedLine::new(0, 10, 20);
+        assert_eq!(BedFormatter::new().format("chr1\0", &line), "chr1\t10\t20");
+        assert_eq!(BedFormatter::new().raw_chrom_names(true).format("chr1\0", &line), "chr1\0\t10\t20");
+    }
+
+    #[test]
+    fn test_chrom_file_name() {
+        assert_eq!(chrom_file_name("chr1"), "chr1");
+        assert_eq!(chrom_file_name("chrUn_GL456372"), "chrUn_GL456372");
+        assert_eq!(chrom_file_name("HLA-A*01:01:01:01"), "HLA-A_01_01_01_01");
+        assert_eq!(chrom_file_name("a/../b"), "a_.._b");
+        assert_eq!(chrom_file_name(".."), "_..");
+        assert_eq!(chrom_file_name(""), "_");
+    }
+}
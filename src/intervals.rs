@@ -0,0 +1,634 @@
+use crate::{in_range, BigBed, BedLine, BlockRecords, FileOffsetSize, Strand};
+use crate::error::Error;
+use crate::query::QueryOptions;
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+use std::sync::Arc;
+
+// order records by chromosome, then position
+fn sorted_indices(lines: &[BedLine]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..lines.len()).collect();
+    indices.sort_by_key(|&index| (lines[index].chrom_id, lines[index].start, lines[index].end));
+    indices
+}
+
+/// collapse overlapping (or book-ended) records into single BED3 records
+/// the output is sorted by chromosome and position
+pub fn merge_intervals(lines: &[BedLine]) -> Vec<BedLine> {
+    let mut merged: Vec<BedLine> = Vec::new();
+    for index in sorted_indices(lines) {
+        let line = &lines[index];
+        match merged.last_mut() {
+            Some(last) if last.chrom_id == line.chrom_id && line.start <= last.end => {
+                last.end = last.end.max(line.end);
+            }
+            _ => merged.push(BedLine::new(line.chrom_id, line.start, line.end)),
+        }
+    }
+    merged
+}
+
+/// assign a cluster id to each record, where records on the same chromosome
+/// within `distance` bases of each other share a cluster
+/// the returned ids are in the same order as `lines`, and clusters are
+/// numbered from 0 in order of position
+pub fn cluster(lines: &[BedLine], distance: u32) -> Vec<usize> {
+    let mut ids = vec![0; lines.len()];
+    // the chromosome and end of the current cluster
+    let mut current: Option<(u32, u32)> = None;
+    let mut next_id = 0;
+    for index in sorted_indices(lines) {
+        let line = &lines[index];
+        match current {
+            Some((chrom_id, end)) if chrom_id == line.chrom_id && line.start <= end.saturating_add(distance) => {
+                current = Some((chrom_id, end.max(line.end)));
+            }
+            _ => {
+                next_id += 1;
+                current = Some((line.chrom_id, line.end));
+            }
+        }
+        ids[index] = next_id - 1;
+    }
+    ids
+}
+
+/// remove the parts of records that overlap excluded regions (on the same chromosome)
+/// records overlapping an excluded region are dropped, or if `truncate` is set, cut down
+/// to the parts outside the excluded regions (possibly splitting them in pieces); only the
+/// coordinates are changed, so the other columns are kept as-is
+/// the output is in the same order as `lines`
+pub fn subtract(lines: &[BedLine], exclude: &[BedLine], truncate: bool) -> Vec<BedLine> {
+    let exclude = merge_intervals(exclude);
+    let mut kept = Vec::with_capacity(lines.len());
+    for line in lines {
+        // zero-length records are treated as covering their position
+        let line_end = line.end.max(line.start.saturating_add(1));
+        // the first excluded region that ends after the record starts
+        let first = exclude.partition_point(|region| (region.chrom_id, region.end) <= (line.chrom_id, line.start));
+        let overlapping: Vec<&BedLine> = exclude[first..].iter()
+            .take_while(|region| region.chrom_id == line.chrom_id && region.start < line_end)
+            .collect();
+        if overlapping.is_empty() {
+            kept.push(line.clone());
+        } else if truncate {
+            let mut position = line.start;
+            for region in overlapping {
+                if region.start > position {
+                    kept.push(BedLine{start: position, end: region.start, ..line.clone()});
+                }
+                position = position.max(region.end);
+            }
+            if position < line.end {
+                kept.push(BedLine{start: position, ..line.clone()});
+            }
+        }
+    }
+    kept
+}
+
+// is this record on the reverse strand? (records without a strand are forward)
+fn is_reverse(line: &BedLine) -> bool {
+    line.strand() == Ok(Some(Strand::Reverse))
+}
+
+/// pad a record by `upstream` bases before its start and `downstream` bases after its end,
+/// clamping to the bounds of the chromosome
+/// if `strand_aware` is set, upstream and downstream are swapped for reverse-strand records
+/// (the strand is read from the sixth column, so only set it for files that define one)
+pub fn slop(line: &BedLine, upstream: u32, downstream: u32, chrom_size: u32, strand_aware: bool) -> BedLine {
+    let (left, right) = if strand_aware && is_reverse(line) {
+        (downstream, upstream)
+    } else {
+        (upstream, downstream)
+    };
+    let mut padded = line.clone();
+    padded.start = line.start.saturating_sub(left);
+    padded.end = line.end.saturating_add(right).min(chrom_size.max(line.end));
+    padded
+}
+
+/// the flanking regions of a record: `upstream` bases before its start and
+/// `downstream` bases after its end (clamped to the chromosome, empty flanks are dropped)
+/// if `strand_aware` is set, the flanks are swapped for reverse-strand records
+/// (the strand is read from the sixth column, so only set it for files that define one)
+pub fn flank(line: &BedLine, upstream: u32, downstream: u32, chrom_size: u32, strand_aware: bool) -> Vec<BedLine> {
+    let padded = slop(line, upstream, downstream, chrom_size, strand_aware);
+    let mut flanks = Vec::with_capacity(2);
+    for (start, end) in &[(padded.start, line.start), (line.end, padded.end)] {
+        if start < end {
+            let mut region = line.clone();
+            region.start = *start;
+            region.end = *end;
+            flanks.push(region);
+        }
+    }
+    // keep the upstream flank first
+    if strand_aware && is_reverse(line) {
+        flanks.reverse();
+    }
+    flanks
+}
+
+/// truncate a record to the region from `start` to `end`
+/// only the coordinates are changed, so the other columns (e.g. thickStart or blocks) are kept as-is
+pub fn clip(line: &BedLine, start: u32, end: u32) -> BedLine {
+    let mut clipped = line.clone();
+    clipped.start = line.start.max(start).min(end);
+    clipped.end = line.end.min(end).max(clipped.start);
+    clipped
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// like query, but the records are truncated to the region (see clip),
+    /// as wanted when drawing a region or counting its coverage
+    pub fn query_clipped(&mut self, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedLine>, Error> {
+        self.query_with(chrom, start..end, &QueryOptions::new().max_items(max_items).clip(true))
+    }
+
+    /// find the records overlapping a region, and return their flanking regions
+    /// (e.g. promoters, with `upstream` > 0 and `downstream` = 0)
+    /// `strand_aware` has no effect on files without a strand column
+    pub fn query_flank(&mut self, chrom: &str, start: u32, end: u32, upstream: u32, downstream: u32,
+                       strand_aware: bool) -> Result<Vec<BedLine>, Error> {
+        let chrom_data = self.resolve_chrom(chrom)?;
+        // the sixth column of a bed3+3 is a custom one, not a strand
+        let strand_aware = strand_aware && self.defined_field_count >= 6;
+        let lines = self.query(&chrom_data.name, start, end, 0)?;
+        Ok(lines.iter()
+            .flat_map(|line| flank(line, upstream, downstream, chrom_data.size, strand_aware))
+            .collect())
+    }
+}
+
+/// how the results of an intersection are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntersectMode {
+    /// every overlapping (a, b) pair
+    Pairs,
+    /// each record of a, with the number of records of b it overlaps
+    Counts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntersectOptions {
+    pub mode: IntersectMode,
+    /// the minimum number of overlapping bases for two records to intersect
+    pub min_overlap: u32,
+    /// in Counts mode, also report records of a with no hits
+    pub report_unmatched: bool,
+}
+
+impl Default for IntersectOptions {
+    fn default() -> IntersectOptions {
+        IntersectOptions{mode: IntersectMode::Pairs, min_overlap: 1, report_unmatched: false}
+    }
+}
+
+/// a single result of BigBed::intersect
+/// the records of b use the chromosome ids of their own file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intersection {
+    Pair{chrom: Arc<str>, a: BedLine, b: BedLine},
+    Count{chrom: Arc<str>, a: BedLine, hits: usize},
+}
+
+/// the number of bases shared by two records (ignoring chromosomes)
+pub fn overlap_len(a: &BedLine, b: &BedLine) -> u32 {
+    a.end.min(b.end).saturating_sub(a.start.max(b.start))
+}
+
+// find, for each record of a, the indices of the records in b it overlaps
+// both slices must be sorted by start
+fn sweep(a: &[BedLine], b: &[BedLine], min_overlap: u32) -> Vec<Vec<usize>> {
+    let mut hits = Vec::with_capacity(a.len());
+    let mut active: Vec<usize> = Vec::new();
+    let mut next_b = 0;
+    for a_line in a {
+        // records of b that end before this record can never overlap again
+        active.retain(|&index| b[index].end > a_line.start);
+        while next_b < b.len() && b[next_b].start < a_line.end {
+            if b[next_b].end > a_line.start {
+                active.push(next_b);
+            }
+            next_b += 1;
+        }
+        hits.push(active.iter()
+            .cloned()
+            .filter(|&index| overlap_len(a_line, &b[index]) >= min_overlap.max(1))
+            .collect());
+    }
+    hits
+}
+
+/// the similarity of two sets of records, by the bases they cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jaccard {
+    /// bases covered by both sets
+    pub intersection: u64,
+    /// bases covered by either set
+    pub union: u64,
+    /// the number of overlaps between the merged records of the two sets
+    pub intersections: u64,
+}
+
+impl Jaccard {
+    /// the Jaccard index: intersection / union (0 if neither set covers anything)
+    pub fn jaccard(&self) -> f64 {
+        if self.union == 0 {
+            0.0
+        } else {
+            self.intersection as f64 / self.union as f64
+        }
+    }
+}
+
+// the bases covered by merged (i.e. sorted and disjoint) records
+fn covered_bases(merged: &[BedLine]) -> u64 {
+    merged.iter().map(|line| u64::from(line.end - line.start)).sum()
+}
+
+// the shared bases and number of overlaps of two lists of merged records
+fn merged_overlap(a: &[BedLine], b: &[BedLine]) -> (u64, u64) {
+    let (mut bases, mut overlaps) = (0, 0);
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let shared = overlap_len(&a[i], &b[j]);
+        if shared > 0 {
+            bases += u64::from(shared);
+            overlaps += 1;
+        }
+        // move past whichever record ends first
+        if a[i].end <= b[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (bases, overlaps)
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// compare the bases covered by this file and another (chromosomes are matched by name)
+    /// each chromosome's records are read using the index, one chromosome at a time
+    pub fn jaccard<U: Read + Seek>(&mut self, other: &mut BigBed<U>) -> Result<Jaccard, Error> {
+        let mut result = Jaccard{intersection: 0, union: 0, intersections: 0};
+        let mut seen = HashSet::new();
+        for chrom_data in self.chrom_list()? {
+            let a = merge_intervals(&self.query(&chrom_data.name, 0, chrom_data.size, 0)?);
+            let b = match other.find_chrom(chrom_data.name()) {
+                Ok(Some(other_chrom)) => {
+                    seen.insert(other_chrom.id);
+                    merge_intervals(&other.query(&other_chrom.name, 0, other_chrom.size, 0)?)
+                }
+                // names that don't fit the other file's key simply aren't there
+                Ok(None) | Err(Error::BadKey(_, _)) => Vec::new(),
+                Err(err) => return Err(err),
+            };
+            let (bases, overlaps) = merged_overlap(&a, &b);
+            result.intersection += bases;
+            result.intersections += overlaps;
+            result.union += covered_bases(&a) + covered_bases(&b) - bases;
+        }
+        // chromosomes only in the other file
+        for chrom_data in other.chrom_list()? {
+            if !seen.contains(&chrom_data.id) {
+                result.union += covered_bases(&merge_intervals(&other.query(&chrom_data.name, 0, chrom_data.size, 0)?));
+            }
+        }
+        Ok(result)
+    }
+
+    /// intersect the records of this file with the records of another
+    /// chromosomes are matched by name, and each is read using its index
+    pub fn intersect<U: Read + Seek>(&mut self, other: &mut BigBed<U>, options: &IntersectOptions) -> Result<Vec<Intersection>, Error> {
+        let mut results = Vec::new();
+        for chrom_data in self.chrom_list()? {
+            let other_chrom = match other.find_chrom(chrom_data.name()) {
+                Ok(Some(other_chrom)) => Some(other_chrom),
+                // names that don't fit the other file's key simply aren't there
+                Ok(None) | Err(Error::BadKey(_, _)) => None,
+                Err(err) => return Err(err),
+            };
+            if other_chrom.is_none() && !(options.mode == IntersectMode::Counts && options.report_unmatched) {
+                continue
+            }
+            let name: Arc<str> = Arc::from(chrom_data.name());
+            let a_lines = self.query(&chrom_data.name, 0, chrom_data.size, 0)?;
+            let b_lines = match other_chrom {
+                Some(other_chrom) => other.query(&other_chrom.name, 0, other_chrom.size, 0)?,
+                None => Vec::new(),
+            };
+            for (a_line, hits) in a_lines.iter().zip(sweep(&a_lines, &b_lines, options.min_overlap)) {
+                match options.mode {
+                    IntersectMode::Pairs => {
+                        for index in hits {
+                            results.push(Intersection::Pair{
+                                chrom: Arc::clone(&name), a: a_line.clone(), b: b_lines[index].clone()
+                            });
+                        }
+                    }
+                    IntersectMode::Counts => {
+                        if !hits.is_empty() || options.report_unmatched {
+                            results.push(Intersection::Count{
+                                chrom: Arc::clone(&name), a: a_line.clone(), hits: hits.len()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// count the records overlapping each of many regions (chrom, start, end)
+    /// regions are grouped by chromosome, and each data block is read at most once
+    /// regions on chromosomes that are not in the file have no overlaps
+    pub fn count_overlaps_many<S: AsRef<str>>(&mut self, regions: &[(S, u32, u32)]) -> Result<Vec<u64>, Error> {
+        let mut counts = vec![0; regions.len()];
+        // group the regions by chromosome, keeping each group sorted by start
+        let mut order: Vec<usize> = (0..regions.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a_chrom, a_start, a_end) = &regions[a];
+            let (b_chrom, b_start, b_end) = &regions[b];
+            (a_chrom.as_ref(), a_start, a_end).cmp(&(b_chrom.as_ref(), b_start, b_end))
+        });
+        let big_endian = self.big_endian;
+        for group in order.chunk_by(|&a, &b| regions[a].0.as_ref() == regions[b].0.as_ref()) {
+            let chrom_id = match self.resolve_chrom(regions[group[0]].0.as_ref()) {
+                Ok(chrom) => chrom.id,
+                Err(Error::BadChrom(_)) | Err(Error::BadKey(_, _)) => continue,
+                Err(err) => return Err(err),
+            };
+            // find the blocks for each span of overlapping regions, reading each block once
+            let mut blocks: Vec<FileOffsetSize> = Vec::new();
+            let mut span: Option<(u32, u32)> = None;
+            for &index in group {
+                let (_, start, end) = regions[index];
+                span = match span {
+                    Some((span_start, span_end)) if start <= span_end => Some((span_start, span_end.max(end))),
+                    Some((span_start, span_end)) => {
+                        blocks.extend(self.overlapping_blocks(chrom_id, span_start.saturating_sub(1), span_end.saturating_add(1))?);
+                        Some((start, end))
+                    }
+                    None => Some((start, end)),
+                };
+            }
+            if let Some((span_start, span_end)) = span {
+                blocks.extend(self.overlapping_blocks(chrom_id, span_start.saturating_sub(1), span_end.saturating_add(1))?);
+            }
+            blocks.sort_by_key(|block| block.offset);
+            blocks.dedup();
+            let mut records: Vec<(u32, u32)> = Vec::new();
+            self.read_blocks(&blocks, |_, buff| {
+                records.extend(BlockRecords::new(buff, big_endian)
+                    .filter(|record| record.chrom_id == chrom_id)
+                    .map(|record| (record.start, record.end)));
+                true
+            })?;
+            records.sort_unstable();
+
+            // sweep over the regions, keeping the records that may still overlap
+            let mut active: Vec<(u32, u32)> = Vec::new();
+            let mut next = 0;
+            for &index in group {
+                let (_, start, end) = regions[index];
+                active.retain(|&(_, record_end)| record_end >= start);
+                while next < records.len() && records[next].0 <= end {
+                    if records[next].1 >= start {
+                        active.push(records[next]);
+                    }
+                    next += 1;
+                }
+                counts[index] = active.iter()
+                    .filter(|&&(record_start, record_end)| in_range(chrom_id, record_start, record_end, chrom_id, start, end))
+                    .count() as u64;
+            }
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod test_intervals {
+    use super::*;
+
+    #[test]
+    fn test_merge_intervals() {
+        let lines = vec![
+            BedLine::with_rest(0, 50, 60, "c"),
+            BedLine::new(0, 10, 20),
+            BedLine::new(0, 15, 30),
+            BedLine::new(1, 5, 10),
+            BedLine::new(0, 30, 40),
+            BedLine::new(0, 12, 14),
+        ];
+        assert_eq!(merge_intervals(&lines), vec![
+            BedLine::new(0, 10, 40),
+            BedLine::new(0, 50, 60),
+            BedLine::new(1, 5, 10),
+        ]);
+        assert_eq!(merge_intervals(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let a = vec![BedLine::new(0, 0, 100), BedLine::new(0, 10, 20), BedLine::new(0, 150, 200)];
+        let b = vec![BedLine::new(0, 5, 15), BedLine::new(0, 19, 160), BedLine::new(0, 100, 110), BedLine::new(0, 300, 400)];
+        assert_eq!(sweep(&a, &b, 1), vec![vec![0, 1], vec![0, 1], vec![1]]);
+        assert_eq!(sweep(&a, &b, 10), vec![vec![0, 1], vec![], vec![1]]);
+        assert_eq!(overlap_len(&a[0], &b[1]), 81);
+        assert_eq!(overlap_len(&a[0], &b[2]), 0);
+    }
+
+    #[test]
+    fn test_merged_overlap() {
+        let a = vec![BedLine::new(0, 0, 10), BedLine::new(0, 20, 30), BedLine::new(0, 40, 50)];
+        let b = vec![BedLine::new(0, 5, 25), BedLine::new(0, 30, 40), BedLine::new(0, 45, 60)];
+        assert_eq!(merged_overlap(&a, &b), (15, 3));
+        assert_eq!(merged_overlap(&a, &[]), (0, 0));
+        assert_eq!(covered_bases(&a), 30);
+    }
+
+    #[test]
+    fn test_jaccard() {
+        use std::fs::File;
+        let mut peaks = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let mut bed12 = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        let mut named = BigBed::from_file(File::open("test/bigbeds/named.bb").unwrap()).unwrap();
+        let result = peaks.jaccard(&mut bed12).unwrap();
+        assert_eq!(result, Jaccard{intersection: 77613, union: 9519929, intersections: 71});
+        assert_eq!(bed12.jaccard(&mut peaks).unwrap(), result);
+        // named.bb has no chrX, which only adds to the union
+        assert_eq!(peaks.jaccard(&mut named).unwrap(), Jaccard{intersection: 10389, union: 1347586, intersections: 15});
+        let mut peaks_again = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        assert_eq!(peaks.jaccard(&mut peaks_again).unwrap().jaccard(), 1.0);
+        assert_eq!(Jaccard{intersection: 0, union: 0, intersections: 0}.jaccard(), 0.0);
+    }
+
+    #[test]
+    fn test_intersect() {
+        use std::fs::File;
+        let mut a = BigBed::from_file(File::open("test/bigbeds/short1.bb").unwrap()).unwrap();
+        let mut b = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        let pairs = a.intersect(&mut b, &IntersectOptions::default()).unwrap();
+        let counts = a.intersect(&mut b, &IntersectOptions{mode: IntersectMode::Counts, ..Default::default()}).unwrap();
+        // every pair should actually overlap
+        for pair in &pairs {
+            match pair {
+                Intersection::Pair{a, b, ..} => assert!(overlap_len(a, b) > 0),
+                _ => panic!("Expected only pairs, received {:?}", pair),
+            }
+        }
+        // and the counts should agree with the pairs
+        let total: usize = counts.iter().map(|count| match count {
+            Intersection::Count{hits, ..} => *hits,
+            _ => panic!("Expected only counts, received {:?}", count),
+        }).sum();
+        assert_eq!(total, pairs.len());
+        assert!(total > 0);
+        // reporting unmatched records covers every record in a
+        let all = a.intersect(&mut b, &IntersectOptions{mode: IntersectMode::Counts, report_unmatched: true, ..Default::default()}).unwrap();
+        assert_eq!(all.len(), 10);
+    }
+
+    #[test]
+    fn test_slop() {
+        let forward = BedLine::with_rest(0, 100, 200, "a\t0\t+");
+        let reverse = BedLine::with_rest(0, 100, 200, "a\t0\t-");
+        assert_eq!(slop(&forward, 10, 20, 1000, true), BedLine::with_rest(0, 90, 220, "a\t0\t+"));
+        assert_eq!(slop(&reverse, 10, 20, 1000, true), BedLine::with_rest(0, 80, 210, "a\t0\t-"));
+        assert_eq!(slop(&reverse, 10, 20, 1000, false), BedLine::with_rest(0, 90, 220, "a\t0\t-"));
+        // clamp to the chromosome bounds
+        assert_eq!(slop(&BedLine::new(0, 100, 200), 500, 500, 250, false), BedLine::new(0, 0, 250));
+    }
+
+    #[test]
+    fn test_clip() {
+        let line = BedLine::with_rest(0, 100, 200, "a\t0\t+");
+        assert_eq!(clip(&line, 150, 300), BedLine::with_rest(0, 150, 200, "a\t0\t+"));
+        assert_eq!(clip(&line, 0, 120), BedLine::with_rest(0, 100, 120, "a\t0\t+"));
+        assert_eq!(clip(&line, 120, 130), BedLine::with_rest(0, 120, 130, "a\t0\t+"));
+        assert_eq!(clip(&line, 0, 1000), line);
+        // zero-length records stay in place
+        assert_eq!(clip(&BedLine::new(0, 50, 50), 50, 60), BedLine::new(0, 50, 50));
+
+        use std::fs::File;
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        let full = bb.query("chr1", 500_000, 2_200_000, 0).unwrap();
+        let clipped = bb.query_clipped("chr1", 500_000, 2_200_000, 0).unwrap();
+        assert_eq!(full.iter().map(|line| (line.start, line.end)).collect::<Vec<_>>(),
+                   vec![(22605, 798293), (1088759, 1857033), (2115175, 2445032)]);
+        assert_eq!(clipped.iter().map(|line| (line.start, line.end)).collect::<Vec<_>>(),
+                   vec![(500_000, 798293), (1088759, 1857033), (2115175, 2_200_000)]);
+    }
+
+    #[test]
+    fn test_flank() {
+        let forward = BedLine::with_rest(0, 100, 200, "a\t0\t+");
+        let reverse = BedLine::with_rest(0, 100, 200, "a\t0\t-");
+        assert_eq!(flank(&forward, 50, 0, 1000, true), vec![BedLine::with_rest(0, 50, 100, "a\t0\t+")]);
+        assert_eq!(flank(&reverse, 50, 0, 1000, true), vec![BedLine::with_rest(0, 200, 250, "a\t0\t-")]);
+        assert_eq!(flank(&reverse, 50, 10, 1000, true), vec![
+            BedLine::with_rest(0, 200, 250, "a\t0\t-"),
+            BedLine::with_rest(0, 90, 100, "a\t0\t-"),
+        ]);
+        // flanks that are clamped away are dropped
+        assert_eq!(flank(&BedLine::new(0, 0, 200), 50, 50, 220, false), vec![BedLine::new(0, 200, 220)]);
+    }
+
+    #[test]
+    fn test_query_flank() {
+        use crate::writer::{BigBedWriter, WriterOptions};
+        use std::fs::File;
+        use std::io::Cursor;
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        // GENE0003 is on the reverse strand
+        let flanks = bb.query_flank("chr1", 646748, 653253, 1000, 0, true).unwrap();
+        assert_eq!(flanks.len(), 1);
+        assert_eq!((flanks[0].start(), flanks[0].end()), (653253, 654253));
+        assert_eq!(flanks[0].name(), Some("GENE0003"));
+
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(3);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t100\t200\tpeak1\t3\t-").unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        // without a strand column, the "-" is a custom column and the upstream flank stays before the start
+        let flanks = bb.query_flank("chr1", 0, 1000, 50, 0, true).unwrap();
+        assert_eq!(flanks, vec![BedLine::with_rest(0, 50, 100, "peak1\t3\t-")]);
+    }
+
+    #[test]
+    fn test_subtract() {
+        let lines = vec![
+            BedLine::with_rest(0, 100, 200, "a"),
+            BedLine::with_rest(0, 300, 400, "b"),
+            BedLine::with_rest(1, 100, 200, "c"),
+            BedLine::with_rest(0, 500, 500, "d"),
+            BedLine::with_rest(0, 10, 20, "e"),
+        ];
+        let exclude = vec![
+            BedLine::new(0, 120, 130),
+            BedLine::new(0, 150, 160),
+            BedLine::new(0, 155, 170),
+            BedLine::new(0, 350, 450),
+            BedLine::new(0, 499, 501),
+            BedLine::new(2, 0, 1000),
+        ];
+        assert_eq!(subtract(&lines, &exclude, false), vec![
+            BedLine::with_rest(1, 100, 200, "c"),
+            BedLine::with_rest(0, 10, 20, "e"),
+        ]);
+        assert_eq!(subtract(&lines, &exclude, true), vec![
+            BedLine::with_rest(0, 100, 120, "a"),
+            BedLine::with_rest(0, 130, 150, "a"),
+            BedLine::with_rest(0, 170, 200, "a"),
+            BedLine::with_rest(0, 300, 350, "b"),
+            BedLine::with_rest(1, 100, 200, "c"),
+            BedLine::with_rest(0, 10, 20, "e"),
+        ]);
+        // book-ended regions don't overlap
+        assert_eq!(subtract(&lines[..1], &[BedLine::new(0, 200, 300), BedLine::new(0, 0, 100)], false), lines[..1].to_vec());
+        assert_eq!(subtract(&lines, &[], true), lines);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let lines = vec![
+            BedLine::new(0, 100, 200),
+            BedLine::new(1, 0, 10),
+            BedLine::new(0, 250, 300),
+            BedLine::new(0, 10, 20),
+            BedLine::new(0, 150, 160),
+        ];
+        assert_eq!(cluster(&lines, 0), vec![1, 3, 2, 0, 1]);
+        assert_eq!(cluster(&lines, 50), vec![1, 2, 1, 0, 1]);
+        assert_eq!(cluster(&lines, 100), vec![0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_count_overlaps_many() {
+        use std::fs::File;
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let regions = vec![
+            ("chr2", 0, 50000000),
+            ("chr1", 1000000, 5000000),
+            ("chrZ", 0, 100),
+            ("chr1", 0, 1),
+            ("chr1", 4000000, 8000000),
+            ("chr1", 0, 250000000),
+            ("chr1", 1000000, 5000000),
+        ];
+        let counts = bb.count_overlaps_many(&regions).unwrap();
+        let expected: Vec<u64> = regions.iter().map(|&(chrom, start, end)| {
+            match bb.query(chrom, start, end, 0) {
+                Ok(lines) => lines.len() as u64,
+                Err(_) => 0,
+            }
+        }).collect();
+        assert_eq!(counts, expected);
+        assert_eq!(counts[1], 46);
+        assert_eq!(bb.count_overlaps_many::<&str>(&[]).unwrap(), vec![]);
+    }
+}
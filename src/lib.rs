@@ -1,19 +1,72 @@
 extern crate flate2;
 
 pub mod error;
+pub mod assembly;
+pub mod bbi;
+pub mod bptree;
+pub mod rtree;
+pub mod extra;
+pub mod schema;
+pub mod serve;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tabix;
+pub mod bed;
+pub mod blocks;
+pub mod format;
+pub mod intervals;
+pub mod metadata;
+pub mod metrics;
+#[cfg(feature = "noodles")]
+pub mod noodles;
+pub mod query;
+pub mod reader;
+pub mod region;
+pub mod repair;
+pub mod scan;
+pub mod sink;
+pub mod writer;
+pub mod zoom;
 use crate::error::Error::{self, *};
+use crate::error::Warning;
+pub use crate::bed::{BedRecord, Rgb, Strand};
+pub use crate::format::BedFormatter;
+use crate::bptree::{BPlusTree, BPlusTreeItems};
+use crate::rtree::CIRTreeFile;
+use crate::metrics::Metrics;
+use crate::query::QueryOptions;
+use crate::region::GenomicRange;
+use crate::bbi::{BbiHeader, BIGBED_SIG};
+pub use crate::bbi::ZoomLevel;
 
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::collections::VecDeque;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
 use std::convert::TryInto;
 use flate2::{Decompress, FlushDecompress};
+use flate2::read::MultiGzDecoder;
 
+pub(crate) static BPT_SIG: [u8; 4] = [0x78, 0xCA, 0x8C, 0x91];
+pub(crate) static CIRTREE_SIG: [u8; 4] = [0x24, 0x68, 0xAC, 0xE0];
+// the size of the extension header fields we know about
+// (extension_size, extra_index_count and extra_index_list_offset)
+const EXTENSION_FIELDS_SIZE: usize = 12;
+// the size of the whole extension header in current versions of the format
+const EXTENSION_HEADER_SIZE: usize = 64;
 
-static BIGBED_SIG: [u8; 4] = [0x87, 0x89, 0xF2, 0xEB];
-static BPT_SIG: [u8; 4] = [0x78, 0xCA, 0x8C, 0x91];
-static CIRTREE_SIG: [u8; 4] = [0x24, 0x68, 0xAC, 0xE0];
+/// the most warnings a BigBed keeps (later ones are dropped until take_warnings is called),
+/// so that queries over a badly damaged file don't use up memory
+pub const MAX_WARNINGS: usize = 1000;
 
 
+// the warning for an extension header larger than we know about
+pub(crate) fn larger_extension_warning(size: usize) -> Warning {
+    Warning::UnsupportedFeature(format!(
+        "extension header of {} bytes (expected {}); the extra bytes were skipped", size, EXTENSION_HEADER_SIZE
+    ))
+}
+
 /// a collection of useful methods for producing bytes from a type that implements Read
 pub trait ByteReader: Read {
     fn read_u64(&mut self, big_endian: bool) -> u64 {
@@ -57,20 +110,24 @@ pub trait ByteReader: Read {
 
 impl<T: Read> ByteReader for T {}
 
-#[derive(Debug, PartialEq)]
-pub struct ZoomLevel {
-    reduction_level: u32,
-    reserved: u32,
-    data_offset: u64,
-    index_offset: u64,
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct FileOffsetSize{
     offset: usize,
     size: usize,
 }
 
+impl FileOffsetSize {
+    /// the offset of the (compressed) block in the file
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// the size of the (compressed) block in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
 pub fn find_file_offset_gap(block_list: &[FileOffsetSize]) -> (&[FileOffsetSize], &[FileOffsetSize]) {
     for (index, block) in block_list.iter().enumerate() {
         let next = index + 1;
@@ -79,10 +136,10 @@ pub fn find_file_offset_gap(block_list: &[FileOffsetSize]) -> (&[FileOffsetSize]
             return (&block_list[..next], &block_list[next..])
         }
     }
-    (&block_list[..], &[])
+    (block_list, &[])
 }
 
-fn strip_null(inp: &str) -> &str {
+pub(crate) fn strip_null(inp: &str) -> &str {
     let mut start = 0;
     for (index, byte) in inp.bytes().enumerate() {
         if start == index && byte == 0 {
@@ -99,299 +156,315 @@ fn strip_null(inp: &str) -> &str {
     &inp[start..]
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Chrom{
-    name: String,
-    id: u32,
-    size: u32,
+/// names of the standard BED columns, in order
+static BED_FIELD_NAMES: [&str; 12] = [
+    "chrom", "chromStart", "chromEnd", "name", "score", "strand",
+    "thickStart", "thickEnd", "itemRgb", "blockCount", "blockSizes", "chromStarts"
+];
+
+// does a record fall in the given query range?
+// (zero-length records at the end of the range, or in a zero-length range, count)
+fn in_range(chr: u32, s: u32, e: u32, chrom_id: u32, start: u32, end: u32) -> bool {
+    chr == chrom_id && ((s < end && e > start) || (s == e && (s == end || end == start)))
 }
 
-#[derive(Debug, PartialEq)]
-pub struct BedLine {
+// a single record, as stored in an uncompressed data block
+pub(crate) struct RawRecord<'a> {
     chrom_id: u32,
     start: u32,
     end: u32,
-    rest: Option<String>,
+    rest: &'a [u8],
 }
 
-#[derive(Debug)]
-struct BPlusTreeFile { 
+impl<'a> RawRecord<'a> {
+    pub(crate) fn in_range(&self, chrom_id: u32, start: u32, end: u32) -> bool {
+        in_range(self.chrom_id, self.start, self.end, chrom_id, start, end)
+    }
+
+    // are the extra columns valid text? (if not, to_bed_line replaces the invalid bytes)
+    pub(crate) fn is_utf8(&self) -> bool {
+        std::str::from_utf8(self.rest).is_ok()
+    }
+
+    pub(crate) fn to_bed_line(&self) -> BedLine {
+        // get the rest of the data if it is present
+        let rest = if self.rest.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(self.rest).into_owned())
+        };
+        BedLine{chrom_id: self.chrom_id, start: self.start, end: self.end, rest}
+    }
+}
+
+// iterates over the records in an uncompressed data block
+pub(crate) struct BlockRecords<'a> {
+    buff: &'a [u8],
+    index: usize,
     big_endian: bool,
-    block_size: u32,
-    key_size: usize,
-    val_size: usize,
-    item_count: u64,
-    root_offset: u64,
 }
 
-impl BPlusTreeFile {
-    fn with_reader<T: Read + Seek>(reader: &mut T) -> Result<BPlusTreeFile, Error> {
-        // check the signature first
-        let mut buff = [0; 4];
-        reader.read_exact(&mut buff)?;
-        let big_endian =
-            if buff == BPT_SIG {
-                true
-            } else if buff.iter().eq(BPT_SIG.iter().rev()) {
-                false
-            } else {
-                return Err(Error::BadSig{expected: BPT_SIG, received: buff});
-            };
+impl<'a> BlockRecords<'a> {
+    pub(crate) fn new(buff: &'a [u8], big_endian: bool) -> BlockRecords<'a> {
+        BlockRecords{buff, index: 0, big_endian}
+    }
 
-        //read all the header information
-        let block_size = reader.read_u32(big_endian);
-        let key_size = reader.read_u32(big_endian).try_into()?;
-        let val_size = reader.read_u32(big_endian).try_into()?;
-        let item_count = reader.read_u64(big_endian);
-
-        // skip over the reserved region and get the root offset
-        let root_offset = reader.seek(SeekFrom::Current(8))?;
-        Ok(BPlusTreeFile{big_endian, block_size, key_size, val_size, item_count, root_offset})
-    }
-
-    //TODO: eventually abstract the traversal function as an iterator
-    fn chrom_list<T: Read + Seek>(&self, reader: &mut T) -> Result<Vec<Chrom>, Error> {
-        // move reader to the root_offset
-        let mut chroms: Vec<Chrom> = Vec::new();
-        let mut offsets = VecDeque::new();
-        offsets.push_back(self.root_offset);
-        while let Some(offset) = offsets.pop_front() {
-            // move to the offset
-            reader.seek(SeekFrom::Start(offset))?;
-            
-            // read block header
-            let is_leaf = reader.read_u8();
-            let _reserved = reader.read_u8();
-            let child_count = reader.read_u16(self.big_endian);
-            if is_leaf != 0 {
-                let mut valbuf: Vec<u8> = vec![0; self.val_size.try_into().unwrap()];
-                for _  in 0..child_count {
-                    let mut keybuf: Vec<u8> = vec![0; self.key_size.try_into().unwrap()];
-                    //TODO: move this into the declaration of the file
-                    if self.val_size != 8 {
-                        panic!("Expected chromosome data to be 8 bytes not, {}", self.val_size)
-                    }
-                    reader.read_exact(&mut keybuf)?;
-                    reader.read_exact(&mut valbuf)?;
-                    
-                    let id = if self.big_endian {
-                        u32::from_be_bytes(valbuf[0..4].try_into().unwrap())
-                    } else {
-                        u32::from_le_bytes(valbuf[0..4].try_into().unwrap())
-                    };
-                    let size = if self.big_endian {
-                        u32::from_be_bytes(valbuf[4..8].try_into().unwrap())
-                    } else {
-                        u32::from_le_bytes(valbuf[4..8].try_into().unwrap())
-                    };
-                    let chrom = Chrom{
-                        name: String::from_utf8(keybuf).unwrap(), id, size
-                    };
-                    chroms.push(chrom);
-                }
-            } else {
-                for _ in 0..child_count {
-                    // skip over the key in each block
-                    // note that keysize is typically a few bytes, so converting into 
-                    // the i32 format should not cause a panic
-                    reader.seek(SeekFrom::Current(self.key_size.try_into()?))?;
-                    // read an offset and add it to the list to traverse
-                    let offset = reader.read_u64(self.big_endian);
-                    offsets.push_back(offset);
-                }
-            }
-        }
-        Ok(chroms)
+    fn read_u32(&mut self) -> u32 {
+        let bytes: [u8; 4] = self.buff[self.index..self.index+4].try_into().expect("Failed to convert bytes");
+        self.index += 4;
+        if self.big_endian {u32::from_be_bytes(bytes)} else {u32::from_le_bytes(bytes)}
     }
+}
 
-    // TODO: abstract this method
-    fn find<T: Read + Seek>(&self, chrom: &str, reader: &mut T) -> Result<Option<Chrom>, Error> {
-        if chrom.len() > self.key_size {
-            return Err(Error::BadKey(chrom.to_owned(), self.key_size))
+impl<'a> Iterator for BlockRecords<'a> {
+    type Item = RawRecord<'a>;
+
+    fn next(&mut self) -> Option<RawRecord<'a>> {
+        // each record has at least a chrom_id, start, and end
+        if self.index + 12 > self.buff.len() {
+            return None
         }
-        // if key is too short, we need to pad it with null character
-        if chrom.len() != (self.key_size) {
-            // prepare a new key
-            let mut padded_key = String::with_capacity(self.key_size);
-            padded_key.push_str(chrom);
+        let chrom_id = self.read_u32();
+        let start = self.read_u32();
+        let end = self.read_u32();
+        // the rest of the data runs until the next '\0' character
+        let remaining = &self.buff[self.index..];
+        let rest_length = remaining.iter().position(|byte| *byte == 0).unwrap_or(0);
+        let rest = &remaining[..rest_length];
+        // rest_length + 1 will be at the null character
+        self.index += rest_length + 1;
+        Some(RawRecord{chrom_id, start, end, rest})
+    }
+}
 
-            let needed: usize = self.key_size - chrom.len();
-            for _ in 0..needed {
-                padded_key.push('\0');
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chrom{
+    name: String,
+    id: u32,
+    size: u32,
+}
+
+impl Chrom {
+    /// the name of this chromosome (without any null padding)
+    pub fn name(&self) -> &str {
+        strip_null(&self.name)
+    }
+
+    /// the name as stored in the chromosome tree, padded with nulls to the key size
+    pub fn raw_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// compare names in natural order, so that runs of digits compare by value
+/// (chr2 sorts before chr10, unlike in the ASCII order of the chromosome tree)
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a.as_bytes(), b.as_bytes());
+    while let (Some(&a_byte), Some(&b_byte)) = (a_rest.first(), b_rest.first()) {
+        if a_byte.is_ascii_digit() && b_byte.is_ascii_digit() {
+            let a_len = a_rest.iter().take_while(|byte| byte.is_ascii_digit()).count();
+            let b_len = b_rest.iter().take_while(|byte| byte.is_ascii_digit()).count();
+            // compare the values without leading zeros: longer numbers are larger,
+            // and numbers of the same length compare like their digits
+            let a_digits: Vec<u8> = a_rest[..a_len].iter().copied().skip_while(|&byte| byte == b'0').collect();
+            let b_digits: Vec<u8> = b_rest[..b_len].iter().copied().skip_while(|&byte| byte == b'0').collect();
+            let order = a_digits.len().cmp(&b_digits.len()).then_with(|| a_digits.cmp(&b_digits));
+            if order != Ordering::Equal {
+                return order
             }
-            self._find_internal(&padded_key, reader)
+            a_rest = &a_rest[a_len..];
+            b_rest = &b_rest[b_len..];
+        } else if a_byte != b_byte {
+            return a_byte.cmp(&b_byte)
         } else {
-            self._find_internal(chrom, reader)
-        }
-    }
-
-    fn _find_internal<T: Read + Seek>(&self, chrom: &str, reader: &mut T) -> Result<Option<Chrom>, Error> {
-        let mut offsets = VecDeque::new();
-        offsets.push_back(self.root_offset);
-        while let Some(offset) = offsets.pop_front() {
-            // move to the offset
-            reader.seek(SeekFrom::Start(offset))?;
-
-            // read block header
-            let is_leaf = reader.read_u8();
-            let _reserved = reader.read_u8();
-            let child_count = reader.read_u16(self.big_endian);
-            if is_leaf != 0 {
-                let mut valbuf: Vec<u8> = vec![0; self.val_size.try_into().unwrap()];
-                for _  in 0..child_count {
-                    let mut keybuf: Vec<u8> = vec![0; self.key_size.try_into().unwrap()];
-                    reader.read(&mut keybuf)?;
-                    reader.read(&mut valbuf)?;
-                    let other_key = String::from_utf8(keybuf).unwrap();
-                    if other_key == chrom {
-                        if self.val_size != 8 {
-                            panic!("Expected chromosome data to be 8 bytes not, {}", self.val_size)
-                        }
-                        let id = if self.big_endian {
-                            u32::from_be_bytes(valbuf[0..4].try_into().unwrap())
-                        } else {
-                            u32::from_le_bytes(valbuf[0..4].try_into().unwrap())
-                        };
-                        let size = if self.big_endian {
-                            u32::from_be_bytes(valbuf[4..8].try_into().unwrap())
-                        } else {
-                            u32::from_le_bytes(valbuf[4..8].try_into().unwrap())
-                        };
-                        // return the proper data
-                        return Ok(Some(Chrom{name: other_key, id, size}))
-                    }
-                }
-            } else {
-                // skip past the first key
-                reader.seek(SeekFrom::Current(self.key_size.try_into()?))?;
-                // read the offset
-                let mut prev_offset = reader.read_u64(self.big_endian);
-                for _ in 1..child_count {
-                    let mut keybuf: Vec<u8> = vec![0; self.key_size];
-                    reader.read(&mut keybuf)?;
-                    let other_key = String::from_utf8(keybuf).unwrap();
-                    // if find a bigger key, that means we passed our good key
-                    if chrom < &other_key {
-                        break;
-                    }
-                    // otherwise: read the next offset and keep going
-                    prev_offset = reader.read_u64(self.big_endian);
+            a_rest = &a_rest[1..];
+            b_rest = &b_rest[1..];
+        }
+    }
+    // names that are otherwise equal (e.g. chr01 and chr1) fall back to plain order
+    a_rest.len().cmp(&b_rest.len()).then_with(|| a.cmp(b))
+}
+
+/// match a name against a shell-style wildcard pattern, where '*' matches any run
+/// of characters and '?' matches a single character (e.g. chr*_random)
+/// a pattern without wildcards only matches the name itself
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // the position of the last '*' in the pattern, and of the name when it was reached
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // let the last '*' take one more character
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
                 }
-                offsets.push_back(prev_offset);
+                None => return false,
             }
         }
-        Ok(None)
     }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
-#[derive(Debug)]
-struct CIRTreeFile {
-    big_endian: bool,
-    block_size: u32,
-    item_count: u64,
-    start_chrom_ix: u32,
-    start_base: u32,
-    end_chrom_ix: u32,
-    end_base: u32,
-    file_size: u64,
-    items_per_slot: u32,
-    root_offset: u64,
+/// records are ordered by (chrom_id, start, end), with ties broken by the
+/// extra columns so that the order agrees with equality
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BedLine {
+    chrom_id: u32,
+    start: u32,
+    end: u32,
+    rest: Option<String>,
+}
+
+impl BedLine {
+    pub fn chrom_id(&self) -> u32 {
+        self.chrom_id
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// any columns after chromEnd, still tab-separated
+    pub fn rest(&self) -> Option<&str> {
+        self.rest.as_deref()
+    }
+
+    /// the number of bases covered
+    pub fn len(&self) -> u32 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// whether the record covers no bases (e.g. an insertion point)
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// whether two records on the same chromosome share at least one base
+    pub fn overlaps(&self, other: &BedLine) -> bool {
+        self.chrom_id == other.chrom_id && self.start < other.end && other.start < self.end
+    }
+
+    /// whether the record covers the base at `pos`
+    pub fn contains(&self, pos: u32) -> bool {
+        self.start <= pos && pos < self.end
+    }
 }
 
-fn cir_overlaps(q_chrom: u32, q_start: u32, q_end: u32, 
-                start_chrom: u32, start_base: u32, 
-                end_chrom: u32, end_base: u32) -> bool {
-    (q_chrom, q_start) < (end_chrom, end_base) 
-    && (q_chrom, q_end) > (start_chrom, start_base)
+/// a BedLine along with the name of its chromosome
+/// the name is shared between all records from the same chromosome
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedBedLine {
+    pub chrom: Arc<str>,
+    pub line: BedLine,
 }
 
-impl CIRTreeFile {
-    fn with_reader<T: Read + Seek>(reader: &mut T) -> Result<CIRTreeFile, Error> {
-        // check the signature first
-        let mut buff = [0; 4];
-        reader.read_exact(&mut buff)?;
-        let big_endian =
-            if buff == CIRTREE_SIG {
-                true
-            } else if buff.iter().eq(CIRTREE_SIG.iter().rev()) {
-                false
-            } else {
-                return Err(Error::BadSig{expected: CIRTREE_SIG, received: buff});
-            };
+impl fmt::Display for NamedBedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", BedFormatter::default().format(&self.chrom, &self.line))
+    }
+}
 
-        //read all the header information
-        let block_size = reader.read_u32(big_endian);
-        let item_count = reader.read_u64(big_endian);
-        let start_chrom_ix = reader.read_u32(big_endian);
-        let start_base = reader.read_u32(big_endian);
-        let end_chrom_ix = reader.read_u32(big_endian);
-        let end_base = reader.read_u32(big_endian);
-        let file_size = reader.read_u64(big_endian);
-        let items_per_slot = reader.read_u32(big_endian);
-
-        // skip over the reserved region and get the root offset
-        let root_offset = reader.seek(SeekFrom::Current(4))?;
-
-        Ok(CIRTreeFile{
-            big_endian,
-            block_size,
-            item_count,
-            start_chrom_ix,
-            start_base,
-            end_chrom_ix,
-            end_base,
-            file_size,
-            items_per_slot,
-            root_offset,
-        })
+// the names to try for a chromosome, in order: the name itself, the name without
+// a 'chr' prefix (in any case), and the name with each common form of the prefix
+fn chrom_aliases(chrom: &str) -> Vec<String> {
+    let base = match chrom.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("chr") => &chrom[3..],
+        _ => chrom,
+    };
+    let mut aliases = vec![chrom.to_owned()];
+    if base.is_empty() {
+        return aliases
+    }
+    for alias in [base.to_owned(), format!("chr{}", base), format!("Chr{}", base), format!("CHR{}", base)].iter() {
+        if !aliases.contains(alias) {
+            aliases.push(alias.clone());
+        }
     }
+    aliases
+}
 
-    fn find_blocks<T: Read + Seek>(&self, chrom_id: u32, start: u32, end: u32, reader: &mut T) -> Result<Vec<FileOffsetSize>, Error> {
-        let mut blocks = Vec::<FileOffsetSize>::new();
-        let mut offsets = VecDeque::new();
-        offsets.push_back(self.root_offset);
-        while let Some(offset) = offsets.pop_front() {
-            // move to the offset
-            reader.seek(SeekFrom::Start(offset))?;
-            
-            // read block header
-            let is_leaf = reader.read_u8();
-            let _reserved = reader.read_u8();
-            let child_count = reader.read_u16(self.big_endian);
-
-            if is_leaf != 0 {
-                for _  in 0..child_count {
-                    let start_chrom = reader.read_u32(self.big_endian);
-                    let start_base = reader.read_u32(self.big_endian);
-                    let end_chrom = reader.read_u32(self.big_endian);
-                    let end_base = reader.read_u32(self.big_endian);
-                    let offset = reader.read_u64(self.big_endian).try_into()?;
-                    let size = reader.read_u64(self.big_endian).try_into()?;
-                    //eprint!("chrom_id {}; start {}; end {}; start_chrom {}; start_base {}; end_chrom {}; end_base {};",
-                    //          chrom_id, start, end, start_chrom, start_base, end_chrom, end_base);
-                    if cir_overlaps(chrom_id, start, end, start_chrom, start_base, end_chrom, end_base) {
-                        blocks.push(FileOffsetSize{offset, size})
-                    }
-                }
-            } else {
-                for _ in 0..child_count {
-                    // load the data in the Node
-                    let start_chrom = reader.read_u32(self.big_endian);
-                    let start_base = reader.read_u32(self.big_endian);
-                    let end_chrom = reader.read_u32(self.big_endian);
-                    let end_base = reader.read_u32(self.big_endian);
-                    let offset = reader.read_u64(self.big_endian);
-
-                    // if we have overlaps in this area, then we should explore the node
-                    //eprint!("chrom_id {}; start {}; end {}; start_chrom {}; start_base {}; end_chrom {}; end_base {};",
-                    //         chrom_id, start, end, start_chrom, start_base, end_chrom, end_base);
-                    if cir_overlaps(chrom_id, start, end, start_chrom, start_base, end_chrom, end_base) {
-                        offsets.push_back(offset);
-                    }
-                }
-            }
+// decode an item of the chromosome tree
+fn chrom_from_item(key: Vec<u8>, val: &[u8], big_endian: bool) -> Result<Chrom, Error> {
+    if val.len() != 8 {
+        return Err(Error::Misc("Expected chromosome data to be 8 bytes"))
+    }
+    let id = if big_endian {
+        u32::from_be_bytes(val[0..4].try_into().unwrap())
+    } else {
+        u32::from_le_bytes(val[0..4].try_into().unwrap())
+    };
+    let size = if big_endian {
+        u32::from_be_bytes(val[4..8].try_into().unwrap())
+    } else {
+        u32::from_le_bytes(val[4..8].try_into().unwrap())
+    };
+    let name = match String::from_utf8(key) {
+        Ok(name) => name,
+        Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    };
+    Ok(Chrom{name, id, size})
+}
+
+// decompresses data blocks, reusing its buffer
+pub(crate) struct Inflater {
+    decompressor: Decompress,
+    buffer: Vec<u8>,
+}
+
+impl Inflater {
+    pub(crate) fn new(buffer_size: usize) -> Inflater {
+        Inflater{decompressor: Decompress::new(true), buffer: vec![0u8; buffer_size]}
+    }
+
+    pub(crate) fn inflate(&mut self, compressed: &[u8]) -> Result<&[u8], Error> {
+        let status = self.decompressor.decompress(compressed, &mut self.buffer, FlushDecompress::Finish)?;
+        match status {
+            flate2::Status::Ok | flate2::Status::StreamEnd => {}
+            // BufError: the block did not fit in the buffer (or was cut short)
+            _ => return Err(Error::DecompressError(format!("stopped with status {:?}", status))),
         }
-        Ok(blocks)
+        let uncompressed_size = self.decompressor.total_out() as usize;
+        self.decompressor.reset(true);
+        Ok(&self.buffer[..uncompressed_size])
+    }
+}
+
+/// an iterator over the chromosomes of a BigBed file
+pub struct Chroms<'a, T: Read + Seek> {
+    items: BPlusTreeItems<'a, T>,
+    big_endian: bool,
+}
+
+impl<'a, T: Read + Seek> Iterator for Chroms<'a, T> {
+    type Item = Result<Chrom, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let big_endian = self.big_endian;
+        self.items.next().map(|item| item.and_then(|(key, val)| chrom_from_item(key, &val, big_endian)))
     }
 }
 
@@ -414,59 +487,97 @@ pub struct BigBed<T: Read + Seek>  {
     pub extension_size: Option<u16>,
     pub extra_index_count: Option<u16>,
     pub extra_index_list_offset: Option<u64>,
-    chrom_bpt: BPlusTreeFile,
+    /// the rest of the extension header after the fields above (reserved in current
+    /// versions of the format), kept as raw bytes so that they can be written back out
+    pub extension_reserved: Vec<u8>,
+    /// any bytes of a larger extension header than current versions of the format use
+    /// (from a newer version, which this crate skips)
+    pub extension_unknown: Vec<u8>,
+    chrom_bpt: BPlusTree,
     unzoomed_cir: Option<CIRTreeFile>,
+    metrics: Option<Arc<Metrics>>,
+    warnings: Vec<Warning>,
+    // the chromosomes, when known without reading the chromosome tree (see import_metadata)
+    chrom_cache: Option<Vec<Chrom>>,
+}
+
+/// a BigBed read from memory (see BigBed::from_bytes)
+pub type MemoryBigBed = BigBed<Cursor<Vec<u8>>>;
+
+/// a BigBed read from borrowed bytes (see BigBed::from_slice)
+pub type SliceBigBed<'a> = BigBed<Cursor<&'a [u8]>>;
+
+impl BigBed<Cursor<Vec<u8>>> {
+    /// read a BigBed from bytes in memory (e.g. in tests, or where there is no filesystem)
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<MemoryBigBed, Error> {
+        BigBed::from_file(Cursor::new(bytes))
+    }
+
+    /// read a BigBed that has been gzip-compressed as a whole (e.g. by a web server),
+    /// decompressing it into memory
+    pub fn from_gzip<R: Read>(reader: R) -> Result<MemoryBigBed, Error> {
+        let mut bytes = Vec::new();
+        MultiGzDecoder::new(reader).read_to_end(&mut bytes)?;
+        BigBed::from_bytes(bytes)
+    }
+}
+
+impl<'a> BigBed<Cursor<&'a [u8]>> {
+    /// read a BigBed from borrowed bytes, without copying them
+    pub fn from_slice(bytes: &'a [u8]) -> Result<SliceBigBed<'a>, Error> {
+        BigBed::from_file(Cursor::new(bytes))
+    }
 }
 
 impl<T: Read + Seek> BigBed<T> {
-    pub fn from_file(mut reader: T) -> Result<BigBed<T>, Error> {
-        let mut buff = [0; 4];
-        reader.read_exact(&mut buff)?;
-        let big_endian =
-            if buff == BIGBED_SIG {
-                true
-            } else if buff.iter().eq(BIGBED_SIG.iter().rev()) {
-                false
-            } else {
-                return Err(Error::BadSig{expected: BIGBED_SIG, received: buff});
-            };
-        let version = reader.read_u16(big_endian);
-        let zoom_levels = reader.read_u16(big_endian);
-        let chrom_tree_offset = reader.read_u64(big_endian);
-        let unzoomed_data_offset = reader.read_u64(big_endian);
-        let unzoomed_index_offset = reader.read_u64(big_endian);
-        let field_count = reader.read_u16(big_endian);
-        let defined_field_count = reader.read_u16(big_endian);
-        let as_offset = reader.read_u64(big_endian);
-        let total_summary_offset = reader.read_u64(big_endian);
-        let uncompress_buf_size = reader.read_u32(big_endian).try_into()?;
-        let extension_offset = reader.read_u64(big_endian);
-
-        let mut level_list: Vec<ZoomLevel> = Vec::with_capacity(usize::from(zoom_levels));
-        for _ in 0..usize::from(zoom_levels) {
-            level_list.push(ZoomLevel{
-                reduction_level: reader.read_u32(big_endian),
-                reserved: reader.read_u32(big_endian),
-                data_offset: reader.read_u64(big_endian),
-                index_offset: reader.read_u64(big_endian)
-            })
-        }
+    pub fn from_file(reader: T) -> Result<BigBed<T>, Error> {
+        let mut bigbed = BigBed::read_header(reader)?;
+        bigbed.read_chrom_tree()?;
+        Ok(bigbed)
+    }
+
+    // read the header (and extension header) only; until read_chrom_tree is called,
+    // the file appears to have no chromosomes
+    pub(crate) fn read_header(mut reader: T) -> Result<BigBed<T>, Error> {
+        let BbiHeader{
+            big_endian, version, zoom_levels, chrom_tree_offset, unzoomed_data_offset,
+            unzoomed_index_offset, field_count, defined_field_count, as_offset,
+            total_summary_offset, uncompress_buf_size, extension_offset, level_list, ..
+        } = BbiHeader::read(&mut reader, BIGBED_SIG)?;
+        let uncompress_buf_size = uncompress_buf_size.try_into()?;
 
         let mut extension_size = None;
         let mut extra_index_count = None;
         let mut extra_index_list_offset = None;
+        let mut extension_reserved = Vec::new();
+        let mut extension_unknown = Vec::new();
+        let mut warnings = Vec::new();
 
         if extension_offset != 0 {
             // move to extension
             reader.seek(SeekFrom::Start(extension_offset))?;
-            extension_size = Some(reader.read_u16(big_endian));
-            extra_index_count = Some(reader.read_u16(big_endian));
-            extra_index_list_offset = Some(reader.read_u64(big_endian));
+            let size = reader.read_u16(big_endian);
+            extension_size = Some(size);
+            // only read the fields that fit in the extension
+            let size = usize::from(size);
+            if size >= 4 {
+                extra_index_count = Some(reader.read_u16(big_endian));
+            }
+            if size >= EXTENSION_FIELDS_SIZE {
+                extra_index_list_offset = Some(reader.read_u64(big_endian));
+            }
+            // keep whatever follows the fields we know about
+            extension_reserved = vec![0; size.min(EXTENSION_HEADER_SIZE).saturating_sub(EXTENSION_FIELDS_SIZE)];
+            reader.read_exact(&mut extension_reserved)?;
+            if size > EXTENSION_HEADER_SIZE {
+                extension_unknown = vec![0; size - EXTENSION_HEADER_SIZE];
+                reader.read_exact(&mut extension_unknown)?;
+                warnings.push(larger_extension_warning(size));
+            }
         }
 
-        //move to the B+ tree file region
-        reader.seek(SeekFrom::Start(chrom_tree_offset))?;
-        let chrom_bpt = BPlusTreeFile::with_reader(&mut reader)?;
+        // a placeholder for the chromosome tree, with no keys
+        let chrom_bpt = BPlusTree{big_endian, block_size: 0, key_size: 0, val_size: 0, item_count: 0, root_offset: chrom_tree_offset};
 
         Ok(BigBed{
             reader, big_endian, version, zoom_levels, chrom_tree_offset, 
@@ -474,10 +585,50 @@ impl<T: Read + Seek> BigBed<T> {
             defined_field_count, as_offset, total_summary_offset, 
             uncompress_buf_size, extension_offset, level_list,
             extension_size, extra_index_count, extra_index_list_offset,
-            chrom_bpt, unzoomed_cir: None,
+            extension_reserved, extension_unknown, chrom_bpt, unzoomed_cir: None, metrics: None, warnings,
+            chrom_cache: Some(Vec::new()),
         })
     }
+
+    // read the header of the chromosome tree (after read_header)
+    pub(crate) fn read_chrom_tree(&mut self) -> Result<(), Error> {
+        //move to the B+ tree file region
+        self.reader.seek(SeekFrom::Start(self.chrom_tree_offset))?;
+        self.chrom_bpt = BPlusTree::with_reader(&mut self.reader)?;
+        self.chrom_cache = None;
+        Ok(())
+    }
     
+    /// the fixed header of the file (shared with BigWig files)
+    pub fn header(&self) -> BbiHeader {
+        BbiHeader{
+            magic: BIGBED_SIG, big_endian: self.big_endian, version: self.version,
+            zoom_levels: self.zoom_levels, chrom_tree_offset: self.chrom_tree_offset,
+            unzoomed_data_offset: self.unzoomed_data_offset, unzoomed_index_offset: self.unzoomed_index_offset,
+            field_count: self.field_count, defined_field_count: self.defined_field_count,
+            as_offset: self.as_offset, total_summary_offset: self.total_summary_offset,
+            uncompress_buf_size: self.uncompress_buf_size as u32, extension_offset: self.extension_offset,
+            level_list: self.level_list.clone(),
+        }
+    }
+
+    /// problems found while reading the file that did not stop it from being read,
+    /// including data-quality problems with the records found by queries
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// remove and return the warnings so far (e.g. to report them after each query)
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // record warnings, up to MAX_WARNINGS at a time
+    pub(crate) fn add_warnings(&mut self, warnings: Vec<Warning>) {
+        let room = MAX_WARNINGS.saturating_sub(self.warnings.len());
+        self.warnings.extend(warnings.into_iter().take(room));
+    }
+
     pub fn attach_unzoomed_cir(&mut self) -> Result<(), Error>{
         if self.unzoomed_cir.is_none() {
             // if not, seek to where the reader should be
@@ -497,40 +648,34 @@ impl<T: Read + Seek> BigBed<T> {
         self.attach_unzoomed_cir()?;
         // this operation is guaranteed to work now
         let index = self.unzoomed_cir.as_ref().unwrap();
-        Ok(index.find_blocks(chrom_id, start, end, &mut self.reader)?)
+        index.find_blocks(chrom_id, start, end, &mut self.reader)
     }
  
-    pub fn query(&mut self, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedLine>, Error> {
-        let mut lines: Vec<BedLine> = Vec::new();
-        let mut item_count: u32 = 0;
-
-        let chrom_id: Option<u32>;
-        // search for the chrom_id
-        if let Some(chrom_data) = self.find_chrom(chrom)? {
-            chrom_id = Some(chrom_data.id);
-        // search for chrom_id without the 'chr'
-        } else if let Some(chrom_data) = self.find_chrom(&chrom[3..])? {
-            chrom_id = Some(chrom_data.id);
-        } else {
-            return Err(BadChrom(chrom.to_owned()));
-        }
-        // this operation is safe, otherwise the return above will be invoked
-        let chrom_id = chrom_id.unwrap();
-        // from kent:
-        // "Find blocks with padded start and end to make sure we include zero-length insertions"
-        let padded_start = if start > 0 {start - 1} else {start};
-        let padded_end = end + 1;
-        let blocks = self.overlapping_blocks(chrom_id, padded_start, padded_end)?;
-        
-        let mut decompressor = None;
-        let mut decom_buff = None;
-        if self.uncompress_buf_size > 0 {
-            decompressor = Some(Decompress::new(true));
-            decom_buff = Some(vec![0u8; self.uncompress_buf_size]);
+    /// find the chromosome to use for a query, trying the name with and without a 'chr'
+    /// prefix (in any case) if the name itself is not in the file, so that '1', 'chr1'
+    /// and 'Chr1' all find whichever of them the file uses
+    pub fn resolve_chrom(&mut self, chrom: &str) -> Result<Chrom, Error> {
+        let mut error = BadChrom(chrom.to_owned());
+        for (index, alias) in chrom_aliases(chrom).iter().enumerate() {
+            match self.find_chrom(alias) {
+                Ok(Some(chrom_data)) => return Ok(chrom_data),
+                Ok(None) => {}
+                // an alias can be too long for the file when the name itself is not
+                Err(err @ BadKey(_, _)) => if index == 0 {
+                    error = err;
+                },
+                Err(err) => return Err(err),
+            }
         }
+        Err(error)
+    }
 
-        let mut remaining = &blocks[..];
-        while remaining.len() > 0 {
+    // read the given blocks (merging reads of contiguous blocks) and hand each
+    // block, still compressed, to `visit`, stopping early if it returns false
+    pub(crate) fn read_raw_blocks<F>(&mut self, blocks: &[FileOffsetSize], mut visit: F) -> Result<(), Error>
+        where F: FnMut(&FileOffsetSize, &[u8]) -> Result<bool, Error> {
+        let mut remaining = blocks;
+        while !remaining.is_empty() {
             // iterate through the list of blocks, get a slice of contiguous blocks
             let split = find_file_offset_gap(remaining);
             let before_gap = split.0;
@@ -543,104 +688,84 @@ impl<T: Read + Seek> BigBed<T> {
             // (otherwise the loop would terminate)
             let merged_size = before_gap.last().unwrap().offset + before_gap.last().unwrap().size - merged_offset;
             // read in all the contigious blocks
-            let mut merged_buff: Vec<u8> = vec![0; merged_size as usize];
+            let mut merged_buff: Vec<u8> = vec![0; merged_size];
             self.reader.seek(SeekFrom::Start(merged_offset.try_into()?))?;
             self.reader.read_exact(&mut merged_buff)?;
-            
-            
+
             // for each block in the merged group
             for block in before_gap {
-                let mut index: usize = 0;
                 let block_start = block.offset - merged_offset;
-                let mut block_end = block_start + block.size;
-                let mut buff = &merged_buff[block_start..block_end];
-                if self.uncompress_buf_size > 0 {
-                    let debuff =  decom_buff.as_mut().unwrap();
-                    let decomp =  decompressor.as_mut().unwrap();
-                    let status = decomp.decompress(&buff, debuff, FlushDecompress::Finish)?;
-                    match status {
-                        flate2::Status::Ok | flate2::Status::StreamEnd => {}
-                        _ => {
-                            eprintln!("{:?}", status);
-                            return Err(Error::Misc("Decompression error!"));
-                        }
-                    }
-                    block_end = decomp.total_out() as usize;
-                    decomp.reset(true);
-                    buff = &*debuff;
-                }
-                // iterate over the individual bytes in this block
-                while index < block_end {
-                    // read in chrom_id
-                    let bytes: [u8; 4] = buff[index..index+4].try_into().expect("Failed to convert bytes");
-                    let chr = if self.big_endian {u32::from_be_bytes(bytes)} else {u32::from_le_bytes(bytes)};
-                    index += 4;
-                    // read in start
-                    let bytes: [u8; 4] = buff[index..index+4].try_into().expect("Failed to convert bytes");
-                    let s = if self.big_endian {u32::from_be_bytes(bytes)} else {u32::from_le_bytes(bytes)};
-                    index += 4;
-                    // read in end
-                    let bytes: [u8; 4] = buff[index..index+4].try_into().expect("Failed to convert bytes");
-                    let e = if self.big_endian {u32::from_be_bytes(bytes)} else {u32::from_le_bytes(bytes)};
-                    index += 4;
-
-                    // calculate how much data is left (if any)
-                    // find the next '\0' character
-                    let mut rest_length = 0;
-                    for (index, byte) in buff[index..block_end].iter().enumerate() {
-                        if byte == &0 {
-                            rest_length = index;
-                            break;
-                        }
-                    }
-                    // check if this data is in the correct range
-                    if chr == chrom_id && ( (s < end && e > start) || (s == e && (s == end || end == start) )) {
-                        item_count += 1;
-                        if max_items > 0 && item_count > max_items {
-                            break;
-                        }
-                        // get the rest of the data if it is present
-                        let rest = if rest_length > 0 {
-                            Some(String::from_utf8(buff[index..rest_length+index].to_vec()).expect("FUCK"))
-                        } else {
-                            None
-                        };
-                        // add the BedLine to the list
-                        lines.push(BedLine{
-                            chrom_id: chr,
-                            start: s,
-                            end: e,
-                            rest
-                        });
-                    }
-                    // rest_length + 1 will be at the null character
-                    index += rest_length + 1;
-                }
-                // propagate the break statement
-                if max_items > 0 && item_count > max_items {
-                    break;
+                let block_end = block_start + block.size;
+                if !visit(block, &merged_buff[block_start..block_end])? {
+                    return Ok(())
                 }
             }
-            if max_items > 0 && item_count > max_items {
-                break;
-            }
         }
-        Ok(lines)
+        Ok(())
     }
 
-    pub fn write_bed(&mut self, chrom: Option<&str>, start: Option<u32>, end: Option<u32>, max_items: Option<u32>, mut output: impl Write) -> Result<(), Error> {
-        let item_count = 0;
-        for chrom_data in self.chrom_list()? {
-            //TODO: check for null characters
-            if let Some(name) = chrom {
-                if name != strip_null(&chrom_data.name) {
-                    continue
-                }
-            }
-            let start = match start {
-                None => 0,
-                Some(value) => value,
+    // read the given blocks (merging reads of contiguous blocks) and hand each
+    // uncompressed block to `visit`, stopping early if it returns false
+    pub(crate) fn read_blocks<F>(&mut self, blocks: &[FileOffsetSize], mut visit: F) -> Result<(), Error>
+        where F: FnMut(&FileOffsetSize, &[u8]) -> bool {
+        let mut inflater = None;
+        if self.uncompress_buf_size > 0 {
+            inflater = Some(Inflater::new(self.uncompress_buf_size));
+        }
+        let decompressed = if inflater.is_some() { 1 } else { 0 };
+        let metrics = self.metrics.clone();
+        self.read_raw_blocks(blocks, |block, buff| {
+            let buff = match inflater.as_mut() {
+                Some(inflater) => inflater.inflate(buff)?,
+                None => buff,
             };
+            if let Some(metrics) = &metrics {
+                metrics.add_blocks(1, decompressed);
+            }
+            Ok(visit(block, buff))
+        })
+    }
+
+    /// find the records on a chromosome overlapping start to end, returning at most
+    /// `max_items` of them (0 for no limit); see query_with for more options
+    pub fn query(&mut self, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedLine>, Error> {
+        self.query_with(chrom, start..end, &QueryOptions::new().max_items(max_items))
+    }
+
+    /// like query, but each record carries the name of its chromosome
+    pub fn query_named(&mut self, chrom: &str, start: u32, end: u32) -> Result<Vec<NamedBedLine>, Error> {
+        let chrom_data = self.resolve_chrom(chrom)?;
+        let name: Arc<str> = Arc::from(chrom_data.name());
+        let lines = self.query(&chrom_data.name, start, end, 0)?;
+        Ok(lines.into_iter().map(|line| NamedBedLine{chrom: Arc::clone(&name), line}).collect())
+    }
+
+    /// write the records as BED text, only from the chromosomes matching `chrom` if it is
+    /// given (a name, or a wildcard pattern like chr*_random, see glob_match)
+    /// the chromosomes are written in name order, and the records of each in order of their
+    /// starts, as stored (see verify_sorted to check a file is sorted)
+    pub fn write_bed(&mut self, chrom: Option<&str>, start: Option<u32>, end: Option<u32>, max_items: Option<u32>, output: impl Write) -> Result<(), Error> {
+        self.write_bed_with(chrom, start, end, max_items, &BedFormatter::default(), output)
+    }
+
+    /// like write_bed, but using the provided formatter to produce each line
+    pub fn write_bed_with(&mut self, chrom: Option<&str>, start: Option<u32>, end: Option<u32>, max_items: Option<u32>, formatter: &BedFormatter, output: impl Write) -> Result<(), Error> {
+        let chroms: Vec<&str> = chrom.into_iter().collect();
+        self.write_bed_chroms(&chroms, start, end, max_items, formatter, output)
+    }
+
+    /// like write_bed_with, but from the chromosomes matching any of `chroms`
+    /// (names or wildcard patterns; all chromosomes if it is empty), in name order,
+    /// with at most `max_items` records from each chromosome
+    pub fn write_bed_chroms<S: AsRef<str>>(&mut self, chroms: &[S], start: Option<u32>, end: Option<u32>, max_items: Option<u32>, formatter: &BedFormatter, mut output: impl Write) -> Result<(), Error> {
+        let item_count = 0;
+        let chroms = if chroms.is_empty() {
+            self.chrom_list()?
+        } else {
+            self.chroms_matching_any(chroms)?
+        };
+        for chrom_data in chroms {
+            let start = start.unwrap_or_default();
             let end = match end {
                 None => chrom_data.size,
                 Some(value) => value,
@@ -650,21 +775,40 @@ impl<T: Read + Seek> BigBed<T> {
             if let Some(max_value) = max_items {
                 items_left = max_value - item_count;
                 // stop iteration if we have exceeded the limit
-                if items_left <= 0 {
+                if items_left == 0 {
                     break;
                 }
             }
 
-            let name_to_print = strip_null(&chrom_data.name);
             let interval_list = self.query(&chrom_data.name, start, end, items_left)?;
-            for bed_line in interval_list.into_iter() {
-                match bed_line.rest {
-                    None => {
-                        output.write(format!("{}\t{}\t{}\n", name_to_print, bed_line.start, bed_line.end).as_bytes())?;
-                    } Some(data) => {
-                        output.write(format!("{}\t{}\t{}\t{}\n", name_to_print, bed_line.start, bed_line.end, data).as_bytes())?;
-                    }
-                }
+            for bed_line in interval_list.iter() {
+                formatter.write(&mut output, &chrom_data.name, bed_line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// like write_bed_with, but from each of `regions` in turn (in the order given, so
+    /// overlapping regions write their shared records twice), with at most `max_items` records
+    /// from each region
+    /// a region's chromosome is resolved like a query's, and regions on chromosomes that are not
+    /// in the file are skipped
+    pub fn write_bed_regions(&mut self, regions: &[GenomicRange], max_items: Option<u32>, formatter: &BedFormatter, mut output: impl Write) -> Result<(), Error> {
+        for region in regions {
+            let chrom_data = match self.resolve_chrom(&region.chrom) {
+                Ok(chrom_data) => chrom_data,
+                Err(BadChrom(_)) | Err(BadKey(..)) => continue,
+                Err(err) => return Err(err),
+            };
+            // as in write_bed_chroms, a limit of 0 writes nothing
+            let items_left = match max_items {
+                Some(0) => break,
+                Some(max_value) => max_value,
+                None => 0,
+            };
+            let interval_list = self.query(&chrom_data.name, region.start, region.end, items_left)?;
+            for bed_line in interval_list.iter() {
+                formatter.write(&mut output, &chrom_data.name, bed_line)?;
             }
         }
         Ok(())
@@ -672,20 +816,23 @@ impl<T: Read + Seek> BigBed<T> {
 
     
     pub fn to_string(&mut self, chrom: Option<&str>, start: Option<u32>, end: Option<u32>, max_items: Option<u32>) -> Result<Vec<String>, Error> {
+        let chroms: Vec<&str> = chrom.into_iter().collect();
+        self.to_strings(&chroms, start, end, max_items)
+    }
+
+    /// like to_string, but from the chromosomes matching any of `chroms`
+    /// (names or wildcard patterns; all chromosomes if it is empty), in name order
+    pub fn to_strings<S: AsRef<str>>(&mut self, chroms: &[S], start: Option<u32>, end: Option<u32>, max_items: Option<u32>) -> Result<Vec<String>, Error> {
         //TODO: use the unzoomed circle to get an item count here
         let mut output: Vec<String> = Vec::new();
         let item_count = 0;
-        for chrom_data in self.chrom_list()? {
-            //TODO: check for null characters
-            if let Some(name) = chrom {
-                if name != strip_null(&chrom_data.name) {
-                    continue
-                }
-            }
-            let start = match start {
-                None => 0,
-                Some(value) => value,
-            };
+        let chroms = if chroms.is_empty() {
+            self.chrom_list()?
+        } else {
+            self.chroms_matching_any(chroms)?
+        };
+        for chrom_data in chroms {
+            let start = start.unwrap_or_default();
             let end = match end {
                 None => chrom_data.size,
                 Some(value) => value,
@@ -695,33 +842,184 @@ impl<T: Read + Seek> BigBed<T> {
             if let Some(max_value) = max_items {
                 items_left = max_value - item_count;
                 // stop iteration if we have exceeded the limit
-                if items_left <= 0 {
+                if items_left == 0 {
                     break;
                 }
             }
 
             let name_to_print = strip_null(&chrom_data.name);
             let interval_list = self.query(&chrom_data.name, start, end, items_left)?;
-            for bed_line in interval_list.into_iter() {
-                match bed_line.rest {
-                    None => {
-                        output.push(format!("{}\t{}\t{}\n", name_to_print, bed_line.start, bed_line.end));
-                    } Some(data) => {
-                        output.push(format!("{}\t{}\t{}\t{}\n", name_to_print, bed_line.start, bed_line.end, data));
-                    }
-                }
+            let formatter = BedFormatter::default();
+            for bed_line in interval_list.iter() {
+                let mut line = formatter.format(name_to_print, bed_line);
+                line.push('\n');
+                output.push(line);
             }
         }
         Ok(output)
     } 
 
     pub fn chrom_list(&mut self) -> Result<Vec<Chrom>, Error> {
-        self.chrom_bpt.chrom_list(&mut self.reader)
+        if let Some(chroms) = &self.chrom_cache {
+            return Ok(chroms.clone())
+        }
+        self.chroms().collect()
+    }
+
+    /// the chromosomes whose names match a wildcard pattern (see glob_match), in name order
+    /// a pattern without wildcards is looked up like a query's chromosome (see resolve_chrom),
+    /// so '1' matches 'chr1' in a file that uses that name
+    pub fn chroms_matching(&mut self, pattern: &str) -> Result<Vec<Chrom>, Error> {
+        if !pattern.contains(['*', '?']) {
+            return match self.resolve_chrom(pattern) {
+                Ok(chrom) => Ok(vec![chrom]),
+                Err(BadChrom(_)) | Err(BadKey(_, _)) => Ok(Vec::new()),
+                Err(err) => Err(err),
+            }
+        }
+        let mut chroms = self.chrom_list()?;
+        chroms.retain(|chrom| glob_match(pattern, chrom.name()));
+        Ok(chroms)
+    }
+
+    /// the chromosomes matching any of several names or patterns (see chroms_matching),
+    /// each listed once, in name order
+    pub fn chroms_matching_any<S: AsRef<str>>(&mut self, patterns: &[S]) -> Result<Vec<Chrom>, Error> {
+        let mut ids = std::collections::HashSet::new();
+        for pattern in patterns {
+            ids.extend(self.chroms_matching(pattern.as_ref())?.iter().map(|chrom| chrom.id));
+        }
+        let mut chroms = self.chrom_list()?;
+        chroms.retain(|chrom| ids.contains(&chrom.id));
+        Ok(chroms)
+    }
+
+    /// like chrom_list, but in natural order (chr1, chr2, ..., chr10) rather than name order
+    pub fn chrom_list_natural(&mut self) -> Result<Vec<Chrom>, Error> {
+        let mut chroms = self.chrom_list()?;
+        chroms.sort_by(|a, b| natural_cmp(a.name(), b.name()));
+        Ok(chroms)
+    }
+
+    /// iterate over the chromosomes in the file (in name order),
+    /// reading the chromosome tree only as far as needed
+    pub fn chroms(&mut self) -> Chroms<'_, T> {
+        let big_endian = self.chrom_bpt.big_endian;
+        Chroms{items: self.chrom_bpt.items(&mut self.reader), big_endian}
     }
 
     pub fn find_chrom(&mut self, chrom: &str) -> Result<Option<Chrom>, Error> {
-        self.chrom_bpt.find(chrom, &mut self.reader)
+        if let Some(chroms) = &self.chrom_cache {
+            if chrom.len() > self.chrom_bpt.key_size {
+                return Err(BadKey(chrom.to_owned(), self.chrom_bpt.key_size))
+            }
+            return Ok(chroms.iter().find(|cached| cached.name() == chrom).cloned())
+        }
+        let big_endian = self.chrom_bpt.big_endian;
+        match self.chrom_bpt.find(chrom.as_bytes(), &mut self.reader)? {
+            Some(val) => {
+                // the stored key is the name padded with nulls
+                let mut key = chrom.as_bytes().to_vec();
+                key.resize(self.chrom_bpt.key_size, 0);
+                Ok(Some(chrom_from_item(key, &val, big_endian)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// look up many chromosomes at once, reading each node of the chromosome tree at most once
+    /// (e.g. to check that every chromosome in a BED file is in this one)
+    /// the results are in the same order as `chroms`; names too long to be in the file are not found
+    pub fn find_chroms(&mut self, chroms: &[&str]) -> Result<Vec<Option<Chrom>>, Error> {
+        if let Some(cached) = &self.chrom_cache {
+            return Ok(chroms.iter().map(|chrom| cached.iter().find(|cached| cached.name() == *chrom).cloned()).collect())
+        }
+        let big_endian = self.chrom_bpt.big_endian;
+        let keys: Vec<&[u8]> = chroms.iter().map(|chrom| chrom.as_bytes()).collect();
+        let values = self.chrom_bpt.find_many(&keys, &mut self.reader)?;
+        chroms.iter().zip(values).map(|(chrom, value)| match value {
+            Some(val) => {
+                // the stored key is the name padded with nulls
+                let mut key = chrom.as_bytes().to_vec();
+                key.resize(self.chrom_bpt.key_size, 0);
+                chrom_from_item(key, &val, big_endian).map(Some)
+            }
+            None => Ok(None),
+        }).collect()
+    }
+
+    /// the canonical BED type of this file (e.g. "bed3", "bed6+4")
+    /// this matches the `-type` argument bedToBigBed expects
+    pub fn bed_type(&self) -> String {
+        if self.field_count > self.defined_field_count {
+            format!("bed{}+{}", self.defined_field_count, self.field_count - self.defined_field_count)
+        } else {
+            format!("bed{}", self.defined_field_count)
+        }
     }
+
+    /// the ordered names of every column in this file
+    /// names are taken from the autoSql definition if present,
+    /// otherwise standard BED names are used (with custom fields named "field{N}")
+    pub fn field_names(&mut self) -> Result<Vec<String>, Error> {
+        if let Some(schema) = self.schema()? {
+            if schema.fields.len() == usize::from(self.field_count) {
+                return Ok(schema.fields.into_iter().map(|field| field.name).collect())
+            }
+        }
+        let mut names = Vec::with_capacity(usize::from(self.field_count));
+        for index in 0..usize::from(self.field_count) {
+            match BED_FIELD_NAMES.get(index) {
+                Some(name) if index < usize::from(self.defined_field_count) => {
+                    names.push((*name).to_owned());
+                }
+                _ => names.push(format!("field{}", index + 1)),
+            }
+        }
+        Ok(names)
+    }
+
+    /// the columns (counting from 0) declared as float or double in the autoSql definition
+    /// (empty if the file has no usable definition)
+    pub fn float_columns(&mut self) -> Result<Vec<usize>, Error> {
+        let fields = match self.schema()? {
+            Some(schema) => schema.fields,
+            None => return Ok(Vec::new()),
+        };
+        if fields.len() != usize::from(self.field_count) {
+            return Ok(Vec::new())
+        }
+        Ok(fields.iter().enumerate()
+            .filter(|(_, field)| field.array.is_none() && field.field_type.is_float())
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    /// read the raw autoSql definition stored at `as_offset`
+    /// returns None if the file does not contain an autoSql definition
+    pub fn autosql_text(&mut self) -> Result<Option<String>, Error> {
+        if self.as_offset == 0 {
+            return Ok(None)
+        }
+        self.reader.seek(SeekFrom::Start(self.as_offset))?;
+        // the definition is stored as a null-terminated string
+        let mut text: Vec<u8> = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            self.reader.read_exact(&mut byte)?;
+            if byte[0] == 0 {
+                break;
+            }
+            text.push(byte[0]);
+        }
+        Ok(Some(String::from_utf8_lossy(&text).into_owned()))
+    }
+}
+
+// open one of the test files, shared by the tests of every module
+#[cfg(test)]
+pub(crate) fn bb_from_file(filename: &str) -> BigBed<std::fs::File> {
+    BigBed::from_file(std::fs::File::open(filename).unwrap()).unwrap()
 }
 
 #[cfg(test)]
@@ -730,7 +1028,7 @@ mod test_bb {
     use super::*;
 
     //TODO: add testcase for nonexistent file
-    fn bb_from_file(filename: &str) -> Result<BigBed<File>, Error> {
+    fn open(filename: &str) -> Result<BigBed<File>, Error> {
         BigBed::from_file(File::open(filename)?)
     }
 
@@ -738,22 +1036,68 @@ mod test_bb {
     #[test]
     fn from_file_not_bigbed() {
         // this produces a 'File I/O error because the file is empty (no bytes can be read)
-        let result = bb_from_file("test/beds/empty.bed").unwrap_err();
+        let result = open("test/beds/empty.bed").unwrap_err();
         if let Error::IOError(_) = result {
             // do a more manual check?
         } else {
             panic!("Expected IOError, received {:?}", result)
         }
-        let result = bb_from_file("test/beds/one.bed").unwrap_err();
+        let result = open("test/beds/one.bed").unwrap_err();
         assert_eq!(result, Error::BadSig{expected: BIGBED_SIG, received: [99, 104, 114, 55]});
-        let result = bb_from_file("test/notbed.png").unwrap_err();
+        let result = open("test/notbed.png").unwrap_err();
         assert_eq!(result, Error::BadSig{expected: BIGBED_SIG, received: [137, 80, 78, 71]});
     }
 
+    #[test]
+    fn from_memory() {
+        let bytes = std::fs::read("test/bigbeds/long.bb").unwrap();
+        let expected = bb_from_file("test/bigbeds/long.bb").query("chr2", 0, 5_000_000, 0).unwrap();
+        let mut borrowed = BigBed::from_slice(&bytes).unwrap();
+        assert_eq!(borrowed.query("chr2", 0, 5_000_000, 0).unwrap(), expected);
+        let mut owned: MemoryBigBed = BigBed::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(owned.query("chr2", 0, 5_000_000, 0).unwrap(), expected);
+        assert!(matches!(BigBed::from_bytes(Vec::new()), Err(Error::IOError(_))));
+    }
+
+    #[test]
+    fn from_file_gzipped() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        let bytes = std::fs::read("test/bigbeds/bed12.bb").unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(BigBed::from_file(Cursor::new(gzipped.clone())).unwrap_err(), Error::Gzipped);
+        assert!(Error::Gzipped.hint().is_some());
+        let mut bb = BigBed::from_gzip(&gzipped[..]).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed(None, None, None, None, &mut output).unwrap();
+        assert_eq!(output, std::fs::read("test/beds/bed12.bed").unwrap());
+        // a BigBed that isn't compressed is not a gzip stream
+        assert!(BigBed::from_gzip(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_inflate_errors() {
+        use flate2::Compression;
+        use flate2::write::ZlibEncoder;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[7u8; 1000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut inflater = Inflater::new(1000);
+        assert_eq!(inflater.inflate(&compressed).unwrap(), &[7u8; 1000][..]);
+        // a block larger than the buffer
+        let mut inflater = Inflater::new(10);
+        assert_eq!(inflater.inflate(&compressed), Err(Error::DecompressError(String::from("stopped with status BufError"))));
+        // a block that isn't zlib data
+        let mut inflater = Inflater::new(1000);
+        assert!(matches!(inflater.inflate(b"not zlib"), Err(Error::DecompressError(detail)) if !detail.is_empty()));
+    }
+
     //test a bigbed made from a one-line bed file
     #[test]
     fn from_file_onebed() {
-        let bb = bb_from_file("test/bigbeds/one.bb").unwrap();
+        let bb = bb_from_file("test/bigbeds/one.bb");
         assert_eq!(bb.as_offset, 304);
         assert_eq!(bb.chrom_tree_offset, 628);
         assert_eq!(bb.defined_field_count, 3);
@@ -761,8 +1105,9 @@ mod test_bb {
         assert_eq!(bb.extension_size, Some(64));
         assert_eq!(bb.extra_index_count, Some(0));
         assert_eq!(bb.extra_index_list_offset, Some(0));
+        assert_eq!(bb.extension_reserved, vec![0; 52]);
         assert_eq!(bb.field_count, 3);
-        assert_eq!(bb.big_endian, false);
+        assert!(!bb.big_endian);
         assert_eq!(bb.total_summary_offset, 524);
         assert_eq!(bb.uncompress_buf_size, 16384);
         assert!(bb.unzoomed_cir.is_none());
@@ -775,9 +1120,44 @@ mod test_bb {
         ])
     }
 
+    #[test]
+    fn from_file_extension_reserved() {
+        // reserved bytes in the extension header are kept as they are
+        let mut data = std::fs::read("test/bigbeds/one.bb").unwrap();
+        data[564 + 12..564 + 16].copy_from_slice(b"new!");
+        let bb = BigBed::from_file(std::io::Cursor::new(data)).unwrap();
+        assert_eq!(&bb.extension_reserved[..4], b"new!");
+        assert_eq!(bb.extension_reserved.len(), 52);
+        // and files without an extension have none
+        let mut data = std::fs::read("test/bigbeds/one.bb").unwrap();
+        data[56..64].copy_from_slice(&[0; 8]);
+        let bb = BigBed::from_file(std::io::Cursor::new(data)).unwrap();
+        assert_eq!((bb.extension_size, bb.extension_reserved.len()), (None, 0));
+        assert!(bb.warnings().is_empty());
+    }
+
+    #[test]
+    fn from_file_larger_extension() {
+        // a newer, larger extension header is skipped (with a warning) rather than misread
+        let mut data = std::fs::read("test/bigbeds/one.bb").unwrap();
+        data[564..566].copy_from_slice(&80u16.to_le_bytes());
+        let mut bb = BigBed::from_file(std::io::Cursor::new(data)).unwrap();
+        assert_eq!(bb.extension_size, Some(80));
+        assert_eq!((bb.extension_reserved.len(), bb.extension_unknown.len()), (52, 16));
+        assert!(matches!(bb.warnings(), [Warning::UnsupportedFeature(_)]));
+        assert_eq!(bb.query("chr7", 0, 159345973, 0).unwrap().len(), 1);
+
+        // and a smaller one only provides the fields that fit
+        let mut data = std::fs::read("test/bigbeds/one.bb").unwrap();
+        data[564..566].copy_from_slice(&4u16.to_le_bytes());
+        let bb = BigBed::from_file(std::io::Cursor::new(data)).unwrap();
+        assert_eq!((bb.extra_index_count, bb.extra_index_list_offset), (Some(0), None));
+        assert!(bb.extension_reserved.is_empty() && bb.warnings().is_empty());
+    }
+
     #[test]
     fn from_file_longbed() {
-        let bb = bb_from_file("test/bigbeds/long.bb").unwrap();
+        let bb = bb_from_file("test/bigbeds/long.bb");
         assert_eq!(bb.as_offset, 304);
         assert_eq!(bb.chrom_tree_offset, 628);
         assert_eq!(bb.defined_field_count, 3);
@@ -786,7 +1166,7 @@ mod test_bb {
         assert_eq!(bb.extra_index_count, Some(0));
         assert_eq!(bb.extra_index_list_offset, Some(0));
         assert_eq!(bb.field_count, 3);
-        assert_eq!(bb.big_endian, false);
+        assert!(!bb.big_endian);
         assert_eq!(bb.total_summary_offset, 524);
         assert_eq!(bb.uncompress_buf_size, 16384);
         assert!(bb.unzoomed_cir.is_none());
@@ -805,13 +1185,13 @@ mod test_bb {
 
     #[test]
     fn test_chrom_list() {
-        let mut bb = bb_from_file("test/bigbeds/one.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/one.bb");
         // should only include the chromosomes mapped in the file
         assert_eq!(bb.chrom_list().unwrap(), vec![Chrom{name: String::from("chr7"), id: 0, size: 159345973}]);
         // same list should be generated a second time
         assert_eq!(bb.chrom_list().unwrap(), vec![Chrom{name: String::from("chr7"), id: 0, size: 159345973}]);
         // should include all chromosomes
-        let mut bb = bb_from_file("test/bigbeds/long.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
         assert_eq!(bb.chrom_list().unwrap(), vec![
             Chrom{name: String::from("chr1\0"), id: 0, size: 248956422},
             Chrom{name: String::from("chr10"), id: 1, size: 133797422},
@@ -838,7 +1218,7 @@ mod test_bb {
             Chrom{name: String::from("chrX\0"), id: 22, size: 156040895},
             Chrom{name: String::from("chrY\0"), id: 23, size: 57227415}
         ]);
-        let mut bb = bb_from_file("test/bigbeds/tair10-nochr.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/tair10-nochr.bb");
         assert_eq!(bb.chrom_list().unwrap(), vec![
             Chrom{name: String::from("1"), id: 0, size: 30427671},
             Chrom{name: String::from("2"), id: 1, size: 19698289},
@@ -848,7 +1228,7 @@ mod test_bb {
             Chrom{name: String::from("C"), id: 5, size: 154478},
             Chrom{name: String::from("M"), id: 6, size: 366924}
         ]);
-        let mut bb = bb_from_file("test/bigbeds/tair10.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/tair10.bb");
         assert_eq!(bb.chrom_list().unwrap(), vec![
             Chrom{name: String::from("Chr1"), id: 0, size: 30427671},
             Chrom{name: String::from("Chr2"), id: 1, size: 19698289},
@@ -859,7 +1239,7 @@ mod test_bb {
             Chrom{name: String::from("ChrM"), id: 6, size: 366924}
         ]);
         // testing with an extremely large chrom.sizes file:
-        let mut bb = bb_from_file("test/bigbeds/mm10.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/mm10.bb");
         assert_eq!(bb.chrom_list().unwrap(), vec![
             Chrom{name: String::from("chr1\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"), id: 0, size: 195471971},
             Chrom{name: String::from("chr10\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"), id: 1, size: 130694993},
@@ -930,9 +1310,156 @@ mod test_bb {
         ]);
     }
     
+    #[test]
+    fn test_find_chroms() {
+        let mut bb = bb_from_file("test/bigbeds/mm10.bb");
+        let names = ["chrX", "chr1", "chrNope", "chr4_GL456216_random", "chr1", "a_name_much_longer_than_any_in_the_file"];
+        let found = bb.find_chroms(&names).unwrap();
+        assert_eq!(found.len(), names.len());
+        for (name, chrom) in names.iter().zip(found) {
+            assert_eq!(chrom, bb.find_chrom(name).unwrap_or(None));
+        }
+        assert_eq!(bb.find_chroms(&["chr1"]).unwrap()[0].as_ref().map(Chrom::size), Some(195471971));
+        assert!(bb.find_chroms(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["chr10", "chr2", "chrX", "chr1_GL456210_random", "chr1", "chr01", "chrM", "chr2a", "10", "9"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["9", "10", "chr01", "chr1", "chr1_GL456210_random", "chr2", "chr2a", "chr10", "chrM", "chrX"]);
+
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let names: Vec<String> = bb.chrom_list_natural().unwrap().iter().map(|chrom| chrom.name().to_owned()).collect();
+        let mut expected: Vec<String> = (1..=22).map(|number| format!("chr{}", number)).collect();
+        expected.push(String::from("chrX"));
+        expected.push(String::from("chrY"));
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("chr1", "chr1"));
+        assert!(!glob_match("chr1", "chr10"));
+        assert!(glob_match("chr*_random", "chr1_GL456210_random"));
+        assert!(!glob_match("chr*_random", "chrUn_GL456239"));
+        assert!(glob_match("chr?", "chrX"));
+        assert!(!glob_match("chr?", "chr10"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let names: Vec<String> = bb.chroms_matching("chr1?").unwrap().iter().map(|chrom| chrom.name().to_owned()).collect();
+        assert_eq!(names, (10..=19).map(|number| format!("chr{}", number)).collect::<Vec<String>>());
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed(Some("chr[XY]"), None, None, None, &mut output).unwrap();
+        assert!(output.is_empty());
+        bb.write_bed(Some("chr?"), None, None, None, &mut output).unwrap();
+        // chr1 to chr9, chrX and chrY, in name order
+        let expected = std::fs::read_to_string("test/beds/long.bed").unwrap().lines()
+            .filter(|line| line.split('\t').next().unwrap().len() == 4)
+            .count();
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), expected);
+    }
+
+    #[test]
+    fn test_chrom_aliases() {
+        assert_eq!(chrom_aliases("1"), vec!["1", "chr1", "Chr1", "CHR1"]);
+        assert_eq!(chrom_aliases("Chr1"), vec!["Chr1", "1", "chr1", "CHR1"]);
+        assert_eq!(chrom_aliases("chr"), vec!["chr"]);
+
+        // names without wildcards are resolved, whatever prefix (or padding) the file uses
+        let mut tair10 = bb_from_file("test/bigbeds/tair10.bb");
+        let mut nochr = bb_from_file("test/bigbeds/tair10-nochr.bb");
+        let mut mm10 = bb_from_file("test/bigbeds/mm10.bb");
+        for name in &["1", "chr1", "Chr1"] {
+            assert_eq!(tair10.chroms_matching(name).unwrap()[0].name(), "Chr1");
+            assert_eq!(nochr.chroms_matching(name).unwrap()[0].name(), "1");
+            assert_eq!(mm10.chroms_matching(name).unwrap()[0].name(), "chr1");
+        }
+        assert_eq!(mm10.chroms_matching("chrNope").unwrap(), vec![]);
+        let mut output: Vec<u8> = Vec::new();
+        tair10.write_bed(Some("1"), None, None, None, &mut output).unwrap();
+        let expected = std::fs::read_to_string("test/beds/tair10.bed").unwrap().lines()
+            .filter(|line| line.starts_with("Chr1\t"))
+            .count();
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), expected);
+        assert_eq!(tair10.to_string(Some("chr1"), None, None, None).unwrap().len(), expected);
+    }
+
+    #[test]
+    fn test_chroms_matching_any() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let names = |chroms: Vec<Chrom>| chroms.iter().map(|chrom| chrom.name().to_owned()).collect::<Vec<String>>();
+        // in name order, once each
+        assert_eq!(names(bb.chroms_matching_any(&["chrX", "chr2", "chr2?", "2", "chrNope"]).unwrap()),
+            vec!["chr2", "chr20", "chr21", "chr22", "chrX"]);
+        assert_eq!(bb.chroms_matching_any::<&str>(&[]).unwrap(), vec![]);
+
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed_chroms(&["chr3", "chr1"], None, None, None, &BedFormatter::default(), &mut output).unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+        bb.write_bed(Some("chr1"), None, None, None, &mut expected).unwrap();
+        bb.write_bed(Some("chr3"), None, None, None, &mut expected).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(bb.to_strings(&["chr3", "chr1"], None, None, None).unwrap().concat().into_bytes(), expected);
+        // no chromosomes means all of them
+        let mut all: Vec<u8> = Vec::new();
+        bb.write_bed(None, None, None, None, &mut all).unwrap();
+        output.clear();
+        bb.write_bed_chroms::<&str>(&[], None, None, None, &BedFormatter::default(), &mut output).unwrap();
+        assert_eq!(output, all);
+    }
+
+    #[test]
+    fn test_write_bed_regions() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let formatter = BedFormatter::default();
+        let mut expected: Vec<u8> = Vec::new();
+        bb.write_bed(Some("chr3"), Some(1_000_000), Some(5_000_000), None, &mut expected).unwrap();
+        bb.write_bed(Some("chr1"), Some(0), Some(2_000_000), None, &mut expected).unwrap();
+        // each region keeps its own bounds, in the order given; unknown chromosomes are skipped
+        let regions = vec![
+            GenomicRange::new("chr3", 1_000_000, 5_000_000),
+            GenomicRange::new("chrNope", 0, 100),
+            GenomicRange::new("1", 0, 2_000_000),
+        ];
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed_regions(&regions, None, &formatter, &mut output).unwrap();
+        assert!(!expected.is_empty());
+        assert_eq!(output, expected);
+        // the limit applies to each region, as it applies to each chromosome in write_bed_chroms
+        let mut expected: Vec<u8> = Vec::new();
+        bb.write_bed(Some("chr3"), Some(1_000_000), Some(5_000_000), Some(2), &mut expected).unwrap();
+        bb.write_bed(Some("chr1"), Some(0), Some(2_000_000), Some(2), &mut expected).unwrap();
+        output.clear();
+        bb.write_bed_regions(&regions, Some(2), &formatter, &mut output).unwrap();
+        assert_eq!(output.iter().filter(|&&byte| byte == b'\n').count(), 4);
+        assert_eq!(output, expected);
+        output.clear();
+        bb.write_bed_chroms(&["chr3", "chr1"], None, None, Some(2), &formatter, &mut output).unwrap();
+        assert_eq!(output.iter().filter(|&&byte| byte == b'\n').count(), 4);
+        output.clear();
+        bb.write_bed_regions(&regions, Some(0), &formatter, &mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_chroms() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let first: Vec<Chrom> = bb.chroms().take(2).map(Result::unwrap).collect();
+        assert_eq!(first, vec![
+            Chrom{name: String::from("chr1\0"), id: 0, size: 248956422},
+            Chrom{name: String::from("chr10"), id: 1, size: 133797422},
+        ]);
+        let all: Vec<Chrom> = bb.chroms().map(Result::unwrap).collect();
+        assert_eq!(all, bb.chrom_list().unwrap());
+    }
+
     #[test]
     fn test_find_chrom_one() {
-         let mut bb = bb_from_file("test/bigbeds/one.bb").unwrap();
+         let mut bb = bb_from_file("test/bigbeds/one.bb");
          assert_eq!(bb.find_chrom("chr1").unwrap(), None);
          assert_eq!(bb.find_chrom("chr7").unwrap(), Some(Chrom{name: String::from("chr7"), id: 0, size: 159345973}));
          // does it work again?
@@ -948,7 +1475,7 @@ mod test_bb {
 
     #[test]
     fn test_find_chrom_long() {
-        let mut bb = bb_from_file("test/bigbeds/long.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
         assert_eq!(bb.find_chrom("chr2\0").unwrap(), Some(Chrom{name: String::from("chr2\0"), id: 11, size: 242193529}));
         // should work without padding
         assert_eq!(bb.find_chrom("chr2").unwrap(), Some(Chrom{name: String::from("chr2\0"), id: 11, size: 242193529}));
@@ -958,9 +1485,115 @@ mod test_bb {
         assert_eq!(bb.find_chrom("chr2xx"), Err(Error::BadKey(String::from("chr2xx"), 5)));
     }
 
+    #[test]
+    fn test_bed_type() {
+        let mut bb = bb_from_file("test/bigbeds/one.bb");
+        assert_eq!(bb.bed_type(), "bed3");
+        bb.defined_field_count = 6;
+        bb.field_count = 10;
+        assert_eq!(bb.bed_type(), "bed6+4");
+        bb.defined_field_count = 12;
+        bb.field_count = 12;
+        assert_eq!(bb.bed_type(), "bed12");
+    }
+
+    #[test]
+    fn test_field_names() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        assert_eq!(bb.field_names().unwrap(), vec!["chrom", "chromStart", "chromEnd"]);
+        // without a usable autoSql definition, fall back to standard names
+        bb.as_offset = 0;
+        bb.field_count = 8;
+        bb.defined_field_count = 6;
+        assert_eq!(bb.field_names().unwrap(), vec![
+            "chrom", "chromStart", "chromEnd", "name", "score", "strand", "field7", "field8"
+        ]);
+    }
+
+    #[test]
+    fn test_float_columns() {
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        assert_eq!(bb.float_columns().unwrap(), vec![6, 7, 8]);
+        let formatter = BedFormatter::new().precision(1).float_columns(&bb.float_columns().unwrap());
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed_with(Some("chr1"), None, None, Some(1), &formatter, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "chr1\t17007\t18164\tpeak1\t518\t.\t35.8\t40.0\t32.5\t662\n");
+        assert_eq!(bb_from_file("test/bigbeds/bed12.bb").float_columns().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_autosql_text() {
+        let mut bb = bb_from_file("test/bigbeds/one.bb");
+        let text = bb.autosql_text().unwrap().unwrap();
+        assert!(text.starts_with("table bed\n\"Browser Extensible Data\"\n"));
+        assert!(text.contains("uint   chromEnd;    \"End position in chromosome\""));
+        assert!(text.ends_with(")\n"));
+        // reading it a second time should produce the same result
+        assert_eq!(bb.autosql_text().unwrap(), Some(text));
+    }
+
+    #[test]
+    fn test_query_bed12() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let lines = bb.query("chr1", 0, 400000, 0).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].strand(), Ok(Some(Strand::Forward)));
+        assert_eq!(lines[1].item_rgb(), Ok(Some(Rgb::new(255, 0, 0))));
+        assert_eq!(lines[1].blocks(), Ok(Some(vec![(314982, 318969), (325505, 332305), (340991, 342627), (344397, 344592)])));
+        // every record in the file should have well-formed blocks
+        for line in bb.query("chr2", 0, 242193529, 0).unwrap() {
+            assert!(line.blocks().unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_write_bed() {
+        for name in &["one", "short1", "long", "tair10", "bed12", "peaks", "named"] {
+            let mut bb = bb_from_file(&format!("test/bigbeds/{}.bb", name));
+            let mut output: Vec<u8> = Vec::new();
+            bb.write_bed(None, None, None, None, &mut output).unwrap();
+            let expected = std::fs::read(format!("test/beds/{}.bed", name)).unwrap();
+            assert!(output == expected, "output differs for {}.bb", name);
+        }
+        // uncompressed files should produce the same output
+        let mut bb = bb_from_file("test/bigbeds/long-unc.bb");
+        let mut output: Vec<u8> = Vec::new();
+        bb.write_bed(None, None, None, None, &mut output).unwrap();
+        assert!(output == std::fs::read("test/beds/long.bed").unwrap());
+        // a custom formatter
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let mut output: Vec<u8> = Vec::new();
+        let formatter = BedFormatter::new().columns(4).delimiter(",");
+        bb.write_bed_with(Some("chrX"), None, None, Some(2), &formatter, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "chrX,150034,202998,GENE0241\nchrX,486293,510150,GENE0242\n");
+        // raw names keep the null padding of the chromosome tree keys
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let chr2 = bb.resolve_chrom("chr2").unwrap();
+        assert_eq!(chr2.raw_name(), "chr2\0");
+        let mut output: Vec<u8> = Vec::new();
+        let formatter = BedFormatter::new().raw_chrom_names(true);
+        bb.write_bed_with(Some("chr2"), None, None, Some(1), &formatter, &mut output).unwrap();
+        assert_eq!(output, b"chr2\0\t856082\t1576378\n");
+    }
+
+    #[test]
+    fn test_query_named() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let lines = bb.query_named("chr2", 0, 2400000).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&*lines[0].chrom, "chr2");
+        // the name is shared rather than copied
+        assert!(Arc::ptr_eq(&lines[0].chrom, &lines[1].chrom));
+        assert_eq!(lines[0].to_string(), "chr2\t856082\t1576378");
+        assert_eq!(bb.query_named("chrZ", 0, 100), Err(Error::BadChrom(String::from("chrZ"))));
+        // short names should not cause problems
+        assert_eq!(bb.query_named("Z", 0, 100), Err(Error::BadChrom(String::from("Z"))));
+        assert_eq!(bb.query_named("X", 0, 100).unwrap(), bb.query_named("chrX", 0, 100).unwrap());
+    }
+
     #[test]
     fn test_overlapping_blocks() {
-        let mut bb = bb_from_file("test/bigbeds/long.bb").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
         assert_eq!(bb.overlapping_blocks(0, 100, 1000000), Ok(vec![FileOffsetSize{offset: 984, size: 3324}]));
         // swapped start and stop positions should produce no blocks
         assert_eq!(bb.overlapping_blocks(0, 100000, 10), Ok(vec![]));
@@ -0,0 +1,285 @@
+use crate::{larger_extension_warning, BigBed, Chrom, ZoomLevel};
+use crate::bptree::BPlusTree;
+use crate::error::Error;
+use crate::rtree::CIRTreeFile;
+use std::convert::TryInto;
+use std::io::{Read, Seek};
+
+/// the signature at the start of exported metadata
+pub static METADATA_SIG: [u8; 4] = *b"RBBM";
+/// the version of the metadata format (metadata from other versions is rejected)
+pub const METADATA_VERSION: u16 = 1;
+
+// appends little-endian values to a buffer
+struct MetadataWriter {
+    data: Vec<u8>,
+}
+
+impl MetadataWriter {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.data.extend_from_slice(value);
+    }
+}
+
+// reads the values written by a MetadataWriter, failing (rather than panicking) on short data
+struct MetadataReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> MetadataReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        if count > self.data.len() {
+            return Err(Error::Misc("Metadata is truncated"))
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let length = self.u64()?.try_into()?;
+        Ok(self.take(length)?.to_vec())
+    }
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// serialize the parsed header, chromosome list and R-tree header into a small blob,
+    /// which import_metadata can use to open the same file again without reading them
+    pub fn export_metadata(&mut self) -> Result<Vec<u8>, Error> {
+        let chroms = self.chrom_list()?;
+        self.attach_unzoomed_cir()?;
+        let mut writer = MetadataWriter{data: METADATA_SIG.to_vec()};
+        writer.u16(METADATA_VERSION);
+
+        // the header
+        writer.bool(self.big_endian);
+        writer.u16(self.version);
+        writer.u64(self.chrom_tree_offset);
+        writer.u64(self.unzoomed_data_offset);
+        writer.u64(self.unzoomed_index_offset);
+        writer.u16(self.field_count);
+        writer.u16(self.defined_field_count);
+        writer.u64(self.as_offset);
+        writer.u64(self.total_summary_offset);
+        writer.u32(self.uncompress_buf_size.try_into()?);
+        writer.u64(self.extension_offset);
+        writer.u16(self.zoom_levels);
+        for level in &self.level_list {
+            writer.u32(level.reduction_level);
+            writer.u32(level.reserved);
+            writer.u64(level.data_offset);
+            writer.u64(level.index_offset);
+        }
+
+        // the extension header
+        writer.bool(self.extension_size.is_some());
+        writer.u16(self.extension_size.unwrap_or(0));
+        writer.bool(self.extra_index_count.is_some());
+        writer.u16(self.extra_index_count.unwrap_or(0));
+        writer.bool(self.extra_index_list_offset.is_some());
+        writer.u64(self.extra_index_list_offset.unwrap_or(0));
+        writer.bytes(&self.extension_reserved);
+        writer.bytes(&self.extension_unknown);
+
+        // the chromosome tree and list
+        let tree = &self.chrom_bpt;
+        writer.bool(tree.big_endian);
+        writer.u32(tree.block_size);
+        writer.u64(tree.key_size as u64);
+        writer.u64(tree.val_size as u64);
+        writer.u64(tree.item_count);
+        writer.u64(tree.root_offset);
+        writer.u64(chroms.len() as u64);
+        for chrom in &chroms {
+            writer.bytes(chrom.name.as_bytes());
+            writer.u32(chrom.id);
+            writer.u32(chrom.size);
+        }
+
+        // the R-tree (attached above)
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        writer.bool(index.big_endian);
+        writer.u32(index.block_size);
+        writer.u64(index.item_count);
+        writer.u32(index.start_chrom_ix);
+        writer.u32(index.start_base);
+        writer.u32(index.end_chrom_ix);
+        writer.u32(index.end_base);
+        writer.u64(index.file_size);
+        writer.u32(index.items_per_slot);
+        writer.u64(index.root_offset);
+        Ok(writer.data)
+    }
+
+    /// open a BigBed file using metadata from export_metadata, skipping the reads of
+    /// the header, chromosome list and R-tree header
+    /// the metadata must have been exported from the same file: it is not checked against it
+    pub fn import_metadata(reader: T, metadata: &[u8]) -> Result<BigBed<T>, Error> {
+        let mut fields = MetadataReader{data: metadata};
+        let sig: [u8; 4] = fields.take(4)?.try_into().unwrap();
+        if sig != METADATA_SIG {
+            return Err(Error::BadSig{expected: METADATA_SIG, received: sig})
+        }
+        if fields.u16()? != METADATA_VERSION {
+            return Err(Error::Misc("Unsupported metadata version"))
+        }
+
+        let big_endian = fields.bool()?;
+        let version = fields.u16()?;
+        let chrom_tree_offset = fields.u64()?;
+        let unzoomed_data_offset = fields.u64()?;
+        let unzoomed_index_offset = fields.u64()?;
+        let field_count = fields.u16()?;
+        let defined_field_count = fields.u16()?;
+        let as_offset = fields.u64()?;
+        let total_summary_offset = fields.u64()?;
+        let uncompress_buf_size = fields.u32()?.try_into()?;
+        let extension_offset = fields.u64()?;
+        let zoom_levels = fields.u16()?;
+        let mut level_list = Vec::with_capacity(usize::from(zoom_levels));
+        for _ in 0..zoom_levels {
+            level_list.push(ZoomLevel{
+                reduction_level: fields.u32()?,
+                reserved: fields.u32()?,
+                data_offset: fields.u64()?,
+                index_offset: fields.u64()?,
+            });
+        }
+
+        let (has_size, size) = (fields.bool()?, fields.u16()?);
+        let (has_count, count) = (fields.bool()?, fields.u16()?);
+        let (has_list_offset, list_offset) = (fields.bool()?, fields.u64()?);
+        let extension_reserved = fields.bytes()?;
+        let extension_unknown = fields.bytes()?;
+
+        let chrom_bpt = BPlusTree{
+            big_endian: fields.bool()?,
+            block_size: fields.u32()?,
+            key_size: fields.u64()?.try_into()?,
+            val_size: fields.u64()?.try_into()?,
+            item_count: fields.u64()?,
+            root_offset: fields.u64()?,
+        };
+        let chrom_count: usize = fields.u64()?.try_into()?;
+        let mut chroms = Vec::new();
+        for _ in 0..chrom_count {
+            let name = String::from_utf8_lossy(&fields.bytes()?).into_owned();
+            chroms.push(Chrom{name, id: fields.u32()?, size: fields.u32()?});
+        }
+
+        let unzoomed_cir = CIRTreeFile{
+            big_endian: fields.bool()?,
+            block_size: fields.u32()?,
+            item_count: fields.u64()?,
+            start_chrom_ix: fields.u32()?,
+            start_base: fields.u32()?,
+            end_chrom_ix: fields.u32()?,
+            end_base: fields.u32()?,
+            file_size: fields.u64()?,
+            items_per_slot: fields.u32()?,
+            root_offset: fields.u64()?,
+        };
+
+        let mut warnings = Vec::new();
+        if !extension_unknown.is_empty() {
+            warnings.push(larger_extension_warning(usize::from(size)));
+        }
+        Ok(BigBed{
+            reader, big_endian, version, zoom_levels, chrom_tree_offset,
+            unzoomed_data_offset, unzoomed_index_offset, field_count,
+            defined_field_count, as_offset, total_summary_offset,
+            uncompress_buf_size, extension_offset, level_list,
+            extension_size: if has_size { Some(size) } else { None },
+            extra_index_count: if has_count { Some(count) } else { None },
+            extra_index_list_offset: if has_list_offset { Some(list_offset) } else { None },
+            extension_reserved, extension_unknown, chrom_bpt,
+            unzoomed_cir: Some(unzoomed_cir), metrics: None, warnings,
+            chrom_cache: Some(chroms),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_metadata {
+    use super::*;
+    use crate::metrics::{Metrics, MeteredReader};
+    use std::fs::File;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_round_trip() {
+        for filename in &["test/bigbeds/long.bb", "test/bigbeds/bed12.bb", "test/bigbeds/mm10.bb"] {
+            let mut bb = BigBed::from_file(File::open(filename).unwrap()).unwrap();
+            let metadata = bb.export_metadata().unwrap();
+
+            // reopening with the metadata reads nothing until a query
+            let metrics = Metrics::new();
+            let file = MeteredReader::new(File::open(filename).unwrap(), Arc::clone(&metrics));
+            let mut reopened = BigBed::import_metadata(file, &metadata).unwrap();
+            assert_eq!((metrics.seeks(), metrics.bytes_read()), (0, 0));
+            assert_eq!(reopened.chrom_list().unwrap(), bb.chrom_list().unwrap());
+            assert_eq!(reopened.find_chrom("chr1").unwrap(), bb.find_chrom("chr1").unwrap());
+            assert_eq!(reopened.find_chrom("nope").unwrap(), None);
+            assert_eq!((metrics.seeks(), metrics.bytes_read()), (0, 0));
+            assert_eq!(reopened.level_list, bb.level_list);
+            assert_eq!(reopened.export_metadata().unwrap(), metadata);
+
+            // and reads the same records
+            let chrom = bb.chrom_list().unwrap().remove(0);
+            assert_eq!(reopened.query(chrom.name(), 0, chrom.size(), 0).unwrap(), bb.query(chrom.name(), 0, chrom.size(), 0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_bad_metadata() {
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/one.bb").unwrap()).unwrap();
+        let metadata = bb.export_metadata().unwrap();
+        let open = |metadata: &[u8]| BigBed::import_metadata(File::open("test/bigbeds/one.bb").unwrap(), metadata).map(|_| ());
+        assert_eq!(open(&metadata[..metadata.len() - 1]), Err(Error::Misc("Metadata is truncated")));
+        assert_eq!(open(b"PNG!"), Err(Error::BadSig{expected: METADATA_SIG, received: *b"PNG!"}));
+        let mut newer = metadata.clone();
+        newer[4] = 2;
+        assert_eq!(open(&newer), Err(Error::Misc("Unsupported metadata version")));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::BigBed;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// counters for the I/O done while reading a file
+/// a single Metrics can be shared (e.g. between a MeteredReader and the BigBed reading from it,
+/// or between several files) and read at any time
+#[derive(Debug, Default)]
+pub struct Metrics {
+    seeks: AtomicU64,
+    bytes_read: AtomicU64,
+    blocks_read: AtomicU64,
+    blocks_decompressed: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Metrics> {
+        Arc::new(Metrics::default())
+    }
+
+    /// the number of seeks made by a MeteredReader (not counting position queries)
+    pub fn seeks(&self) -> u64 {
+        self.seeks.load(Ordering::Relaxed)
+    }
+
+    /// the number of bytes fetched through a MeteredReader
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// the number of data blocks read
+    pub fn blocks_read(&self) -> u64 {
+        self.blocks_read.load(Ordering::Relaxed)
+    }
+
+    pub fn blocks_decompressed(&self) -> u64 {
+        self.blocks_decompressed.load(Ordering::Relaxed)
+    }
+
+    /// the number of data blocks taken from a cache instead of being read again
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_misses(&self) -> u64 {
+        self.cache_misses.load(Ordering::Relaxed)
+    }
+
+    /// the fraction of cache lookups that were hits (None if there were no lookups)
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        let hits = self.cache_hits();
+        let total = hits + self.cache_misses();
+        if total == 0 {
+            None
+        } else {
+            Some(hits as f64 / total as f64)
+        }
+    }
+
+    /// set every counter back to zero
+    pub fn reset(&self) {
+        for counter in &[&self.seeks, &self.bytes_read, &self.blocks_read, &self.blocks_decompressed, &self.cache_hits, &self.cache_misses] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn add_blocks(&self, read: u64, decompressed: u64) {
+        self.blocks_read.fetch_add(read, Ordering::Relaxed);
+        self.blocks_decompressed.fetch_add(decompressed, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_cache_lookups(&self, hits: u64, misses: u64) {
+        self.cache_hits.fetch_add(hits, Ordering::Relaxed);
+        self.cache_misses.fetch_add(misses, Ordering::Relaxed);
+    }
+}
+
+/// a reader that counts the seeks made and bytes read through it
+pub struct MeteredReader<T> {
+    inner: T,
+    metrics: Arc<Metrics>,
+}
+
+impl<T> MeteredReader<T> {
+    pub fn new(inner: T, metrics: Arc<Metrics>) -> MeteredReader<T> {
+        MeteredReader{inner, metrics}
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read> Read for MeteredReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.metrics.bytes_read.fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
+}
+
+impl<T: Seek> Seek for MeteredReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if pos != SeekFrom::Current(0) {
+            self.metrics.seeks.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.seek(pos)
+    }
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// register metrics to be updated as data blocks are read, decompressed and cached
+    /// (to also count seeks and bytes, read the file through a MeteredReader with the same metrics)
+    pub fn register_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// the registered metrics, if any
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test_metrics {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_metered_reader() {
+        let metrics = Metrics::new();
+        let mut reader = MeteredReader::new(io::Cursor::new(vec![7u8; 100]), Arc::clone(&metrics));
+        let mut buff = [0u8; 10];
+        reader.read_exact(&mut buff).unwrap();
+        reader.seek(SeekFrom::Start(50)).unwrap();
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(reader.stream_position().unwrap(), 60);
+        assert_eq!((metrics.seeks(), metrics.bytes_read()), (1, 20));
+        metrics.reset();
+        assert_eq!((metrics.seeks(), metrics.bytes_read()), (0, 0));
+    }
+
+    #[test]
+    fn test_register_metrics() {
+        let metrics = Metrics::new();
+        let file = MeteredReader::new(File::open("test/bigbeds/long.bb").unwrap(), Arc::clone(&metrics));
+        let mut bb = BigBed::from_file(file).unwrap();
+        bb.register_metrics(Arc::clone(&metrics));
+        bb.query("chr1", 0, 10_000_000, 0).unwrap();
+        assert!(metrics.seeks() > 0);
+        assert!(metrics.bytes_read() > 0);
+        assert!(metrics.blocks_read() > 0);
+        assert_eq!(metrics.blocks_read(), metrics.blocks_decompressed());
+        assert_eq!(metrics.cache_hit_ratio(), None);
+
+        // adjacent windows share blocks, so some are taken from the cache
+        metrics.reset();
+        let windows: Vec<_> = bb.windows("chr1", 1_000_000, 500_000).unwrap().collect();
+        assert!(!windows.is_empty());
+        assert!(metrics.cache_hits() > 0);
+        assert!(metrics.cache_hit_ratio().unwrap() > 0.0);
+        assert_eq!(metrics.blocks_read(), metrics.cache_misses());
+    }
+}
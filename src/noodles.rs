@@ -0,0 +1,234 @@
+use crate::{BedLine, BedRecord, NamedBedLine};
+use crate::bed::{parse_score, Strand};
+use crate::error::Error;
+use noodles_bed::feature::record::Strand as FeatureStrand;
+use noodles_bed::feature::record::other_fields::Value;
+use noodles_bed::feature::record_buf::OtherFields;
+use noodles_bed::feature::record_buf::other_fields::Value as ValueBuf;
+use noodles_bed::feature::{Record as FeatureRecord, RecordBuf};
+use noodles_core::Position;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+// chromStart is 0-based, while a feature start in the bed crate is 1-based
+fn feature_start(start: u32) -> Result<Position, Error> {
+    Position::MIN.checked_add(start as usize).ok_or_else(|| Error::BadField("chromStart", start.to_string()))
+}
+
+// split the columns after chromEnd into the standard columns of a bedN and the rest
+fn split_rest<const N: usize>(line: &BedLine) -> Result<(Vec<&str>, OtherFields), Error> {
+    let mut columns: Vec<&str> = line.rest().map(|rest| rest.split('\t').collect()).unwrap_or_default();
+    if columns.len() < N - 3 {
+        return Err(Error::BadField("field count", format!("expected {} columns, found {}", N, columns.len() + 3)))
+    }
+    let other: Vec<ValueBuf> = columns.split_off(N - 3).into_iter().map(ValueBuf::from).collect();
+    Ok((columns, OtherFields::from(other)))
+}
+
+fn utf8(field: &'static str, bytes: &[u8]) -> Result<String, Error> {
+    std::str::from_utf8(bytes)
+        .map(str::to_owned)
+        .map_err(|_| Error::BadField(field, String::from_utf8_lossy(bytes).into_owned()))
+}
+
+// the text of a custom column
+fn value_to_string(value: Value) -> Result<String, Error> {
+    Ok(match value {
+        Value::Int64(n) => n.to_string(),
+        Value::UInt64(n) => n.to_string(),
+        Value::Float64(n) => n.to_string(),
+        Value::Character(c) => char::from(c).to_string(),
+        Value::String(text) => utf8("field", text)?,
+    })
+}
+
+// build a bed crate record with N standard columns, where `$standard` sets the columns
+// after chromEnd from `$columns` (a name of "." and a strand of "." are left unset)
+macro_rules! impl_try_from_named_bed_line {
+    ($n:literal, |$builder:ident, $columns:ident| $standard:expr) => {
+        impl TryFrom<&NamedBedLine> for RecordBuf<$n> {
+            type Error = Error;
+
+            fn try_from(record: &NamedBedLine) -> Result<RecordBuf<$n>, Error> {
+                let ($columns, other_fields) = split_rest::<$n>(&record.line)?;
+                let mut $builder = RecordBuf::<$n>::builder()
+                    .set_reference_sequence_name(&*record.chrom)
+                    .set_feature_start(feature_start(record.line.start)?)
+                    .set_other_fields(other_fields);
+                // a feature end of 0 can't be represented, but is implied by a start of 0
+                if let Some(end) = Position::new(record.line.end as usize) {
+                    $builder = $builder.set_feature_end(end);
+                }
+                Ok($standard.build())
+            }
+        }
+    };
+}
+
+impl_try_from_named_bed_line!(3, |builder, _columns| builder);
+impl_try_from_named_bed_line!(4, |builder, columns| {
+    if columns[0] == "." { builder } else { builder.set_name(columns[0]) }
+});
+impl_try_from_named_bed_line!(5, |builder, columns| {
+    let builder = builder.set_score(parse_score(columns[1])?);
+    if columns[0] == "." { builder } else { builder.set_name(columns[0]) }
+});
+impl_try_from_named_bed_line!(6, |builder, columns| {
+    let builder = builder.set_score(parse_score(columns[1])?);
+    let builder = if columns[0] == "." { builder } else { builder.set_name(columns[0]) };
+    match columns[2].parse::<Strand>()? {
+        Strand::Forward => builder.set_strand(FeatureStrand::Forward),
+        Strand::Reverse => builder.set_strand(FeatureStrand::Reverse),
+        Strand::Unknown => builder,
+    }
+});
+
+// read any bed crate record, standard columns first
+// the chromosome has no id outside of a BigBed file, so chrom_id is 0
+fn to_named_bed_line<const N: usize, R: FeatureRecord<N>>(record: &R) -> Result<NamedBedLine, Error> {
+    let chrom = utf8("chrom", record.reference_sequence_name())?;
+    let start = u32::try_from(record.feature_start()?.get() - 1)?;
+    let end = match record.feature_end() {
+        Some(end) => u32::try_from(end?.get())?,
+        None => start,
+    };
+    let mut fields = Vec::new();
+    if let Some(name) = record.name() {
+        fields.push(match name {
+            Some(name) => utf8("name", name)?,
+            None => String::from("."),
+        });
+    }
+    if let Some(score) = record.score() {
+        fields.push(score?.to_string());
+    }
+    if let Some(strand) = record.strand() {
+        let strand = match strand? {
+            Some(FeatureStrand::Forward) => Strand::Forward,
+            Some(FeatureStrand::Reverse) => Strand::Reverse,
+            None => Strand::Unknown,
+        };
+        fields.push(strand.to_string());
+    }
+    for value in record.other_fields().iter() {
+        fields.push(value_to_string(value)?);
+    }
+    let rest = if fields.is_empty() { None } else { Some(fields.join("\t")) };
+    Ok(NamedBedLine{chrom: Arc::from(chrom), line: BedLine{chrom_id: 0, start, end, rest}})
+}
+
+impl<const N: usize> TryFrom<&RecordBuf<N>> for NamedBedLine where RecordBuf<N>: FeatureRecord<N> {
+    type Error = Error;
+
+    fn try_from(record: &RecordBuf<N>) -> Result<NamedBedLine, Error> {
+        to_named_bed_line(record)
+    }
+}
+
+impl<const N: usize> TryFrom<&noodles_bed::Record<N>> for NamedBedLine where noodles_bed::Record<N>: FeatureRecord<N> {
+    type Error = Error;
+
+    fn try_from(record: &noodles_bed::Record<N>) -> Result<NamedBedLine, Error> {
+        to_named_bed_line(record)
+    }
+}
+
+impl BedRecord {
+    /// convert to a bed crate record on `chrom` with N standard columns
+    /// N can't be more than the record's defined_field_count, so custom columns are never
+    /// read as standard ones, but can be less (the other standard columns become other fields)
+    pub fn to_noodles<const N: usize>(&self, chrom: &str) -> Result<RecordBuf<N>, Error>
+        where for<'a> RecordBuf<N>: TryFrom<&'a NamedBedLine, Error = Error> {
+        if N > usize::from(self.defined_field_count()) {
+            return Err(Error::BadField("field count", format!("expected at most {} standard columns, found {}", self.defined_field_count(), N)))
+        }
+        let named = NamedBedLine{chrom: Arc::from(chrom), line: BedLine::from(self.clone())};
+        RecordBuf::<N>::try_from(&named)
+    }
+
+    /// convert a bed crate record with N standard columns, returning its chromosome too
+    /// (the chromosome has no id outside of a BigBed file, so chrom_id is 0)
+    pub fn from_noodles<const N: usize, R: FeatureRecord<N>>(record: &R) -> Result<(Arc<str>, BedRecord), Error> {
+        let named = to_named_bed_line(record)?;
+        Ok((named.chrom, BedRecord::new(&named.line, N as u16)))
+    }
+}
+
+#[cfg(test)]
+mod test_noodles {
+    use super::*;
+    use noodles_bed::io::Reader;
+
+    fn named(chrom: &str, start: u32, end: u32, rest: Option<&str>) -> NamedBedLine {
+        NamedBedLine{chrom: Arc::from(chrom), line: BedLine{chrom_id: 0, start, end, rest: rest.map(str::to_owned)}}
+    }
+
+    #[test]
+    fn test_record_buf_round_trip() {
+        let bed3 = named("chr1", 0, 100, None);
+        let record = RecordBuf::<3>::try_from(&bed3).unwrap();
+        assert_eq!(record.feature_start(), Position::MIN);
+        assert_eq!(NamedBedLine::try_from(&record).unwrap(), bed3);
+
+        let bed6 = named("chr2", 9, 20, Some("gene\t500\t-\textra"));
+        let record = RecordBuf::<6>::try_from(&bed6).unwrap();
+        assert_eq!(record.strand(), Some(FeatureStrand::Reverse));
+        assert_eq!(record.other_fields().as_ref().len(), 1);
+        assert_eq!(NamedBedLine::try_from(&record).unwrap(), bed6);
+
+        let unnamed = named("chr2", 9, 20, Some(".\t0\t."));
+        let record = RecordBuf::<6>::try_from(&unnamed).unwrap();
+        assert_eq!(record.name(), None);
+        assert_eq!(record.strand(), None);
+        assert_eq!(NamedBedLine::try_from(&record).unwrap(), unnamed);
+
+        // the same record can be read as fewer standard columns
+        let record = RecordBuf::<4>::try_from(&bed6).unwrap();
+        assert_eq!(record.other_fields().as_ref().len(), 3);
+        assert_eq!(NamedBedLine::try_from(&record).unwrap(), bed6);
+
+        assert!(RecordBuf::<6>::try_from(&named("chr1", 0, 100, Some("gene"))).is_err());
+        assert!(RecordBuf::<5>::try_from(&named("chr1", 0, 100, Some("gene\thigh"))).is_err());
+    }
+
+    #[test]
+    fn test_from_record() {
+        let text = b"chr1\t0\t100\tgene\t500\t+\tx\ty\n";
+        let mut reader = Reader::<6, _>::new(&text[..]);
+        let mut record = noodles_bed::Record::default();
+        reader.read_record(&mut record).unwrap();
+        let line = NamedBedLine::try_from(&record).unwrap();
+        assert_eq!(line, named("chr1", 0, 100, Some("gene\t500\t+\tx\ty")));
+        assert_eq!(line.to_string(), "chr1\t0\t100\tgene\t500\t+\tx\ty");
+
+        let mut reader = Reader::<3, _>::new(&text[..]);
+        let mut record = noodles_bed::Record::default();
+        reader.read_record(&mut record).unwrap();
+        assert_eq!(NamedBedLine::try_from(&record).unwrap(), named("chr1", 0, 100, Some("gene\t500\t+\tx\ty")));
+    }
+
+    #[test]
+    fn test_bed_record() {
+        // a bed4+2: the "+" is a custom column, not a strand
+        let line = BedLine{chrom_id: 0, start: 10, end: 20, rest: Some(String::from("peak1\t3\t+"))};
+        let record = BedRecord::new(&line, 4);
+        let converted = record.to_noodles::<4>("chr1").unwrap();
+        assert_eq!(converted.name().map(|name| name.as_ref()), Some(&b"peak1"[..]));
+        assert_eq!(converted.other_fields().as_ref().len(), 2);
+        assert_eq!(record.to_noodles::<3>("chr1").unwrap().other_fields().as_ref().len(), 3);
+        assert!(record.to_noodles::<6>("chr1").is_err());
+
+        let (chrom, back) = BedRecord::from_noodles(&converted).unwrap();
+        assert_eq!((&*chrom, &back), ("chr1", &record));
+        assert_eq!(back.strand(), Ok(None));
+
+        let text = b"chr2\t0\t100\tgene\t500\t-\tx\n";
+        let mut reader = Reader::<6, _>::new(&text[..]);
+        let mut record = noodles_bed::Record::default();
+        reader.read_record(&mut record).unwrap();
+        let (chrom, record) = BedRecord::from_noodles(&record).unwrap();
+        assert_eq!(&*chrom, "chr2");
+        assert_eq!(record.strand(), Ok(Some(Strand::Reverse)));
+        assert_eq!(record.extra_fields(), &[String::from("x")]);
+    }
+}
@@ -0,0 +1,769 @@
+use crate::{BedLine, BigBed, BlockRecords, FileOffsetSize, Strand};
+use crate::error::{Error, Warning};
+use crate::intervals::clip;
+use crate::sink::RecordSink;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// which records count as overlapping the range of a query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    /// any record sharing a base with the range (or a zero-length record at its edge)
+    Any,
+    /// only records entirely inside the range
+    Within,
+    /// only records covering the whole range
+    Contains,
+}
+
+impl Overlap {
+    fn matches(self, line: &BedLine, start: u32, end: u32) -> bool {
+        match self {
+            Overlap::Any => true,
+            Overlap::Within => line.start >= start && line.end <= end,
+            Overlap::Contains => line.start <= start && line.end >= end,
+        }
+    }
+}
+
+/// how to choose records when a query finds more than wanted (see QueryOptions::downsample)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Downsample {
+    /// evenly spaced records (every k-th)
+    Spaced,
+    /// the records with the highest scores (the first ones win ties, and records
+    /// without a valid score come last); only for files with a score column
+    TopScores,
+}
+
+// the positions of the records to keep (in order) when downsampling `lines` (from a file
+// with `defined_field_count` standard columns) to at most `max`
+fn downsample(lines: &[&BedLine], max: usize, method: Downsample, defined_field_count: u16) -> Vec<usize> {
+    if lines.len() <= max {
+        return (0..lines.len()).collect()
+    }
+    match method {
+        Downsample::Spaced => (0..max).map(|index| index * lines.len() / max).collect(),
+        Downsample::TopScores => {
+            let mut by_score: Vec<usize> = (0..lines.len()).collect();
+            by_score.sort_by_key(|&index| std::cmp::Reverse(lines[index].defined_score(defined_field_count).ok().flatten()));
+            by_score.truncate(max);
+            by_score.sort_unstable();
+            by_score
+        }
+    }
+}
+
+// a caller's test of whether to return a record
+type RecordFilter = Arc<dyn Fn(&BedLine) -> bool + Send + Sync>;
+
+/// options for BigBed::query_with
+/// by default, every record overlapping the range is returned, as with query
+#[derive(Clone)]
+pub struct QueryOptions {
+    max_items: u32,
+    overlap: Overlap,
+    filter: Option<RecordFilter>,
+    clip: bool,
+    strand: Option<Strand>,
+    max_bytes: Option<usize>,
+    lenient: bool,
+    max_bases: u64,
+    downsample: Option<(u32, Downsample)>,
+    sorted: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> QueryOptions {
+        QueryOptions{max_items: 0, overlap: Overlap::Any, filter: None, clip: false, strand: None, max_bytes: None, lenient: false, max_bases: 0, downsample: None, sorted: false}
+    }
+}
+
+impl fmt::Debug for QueryOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueryOptions")
+            .field("max_items", &self.max_items)
+            .field("overlap", &self.overlap)
+            .field("filter", &self.filter.as_ref().map(|_| "Fn(&BedLine) -> bool"))
+            .field("clip", &self.clip)
+            .field("strand", &self.strand)
+            .field("max_bytes", &self.max_bytes)
+            .field("lenient", &self.lenient)
+            .field("max_bases", &self.max_bases)
+            .field("downsample", &self.downsample)
+            .field("sorted", &self.sorted)
+            .finish()
+    }
+}
+
+impl QueryOptions {
+    pub fn new() -> QueryOptions {
+        QueryOptions::default()
+    }
+
+    /// return at most this many records (0 for no limit)
+    pub fn max_items(mut self, max_items: u32) -> QueryOptions {
+        self.max_items = max_items;
+        self
+    }
+
+    pub fn overlap(mut self, overlap: Overlap) -> QueryOptions {
+        self.overlap = overlap;
+        self
+    }
+
+    /// only return records for which `filter` returns true
+    pub fn filter<F>(mut self, filter: F) -> QueryOptions
+        where F: Fn(&BedLine) -> bool + Send + Sync + 'static {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// truncate the records to the range (see intervals::clip)
+    pub fn clip(mut self, clip: bool) -> QueryOptions {
+        self.clip = clip;
+        self
+    }
+
+    /// only return records on this strand (records without a strand column never match,
+    /// and neither do the records of a file with fewer than 6 standard columns, such as a bed3+3)
+    pub fn strand(mut self, strand: Strand) -> QueryOptions {
+        self.strand = Some(strand);
+        self
+    }
+
+    /// fail instead of returning records taking up more than about this many bytes
+    pub fn max_bytes(mut self, max_bytes: usize) -> QueryOptions {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// stop once the records returned cover this many bases (0 for no limit), counting
+    /// bases covered by several records once; the record reaching the limit is returned
+    pub fn max_bases(mut self, max_bases: u64) -> QueryOptions {
+        self.max_bases = max_bases;
+        self
+    }
+
+    /// when more than `max` records pass the other options, return a subset of `max` of them
+    /// chosen by `method` (always the same subset for the same file and options), in file order
+    /// the records are only passed on once they have all been found, so this is not suited to
+    /// very large queries
+    pub fn downsample(mut self, max: u32, method: Downsample) -> QueryOptions {
+        self.downsample = Some((max, method));
+        self
+    }
+
+    /// fail if the blocks read hold records of the chromosome out of order, instead of
+    /// passing them on in the order they are stored (for a valid file, the records of a
+    /// query always come in order of their starts, with records starting at the same base
+    /// in the order they are stored)
+    pub fn sorted(mut self, sorted: bool) -> QueryOptions {
+        self.sorted = sorted;
+        self
+    }
+
+    /// skip malformed records (and clip records running past the end of their
+    /// chromosome) instead of returning them or failing, recording a warning for each
+    /// (see BigBed::warnings)
+    pub fn lenient(mut self, lenient: bool) -> QueryOptions {
+        self.lenient = lenient;
+        self
+    }
+
+    // does a record (already known to overlap the range) of a file with
+    // `defined_field_count` standard columns pass the options?
+    fn accepts(&self, line: &BedLine, start: u32, end: u32, defined_field_count: u16) -> Result<bool, Error> {
+        if !self.overlap.matches(line, start, end) {
+            return Ok(false)
+        }
+        if let Some(strand) = self.strand {
+            // column 6 is only the strand if the file defines it
+            if line.defined_strand(defined_field_count)? != Some(strand) {
+                return Ok(false)
+            }
+        }
+        Ok(self.filter.as_ref().map(|filter| filter(line)).unwrap_or(true))
+    }
+}
+
+// roughly how much memory a record takes up
+fn record_size(line: &BedLine) -> usize {
+    mem::size_of::<BedLine>() + line.rest.as_ref().map(String::len).unwrap_or(0)
+}
+
+// passes records on to a sink, noticing when it asks to stop
+// (so that export doesn't go on to the next chromosome)
+struct Watched<'a, S> {
+    sink: &'a mut S,
+    stopped: bool,
+}
+
+impl<'a, S: RecordSink> RecordSink for Watched<'a, S> {
+    fn push(&mut self, chrom: &str, line: BedLine) -> Result<bool, Error> {
+        let keep_going = self.sink.push(chrom, line)?;
+        self.stopped |= !keep_going;
+        Ok(keep_going)
+    }
+}
+
+/// what a query did (see BigBed::query_with_stats), to help understand why it was slow
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryStats {
+    /// the number of data blocks read
+    pub blocks_visited: u64,
+    /// the size of the blocks read, as stored in the file
+    pub bytes_read: u64,
+    /// the size of the blocks read once decompressed (the same as bytes_read for uncompressed files)
+    pub bytes_decompressed: u64,
+    /// the number of records looked at in the blocks, whether or not they were returned
+    pub records_scanned: u64,
+    pub records_returned: u64,
+}
+
+/// where a record is stored: the data block holding it, and its position in the block
+/// this is a small handle that can be kept instead of the record, which fetch reads again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordLocation {
+    pub block: FileOffsetSize,
+    /// the number of records before it in the (decompressed) block
+    pub index: usize,
+}
+
+/// a record, with where it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedBedLine {
+    pub line: BedLine,
+    pub location: RecordLocation,
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// find the records on a chromosome overlapping `range`, as chosen by `options`
+    pub fn query_with(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions) -> Result<Vec<BedLine>, Error> {
+        let mut lines: Vec<BedLine> = Vec::new();
+        self.query_into(chrom, range, options, &mut lines)?;
+        Ok(lines)
+    }
+
+    /// like query_with, but also returns statistics about the work done
+    pub fn query_with_stats(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions) -> Result<(Vec<BedLine>, QueryStats), Error> {
+        let mut lines: Vec<BedLine> = Vec::new();
+        let stats = self.query_stats_into(chrom, range, options, &mut lines)?;
+        Ok((lines, stats))
+    }
+
+    /// like query_with, but each record is pushed to `sink` as it is read instead of being collected
+    /// returns the number of records pushed
+    pub fn query_into<S: RecordSink>(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions, sink: S) -> Result<u64, Error> {
+        self.query_stats_into(chrom, range, options, sink).map(|stats| stats.records_returned)
+    }
+
+    /// like query_with, but each record comes with where it was read from in the file,
+    /// so that it can be read again later (see fetch)
+    pub fn query_located(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions) -> Result<Vec<LocatedBedLine>, Error> {
+        let mut lines = Vec::new();
+        self.query_visit(chrom, range, options, |_, line, location| {
+            lines.push(LocatedBedLine{line, location});
+            Ok(true)
+        })?;
+        Ok(lines)
+    }
+
+    /// like query_located, but only the locations of the records are kept
+    /// (e.g. for a UI that fetches records as they are shown)
+    pub fn query_handles(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions) -> Result<Vec<RecordLocation>, Error> {
+        let mut handles = Vec::new();
+        self.query_visit(chrom, range, options, |_, _, location| {
+            handles.push(location);
+            Ok(true)
+        })?;
+        Ok(handles)
+    }
+
+    /// read and parse the record at `handle` again (e.g. one from query_located or query_handles)
+    pub fn fetch(&mut self, handle: &RecordLocation) -> Result<BedLine, Error> {
+        self.fetch_many(std::slice::from_ref(handle)).map(|mut lines| lines.remove(0))
+    }
+
+    /// like fetch, for many handles at once, reading each block only once
+    /// the records are returned in the order of the handles
+    pub fn fetch_many(&mut self, handles: &[RecordLocation]) -> Result<Vec<BedLine>, Error> {
+        let mut blocks: Vec<FileOffsetSize> = handles.iter().map(|handle| handle.block).collect();
+        blocks.sort_by_key(|block| block.offset());
+        blocks.dedup();
+        let big_endian = self.big_endian;
+        let mut lines: Vec<Option<BedLine>> = vec![None; handles.len()];
+        self.read_blocks(&blocks, |block, buff| {
+            let records: Vec<_> = BlockRecords::new(buff, big_endian).collect();
+            for (handle, line) in handles.iter().zip(lines.iter_mut()) {
+                if handle.block == *block {
+                    *line = records.get(handle.index).map(|record| record.to_bed_line());
+                }
+            }
+            true
+        })?;
+        handles.iter().zip(lines).map(|(handle, line)| {
+            line.ok_or(Error::BadDataBlock{offset: handle.block.offset() as u64, reason: "the block has fewer records than the handle's index"})
+        }).collect()
+    }
+
+    fn query_stats_into<S: RecordSink>(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions, mut sink: S) -> Result<QueryStats, Error> {
+        self.query_visit(chrom, range, options, |chrom, line, _| sink.push(chrom, line))
+    }
+
+    // the query behind query_into and query_located: each record that passes the options
+    // is handed to `visit` with its location, stopping early if it returns false
+    fn query_visit<F>(&mut self, chrom: &str, range: Range<u32>, options: &QueryOptions, mut visit: F) -> Result<QueryStats, Error>
+        where F: FnMut(&str, BedLine, RecordLocation) -> Result<bool, Error> {
+        let (start, end) = (range.start, range.end);
+        // the fifth column of a bed3+2 is a custom one, so it can't rank records
+        if matches!(options.downsample, Some((_, Downsample::TopScores))) && self.defined_field_count < 5 {
+            return Err(Error::Misc("File has no score column"))
+        }
+        let chrom_data = self.resolve_chrom(chrom)?;
+        let chrom_id = chrom_data.id;
+        // from kent:
+        // "Find blocks with padded start and end to make sure we include zero-length insertions"
+        let blocks = self.overlapping_blocks(chrom_id, start.saturating_sub(1), end.saturating_add(1))?;
+
+        let mut stats = QueryStats::default();
+        let mut count: u64 = 0;
+        let (mut covered, mut covered_end) = (0u64, 0u32);
+        let mut bytes = 0;
+        // pass a record that passes the options on to `visit`, returning whether to go on
+        let mut emit = |mut line: BedLine, location: RecordLocation| -> Result<bool, Error> {
+            if options.max_items > 0 && count >= u64::from(options.max_items) {
+                return Ok(false)
+            }
+            if options.clip {
+                line = clip(&line, start, end);
+            }
+            if let Some(max_bytes) = options.max_bytes {
+                bytes += record_size(&line);
+                if bytes > max_bytes {
+                    return Err(Error::Misc("Query results exceed the memory budget"))
+                }
+            }
+            count += 1;
+            // records come in order of their starts, so only the part past the
+            // furthest end so far is newly covered
+            covered += u64::from(line.end.saturating_sub(line.start.max(covered_end)));
+            covered_end = covered_end.max(line.end);
+            match visit(chrom_data.name(), line, location)? {
+                true if options.max_bases > 0 && covered >= options.max_bases => Ok(false),
+                keep_going => Ok(keep_going),
+            }
+        };
+        // when downsampling, the records are only passed on once they have all been found
+        let mut pending: Vec<(BedLine, RecordLocation)> = Vec::new();
+        let mut result = Ok(());
+        let mut warnings = Vec::new();
+        let mut previous = None;
+        let big_endian = self.big_endian;
+        let defined_field_count = self.defined_field_count;
+        self.read_blocks(&blocks, |block, buff| {
+            stats.blocks_visited += 1;
+            stats.bytes_read += block.size as u64;
+            stats.bytes_decompressed += buff.len() as u64;
+            for (index, record) in BlockRecords::new(buff, big_endian).enumerate() {
+                stats.records_scanned += 1;
+                if options.sorted && record.chrom_id == chrom_id {
+                    // the blocks are read in file order, so this also checks across blocks
+                    if Some(record.start) < previous {
+                        result = Err(Error::BadDataBlock{offset: block.offset as u64, reason: "the records are not sorted"});
+                        return false
+                    }
+                    previous = Some(record.start);
+                }
+                // check if this data is in the correct range
+                if !record.in_range(chrom_id, start, end) {
+                    continue
+                }
+                let mut line = record.to_bed_line();
+                let warning = |reason: &str| Warning::Skipped{
+                    chrom: chrom_data.name().to_owned(), start: record.start, end: record.end, reason: reason.to_owned()
+                };
+                if options.lenient {
+                    if line.start > line.end {
+                        warnings.push(warning("the start is after the end"));
+                        continue
+                    }
+                    if line.start > chrom_data.size {
+                        warnings.push(warning("the record is past the end of the chromosome"));
+                        continue
+                    }
+                    if line.end > chrom_data.size {
+                        warnings.push(Warning::Clipped{
+                            chrom: chrom_data.name().to_owned(), start: line.start, end: line.end, size: chrom_data.size
+                        });
+                        line.end = chrom_data.size;
+                    }
+                }
+                match options.accepts(&line, start, end, defined_field_count) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) if options.lenient => {
+                        warnings.push(warning(&err.to_string()));
+                        continue
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        return false
+                    }
+                }
+                if !record.is_utf8() {
+                    warnings.push(Warning::LossyUtf8{chrom: chrom_data.name().to_owned(), start: line.start, end: line.end});
+                }
+                let location = RecordLocation{block: *block, index};
+                if options.downsample.is_some() {
+                    pending.push((line, location));
+                    continue
+                }
+                match emit(line, location) {
+                    Ok(true) => {}
+                    Ok(false) => return false,
+                    Err(err) => {
+                        result = Err(err);
+                        return false
+                    }
+                }
+            }
+            true
+        })?;
+        if let (Some((max, method)), Ok(())) = (options.downsample, &result) {
+            let lines: Vec<&BedLine> = pending.iter().map(|(line, _)| line).collect();
+            for index in downsample(&lines, max as usize, method, defined_field_count) {
+                let (line, location) = pending[index].clone();
+                match emit(line, location) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(err) => {
+                        result = Err(err);
+                        break
+                    }
+                }
+            }
+        }
+        self.add_warnings(warnings);
+        stats.records_returned = count;
+        result.map(|_| stats)
+    }
+
+    /// push every record of the file (or of one chromosome) to `sink`, chromosome by chromosome
+    /// in name order, as chosen by `options` (max_items limits the total number of records)
+    /// the records of each chromosome come in order of their starts if the file is valid
+    /// (see QueryOptions::sorted to make sure of it)
+    /// returns the number of records pushed
+    pub fn export<S: RecordSink>(&mut self, chrom: Option<&str>, options: &QueryOptions, mut sink: S) -> Result<u64, Error> {
+        let chroms = match chrom {
+            Some(name) => vec![self.resolve_chrom(name)?],
+            None => self.chrom_list()?,
+        };
+        let mut count = 0;
+        for chrom in chroms {
+            let mut remaining = options.clone();
+            if options.max_items > 0 {
+                let left = u64::from(options.max_items) - count;
+                if left == 0 {
+                    break;
+                }
+                remaining.max_items = left as u32;
+            }
+            let mut watched = Watched{sink: &mut sink, stopped: false};
+            count += self.query_into(chrom.name(), 0..chrom.size(), &remaining, &mut watched)?;
+            if watched.stopped {
+                break;
+            }
+        }
+        Ok(count)
+    }
+
+    /// run a query on a new thread, sending each record to `sender` (e.g. a std::sync::mpsc::Sender<BedLine>)
+    /// as it is read, so that the caller can use the records as they arrive
+    /// the BigBed moves to the thread, and is handed back (with the result of query_into) when it is joined
+    pub fn query_to_channel<S>(mut self, chrom: &str, range: Range<u32>, sender: S) -> JoinHandle<(BigBed<T>, Result<u64, Error>)>
+        where T: Send + 'static, S: RecordSink + Send + 'static {
+        let chrom = chrom.to_owned();
+        thread::spawn(move || {
+            let result = self.query_into(&chrom, range, &QueryOptions::new(), sender);
+            (self, result)
+        })
+    }
+
+    /// find the records on a chromosome covering the base at `pos` (i.e. start <= pos < end),
+    /// for "what's under the cursor" lookups
+    /// only the blocks whose bounds include the base are read (unlike query, the range is not
+    /// padded for zero-length records, which cover no bases)
+    pub fn at(&mut self, chrom: &str, pos: u32) -> Result<Vec<BedLine>, Error> {
+        let chrom_id = self.resolve_chrom(chrom)?.id;
+        let blocks = self.overlapping_blocks(chrom_id, pos, pos.saturating_add(1))?;
+        let mut lines = Vec::new();
+        let big_endian = self.big_endian;
+        self.read_blocks(&blocks, |_, buff| {
+            lines.extend(BlockRecords::new(buff, big_endian)
+                .filter(|record| record.chrom_id == chrom_id && record.start <= pos && record.end > pos)
+                .map(|record| record.to_bed_line()));
+            true
+        })?;
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod test_query {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::NamedBedLine;
+    use std::fs::File;
+    use std::sync::mpsc;
+
+    fn positions(lines: &[BedLine]) -> Vec<(u32, u32)> {
+        lines.iter().map(|line| (line.start, line.end)).collect()
+    }
+
+    #[test]
+    fn test_query_with() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let range = 650_000..1_380_000;
+        let query = |bb: &mut BigBed<File>, options: QueryOptions| positions(&bb.query_with("chr1", range.clone(), &options).unwrap());
+        let all = vec![(646748, 653253), (889073, 902033), (1143004, 1201760), (1374781, 1391547)];
+        assert_eq!(query(&mut bb, QueryOptions::new()), all);
+        assert_eq!(query(&mut bb, QueryOptions::new().max_items(2)), &all[..2]);
+        assert_eq!(query(&mut bb, QueryOptions::new().overlap(Overlap::Within)), &all[1..3]);
+        assert_eq!(query(&mut bb, QueryOptions::new().strand(Strand::Reverse)), vec![all[0], all[3]]);
+        assert_eq!(query(&mut bb, QueryOptions::new().strand(Strand::Reverse).clip(true)), vec![(650_000, 653253), (1374781, 1_380_000)]);
+        let high_scores = QueryOptions::new().filter(|line| line.score().unwrap().unwrap_or(0) > 400);
+        assert_eq!(query(&mut bb, high_scores.clone()), &all[..2]);
+        // options combine, with max_items counting the records that pass
+        assert_eq!(query(&mut bb, high_scores.max_items(1).overlap(Overlap::Within)), &all[1..2]);
+
+        let options = QueryOptions::new().overlap(Overlap::Contains);
+        assert_eq!(positions(&bb.query_with("chr1", 890_000..900_000, &options).unwrap()), &all[1..2]);
+        assert!(bb.query_with("chr1", range.clone(), &QueryOptions::new().max_bytes(100)).is_err());
+        assert!(bb.query_with("chr1", range, &QueryOptions::new().max_bytes(100_000)).is_ok());
+        // records without a strand column never match a strand
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        assert!(bb.query_with("chr1", 0..10_000_000, &QueryOptions::new().strand(Strand::Forward)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_strand_needs_a_strand_column() {
+        use crate::writer::{BigBedWriter, WriterOptions};
+        use std::io::Cursor;
+        // the sixth column is only a strand if the file defines 6 standard columns
+        let write = |defined_field_count: u16| {
+            let sizes = vec![(String::from("chr1"), 1000)];
+            let options = WriterOptions::new().defined_field_count(defined_field_count);
+            let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+            writer.add_line("chr1\t10\t20\tpeak1\t3\t+").unwrap();
+            writer.add_line("chr1\t30\t40\tpeak2\t5\t-").unwrap();
+            BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+        };
+        let forward = QueryOptions::new().strand(Strand::Forward);
+        let mut bed3_plus_3 = write(3);
+        assert!(bed3_plus_3.query_with("chr1", 0..1000, &forward).unwrap().is_empty());
+        let mut bed6 = write(6);
+        assert_eq!(positions(&bed6.query_with("chr1", 0..1000, &forward).unwrap()), vec![(10, 20)]);
+    }
+
+    #[test]
+    fn test_max_bases() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let all = bb.query("chr1", 0, 248956422, 0).unwrap();
+        let first = u64::from(all[0].end - all[0].start);
+        // the record reaching the limit is included
+        let lines = bb.query_with("chr1", 0..248956422, &QueryOptions::new().max_bases(1)).unwrap();
+        assert_eq!(lines, &all[..1]);
+        let lines = bb.query_with("chr1", 0..248956422, &QueryOptions::new().max_bases(first)).unwrap();
+        assert_eq!(lines, &all[..1]);
+        let lines = bb.query_with("chr1", 0..248956422, &QueryOptions::new().max_bases(first + 1)).unwrap();
+        assert!(lines.len() > 1 && all.starts_with(&lines));
+        // overlapping bases are only counted once
+        let merged = crate::intervals::merge_intervals(&lines[..lines.len() - 1]);
+        let covered: u64 = merged.iter().map(|line| u64::from(line.end - line.start)).sum();
+        assert!(covered <= first);
+        assert_eq!(bb.query_with("chr1", 0..248956422, &QueryOptions::new().max_bases(0)).unwrap(), all);
+    }
+
+    #[test]
+    fn test_downsample() {
+        use crate::writer::{BigBedWriter, WriterOptions};
+        use std::io::Cursor;
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let all = bb.query("chr1", 0, 248956422, 0).unwrap();
+        let options = QueryOptions::new().downsample(10, Downsample::Spaced);
+        let lines = bb.query_with("chr1", 0..248956422, &options).unwrap();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], all[0]);
+        assert_eq!(lines[1], all[all.len() / 10]);
+        assert_eq!(lines, bb.query_with("chr1", 0..248956422, &options).unwrap());
+        // regions with few enough records are untouched
+        let options = QueryOptions::new().downsample(all.len() as u32, Downsample::Spaced);
+        assert_eq!(bb.query_with("chr1", 0..248956422, &options).unwrap(), all);
+
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let all = bb.query("chr1", 0, 2_000_000, 0).unwrap();
+        let options = QueryOptions::new().downsample(3, Downsample::TopScores);
+        let lines = bb.query_with("chr1", 0..2_000_000, &options).unwrap();
+        assert_eq!(lines.len(), 3);
+        // the kept records stay in file order, and none left out scores higher
+        let positions: Vec<usize> = lines.iter().map(|line| all.iter().position(|other| other == line).unwrap()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        let lowest = lines.iter().map(|line| line.score().unwrap()).min().unwrap();
+        assert!(all.iter().filter(|line| !lines.contains(line)).all(|line| line.score().unwrap() <= lowest));
+        // max_items still applies to the subset
+        let options = options.max_items(2);
+        assert_eq!(bb.query_with("chr1", 0..2_000_000, &options).unwrap(), &lines[..2]);
+        // files without a score column can't be ranked by score, even by a fifth column
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(3);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t10\t20\tpeak1\t3").unwrap();
+        writer.add_line("chr1\t30\t40\tpeak2\t5").unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        let options = QueryOptions::new().downsample(1, Downsample::TopScores);
+        assert_eq!(bb.query_with("chr1", 0..1000, &options), Err(Error::Misc("File has no score column")));
+    }
+
+    #[test]
+    fn test_query_with_stats() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let options = QueryOptions::new();
+        let (lines, stats) = bb.query_with_stats("chr1", 0..10_000_000, &options).unwrap();
+        assert_eq!(lines, bb.query_with("chr1", 0..10_000_000, &options).unwrap());
+        assert_eq!(stats.records_returned, lines.len() as u64);
+        assert!(stats.blocks_visited > 0);
+        assert!(stats.records_scanned >= stats.records_returned);
+        // each bed3 record takes 13 bytes once decompressed
+        assert_eq!(stats.bytes_decompressed, stats.records_scanned * 13);
+        assert!(stats.bytes_read < stats.bytes_decompressed);
+        // uncompressed files read what they decompress
+        let mut bb = bb_from_file("test/bigbeds/long-unc.bb");
+        let (_, unc_stats) = bb.query_with_stats("chr1", 0..10_000_000, &options).unwrap();
+        assert_eq!(unc_stats.bytes_read, unc_stats.bytes_decompressed);
+        assert_eq!(unc_stats.records_returned, stats.records_returned);
+    }
+
+    #[test]
+    fn test_query_located() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let options = QueryOptions::new().strand(Strand::Reverse);
+        let located = bb.query_located("chr1", 0..2_000_000, &options).unwrap();
+        let lines: Vec<BedLine> = located.iter().map(|located| located.line.clone()).collect();
+        assert_eq!(lines, bb.query_with("chr1", 0..2_000_000, &options).unwrap());
+        for located in &located {
+            assert_eq!(bb.fetch(&located.location).unwrap(), located.line);
+        }
+        // records in the same block have increasing positions
+        let first = located[0].location;
+        assert!(located.iter().filter(|other| other.location.block == first.block).all(|other| other.location.index >= first.index));
+        let past_end = RecordLocation{block: first.block, index: 100_000};
+        assert!(bb.fetch(&past_end).is_err());
+    }
+
+    #[test]
+    fn test_fetch_many() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let options = QueryOptions::new();
+        let mut handles = bb.query_handles("chr2", 0..50_000_000, &options).unwrap();
+        let lines = bb.query_with("chr2", 0..50_000_000, &options).unwrap();
+        assert_eq!(handles.len(), lines.len());
+        assert_eq!(bb.fetch_many(&handles).unwrap(), lines);
+        // in any order, and with repeats
+        handles.reverse();
+        handles.push(handles[0]);
+        let mut expected = lines.clone();
+        expected.reverse();
+        expected.push(expected[0].clone());
+        assert_eq!(bb.fetch_many(&handles).unwrap(), expected);
+        assert_eq!(bb.fetch_many(&[]).unwrap(), vec![]);
+        // a handle past the end of its block
+        let mut handle = handles[0];
+        handle.index = usize::MAX;
+        assert_eq!(bb.fetch_many(&[handle]), Err(Error::BadDataBlock{
+            offset: handle.block.offset() as u64, reason: "the block has fewer records than the handle's index"
+        }));
+    }
+
+    #[test]
+    fn test_query_to_channel() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let expected = bb.query("chr1", 0, 100_000_000, 0).unwrap();
+        let (sender, receiver) = mpsc::sync_channel(4);
+        let handle = bb.query_to_channel("chr1", 0..100_000_000, sender);
+        // the records arrive while the query runs
+        assert_eq!(receiver.iter().collect::<Vec<BedLine>>(), expected);
+        let (mut bb, result) = handle.join().unwrap();
+        assert_eq!(result.unwrap(), expected.len() as u64);
+        // the file can be used again afterwards
+        assert_eq!(bb.query("chr1", 0, 100_000_000, 0).unwrap(), expected);
+
+        let (sender, receiver) = mpsc::channel::<BedLine>();
+        let (_, result) = bb.query_to_channel("chrNope", 0..100, sender).join().unwrap();
+        assert!(result.is_err() && receiver.recv().is_err());
+    }
+
+    #[test]
+    fn test_at() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        assert_eq!(positions(&bb.at("chr1", 890_000).unwrap()), vec![(889073, 902033)]);
+        // starts are included and ends are not
+        assert_eq!(positions(&bb.at("chr1", 889073).unwrap()), vec![(889073, 902033)]);
+        assert!(bb.at("chr1", 902033).unwrap().is_empty());
+        assert!(bb.at("chr1", 0).unwrap().is_empty());
+        // the same as a one-base query
+        for pos in (0..2_000_000).step_by(10_007) {
+            let expected: Vec<BedLine> = bb.query("chr1", pos, pos + 1, 0).unwrap().into_iter().filter(|line| line.start < line.end).collect();
+            assert_eq!(bb.at("chr1", pos).unwrap(), expected);
+        }
+        assert!(bb.at("chrNope", 100).is_err());
+    }
+
+    #[test]
+    fn test_sorted() {
+        let options = QueryOptions::new().sorted(true);
+        let mut bb = bb_from_file("test/bigbeds/tair10.bb");
+        let mut lines: Vec<NamedBedLine> = Vec::new();
+        bb.export(None, &options, &mut lines).unwrap();
+        let mut all: Vec<NamedBedLine> = Vec::new();
+        bb.export(None, &QueryOptions::new(), &mut all).unwrap();
+        assert_eq!(lines, all);
+        assert!(lines.windows(2).all(|pair| pair[0].chrom != pair[1].chrom || pair[0].line.start <= pair[1].line.start));
+
+        // the second and third records of this file were swapped
+        let mut bb = bb_from_file("test/bigbeds/unsorted.bb");
+        assert_eq!(positions(&bb.query("chr1", 0, 100, 0).unwrap()), vec![(10, 20), (50, 60), (30, 40), (70, 80)]);
+        assert_eq!(bb.query_with("chr1", 0..100, &options), Err(Error::BadDataBlock{offset: 568, reason: "the records are not sorted"}));
+        assert_eq!(bb.query_with("chr2", 0..100, &options).unwrap(), bb.query("chr2", 0, 100, 0).unwrap());
+    }
+
+    #[test]
+    fn test_lenient() {
+        // damage the first two records of an uncompressed file
+        let mut bytes = std::fs::read("test/bigbeds/long-unc.bb").unwrap();
+        let data = bb_from_file("test/bigbeds/long-unc.bb").unzoomed_data_offset as usize + 8;
+        let original = bb_from_file("test/bigbeds/long-unc.bb").query("chr1", 0, 248956422, 3).unwrap();
+        bytes[data + 8..data + 12].copy_from_slice(&250_000_000u32.to_le_bytes());
+        let record = data + 13;
+        bytes[record + 4..record + 8].copy_from_slice(&(original[1].end + 10).to_le_bytes());
+        let mut bb = BigBed::from_file(std::io::Cursor::new(bytes)).unwrap();
+
+        // by default the records are returned as stored
+        let lines = bb.query("chr1", 0, 248956422, 3).unwrap();
+        assert_eq!(positions(&lines)[..2], [(original[0].start, 250_000_000), (original[1].end + 10, original[1].end)]);
+        assert!(bb.warnings().is_empty());
+
+        let options = QueryOptions::new().max_items(2).lenient(true);
+        let lines = bb.query_with("chr1", 0..248956422, &options).unwrap();
+        assert_eq!(positions(&lines), vec![(original[0].start, 248956422), (original[2].start, original[2].end)]);
+        let warnings = bb.take_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0], Warning::Clipped{chrom: String::from("chr1"), start: original[0].start, end: 250_000_000, size: 248956422});
+        assert!(matches!(&warnings[1], Warning::Skipped{reason, ..} if reason == "the start is after the end"));
+        assert!(bb.warnings().is_empty());
+    }
+}
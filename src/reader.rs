@@ -0,0 +1,354 @@
+use crate::BigBed;
+use crate::error::Error;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// the default size of the read buffer
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// a buffered reader that keeps its buffer when seeking to a position inside it
+/// (std's BufReader discards the buffer on every seek, so walking the B+ and R trees,
+/// which seeks between nearby nodes, would otherwise re-read the same bytes over and over)
+pub struct BufferedReader<T> {
+    inner: T,
+    buffer: Box<[u8]>,
+    // the unread part of the buffer is buffer[pos..filled]
+    pos: usize,
+    filled: usize,
+    // the position of `inner` (i.e. of the end of the buffered data), once known
+    inner_pos: Option<u64>,
+    seek_within_buffer: bool,
+}
+
+impl<T> BufferedReader<T> {
+    pub fn new(inner: T) -> BufferedReader<T> {
+        BufferedReader::with_capacity(DEFAULT_BUFFER_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: T) -> BufferedReader<T> {
+        BufferedReader{
+            inner, buffer: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0, filled: 0, inner_pos: None, seek_within_buffer: true,
+        }
+    }
+
+    /// the size of the read buffer
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+}
+
+impl<T: Read> Read for BufferedReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // skip the buffer for large reads (e.g. whole data blocks) when it is empty
+        if self.pos == self.filled && buf.len() >= self.buffer.len() {
+            // the buffer no longer ends where the inner reader is, so seek can't use it
+            self.discard_buffer();
+            let count = self.inner.read(buf)?;
+            self.inner_pos = self.inner_pos.map(|pos| pos + count as u64);
+            return Ok(count)
+        }
+        if self.pos == self.filled {
+            self.filled = self.inner.read(&mut self.buffer)?;
+            self.pos = 0;
+            self.inner_pos = self.inner_pos.map(|pos| pos + self.filled as u64);
+        }
+        let count = buf.len().min(self.filled - self.pos);
+        buf[..count].copy_from_slice(&self.buffer[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+impl<T: Seek> Seek for BufferedReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let remaining = (self.filled - self.pos) as u64;
+        // work out where we are going, if we know where we are
+        let target = match (pos, self.inner_pos) {
+            (SeekFrom::Start(target), _) => Some(target),
+            (SeekFrom::Current(offset), Some(inner_pos)) => (inner_pos - remaining).checked_add_signed(offset),
+            _ => None,
+        };
+        if let (Some(target), Some(inner_pos), true) = (target, self.inner_pos, self.seek_within_buffer) {
+            let buffer_start = inner_pos - self.filled as u64;
+            if target >= buffer_start && target <= inner_pos {
+                self.pos = (target - buffer_start) as usize;
+                return Ok(target)
+            }
+        }
+        let new_pos = match pos {
+            // the inner reader is ahead of us by the unread part of the buffer
+            SeekFrom::Current(offset) if target.is_none() => self.inner.seek(SeekFrom::Current(offset - remaining as i64))?,
+            SeekFrom::Current(_) => self.inner.seek(SeekFrom::Start(target.unwrap_or(0)))?,
+            pos => self.inner.seek(pos)?,
+        };
+        self.discard_buffer();
+        self.inner_pos = Some(new_pos);
+        Ok(new_pos)
+    }
+}
+
+/// the most bytes of an input that Spooled::new keeps in memory, before moving it to a temporary file
+pub const DEFAULT_SPOOL_LIMIT: usize = 64 * 1024 * 1024;
+
+// makes the names of temporary files unique within the process
+static SPOOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    File(File),
+}
+
+/// a seekable copy of an input that can only be read once (like stdin), kept in memory
+/// if it is small and otherwise in a temporary file, which is deleted when this is dropped
+pub struct Spooled {
+    spool: Spool,
+    // the temporary file to delete, if there is one
+    path: Option<PathBuf>,
+}
+
+impl Spooled {
+    /// read all of `reader`, keeping it in memory if it is at most `memory_limit` bytes long
+    pub fn new<R: Read>(mut reader: R, memory_limit: usize) -> io::Result<Spooled> {
+        let mut bytes = Vec::new();
+        reader.by_ref().take(memory_limit as u64 + 1).read_to_end(&mut bytes)?;
+        if bytes.len() <= memory_limit {
+            return Ok(Spooled{spool: Spool::Memory(Cursor::new(bytes)), path: None})
+        }
+        let count = SPOOL_COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("bigbed-spool-{}-{}", process::id(), count));
+        let mut file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        // from here on, the file is deleted when `spooled` is dropped (even on errors)
+        let mut spooled = Spooled{spool: Spool::File(file.try_clone()?), path: Some(path)};
+        file.write_all(&bytes)?;
+        io::copy(&mut reader, &mut file)?;
+        spooled.seek(SeekFrom::Start(0))?;
+        Ok(spooled)
+    }
+
+    /// use a file that can already be seeked as it is (so that files and spooled
+    /// input can be handled alike)
+    pub fn file(file: File) -> Spooled {
+        Spooled{spool: Spool::File(file), path: None}
+    }
+
+    /// whether the input is held in memory rather than in a file
+    pub fn is_in_memory(&self) -> bool {
+        matches!(self.spool, Spool::Memory(_))
+    }
+}
+
+impl Read for Spooled {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.spool {
+            Spool::Memory(cursor) => cursor.read(buf),
+            Spool::File(file) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Spooled {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.spool {
+            Spool::Memory(cursor) => cursor.seek(pos),
+            Spool::File(file) => file.seek(pos),
+        }
+    }
+}
+
+impl Drop for Spooled {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            // there is nothing useful to do if this fails
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// options for opening a BigBed file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpenOptions {
+    buffer_size: usize,
+    seek_within_buffer: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions{buffer_size: DEFAULT_BUFFER_SIZE, seek_within_buffer: true}
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// the size of the read buffer (larger buffers mean fewer reads from slow sources)
+    pub fn buffer_size(mut self, size: usize) -> OpenOptions {
+        self.buffer_size = size;
+        self
+    }
+
+    /// whether a seek to a position inside the buffer reuses the buffer (the default),
+    /// instead of always going back to the underlying reader
+    pub fn seek_within_buffer(mut self, enabled: bool) -> OpenOptions {
+        self.seek_within_buffer = enabled;
+        self
+    }
+
+    /// wrap a reader in a buffer with these options
+    pub fn buffered<T: Read + Seek>(&self, reader: T) -> BufferedReader<T> {
+        let mut reader = BufferedReader::with_capacity(self.buffer_size, reader);
+        reader.seek_within_buffer = self.seek_within_buffer;
+        reader
+    }
+
+    /// read a BigBed from a reader, buffered with these options
+    pub fn from_reader<T: Read + Seek>(&self, reader: T) -> Result<BigBed<BufferedReader<T>>, Error> {
+        BigBed::from_file(self.buffered(reader))
+    }
+
+    /// open the BigBed file at `path`
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<BigBed<BufferedReader<File>>, Error> {
+        self.from_reader(File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod test_reader {
+    use super::*;
+    use crate::metrics::{Metrics, MeteredReader};
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn data() -> Vec<u8> {
+        (0..1000u32).map(|n| (n % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_buffered_reader() {
+        let data = data();
+        let metrics = Metrics::new();
+        let mut reader = BufferedReader::with_capacity(100, MeteredReader::new(Cursor::new(data.clone()), Arc::clone(&metrics)));
+        let mut buff = [0u8; 10];
+        reader.seek(SeekFrom::Start(200)).unwrap();
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[200..210]);
+        // seeks within the buffer (forwards or backwards) don't touch the inner reader
+        assert_eq!(reader.seek(SeekFrom::Start(250)).unwrap(), 250);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[250..260]);
+        assert_eq!(reader.seek(SeekFrom::Current(-55)).unwrap(), 205);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[205..215]);
+        assert_eq!((metrics.seeks(), metrics.bytes_read()), (1, 100));
+        // seeks outside the buffer do
+        assert_eq!(reader.seek(SeekFrom::Current(500)).unwrap(), 715);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[715..725]);
+        assert_eq!(reader.seek(SeekFrom::End(-5)).unwrap(), 995);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[995..]);
+        assert_eq!(metrics.seeks(), 3);
+
+        // a large read skips the buffer, after which its old contents can't be seeked into
+        let mut reader = BufferedReader::with_capacity(100, Cursor::new(data.clone()));
+        let mut large = [0u8; 200];
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut buff).unwrap();
+        reader.read_exact(&mut [0u8; 90]).unwrap();
+        reader.read_exact(&mut large).unwrap();
+        assert_eq!(&large[..], &data[100..300]);
+        assert_eq!(reader.seek(SeekFrom::Start(250)).unwrap(), 250);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[250..260]);
+    }
+
+    #[test]
+    fn test_spooled() {
+        let data = data();
+        let mut spooled = Spooled::new(&data[..], 2000).unwrap();
+        assert!(spooled.is_in_memory());
+        let mut buff = [0u8; 10];
+        spooled.seek(SeekFrom::Start(500)).unwrap();
+        spooled.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[500..510]);
+
+        // larger inputs go to a temporary file, which is removed afterwards
+        let mut spooled = Spooled::new(&data[..], 100).unwrap();
+        assert!(!spooled.is_in_memory());
+        let path = spooled.path.clone().unwrap();
+        assert!(path.exists());
+        spooled.seek(SeekFrom::End(-10)).unwrap();
+        spooled.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[990..]);
+        let mut all = Vec::new();
+        spooled.seek(SeekFrom::Start(0)).unwrap();
+        spooled.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        drop(spooled);
+        assert!(!path.exists());
+
+        // a BigBed can be read from either
+        let bytes = std::fs::read("test/bigbeds/one.bb").unwrap();
+        for limit in &[0, DEFAULT_SPOOL_LIMIT] {
+            let mut bb = OpenOptions::new().from_reader(Spooled::new(&bytes[..], *limit).unwrap()).unwrap();
+            assert_eq!(bb.chrom_list().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_unknown_position() {
+        // relative seeks work before the position is known
+        let data = data();
+        let mut reader = BufferedReader::with_capacity(100, Cursor::new(data.clone()));
+        let mut buff = [0u8; 10];
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Current(20)).unwrap(), 30);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[30..40]);
+        assert_eq!(reader.seek(SeekFrom::Current(-10)).unwrap(), 30);
+        reader.read_exact(&mut buff).unwrap();
+        assert_eq!(&buff, &data[30..40]);
+    }
+
+    #[test]
+    fn test_open_options() {
+        let expected = std::fs::read_to_string("test/beds/long.bed").unwrap();
+        for options in &[OpenOptions::new(), OpenOptions::new().buffer_size(16), OpenOptions::new().seek_within_buffer(false)] {
+            let mut bb = options.open("test/bigbeds/long.bb").unwrap();
+            let mut output = Vec::new();
+            bb.write_bed(None, None, None, None, &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+        assert!(OpenOptions::new().open("test/bigbeds/missing.bb").is_err());
+
+        // reusing the buffer saves seeks while walking the trees
+        let count_seeks = |options: OpenOptions| {
+            let metrics = Metrics::new();
+            let file = MeteredReader::new(File::open("test/bigbeds/long.bb").unwrap(), Arc::clone(&metrics));
+            let mut bb = options.from_reader(file).unwrap();
+            bb.query("chr1", 0, 10_000_000, 0).unwrap();
+            metrics.seeks()
+        };
+        assert!(count_seeks(OpenOptions::new()) < count_seeks(OpenOptions::new().seek_within_buffer(false)));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// a region of a chromosome (0-based, end exclusive, like BED)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GenomicRange {
+    pub chrom: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl GenomicRange {
+    pub fn new(chrom: &str, start: u32, end: u32) -> GenomicRange {
+        GenomicRange{chrom: chrom.to_owned(), start, end}
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// widen the range by `padding` bases on each side, without going past either
+    /// end of a chromosome of `chrom_size` bases
+    pub fn pad(&self, padding: u32, chrom_size: u32) -> GenomicRange {
+        GenomicRange{
+            chrom: self.chrom.clone(),
+            start: self.start.saturating_sub(padding),
+            end: self.end.saturating_add(padding).min(chrom_size.max(self.end)),
+        }
+    }
+}
+
+/// shown as a 1-based position, as typed into a genome browser (e.g. chr1:1501-1879)
+impl fmt::Display for GenomicRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}-{}", self.chrom, u64::from(self.start) + 1, self.end)
+    }
+}
+
+/// parse a 1-based position as shown by Display (e.g. chr1:1501-1879, where commas
+/// in the numbers are allowed, as copied from a genome browser)
+impl FromStr for GenomicRange {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<GenomicRange, Error> {
+        let bad = || Error::BadField("region", text.to_owned());
+        // chromosome names may themselves contain ':', so split at the last one
+        let (chrom, span) = text.trim().rsplit_once(':').ok_or_else(bad)?;
+        let (start, end) = span.split_once('-').ok_or_else(bad)?;
+        let position = |value: &str| value.replace(',', "").trim().parse::<u32>().map_err(|_| bad());
+        let (start, end) = (position(start)?, position(end)?);
+        if chrom.is_empty() || start == 0 || end < start - 1 {
+            return Err(bad())
+        }
+        Ok(GenomicRange{chrom: chrom.to_owned(), start: start - 1, end})
+    }
+}
+
+/// is this a line to skip in a BED file? (blank lines, comments, and track or browser lines)
+pub fn is_bed_header(line: &str) -> bool {
+    line.is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser")
+}
+
+/// parse the chrom, start and end of a BED line
+/// returns None if the line has fewer than three columns or the positions are not numbers
+pub fn parse_bed_region(line: &str) -> Option<(&str, u32, u32)> {
+    let mut fields = line.split('\t');
+    let chrom = fields.next()?;
+    let start = fields.next()?.trim().parse().ok()?;
+    let end = fields.next()?.trim().parse().ok()?;
+    Some((chrom, start, end))
+}
+
+#[cfg(test)]
+mod test_region {
+    use super::*;
+
+    #[test]
+    fn test_is_bed_header() {
+        assert!(is_bed_header(""));
+        assert!(is_bed_header("# comment"));
+        assert!(is_bed_header("track name=peaks"));
+        assert!(is_bed_header("browser position chr1:1-100"));
+        assert!(!is_bed_header("chr1\t0\t100"));
+    }
+
+    #[test]
+    fn test_genomic_range() {
+        let range = GenomicRange::new("chr1", 1500, 1879);
+        assert_eq!(range.to_string(), "chr1:1501-1879");
+        assert_eq!(range.len(), 379);
+        assert_eq!(range.pad(100, 10000), GenomicRange::new("chr1", 1400, 1979));
+        // padding stops at the ends of the chromosome
+        assert_eq!(range.pad(2000, 3000), GenomicRange::new("chr1", 0, 3000));
+        assert_eq!(range.pad(0, 10000), range);
+        assert!(GenomicRange::new("chr1", 5, 5).is_empty());
+    }
+
+    #[test]
+    fn test_parse_genomic_range() {
+        let range = GenomicRange::new("chr1", 1500, 1879);
+        assert_eq!(range.to_string().parse::<GenomicRange>().unwrap(), range);
+        assert_eq!("chr1:1,501-1,879".parse::<GenomicRange>().unwrap(), range);
+        assert_eq!("HLA-A*01:01:1-10".parse::<GenomicRange>().unwrap(), GenomicRange::new("HLA-A*01:01", 0, 10));
+        for bad in &["chr1", "chr1:100", ":1-10", "chr1:0-10", "chr1:20-10", "chr1:a-10"] {
+            assert_eq!(bad.parse::<GenomicRange>(), Err(Error::BadField("region", bad.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_parse_bed_region() {
+        assert_eq!(parse_bed_region("chr1\t10\t20"), Some(("chr1", 10, 20)));
+        assert_eq!(parse_bed_region("chr1\t10\t20\tname\t0\t+"), Some(("chr1", 10, 20)));
+        assert_eq!(parse_bed_region("chr1\t 10\t20 "), Some(("chr1", 10, 20)));
+        assert_eq!(parse_bed_region("chr1\t10"), None);
+        assert_eq!(parse_bed_region("chr1\tten\t20"), None);
+        assert_eq!(parse_bed_region("chr1 10 20"), None);
+    }
+}
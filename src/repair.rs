@@ -0,0 +1,316 @@
+use crate::{BigBed, BlockRecords, Chrom, RawRecord};
+use crate::error::{Error, Warning};
+use crate::writer::{self, BlockWriter, ByteWriter, Header, ZoomBuilder, DEFAULT_BLOCK_SIZE, DEFAULT_ITEMS_PER_SLOT};
+use flate2::bufread::ZlibDecoder;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+/// options for repair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepairOptions {
+    block_size: u32,
+    items_per_slot: u32,
+}
+
+impl Default for RepairOptions {
+    fn default() -> RepairOptions {
+        RepairOptions{block_size: DEFAULT_BLOCK_SIZE, items_per_slot: DEFAULT_ITEMS_PER_SLOT}
+    }
+}
+
+impl RepairOptions {
+    pub fn new() -> RepairOptions {
+        RepairOptions::default()
+    }
+
+    /// the number of children per node of the new indexes
+    pub fn block_size(mut self, block_size: u32) -> RepairOptions {
+        self.block_size = block_size;
+        self
+    }
+
+    /// the number of records per data block in the new file
+    pub fn items_per_slot(mut self, items_per_slot: u32) -> RepairOptions {
+        self.items_per_slot = items_per_slot;
+        self
+    }
+}
+
+/// what repair recovered
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairReport {
+    pub records: u64,
+    pub blocks: u64,
+    pub zoom_levels: u16,
+    /// anything that could not be recovered (e.g. chromosome names, if the chromosome tree is unreadable)
+    pub warnings: Vec<Warning>,
+}
+
+// read the records of the data section in file order without using the index,
+// handing each to `visit`; returns the record count stored at the start of the section
+// (the section is read until that many records are found, since other structures, such
+// as extra indexes, may come between the data and the index)
+fn scan_data_section<T, F>(bigbed: &mut BigBed<T>, mut visit: F) -> Result<u64, Error>
+    where T: Read + Seek, F: FnMut(&RawRecord) -> Result<(), Error> {
+    let big_endian = bigbed.big_endian;
+    let compressed = bigbed.uncompress_buf_size > 0;
+    // the section runs from the record count up to the index
+    let length = bigbed.unzoomed_index_offset.checked_sub(bigbed.unzoomed_data_offset + 8)
+        .ok_or(Error::Misc("The data section ends before it starts"))?;
+    bigbed.reader.seek(SeekFrom::Start(bigbed.unzoomed_data_offset))?;
+    let mut count = [0; 8];
+    bigbed.reader.read_exact(&mut count)?;
+    let count = if big_endian { u64::from_be_bytes(count) } else { u64::from_le_bytes(count) };
+    let mut section = BufReader::new((&mut bigbed.reader).take(length));
+    let mut seen = 0;
+
+    if compressed {
+        // the blocks are separate zlib streams, one after another
+        let mut block = Vec::new();
+        while seen < count && !section.fill_buf()?.is_empty() {
+            block.clear();
+            ZlibDecoder::new(&mut section).read_to_end(&mut block)?;
+            for record in BlockRecords::new(&block, big_endian) {
+                visit(&record)?;
+                seen += 1;
+            }
+        }
+    } else {
+        // the records are stored back to back
+        let mut rest = Vec::new();
+        while seen < count {
+            let mut fields = [0; 12];
+            match section.read_exact(&mut fields) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+            let field = |index: usize| {
+                let bytes: [u8; 4] = fields[index * 4..index * 4 + 4].try_into().unwrap();
+                if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+            };
+            rest.clear();
+            section.read_until(0, &mut rest)?;
+            if rest.last() == Some(&0) {
+                rest.pop();
+            }
+            visit(&RawRecord{chrom_id: field(0), start: field(1), end: field(2), rest: &rest})?;
+            seen += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// rebuild a BigBed file whose indexes are damaged, by reading its data section in order
+/// (without the R-tree or chromosome tree) and writing the records to `output` along with
+/// a fresh chromosome tree, R-tree, zoom levels and total summary
+/// the header must be intact, and the records sorted; if the chromosome tree cannot be read,
+/// the chromosomes are given placeholder names (chrom0, chrom1, ...) and their largest end as their size
+/// extra indexes are not rebuilt
+pub fn repair<R, W>(input: R, mut output: W, options: &RepairOptions) -> Result<RepairReport, Error>
+    where R: Read + Seek, W: Write + Seek {
+    let mut bigbed = BigBed::read_header(input)?;
+    let mut warnings = Vec::new();
+    let mut chroms = match bigbed.read_chrom_tree().and_then(|_| bigbed.chrom_list()) {
+        Ok(chroms) => chroms,
+        Err(err) => {
+            warnings.push(Warning::Repaired(format!("the chromosome tree could not be read ({})", err)));
+            Vec::new()
+        }
+    };
+    let autosql = match bigbed.autosql_text() {
+        Ok(text) => text,
+        Err(err) => {
+            warnings.push(Warning::Repaired(format!("the autoSql definition could not be read ({})", err)));
+            None
+        }
+    };
+
+    // first pass: check the records are sorted, and find the extent of each chromosome
+    let mut records: u64 = 0;
+    let mut total_length: u64 = 0;
+    let mut ends: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut last = None;
+    let mut sorted = true;
+    let stored_count = scan_data_section(&mut bigbed, |record| {
+        sorted &= last <= Some((record.chrom_id, record.start));
+        last = Some((record.chrom_id, record.start));
+        records += 1;
+        total_length += u64::from(record.end.saturating_sub(record.start));
+        let end = ends.entry(record.chrom_id).or_insert(0);
+        *end = (*end).max(record.end);
+        Ok(())
+    })?;
+    if !sorted {
+        return Err(Error::Misc("The records in the data section are not sorted"))
+    }
+    if stored_count != records {
+        warnings.push(Warning::Repaired(format!("the data section claims {} records, but holds {}", stored_count, records)));
+    }
+    let missing: Vec<(u32, u32)> = ends.iter()
+        .filter(|(id, _)| !chroms.iter().any(|chrom| chrom.id == **id))
+        .map(|(id, end)| (*id, *end))
+        .collect();
+    if !missing.is_empty() {
+        warnings.push(Warning::Repaired(format!("{} chromosomes were given placeholder names", missing.len())));
+    }
+    chroms.extend(missing.into_iter().map(|(id, end)| Chrom{name: format!("chrom{}", id), id, size: end}));
+    if bigbed.extra_index_count.unwrap_or(0) > 0 {
+        warnings.push(Warning::Repaired(format!("{} extra indexes were not rebuilt", bigbed.extra_index_count.unwrap_or(0))));
+    }
+    if !bigbed.extension_unknown.is_empty() {
+        warnings.push(Warning::Repaired(format!("{} unknown bytes of the extension header were dropped", bigbed.extension_unknown.len())));
+    }
+
+    // second pass: write the new file, leaving space for the header
+    let compress = bigbed.uncompress_buf_size > 0;
+    let mut header = Header{
+        field_count: bigbed.field_count,
+        defined_field_count: bigbed.defined_field_count,
+        ..Header::default()
+    };
+    output.seek(SeekFrom::Start(0))?;
+    output.write_all(&vec![0; Header::reserved_size() as usize])?;
+    if let Some(text) = autosql {
+        header.as_offset = output.stream_position()?;
+        output.write_all(text.as_bytes())?;
+        output.write_all(&[0])?;
+    }
+    header.total_summary_offset = output.stream_position()?;
+    writer::write_total_summary(&mut output, &crate::zoom::Summary::empty())?;
+    header.extension_offset = output.stream_position()?;
+    writer::write_extension(&mut output, &bigbed.extension_reserved)?;
+    header.chrom_tree_offset = output.stream_position()?;
+    writer::write_chrom_tree(&mut output, &chroms, options.block_size)?;
+
+    header.data_offset = output.stream_position()?;
+    output.write_u64(records)?;
+    let sizes: BTreeMap<u32, u32> = chroms.iter().map(|chrom| (chrom.id, chrom.size)).collect();
+    let mean_length = total_length.checked_div(records).unwrap_or(0);
+    let mut zooms = ZoomBuilder::new((mean_length * 10).max(10).try_into().unwrap_or(u32::MAX));
+    let mut blocks = BlockWriter::new(options.items_per_slot, compress);
+    // the records of the current chromosome, for the zoom levels
+    let mut intervals: Vec<(u32, u32)> = Vec::new();
+    let mut current = None;
+    scan_data_section(&mut bigbed, |record| {
+        if current != Some(record.chrom_id) {
+            if let Some(chrom_id) = current {
+                zooms.add_chrom(chrom_id, sizes[&chrom_id], &intervals);
+            }
+            intervals.clear();
+            current = Some(record.chrom_id);
+        }
+        intervals.push((record.start, record.end));
+        blocks.add(&mut output, record.chrom_id, record.start, record.end, record.rest)?;
+        Ok(())
+    })?;
+    if let Some(chrom_id) = current {
+        zooms.add_chrom(chrom_id, sizes[&chrom_id], &intervals);
+    }
+    blocks.flush(&mut output)?;
+    header.index_offset = output.stream_position()?;
+    writer::write_cir_tree(&mut output, &blocks.leaves, options.block_size, header.index_offset)?;
+
+    let total = zooms.total;
+    let mut max_block_size = blocks.max_block_size;
+    for (reduction, level_records) in zooms.finish(records) {
+        let (level, block_size) = writer::write_zoom_level(&mut output, reduction, &level_records, options.items_per_slot, options.block_size, compress)?;
+        header.level_list.push(level);
+        max_block_size = max_block_size.max(block_size);
+    }
+    header.uncompress_buf_size = if compress { max_block_size.try_into()? } else { 0 };
+
+    // go back to fill in the header and total summary
+    output.seek(SeekFrom::Start(0))?;
+    header.write(&mut output)?;
+    output.seek(SeekFrom::Start(header.total_summary_offset))?;
+    writer::write_total_summary(&mut output, &total)?;
+    output.flush()?;
+    Ok(RepairReport{
+        records,
+        blocks: blocks.leaves.len() as u64,
+        zoom_levels: header.level_list.len() as u16,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod test_repair {
+    use super::*;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    // every record of a file, as BED text
+    fn bed_text<T: Read + Seek>(bb: &mut BigBed<T>) -> String {
+        let mut output = Vec::new();
+        bb.write_bed(None, None, None, None, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn repaired(data: Vec<u8>, options: &RepairOptions) -> (BigBed<Cursor<Vec<u8>>>, RepairReport) {
+        let mut output = Cursor::new(Vec::new());
+        let report = repair(Cursor::new(data), &mut output, options).unwrap();
+        (BigBed::from_file(Cursor::new(output.into_inner())).unwrap(), report)
+    }
+
+    #[test]
+    fn test_repair() {
+        let files = ["test/bigbeds/one.bb", "test/bigbeds/long.bb", "test/bigbeds/long-unc.bb", "test/bigbeds/bed12.bb", "test/bigbeds/tair10.bb"];
+        // small nodes and blocks give trees several levels deep
+        for options in &[RepairOptions::new(), RepairOptions::new().block_size(2).items_per_slot(3)] {
+            for filename in &files {
+                let mut bb = BigBed::from_file(File::open(filename).unwrap()).unwrap();
+                let (mut fixed, report) = repaired(std::fs::read(filename).unwrap(), options);
+                assert_eq!(report.warnings, vec![], "{}", filename);
+                assert_eq!(bed_text(&mut fixed), bed_text(&mut bb), "{}", filename);
+                assert_eq!(fixed.chrom_list().unwrap(), bb.chrom_list().unwrap());
+                assert_eq!((fixed.field_count, fixed.defined_field_count), (bb.field_count, bb.defined_field_count));
+                assert_eq!(fixed.autosql_text().unwrap(), bb.autosql_text().unwrap());
+                assert_eq!(fixed.uncompress_buf_size > 0, bb.uncompress_buf_size > 0);
+                assert_eq!(fixed.verify_zoom_levels(20, 1).unwrap(), vec![], "{}", filename);
+                assert_eq!(fixed.verify_sorted().unwrap(), None);
+                assert_eq!(usize::from(report.zoom_levels), fixed.level_list.len());
+            }
+        }
+        let (fixed, report) = repaired(std::fs::read("test/bigbeds/long.bb").unwrap(), &RepairOptions::new());
+        assert_eq!((report.records, report.blocks), (10000, 24));
+        assert!(!fixed.level_list.is_empty());
+    }
+
+    #[test]
+    fn test_repair_damaged() {
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        let expected = bed_text(&mut bb);
+        // with the R-tree wiped out, the records are still recovered
+        let mut data = std::fs::read("test/bigbeds/bed12.bb").unwrap();
+        let index = bb.unzoomed_index_offset as usize;
+        data[index..index + 48].copy_from_slice(&[0; 48]);
+        assert!(BigBed::from_file(Cursor::new(data.clone())).unwrap().query("chr1", 0, 1000, 0).is_err());
+        let (mut fixed, report) = repaired(data.clone(), &RepairOptions::new());
+        assert_eq!(report.warnings, vec![]);
+        assert_eq!(bed_text(&mut fixed), expected);
+
+        // and without the chromosome tree, on placeholder chromosomes
+        let chrom_tree = bb.chrom_tree_offset as usize;
+        data[chrom_tree..chrom_tree + 32].copy_from_slice(&[0; 32]);
+        assert!(BigBed::from_file(Cursor::new(data.clone())).is_err());
+        let (mut fixed, report) = repaired(data, &RepairOptions::new());
+        assert_eq!(report.warnings.len(), 2);
+        let chroms = bb.chrom_list().unwrap();
+        let mut with_data = 0;
+        for chrom in chroms {
+            let lines = bb.query(chrom.name(), 0, chrom.size(), 0).unwrap();
+            if !lines.is_empty() {
+                with_data += 1;
+                assert_eq!(fixed.query(&format!("chrom{}", chrom.id()), 0, chrom.size(), 0).unwrap(), lines);
+            }
+        }
+        assert_eq!(fixed.chrom_list().unwrap().len(), with_data);
+
+        // unsorted records can't be indexed
+        let data = std::fs::read("test/bigbeds/unsorted.bb").unwrap();
+        assert_eq!(repair(Cursor::new(data), Cursor::new(Vec::new()), &RepairOptions::new()).unwrap_err(),
+                   Error::Misc("The records in the data section are not sorted"));
+    }
+}
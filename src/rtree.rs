@@ -0,0 +1,258 @@
+use crate::{ByteReader, FileOffsetSize, CIRTREE_SIG};
+use crate::error::Error;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+
+/// the header of a chromosome interval R-tree (CIR tree), which indexes the
+/// data blocks of a bbi file (BigBed or BigWig) by (chrom_id, base) ranges
+#[derive(Debug, Clone, PartialEq)]
+pub struct CIRTreeFile {
+    pub big_endian: bool,
+    /// the maximum number of children per node
+    pub block_size: u32,
+    /// the number of data blocks indexed
+    pub item_count: u64,
+    pub start_chrom_ix: u32,
+    pub start_base: u32,
+    pub end_chrom_ix: u32,
+    pub end_base: u32,
+    /// the offset of the end of the indexed data
+    pub file_size: u64,
+    pub items_per_slot: u32,
+    /// the offset of the root node
+    pub root_offset: u64,
+}
+
+/// an entry in a CIR tree node: the bounds of a child node, or of a data block in a leaf
+#[derive(Debug, Clone, PartialEq)]
+pub struct CIRChild {
+    pub start_chrom: u32,
+    pub start_base: u32,
+    pub end_chrom: u32,
+    pub end_base: u32,
+    /// the offset of the child node, or of the data block
+    pub offset: u64,
+    /// the size of the data block (None for child nodes)
+    pub size: Option<u64>,
+}
+
+/// a node of a CIR tree, as reported by `CIRTreeFile::visit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CIRNode {
+    pub offset: u64,
+    /// the depth of the node (the root is level 0)
+    pub level: u32,
+    pub is_leaf: bool,
+    pub children: Vec<CIRChild>,
+}
+
+/// whether a query (chrom_id, start, end) overlaps the bounds of a node or block
+pub fn cir_overlaps(q_chrom: u32, q_start: u32, q_end: u32, 
+                start_chrom: u32, start_base: u32, 
+                end_chrom: u32, end_base: u32) -> bool {
+    (q_chrom, q_start) < (end_chrom, end_base) 
+    && (q_chrom, q_end) > (start_chrom, start_base)
+}
+
+impl CIRTreeFile {
+    /// read the header of a CIR tree at the current position of the reader
+    pub fn with_reader<T: Read + Seek>(reader: &mut T) -> Result<CIRTreeFile, Error> {
+        // check the signature first
+        let mut buff = [0; 4];
+        reader.read_exact(&mut buff)?;
+        let big_endian =
+            if buff == CIRTREE_SIG {
+                true
+            } else if buff.iter().eq(CIRTREE_SIG.iter().rev()) {
+                false
+            } else {
+                return Err(Error::BadSig{expected: CIRTREE_SIG, received: buff});
+            };
+
+        //read all the header information
+        let block_size = reader.read_u32(big_endian);
+        let item_count = reader.read_u64(big_endian);
+        let start_chrom_ix = reader.read_u32(big_endian);
+        let start_base = reader.read_u32(big_endian);
+        let end_chrom_ix = reader.read_u32(big_endian);
+        let end_base = reader.read_u32(big_endian);
+        let file_size = reader.read_u64(big_endian);
+        let items_per_slot = reader.read_u32(big_endian);
+
+        // skip over the reserved region and get the root offset
+        let root_offset = reader.seek(SeekFrom::Current(4))?;
+
+        Ok(CIRTreeFile{
+            big_endian,
+            block_size,
+            item_count,
+            start_chrom_ix,
+            start_base,
+            end_chrom_ix,
+            end_base,
+            file_size,
+            items_per_slot,
+            root_offset,
+        })
+    }
+
+    /// walk every node of the tree (parents before their children), handing each to `visit`
+    pub fn visit<T, F>(&self, reader: &mut T, mut visit: F) -> Result<(), Error>
+        where T: Read + Seek, F: FnMut(&CIRNode) {
+        let mut offsets = VecDeque::new();
+        offsets.push_back((self.root_offset, 0));
+        while let Some((offset, level)) = offsets.pop_front() {
+            reader.seek(SeekFrom::Start(offset))?;
+
+            // read block header
+            let is_leaf = reader.read_u8() != 0;
+            let _reserved = reader.read_u8();
+            let child_count = reader.read_u16(self.big_endian);
+            let mut children = Vec::with_capacity(usize::from(child_count));
+            for _ in 0..child_count {
+                let start_chrom = reader.read_u32(self.big_endian);
+                let start_base = reader.read_u32(self.big_endian);
+                let end_chrom = reader.read_u32(self.big_endian);
+                let end_base = reader.read_u32(self.big_endian);
+                let offset = reader.read_u64(self.big_endian);
+                let size = if is_leaf { Some(reader.read_u64(self.big_endian)) } else { None };
+                children.push(CIRChild{start_chrom, start_base, end_chrom, end_base, offset, size});
+            }
+            if !is_leaf {
+                offsets.extend(children.iter().map(|child| (child.offset, level + 1)));
+            }
+            visit(&CIRNode{offset, level, is_leaf, children});
+        }
+        Ok(())
+    }
+
+    /// find the data blocks that may contain records overlapping a region
+    pub fn find_blocks<T: Read + Seek>(&self, chrom_id: u32, start: u32, end: u32, reader: &mut T) -> Result<Vec<FileOffsetSize>, Error> {
+        self.blocks_where(reader, |start_chrom, start_base, end_chrom, end_base| {
+            cir_overlaps(chrom_id, start, end, start_chrom, start_base, end_chrom, end_base)
+        })
+    }
+
+    /// every data block in the index
+    pub fn all_blocks<T: Read + Seek>(&self, reader: &mut T) -> Result<Vec<FileOffsetSize>, Error> {
+        self.blocks_where(reader, |_, _, _, _| true)
+    }
+
+    /// find the data blocks whose bounds (start_chrom, start_base, end_chrom, end_base) satisfy `overlaps`
+    /// (only nodes whose bounds satisfy `overlaps` are explored)
+    pub fn blocks_where<T, F>(&self, reader: &mut T, overlaps: F) -> Result<Vec<FileOffsetSize>, Error>
+        where T: Read + Seek, F: Fn(u32, u32, u32, u32) -> bool {
+        let mut blocks = Vec::<FileOffsetSize>::new();
+        for leaf in self.leaves_where(reader, overlaps)? {
+            // leaves always have a size
+            blocks.push(FileOffsetSize{offset: leaf.offset.try_into()?, size: leaf.size.unwrap_or(0).try_into()?});
+        }
+        Ok(blocks)
+    }
+
+    /// like blocks_where, but returning the leaf entries (with the bounds of each data block)
+    pub fn leaves_where<T, F>(&self, reader: &mut T, overlaps: F) -> Result<Vec<CIRChild>, Error>
+        where T: Read + Seek, F: Fn(u32, u32, u32, u32) -> bool {
+        let mut leaves = Vec::new();
+        let mut offsets = VecDeque::new();
+        offsets.push_back(self.root_offset);
+        while let Some(offset) = offsets.pop_front() {
+            // move to the offset
+            reader.seek(SeekFrom::Start(offset))?;
+            
+            // read block header
+            let is_leaf = reader.read_u8();
+            let _reserved = reader.read_u8();
+            let child_count = reader.read_u16(self.big_endian);
+
+            if is_leaf != 0 {
+                for _  in 0..child_count {
+                    let start_chrom = reader.read_u32(self.big_endian);
+                    let start_base = reader.read_u32(self.big_endian);
+                    let end_chrom = reader.read_u32(self.big_endian);
+                    let end_base = reader.read_u32(self.big_endian);
+                    let offset = reader.read_u64(self.big_endian);
+                    let size = reader.read_u64(self.big_endian);
+                    if overlaps(start_chrom, start_base, end_chrom, end_base) {
+                        leaves.push(CIRChild{start_chrom, start_base, end_chrom, end_base, offset, size: Some(size)})
+                    }
+                }
+            } else {
+                for _ in 0..child_count {
+                    // load the data in the Node
+                    let start_chrom = reader.read_u32(self.big_endian);
+                    let start_base = reader.read_u32(self.big_endian);
+                    let end_chrom = reader.read_u32(self.big_endian);
+                    let end_base = reader.read_u32(self.big_endian);
+                    let offset = reader.read_u64(self.big_endian);
+
+                    // if we have overlaps in this area, then we should explore the node
+                    if overlaps(start_chrom, start_base, end_chrom, end_base) {
+                        offsets.push_back(offset);
+                    }
+                }
+            }
+        }
+        Ok(leaves)
+    }
+}
+
+#[cfg(test)]
+mod test_rtree {
+    use super::*;
+    use crate::BigBed;
+    use std::fs::File;
+
+    #[test]
+    fn test_cir_overlaps() {
+        assert!(cir_overlaps(1, 10, 20, 1, 15, 1, 30));
+        assert!(cir_overlaps(1, 10, 20, 0, 100, 2, 0));
+        assert!(!cir_overlaps(1, 10, 20, 1, 20, 1, 30));
+        assert!(!cir_overlaps(1, 10, 20, 2, 0, 2, 30));
+    }
+
+    #[test]
+    fn test_with_reader() {
+        let mut bb = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        let mut file = File::open("test/bigbeds/long.bb").unwrap();
+        file.seek(SeekFrom::Start(bb.unzoomed_index_offset)).unwrap();
+        let index = CIRTreeFile::with_reader(&mut file).unwrap();
+        assert_eq!(index.file_size, bb.unzoomed_index_offset);
+        assert_eq!(index.root_offset, bb.unzoomed_index_offset + 48);
+        let all = index.all_blocks(&mut file).unwrap();
+        assert_eq!(all.len() as u64, index.item_count);
+        assert!(all.iter().all(|block| (block.offset() as u64) < index.file_size));
+        assert_eq!(index.find_blocks(0, 0, 10000000, &mut file).unwrap(), bb.overlapping_blocks(0, 0, 10000000).unwrap());
+        // not a CIR tree
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert!(CIRTreeFile::with_reader(&mut file).is_err());
+    }
+
+    #[test]
+    fn test_visit() {
+        let bb = BigBed::from_file(File::open("test/bigbeds/long.bb").unwrap()).unwrap();
+        let mut file = File::open("test/bigbeds/long.bb").unwrap();
+        file.seek(SeekFrom::Start(bb.unzoomed_index_offset)).unwrap();
+        let index = CIRTreeFile::with_reader(&mut file).unwrap();
+        let mut nodes = Vec::new();
+        index.visit(&mut file, |node| nodes.push(node.clone())).unwrap();
+        assert_eq!(nodes[0].offset, index.root_offset);
+        assert_eq!(nodes[0].level, 0);
+        // every block is in a leaf, and every node other than the root has a parent
+        let blocks: Vec<FileOffsetSize> = nodes.iter()
+            .filter(|node| node.is_leaf)
+            .flat_map(|node| node.children.iter())
+            .map(|child| FileOffsetSize{offset: child.offset as usize, size: child.size.unwrap() as usize})
+            .collect();
+        assert_eq!(blocks, index.all_blocks(&mut file).unwrap());
+        for node in &nodes[1..] {
+            let parent = nodes.iter().find(|parent| parent.children.iter().any(|child| child.offset == node.offset)).unwrap();
+            assert!(!parent.is_leaf);
+            assert_eq!(parent.level + 1, node.level);
+        }
+        // all leaves are at the same depth
+        let depth = nodes.iter().find(|node| node.is_leaf).unwrap().level;
+        assert!(nodes.iter().all(|node| node.is_leaf == (node.level == depth)));
+    }
+}
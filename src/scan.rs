@@ -0,0 +1,1006 @@
+use crate::{in_range, BigBed, BedLine, BlockRecords, ByteReader, Chrom, FileOffsetSize, NamedBedLine, RawRecord};
+use crate::zoom::bin_bounds;
+use crate::error::Error;
+use crate::rtree::CIRChild;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// drops exact duplicate records (same position and extra columns) from a sorted stream
+/// only hashes of records sharing the current start position are kept,
+/// so memory use is bounded by the number of records starting at one base
+#[derive(Debug, Default)]
+pub struct Deduplicator {
+    position: Option<(u32, u32)>,
+    seen: HashSet<u64>,
+}
+
+impl Deduplicator {
+    pub fn new() -> Deduplicator {
+        Deduplicator::default()
+    }
+
+    /// returns true if this record has not been seen before
+    pub fn is_new(&mut self, chrom_id: u32, start: u32, end: u32, rest: &[u8]) -> bool {
+        if self.position != Some((chrom_id, start)) {
+            self.position = Some((chrom_id, start));
+            self.seen.clear();
+        }
+        let mut hasher = DefaultHasher::new();
+        end.hash(&mut hasher);
+        rest.hash(&mut hasher);
+        self.seen.insert(hasher.finish())
+    }
+
+    pub fn is_new_line(&mut self, line: &BedLine) -> bool {
+        let rest = line.rest.as_ref().map(|rest| rest.as_bytes()).unwrap_or(&[]);
+        self.is_new(line.chrom_id, line.start, line.end, rest)
+    }
+}
+
+/// remove exact duplicates from sorted records
+pub fn dedup(lines: Vec<BedLine>) -> Vec<BedLine> {
+    let mut deduplicator = Deduplicator::new();
+    lines.into_iter().filter(|line| deduplicator.is_new_line(line)).collect()
+}
+
+/// statistics of the score column over a set of records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreStats {
+    pub count: u64,
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    /// the sample standard deviation (0 for a single record)
+    pub std_dev: f64,
+}
+
+impl ScoreStats {
+    /// compute the statistics of some scores, or None if there are none
+    pub fn from_scores(scores: impl IntoIterator<Item = u16>) -> Option<ScoreStats> {
+        let (mut count, mut min, mut max, mut sum, mut sum_squares) = (0u64, u16::MAX, 0u16, 0.0, 0.0);
+        for score in scores {
+            count += 1;
+            min = min.min(score);
+            max = max.max(score);
+            sum += f64::from(score);
+            sum_squares += f64::from(score) * f64::from(score);
+        }
+        if count == 0 {
+            return None
+        }
+        let n = count as f64;
+        // this matches the standard deviation calculation in the kent source
+        let std_dev = if count > 1 {
+            ((sum_squares - sum * sum / n) / (n - 1.0)).max(0.0).sqrt()
+        } else {
+            0.0
+        };
+        Some(ScoreStats{count, min, max, mean: sum / n, std_dev})
+    }
+}
+
+/// statistics of the lengths (end - start) of a set of records
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthStats {
+    pub count: u64,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    pub median: f64,
+    /// the number of records in each power-of-two length range, as (lower bound, count):
+    /// lengths of 0, then 1, 2-3, 4-7 and so on (from the shortest record's range to the longest's)
+    pub histogram: Vec<(u32, u64)>,
+}
+
+// the histogram bin of a length: 0 for 0, then 1 + floor(log2(length))
+fn length_bin(length: u32) -> usize {
+    (32 - length.leading_zeros()) as usize
+}
+
+// the smallest length in a histogram bin
+fn bin_lower_bound(bin: usize) -> u32 {
+    if bin == 0 { 0 } else { 1 << (bin - 1) }
+}
+
+impl LengthStats {
+    /// compute the statistics of some lengths, or None if there are none
+    pub fn from_lengths(lengths: impl IntoIterator<Item = u32>) -> Option<LengthStats> {
+        let mut lengths: Vec<u32> = lengths.into_iter().collect();
+        if lengths.is_empty() {
+            return None
+        }
+        lengths.sort_unstable();
+        let count = lengths.len();
+        let (min, max) = (lengths[0], lengths[count - 1]);
+        let mean = lengths.iter().map(|&length| f64::from(length)).sum::<f64>() / count as f64;
+        let median = if count % 2 == 1 {
+            f64::from(lengths[count / 2])
+        } else {
+            (f64::from(lengths[count / 2 - 1]) + f64::from(lengths[count / 2])) / 2.0
+        };
+        let mut bins = vec![0u64; 33];
+        for &length in &lengths {
+            bins[length_bin(length)] += 1;
+        }
+        let histogram = (length_bin(min)..=length_bin(max)).map(|bin| (bin_lower_bound(bin), bins[bin])).collect();
+        Some(LengthStats{count: count as u64, min, max, mean, median, histogram})
+    }
+}
+
+/// a record found out of (chrom_id, start) order by `verify_sorted`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsortedRecord {
+    /// the offset of the data block holding the record
+    pub block_offset: usize,
+    /// the (chrom_id, start) of the record before it
+    pub previous: (u32, u32),
+    /// the (chrom_id, start) of the record
+    pub record: (u32, u32),
+}
+
+/// a record whose coordinates do not fit its chromosome, found by `check_records`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRecord {
+    /// the offset of the data block holding the record
+    pub block_offset: usize,
+    pub chrom_id: u32,
+    pub start: u32,
+    pub end: u32,
+    /// the size of the chromosome, or None if its id is not in the chromosome tree
+    pub chrom_size: Option<u32>,
+}
+
+// the 64-bit FNV-1a hash, which (unlike DefaultHasher) is the same on every platform and release
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// a digest of the records of a file, independent of how they are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub records: u64,
+    /// the FNV-1a hash of the records written as plain BED
+    pub digest: u64,
+}
+
+impl std::fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:016x}", self.digest)
+    }
+}
+
+/// the number of data blocks and records on a chromosome
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromCount {
+    pub chrom: String,
+    pub size: u32,
+    /// the data blocks holding records on this chromosome (according to the index)
+    pub blocks: u64,
+    /// the records on this chromosome (None if only the index was read)
+    pub records: Option<u64>,
+}
+
+/// statistics of the data blocks of a file, to help diagnose badly built files
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStats {
+    pub blocks: u64,
+    /// the total size of the blocks as stored
+    pub compressed_bytes: u64,
+    /// the total size of the blocks once decompressed (the same as compressed_bytes if uncompressed)
+    pub uncompressed_bytes: u64,
+    /// the total number of records
+    pub items: u64,
+    /// the most records in a single block (usually the itemsPerSlot the file was built with)
+    pub max_items: u64,
+}
+
+impl BlockStats {
+    fn mean(total: u64, blocks: u64) -> f64 {
+        if blocks == 0 { 0.0 } else { total as f64 / blocks as f64 }
+    }
+
+    pub fn mean_compressed_size(&self) -> f64 {
+        BlockStats::mean(self.compressed_bytes, self.blocks)
+    }
+
+    pub fn mean_uncompressed_size(&self) -> f64 {
+        BlockStats::mean(self.uncompressed_bytes, self.blocks)
+    }
+
+    /// the mean number of records per block (items per slot)
+    pub fn mean_items(&self) -> f64 {
+        BlockStats::mean(self.items, self.blocks)
+    }
+
+    /// uncompressed size / compressed size (0 for a file without blocks)
+    pub fn compression_ratio(&self) -> f64 {
+        BlockStats::mean(self.uncompressed_bytes, self.compressed_bytes)
+    }
+}
+
+/// the part of a chromosome holding records, according to the index
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromExtent {
+    pub chrom: String,
+    pub size: u32,
+    /// the smallest start and largest end of the records (None if there are none)
+    pub extent: Option<(u32, u32)>,
+}
+
+impl ChromExtent {
+    pub fn has_data(&self) -> bool {
+        self.extent.is_some()
+    }
+}
+
+// a small, fast pseudo-random number generator (splitmix64)
+// we only need reproducible sampling, not cryptographic quality
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // a number in the range [0, bound)
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// the records overlapping one window of a chromosome
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub start: u32,
+    pub end: u32,
+    pub lines: Vec<BedLine>,
+}
+
+/// an iterator over tiling windows of a chromosome (see BigBed::windows)
+/// blocks shared by adjacent windows are only read and decompressed once
+pub struct Windows<'a, T: Read + Seek> {
+    bigbed: &'a mut BigBed<T>,
+    chrom: Chrom,
+    window_size: u32,
+    step: u32,
+    position: Option<u32>,
+    // the records of the blocks read for the previous window, by block offset
+    cache: Vec<(usize, Vec<BedLine>)>,
+}
+
+impl<'a, T: Read + Seek> Windows<'a, T> {
+    fn load_window(&mut self, start: u32, end: u32) -> Result<Window, Error> {
+        let padded_start = start.saturating_sub(1);
+        let blocks = self.bigbed.overlapping_blocks(self.chrom.id, padded_start, end.saturating_add(1))?;
+        // keep the blocks we have already read, and read the rest
+        let mut cache: Vec<(usize, Vec<BedLine>)> = Vec::with_capacity(blocks.len());
+        let mut missing = Vec::new();
+        for block in blocks {
+            match self.cache.iter().position(|(offset, _)| *offset == block.offset) {
+                Some(index) => cache.push(self.cache.swap_remove(index)),
+                None => missing.push(block),
+            }
+        }
+        if let Some(metrics) = self.bigbed.metrics() {
+            metrics.add_cache_lookups(cache.len() as u64, missing.len() as u64);
+        }
+        let big_endian = self.bigbed.big_endian;
+        self.bigbed.read_blocks(&missing, |block, buff| {
+            let lines = BlockRecords::new(buff, big_endian).map(|record| record.to_bed_line()).collect();
+            cache.push((block.offset, lines));
+            true
+        })?;
+        // the blocks are stored in file order, which matches the order of the records
+        cache.sort_by_key(|(offset, _)| *offset);
+
+        let chrom_id = self.chrom.id;
+        let lines = cache.iter()
+            .flat_map(|(_, lines)| lines.iter())
+            .filter(|line| in_range(line.chrom_id, line.start, line.end, chrom_id, start, end))
+            .cloned()
+            .collect();
+        self.cache = cache;
+        Ok(Window{start, end, lines})
+    }
+}
+
+impl<'a, T: Read + Seek> Iterator for Windows<'a, T> {
+    type Item = Result<Window, Error>;
+
+    fn next(&mut self) -> Option<Result<Window, Error>> {
+        let start = self.position?;
+        let end = start.saturating_add(self.window_size).min(self.chrom.size);
+        // move on to the next window (stopping after the one that reaches the end)
+        self.position = if end >= self.chrom.size {
+            None
+        } else {
+            start.checked_add(self.step)
+        };
+        Some(self.load_window(start, end))
+    }
+}
+
+// the bounds of the records of a data block that are on a chromosome, from the block's R-tree entry
+fn leaf_bounds(leaf: &CIRChild, chrom: &Chrom) -> (u32, u32) {
+    let start = if leaf.start_chrom == chrom.id { leaf.start_base } else { 0 };
+    let end = if leaf.end_chrom == chrom.id { leaf.end_base } else { chrom.size };
+    (start, end)
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    // visit every record in the file, chromosome by chromosome, without
+    // collecting them; stops early if `visit` returns false
+    pub(crate) fn scan_records<F>(&mut self, mut visit: F) -> Result<(), Error>
+        where F: FnMut(&Chrom, &RawRecord) -> bool {
+        let big_endian = self.big_endian;
+        for chrom in self.chrom_list()? {
+            let blocks = self.overlapping_blocks(chrom.id, 0, chrom.size.saturating_add(1))?;
+            let mut keep_going = true;
+            self.read_blocks(&blocks, |_, buff| {
+                for record in BlockRecords::new(buff, big_endian) {
+                    // blocks may span chromosomes, so only visit this chromosome's records
+                    if record.chrom_id == chrom.id && !visit(&chrom, &record) {
+                        keep_going = false;
+                        return false
+                    }
+                }
+                true
+            })?;
+            if !keep_going {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// like query, but exact duplicate records (same position and extra columns) are dropped
+    /// `max_items` counts unique records
+    pub fn query_unique(&mut self, chrom: &str, start: u32, end: u32, max_items: u32) -> Result<Vec<BedLine>, Error> {
+        let chrom_id = self.resolve_chrom(chrom)?.id;
+        let blocks = self.overlapping_blocks(chrom_id, start.saturating_sub(1), end.saturating_add(1))?;
+        let big_endian = self.big_endian;
+        let mut deduplicator = Deduplicator::new();
+        let mut lines = Vec::new();
+        self.read_blocks(&blocks, |_, buff| {
+            for record in BlockRecords::new(buff, big_endian) {
+                if record.in_range(chrom_id, start, end) && deduplicator.is_new(record.chrom_id, record.start, record.end, record.rest) {
+                    if max_items > 0 && lines.len() >= max_items as usize {
+                        return false
+                    }
+                    lines.push(record.to_bed_line());
+                }
+            }
+            true
+        })?;
+        Ok(lines)
+    }
+
+    /// pick `n` records uniformly at random (by reservoir sampling) in a single pass over the file
+    /// the same seed always produces the same sample, which is returned in file order
+    pub fn sample(&mut self, n: usize, seed: u64) -> Result<Vec<NamedBedLine>, Error> {
+        let mut rng = SplitMix64::new(seed);
+        // each sampled record is kept with its position in the file, to restore the order later
+        let mut reservoir: Vec<(u64, NamedBedLine)> = Vec::with_capacity(n);
+        let mut seen: u64 = 0;
+        let mut name: Option<Arc<str>> = None;
+        if n > 0 {
+            self.scan_records(|chrom, record| {
+                // only allocate each chromosome name once
+                let chrom_name = match &name {
+                    Some(current) if &**current == chrom.name() => Arc::clone(current),
+                    _ => {
+                        let current: Arc<str> = Arc::from(chrom.name());
+                        name = Some(Arc::clone(&current));
+                        current
+                    }
+                };
+                if reservoir.len() < n {
+                    reservoir.push((seen, NamedBedLine{chrom: chrom_name, line: record.to_bed_line()}));
+                } else {
+                    let index = rng.below(seen + 1) as usize;
+                    if index < n {
+                        reservoir[index] = (seen, NamedBedLine{chrom: chrom_name, line: record.to_bed_line()});
+                    }
+                }
+                seen += 1;
+                true
+            })?;
+        }
+        reservoir.sort_by_key(|(position, _)| *position);
+        Ok(reservoir.into_iter().map(|(_, line)| line).collect())
+    }
+
+    /// compute a digest of the records (chromosome names, coordinates and extra columns)
+    /// files with the same records have the same fingerprint, regardless of compression,
+    /// block size, or chromosome ids
+    pub fn fingerprint(&mut self) -> Result<Fingerprint, Error> {
+        let mut hasher = Fnv1a::new();
+        let mut records = 0;
+        self.scan_records(|chrom, record| {
+            hasher.write(chrom.name().as_bytes());
+            hasher.write(format!("\t{}\t{}", record.start, record.end).as_bytes());
+            if !record.rest.is_empty() {
+                hasher.write(b"\t");
+                hasher.write(record.rest);
+            }
+            hasher.write(b"\n");
+            records += 1;
+            true
+        })?;
+        Ok(Fingerprint{records, digest: hasher.finish()})
+    }
+
+    /// the statistics of the lengths of the records overlapping a region (chrom, start, end),
+    /// or of every record if no region is given; None if there are no records
+    pub fn length_stats(&mut self, region: Option<(&str, u32, u32)>) -> Result<Option<LengthStats>, Error> {
+        let mut lengths = Vec::new();
+        match region {
+            Some((chrom, start, end)) => {
+                lengths.extend(self.query(chrom, start, end, 0)?.iter().map(|line| line.end.saturating_sub(line.start)));
+            }
+            None => self.scan_records(|_, record| {
+                lengths.push(record.end.saturating_sub(record.start));
+                true
+            })?,
+        }
+        Ok(LengthStats::from_lengths(lengths))
+    }
+
+    /// the smallest start and largest end of the records on a chromosome (None if it has none),
+    /// using only the leaves of the R-tree, so no data is read or decompressed
+    /// (data blocks holding several chromosomes only bound them by the edges of the chromosome)
+    pub fn chrom_bounds(&mut self, chrom: &str) -> Result<Option<(u32, u32)>, Error> {
+        let chrom = self.resolve_chrom(chrom)?;
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        let leaves = index.leaves_where(&mut self.reader, |start_chrom, _, end_chrom, _| {
+            start_chrom <= chrom.id && chrom.id <= end_chrom
+        })?;
+        Ok(leaves.iter().map(|leaf| leaf_bounds(leaf, &chrom)).fold(None, |bounds, (start, end)| match bounds {
+            Some((min_start, max_end)) => Some((start.min(min_start), end.max(max_end))),
+            None => Some((start, end)),
+        }))
+    }
+
+    /// like chrom_bounds, but for every chromosome (including those without records)
+    /// in a single pass over the R-tree
+    pub fn data_extents(&mut self) -> Result<Vec<ChromExtent>, Error> {
+        let chroms = self.chrom_list()?;
+        let positions: HashMap<u32, usize> = chroms.iter().enumerate().map(|(index, chrom)| (chrom.id, index)).collect();
+        let mut extents: Vec<ChromExtent> = chroms.iter()
+            .map(|chrom| ChromExtent{chrom: chrom.name().to_owned(), size: chrom.size, extent: None})
+            .collect();
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        for leaf in index.leaves_where(&mut self.reader, |_, _, _, _| true)? {
+            // a block may hold the end of one chromosome and the start of the next
+            for chrom_id in leaf.start_chrom..=leaf.end_chrom {
+                if let Some(&position) = positions.get(&chrom_id) {
+                    let (start, end) = leaf_bounds(&leaf, &chroms[position]);
+                    let extent = &mut extents[position].extent;
+                    *extent = match *extent {
+                        Some((min_start, max_end)) => Some((start.min(min_start), end.max(max_end))),
+                        None => Some((start, end)),
+                    };
+                }
+            }
+        }
+        Ok(extents)
+    }
+
+    /// count the data blocks on each chromosome (including those without any), using only
+    /// the leaves of the R-tree, so no data is read or decompressed
+    pub fn block_counts_by_chrom(&mut self) -> Result<Vec<ChromCount>, Error> {
+        let mut counts: Vec<ChromCount> = self.chrom_list()?.into_iter()
+            .map(|chrom| ChromCount{chrom: chrom.name().to_owned(), size: chrom.size, blocks: 0, records: None})
+            .collect();
+        let positions: HashMap<u32, usize> = self.chrom_list()?.iter().enumerate().map(|(index, chrom)| (chrom.id, index)).collect();
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        index.visit(&mut self.reader, |node| {
+            if !node.is_leaf {
+                return
+            }
+            // a block may hold the end of one chromosome and the start of the next
+            for child in &node.children {
+                for chrom_id in child.start_chrom..=child.end_chrom {
+                    if let Some(&position) = positions.get(&chrom_id) {
+                        counts[position].blocks += 1;
+                    }
+                }
+            }
+        })?;
+        Ok(counts)
+    }
+
+    /// the number of records in the file, as stored at the start of the data section
+    pub fn record_count(&mut self) -> Result<u64, Error> {
+        self.reader.seek(SeekFrom::Start(self.unzoomed_data_offset))?;
+        Ok(self.reader.read_u64(self.big_endian))
+    }
+
+    /// estimate the number of records in each of `bins` equal bins across a chromosome,
+    /// using only the leaves of the R-tree, so no data is read or decompressed
+    /// every block is taken to hold the mean number of records per block, spread evenly
+    /// over the bases it bounds, so the counts are only a coarse guide (dense and sparse
+    /// stretches within a block even out)
+    pub fn approx_counts(&mut self, chrom: &str, bins: usize) -> Result<Vec<f64>, Error> {
+        let chrom = self.resolve_chrom(chrom)?;
+        let records = self.record_count()?;
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        let mut counts = vec![0.0; bins];
+        if bins == 0 || chrom.size == 0 || index.item_count == 0 {
+            return Ok(counts)
+        }
+        let per_block = records as f64 / index.item_count as f64;
+        let leaves = index.leaves_where(&mut self.reader, |start_chrom, _, end_chrom, _| {
+            start_chrom <= chrom.id && chrom.id <= end_chrom
+        })?;
+        let bin_of = |base: u32| (u64::from(base.min(chrom.size - 1)) * bins as u64 / u64::from(chrom.size)) as usize;
+        for leaf in leaves {
+            // a block holding several chromosomes is shared between them
+            let estimate = per_block / f64::from(leaf.end_chrom - leaf.start_chrom + 1);
+            let (start, end) = leaf_bounds(&leaf, &chrom);
+            if end <= start {
+                counts[bin_of(start)] += estimate;
+                continue
+            }
+            let (first, last) = (bin_of(start), bin_of(end - 1));
+            for (bin, count) in counts.iter_mut().enumerate().take(last + 1).skip(first) {
+                let (bin_start, bin_end) = bin_bounds(0, chrom.size, bins, bin);
+                let overlap = end.min(bin_end).saturating_sub(start.max(bin_start));
+                *count += estimate * f64::from(overlap) / f64::from(end - start);
+            }
+        }
+        Ok(counts)
+    }
+
+    /// read every data block once to compute statistics of their sizes and records
+    pub fn block_stats(&mut self) -> Result<BlockStats, Error> {
+        self.attach_unzoomed_cir()?;
+        let mut blocks: Vec<FileOffsetSize> = self.unzoomed_cir.as_ref().unwrap().all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let mut stats = BlockStats{
+            blocks: blocks.len() as u64, compressed_bytes: 0, uncompressed_bytes: 0,
+            items: 0, max_items: 0,
+        };
+        let big_endian = self.big_endian;
+        self.read_blocks(&blocks, |block, buff| {
+            let items = BlockRecords::new(buff, big_endian).count() as u64;
+            stats.compressed_bytes += block.size as u64;
+            stats.uncompressed_bytes += buff.len() as u64;
+            stats.items += items;
+            stats.max_items = stats.max_items.max(items);
+            true
+        })?;
+        Ok(stats)
+    }
+
+    /// count the data blocks and records on each chromosome (including those without any)
+    /// each block is read once, and records are counted without parsing their fields
+    pub fn counts_by_chrom(&mut self) -> Result<Vec<ChromCount>, Error> {
+        let mut counts = self.block_counts_by_chrom()?;
+        let positions: HashMap<u32, usize> = self.chrom_list()?.iter().enumerate().map(|(index, chrom)| (chrom.id, index)).collect();
+        for count in counts.iter_mut() {
+            count.records = Some(0);
+        }
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        let mut blocks: Vec<FileOffsetSize> = index.all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let big_endian = self.big_endian;
+        self.read_blocks(&blocks, |_, buff| {
+            for record in BlockRecords::new(buff, big_endian) {
+                if let Some(&position) = positions.get(&record.chrom_id) {
+                    counts[position].records = counts[position].records.map(|records| records + 1);
+                }
+            }
+            true
+        })?;
+        Ok(counts)
+    }
+
+    /// check that the records are in non-decreasing (chrom_id, start) order, both within
+    /// and across data blocks, returning the first record that is out of order (if any)
+    pub fn verify_sorted(&mut self) -> Result<Option<UnsortedRecord>, Error> {
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        let mut blocks: Vec<FileOffsetSize> = index.all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let big_endian = self.big_endian;
+        let mut previous: Option<(u32, u32)> = None;
+        let mut unsorted = None;
+        self.read_blocks(&blocks, |block, buff| {
+            for record in BlockRecords::new(buff, big_endian) {
+                let current = (record.chrom_id, record.start);
+                if let Some(previous) = previous {
+                    if current < previous {
+                        unsorted = Some(UnsortedRecord{block_offset: block.offset, previous, record: current});
+                        return false
+                    }
+                }
+                previous = Some(current);
+            }
+            true
+        })?;
+        Ok(unsorted)
+    }
+
+    /// read every data block, checking that each record ends after it starts and within
+    /// its chromosome; returns the number of records read and the invalid ones
+    pub fn check_records(&mut self) -> Result<(u64, Vec<InvalidRecord>), Error> {
+        let sizes: HashMap<u32, u32> = self.chrom_list()?.iter().map(|chrom| (chrom.id, chrom.size)).collect();
+        self.attach_unzoomed_cir()?;
+        let index = self.unzoomed_cir.as_ref().unwrap();
+        let mut blocks: Vec<FileOffsetSize> = index.all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let big_endian = self.big_endian;
+        let mut count = 0;
+        let mut invalid = Vec::new();
+        self.read_blocks(&blocks, |block, buff| {
+            for record in BlockRecords::new(buff, big_endian) {
+                count += 1;
+                let chrom_size = sizes.get(&record.chrom_id).copied();
+                if record.start > record.end || chrom_size.is_none_or(|size| record.end > size) {
+                    invalid.push(InvalidRecord{
+                        block_offset: block.offset,
+                        chrom_id: record.chrom_id,
+                        start: record.start,
+                        end: record.end,
+                        chrom_size,
+                    });
+                }
+            }
+            true
+        })?;
+        Ok((count, invalid))
+    }
+
+    /// the statistics of the score column of the records overlapping a region,
+    /// or None if no record overlaps it (only valid for BED5+ files)
+    pub fn score_stats(&mut self, chrom: &str, start: u32, end: u32) -> Result<Option<ScoreStats>, Error> {
+        // the fifth column of a bed3+2 is a custom one
+        if self.defined_field_count < 5 {
+            return Err(Error::Misc("File has no score column"))
+        }
+        let lines = self.query(chrom, start, end, 0)?;
+        let mut scores = Vec::with_capacity(lines.len());
+        for line in &lines {
+            if let Some(score) = line.defined_score(self.defined_field_count)? {
+                scores.push(score);
+            }
+        }
+        Ok(ScoreStats::from_scores(scores))
+    }
+
+    /// iterate over windows of `window_size` bases tiling a chromosome, starting every `step` bases
+    /// (use step == window_size for non-overlapping windows)
+    pub fn windows(&mut self, chrom: &str, window_size: u32, step: u32) -> Result<Windows<'_, T>, Error> {
+        if window_size == 0 || step == 0 {
+            return Err(Error::Misc("Window size and step must be greater than 0"))
+        }
+        let chrom = self.resolve_chrom(chrom)?;
+        Ok(Windows{bigbed: self, chrom, window_size, step, position: Some(0), cache: Vec::new()})
+    }
+}
+
+#[cfg(test)]
+mod test_scan {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::writer::{BigBedWriter, WriterOptions};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_length_stats() {
+        assert_eq!(LengthStats::from_lengths(vec![]), None);
+        let stats = LengthStats::from_lengths(vec![10, 0, 3, 1, 6]).unwrap();
+        assert_eq!((stats.count, stats.min, stats.max, stats.mean, stats.median), (5, 0, 10, 4.0, 3.0));
+        assert_eq!(stats.histogram, vec![(0, 1), (1, 1), (2, 1), (4, 1), (8, 1)]);
+        let stats = LengthStats::from_lengths(vec![100, 200, 250, 1000]).unwrap();
+        assert_eq!(stats.median, 225.0);
+        assert_eq!(stats.histogram, vec![(64, 1), (128, 2), (256, 0), (512, 1)]);
+
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        let text = std::fs::read_to_string("test/beds/peaks.bed").unwrap();
+        let lengths = |chrom: Option<&str>| -> Vec<u32> {
+            text.lines()
+                .map(|line| line.split('\t').collect::<Vec<&str>>())
+                .filter(|fields| chrom.map(|chrom| fields[0] == chrom).unwrap_or(true))
+                .map(|fields| fields[2].parse::<u32>().unwrap() - fields[1].parse::<u32>().unwrap())
+                .collect()
+        };
+        assert_eq!(bb.length_stats(None).unwrap(), LengthStats::from_lengths(lengths(None)));
+        assert_eq!(bb.length_stats(Some(("chr2", 0, u32::MAX))).unwrap(), LengthStats::from_lengths(lengths(Some("chr2"))));
+        assert_eq!(bb.length_stats(Some(("chr2", 0, 1))).unwrap(), None);
+    }
+
+    #[test]
+    fn test_counts_by_chrom() {
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        let counts = bb.counts_by_chrom().unwrap();
+        let text = std::fs::read_to_string("test/beds/peaks.bed").unwrap();
+        for count in &counts {
+            let expected = text.lines().filter(|line| line.split('\t').next() == Some(count.chrom.as_str())).count();
+            assert_eq!(count.records, Some(expected as u64));
+            assert!(count.blocks > 0 || expected == 0);
+        }
+        assert_eq!(counts.iter().map(|count| count.records.unwrap()).sum::<u64>(), text.lines().count() as u64);
+        // the index alone gives the same block counts
+        let blocks = bb.block_counts_by_chrom().unwrap();
+        assert!(blocks.iter().all(|count| count.records.is_none()));
+        assert_eq!(
+            blocks.iter().map(|count| (&count.chrom, count.blocks)).collect::<Vec<_>>(),
+            counts.iter().map(|count| (&count.chrom, count.blocks)).collect::<Vec<_>>(),
+        );
+        // names are reported without the null padding of the chromosome tree
+        let counts = bb_from_file("test/bigbeds/long.bb").block_counts_by_chrom().unwrap();
+        assert_eq!(counts[0].chrom, "chr1");
+    }
+
+    #[test]
+    fn test_approx_counts() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let total = bb.record_count().unwrap();
+        assert_eq!(total, bb.counts_by_chrom().unwrap().iter().map(|count| count.records.unwrap()).sum::<u64>());
+        let chroms = bb.chrom_list().unwrap();
+        let mut estimated = 0.0;
+        for chrom in &chroms {
+            let counts = bb.approx_counts(chrom.name(), 10).unwrap();
+            assert_eq!(counts.len(), 10);
+            estimated += counts.iter().sum::<f64>();
+        }
+        assert!((estimated - total as f64).abs() < 1e-6 * total as f64);
+
+        // records are spread evenly along chr1, so the estimates come close to the exact counts
+        let chr1 = &chroms[0];
+        let counts = bb.approx_counts("chr1", 4).unwrap();
+        for (bin, count) in counts.iter().enumerate() {
+            let (start, end) = bin_bounds(0, chr1.size(), 4, bin);
+            let exact = bb.query("chr1", start, end, 0).unwrap().iter().filter(|line| line.start >= start).count();
+            assert!((count - exact as f64).abs() <= exact as f64 * 0.2);
+        }
+        assert!(bb.approx_counts("chr1", 0).unwrap().is_empty());
+        assert!(bb.approx_counts("chrNope", 10).is_err());
+    }
+
+    #[test]
+    fn test_chrom_bounds() {
+        let text = std::fs::read_to_string("test/beds/bed12.bed").unwrap();
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        for chrom in bb.chrom_list().unwrap() {
+            let positions: Vec<(u32, u32)> = text.lines()
+                .map(|line| line.split('\t').collect::<Vec<&str>>())
+                .filter(|fields| fields[0] == chrom.name())
+                .map(|fields| (fields[1].parse().unwrap(), fields[2].parse().unwrap()))
+                .collect();
+            let expected = positions.iter().map(|&(start, _)| start).min()
+                .map(|start| (start, positions.iter().map(|&(_, end)| end).max().unwrap()));
+            assert_eq!(bb.chrom_bounds(chrom.name()).unwrap(), expected);
+        }
+        assert!(bb.chrom_bounds("chrNope").is_err());
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let stats = bb.block_stats().unwrap();
+        let counts = bb.counts_by_chrom().unwrap();
+        assert_eq!(stats.items, counts.iter().map(|count| count.records.unwrap()).sum::<u64>());
+        assert_eq!(stats.items, 10000);
+        assert_eq!((stats.blocks, stats.max_items, stats.uncompressed_bytes), (24, 447, 130000));
+        assert!(stats.mean_items() <= stats.max_items as f64);
+        assert!(stats.compression_ratio() > 1.0);
+        assert_eq!(stats.mean_compressed_size() * stats.blocks as f64, stats.compressed_bytes as f64);
+    }
+
+    #[test]
+    fn test_data_extents() {
+        for filename in &["test/bigbeds/bed12.bb", "test/bigbeds/mm10.bb"] {
+            let mut bb = bb_from_file(filename);
+            let extents = bb.data_extents().unwrap();
+            assert_eq!(extents.len(), bb.chrom_list().unwrap().len());
+            for extent in &extents {
+                assert_eq!(extent.extent, bb.chrom_bounds(&extent.chrom).unwrap());
+            }
+            // bedToBigBed only lists the chromosomes with records
+            assert!(extents.iter().all(ChromExtent::has_data));
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut bb = bb_from_file("test/bigbeds/tair10.bb");
+        let windows: Vec<Window> = bb.windows("ChrC", 50000, 50000).unwrap().map(Result::unwrap).collect();
+        let bounds: Vec<(u32, u32)> = windows.iter().map(|window| (window.start, window.end)).collect();
+        assert_eq!(bounds, vec![(0, 50000), (50000, 100000), (100000, 150000), (150000, 154478)]);
+        // each window should match a regular query
+        for window in windows {
+            assert_eq!(window.lines, bb.query("ChrC", window.start, window.end, 0).unwrap());
+        }
+        // overlapping windows
+        let windows: Vec<Window> = bb.windows("Chr1", 2000000, 500000).unwrap().map(Result::unwrap).collect();
+        assert_eq!(windows.len(), 58);
+        assert_eq!((windows[1].start, windows[1].end), (500000, 2500000));
+        for window in windows {
+            assert_eq!(window.lines, bb.query("Chr1", window.start, window.end, 0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let sample = bb.sample(10, 42).unwrap();
+        assert_eq!(sample.len(), 10);
+        // the same seed gives the same sample
+        assert_eq!(sample, bb.sample(10, 42).unwrap());
+        assert_ne!(sample, bb.sample(10, 43).unwrap());
+        // samples are returned in file order, and contain no repeats
+        let names: Vec<&str> = sample.iter().map(|line| line.line.name().unwrap()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+        // asking for more records than exist returns the whole file
+        let all = bb.sample(1000, 1).unwrap();
+        assert_eq!(all.len(), 300);
+        assert_eq!(all[299].to_string(), bb.to_string(None, None, None, None).unwrap()[299].trim_end());
+        assert_eq!(bb.sample(0, 1).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let lines = vec![
+            BedLine::new(0, 10, 20),
+            BedLine::with_rest(0, 10, 20, "a"),
+            BedLine::new(0, 10, 20),
+            BedLine::with_rest(0, 10, 20, "a"),
+            BedLine::new(0, 10, 30),
+            BedLine::new(1, 10, 20),
+            BedLine::new(1, 15, 20),
+            BedLine::new(1, 15, 20),
+        ];
+        assert_eq!(dedup(lines), vec![
+            BedLine::new(0, 10, 20),
+            BedLine::with_rest(0, 10, 20, "a"),
+            BedLine::new(0, 10, 30),
+            BedLine::new(1, 10, 20),
+            BedLine::new(1, 15, 20),
+        ]);
+        // memory is only kept for the current position
+        let mut deduplicator = Deduplicator::new();
+        assert!(deduplicator.is_new(0, 1, 2, b""));
+        assert!(!deduplicator.is_new(0, 1, 2, b""));
+        assert!(deduplicator.is_new(0, 5, 6, b""));
+        assert_eq!(deduplicator.seen.len(), 1);
+    }
+
+    #[test]
+    fn test_query_unique() {
+        // this file has no duplicates, so the results should be unchanged
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        assert_eq!(bb.query_unique("chr1", 0, 10000000, 0).unwrap(), bb.query("chr1", 0, 10000000, 0).unwrap());
+        assert_eq!(bb.query_unique("chr1", 0, 10000000, 3).unwrap(), bb.query("chr1", 0, 10000000, 3).unwrap());
+        let mut bb = bb_from_file("test/bigbeds/dups.bb");
+        assert_eq!(bb.query("chr1", 0, 100, 0).unwrap().len(), 6);
+        assert_eq!(bb.query_unique("chr1", 0, 100, 0).unwrap(), vec![
+            BedLine::with_rest(0, 10, 20, "x"),
+            BedLine::with_rest(0, 10, 20, "y"),
+            BedLine::with_rest(0, 10, 25, "x"),
+            BedLine::with_rest(0, 30, 40, "z"),
+        ]);
+        assert_eq!(bb.query_unique("chr1", 0, 100, 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_windows_errors() {
+        let mut bb = bb_from_file("test/bigbeds/tair10.bb");
+        assert!(bb.windows("Chr1", 0, 10).is_err());
+        assert!(bb.windows("Chr1", 10, 0).is_err());
+        assert_eq!(bb.windows("ChrZ", 10, 10).err(), Some(Error::BadChrom(String::from("ChrZ"))));
+    }
+
+    #[test]
+    fn test_score_stats() {
+        assert_eq!(ScoreStats::from_scores(vec![]), None);
+        assert_eq!(ScoreStats::from_scores(vec![7]), Some(ScoreStats{count: 1, min: 7, max: 7, mean: 7.0, std_dev: 0.0}));
+        let stats = ScoreStats::from_scores(vec![2, 4, 4, 4, 5, 5, 7, 9]).unwrap();
+        assert_eq!((stats.count, stats.min, stats.max, stats.mean), (8, 2, 9, 5.0));
+        assert!((stats.std_dev - (32.0f64 / 7.0).sqrt()).abs() < 1e-9);
+
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        let stats = bb.score_stats("chr1", 1000000, 5000000).unwrap().unwrap();
+        assert_eq!((stats.count, stats.min, stats.max), (46, 4, 972));
+        assert!((stats.mean - 21415.0 / 46.0).abs() < 1e-9);
+        assert!((stats.std_dev - 326.187).abs() < 1e-3);
+        assert_eq!(bb.score_stats("chr1", 0, 1).unwrap(), None);
+        // BED3 files have no scores
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        assert!(bb.score_stats("chr1", 0, 1000000).is_err());
+        // nor do bed3+2 files, though they have a fifth column
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(3);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t10\t20\tpeak1\t3").unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert_eq!(bb.score_stats("chr1", 0, 1000), Err(Error::Misc("File has no score column")));
+    }
+
+    #[test]
+    fn test_verify_sorted() {
+        for name in &["one", "long", "long-unc", "tair10", "bed12", "peaks", "named", "dups"] {
+            let mut bb = bb_from_file(&format!("test/bigbeds/{}.bb", name));
+            assert_eq!(bb.verify_sorted().unwrap(), None, "{}.bb", name);
+        }
+        // the second and third records of this file were swapped
+        let mut bb = bb_from_file("test/bigbeds/unsorted.bb");
+        assert_eq!(bb.verify_sorted().unwrap(), Some(UnsortedRecord{block_offset: 568, previous: (0, 50), record: (0, 30)}));
+    }
+
+    #[test]
+    fn test_check_records() {
+        for name in &["one", "long", "tair10", "bed12"] {
+            let mut bb = bb_from_file(&format!("test/bigbeds/{}.bb", name));
+            let (count, invalid) = bb.check_records().unwrap();
+            assert_eq!(count, bb.record_count().unwrap(), "{}.bb", name);
+            assert_eq!(invalid, vec![], "{}.bb", name);
+        }
+        // move the end of the first record past the end of its chromosome
+        let mut bb = bb_from_file("test/bigbeds/long-unc.bb");
+        let first = bb.unzoomed_data_offset as usize + 8;
+        let mut data = std::fs::read("test/bigbeds/long-unc.bb").unwrap();
+        data[first + 8..first + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut damaged = BigBed::from_file(std::io::Cursor::new(data)).unwrap();
+        let (count, invalid) = damaged.check_records().unwrap();
+        assert_eq!(count, bb.check_records().unwrap().0);
+        let start = bb.query("chr1", 0, 248956422, 1).unwrap()[0].start;
+        assert_eq!(invalid, vec![InvalidRecord{block_offset: first, chrom_id: 0, start, end: u32::MAX, chrom_size: Some(248956422)}]);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"hello");
+        assert_eq!(hasher.finish(), 0xa430d84680aabd0b);
+
+        // the digest is the hash of the BED text
+        let fingerprint = bb_from_file("test/bigbeds/one.bb").fingerprint().unwrap();
+        assert_eq!(fingerprint, Fingerprint{records: 1, digest: 0xb7ff65b2ea8cf127});
+        assert_eq!(fingerprint.to_string(), "b7ff65b2ea8cf127");
+        for name in &["bed12", "peaks"] {
+            let mut hasher = Fnv1a::new();
+            hasher.write(&std::fs::read(format!("test/beds/{}.bed", name)).unwrap());
+            assert_eq!(bb_from_file(&format!("test/bigbeds/{}.bb", name)).fingerprint().unwrap().digest, hasher.finish());
+        }
+        // compression doesn't matter, but the records do
+        let long = bb_from_file("test/bigbeds/long.bb").fingerprint().unwrap();
+        assert_eq!(long, bb_from_file("test/bigbeds/long-unc.bb").fingerprint().unwrap());
+        assert_ne!(long, bb_from_file("test/bigbeds/short1.bb").fingerprint().unwrap());
+    }
+}
@@ -0,0 +1,539 @@
+use crate::{BedLine, BigBed};
+use crate::error::Error;
+use std::fmt;
+use std::io::{Read, Seek};
+
+/// a difference between the fields of two schemas
+/// (positions are 0-based column numbers, in the old schema for removed fields
+/// and in the new schema otherwise)
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    Added{position: usize, name: String},
+    Removed{position: usize, name: String},
+    Renamed{position: usize, from: String, to: String},
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldChange::Added{position, name} => write!(f, "added field {}: {}", position + 1, name),
+            FieldChange::Removed{position, name} => write!(f, "removed field {}: {}", position + 1, name),
+            FieldChange::Renamed{position, from, to} => write!(f, "renamed field {}: {} -> {}", position + 1, from, to),
+        }
+    }
+}
+
+/// compare two lists of field names
+/// fields are matched up by name (keeping their order), and unmatched fields
+/// between two matches are paired up as renames where possible
+pub fn diff_fields(old: &[String], new: &[String]) -> Vec<FieldChange> {
+    // find the longest common subsequence of names
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    // the unmatched fields since the last match
+    let mut removed: Vec<usize> = Vec::new();
+    let mut added: Vec<usize> = Vec::new();
+    let flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>, changes: &mut Vec<FieldChange>| {
+        for (index, &position) in added.iter().enumerate() {
+            match removed.get(index) {
+                Some(&old_position) => changes.push(FieldChange::Renamed{
+                    position, from: old[old_position].clone(), to: new[position].clone()
+                }),
+                None => changes.push(FieldChange::Added{position, name: new[position].clone()}),
+            }
+        }
+        for &position in removed.iter().skip(added.len()) {
+            changes.push(FieldChange::Removed{position, name: old[position].clone()});
+        }
+        removed.clear();
+        added.clear();
+    };
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            flush(&mut removed, &mut added, &mut changes);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+    flush(&mut removed, &mut added, &mut changes);
+    changes
+}
+
+/// the differences between the schemas of two files
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDiff {
+    /// the bed types (e.g. "bed6+4") of the two files
+    pub bed_types: (String, String),
+    pub field_counts: (u16, u16),
+    pub defined_field_counts: (u16, u16),
+    pub changes: Vec<FieldChange>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.bed_types.0 == self.bed_types.1
+            && self.field_counts.0 == self.field_counts.1
+            && self.defined_field_counts.0 == self.defined_field_counts.1
+            && self.changes.is_empty()
+    }
+}
+
+/// a column chosen for output (see BedFormatter::select)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectedColumn {
+    /// the position of the column (counting from 0, so 3 is the name column)
+    pub index: usize,
+    /// the name to give the column, e.g. in a header line
+    pub name: String,
+}
+
+/// choose columns by name, from a comma-separated list like "name,score:peakScore,signalValue"
+/// (where "field:new_name" renames the field), given the field names of the file
+pub fn parse_selection(spec: &str, field_names: &[String]) -> Result<Vec<SelectedColumn>, Error> {
+    spec.split(',').map(|item| {
+        let mut parts = item.trim().splitn(2, ':');
+        let field = parts.next().unwrap_or_default();
+        let name = parts.next().unwrap_or(field);
+        match field_names.iter().position(|field_name| field_name == field) {
+            Some(index) if !name.is_empty() => Ok(SelectedColumn{index, name: name.to_owned()}),
+            _ => Err(Error::BadField("select", item.to_owned())),
+        }
+    }).collect()
+}
+
+/// the type of an autoSql field
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Int,
+    Uint,
+    Short,
+    Ushort,
+    Byte,
+    Ubyte,
+    Bigint,
+    Float,
+    Double,
+    Char,
+    String,
+    Lstring,
+    /// one of the listed values
+    Enum(Vec<String>),
+    /// any number of the listed values, separated by commas
+    Set(Vec<String>),
+    /// any other type (such as a declared object), by name
+    Other(String),
+}
+
+impl FieldType {
+    fn from_name(name: &str) -> FieldType {
+        match name {
+            "int" => FieldType::Int,
+            "uint" => FieldType::Uint,
+            "short" => FieldType::Short,
+            "ushort" => FieldType::Ushort,
+            "byte" => FieldType::Byte,
+            "ubyte" => FieldType::Ubyte,
+            "bigint" => FieldType::Bigint,
+            "float" => FieldType::Float,
+            "double" => FieldType::Double,
+            "char" => FieldType::Char,
+            "string" => FieldType::String,
+            "lstring" => FieldType::Lstring,
+            other => FieldType::Other(other.to_owned()),
+        }
+    }
+
+    /// is this an integer type?
+    pub fn is_integer(&self) -> bool {
+        matches!(self, FieldType::Int | FieldType::Uint | FieldType::Short | FieldType::Ushort
+                 | FieldType::Byte | FieldType::Ubyte | FieldType::Bigint)
+    }
+
+    /// is this a floating-point type?
+    pub fn is_float(&self) -> bool {
+        matches!(self, FieldType::Float | FieldType::Double)
+    }
+}
+
+/// the length of an array field
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArraySize {
+    /// a fixed length, like char[1]
+    Fixed(usize),
+    /// the value of another field, like int[blockCount]
+    Field(String),
+}
+
+/// a field of an autoSql definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoSqlField {
+    pub field_type: FieldType,
+    /// the length, if the field is an array (stored as a comma-separated list,
+    /// except for char arrays, which are strings)
+    pub array: Option<ArraySize>,
+    pub name: String,
+    /// the description of the field (empty if there is none)
+    pub comment: String,
+}
+
+/// a parsed autoSql definition, which describes the columns of a BigBed file
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoSql {
+    /// the name of the table (e.g. "bed" or "narrowPeak")
+    pub name: String,
+    pub comment: String,
+    pub fields: Vec<AutoSqlField>,
+}
+
+// the tokens of an autoSql definition: words, quoted strings, and single punctuation characters
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Quoted(&'a str),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token<'_>>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '"' {
+            let end = rest[1..].find('"').ok_or_else(|| Error::BadField("autoSql", String::from("unterminated comment")))?;
+            tokens.push(Token::Quoted(&rest[1..end + 1]));
+            rest = &rest[end + 2..];
+        } else if "()[];,".contains(c) {
+            tokens.push(Token::Punct(c));
+            rest = &rest[1..];
+        } else {
+            let end = rest.find(|c: char| c.is_whitespace() || "()[];,\"".contains(c)).unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    Ok(tokens)
+}
+
+// walks through the tokens of a definition
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        let token = self.tokens.get(self.position).cloned()
+            .ok_or_else(|| Error::BadField("autoSql", String::from("unexpected end of definition")))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    fn punct(&mut self, expected: char) -> Result<(), Error> {
+        match self.next()? {
+            Token::Punct(c) if c == expected => Ok(()),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    // an optional comment
+    fn comment(&mut self) -> String {
+        match self.peek() {
+            Some(Token::Quoted(text)) => {
+                let text = text.to_string();
+                self.position += 1;
+                text
+            }
+            _ => String::new(),
+        }
+    }
+
+    // the values of an enum or set: (a, b, c)
+    fn values(&mut self) -> Result<Vec<String>, Error> {
+        self.punct('(')?;
+        let mut values = Vec::new();
+        loop {
+            values.push(self.word()?.to_owned());
+            match self.next()? {
+                Token::Punct(',') => continue,
+                Token::Punct(')') => return Ok(values),
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+
+    // a field: type[size] name (index options); "comment"
+    fn field(&mut self) -> Result<AutoSqlField, Error> {
+        let type_name = self.word()?;
+        let field_type = match type_name {
+            "enum" => FieldType::Enum(self.values()?),
+            "set" => FieldType::Set(self.values()?),
+            name => FieldType::from_name(name),
+        };
+        let array = if self.peek() == Some(&Token::Punct('[')) {
+            self.position += 1;
+            let size = self.word()?;
+            self.punct(']')?;
+            Some(match size.parse() {
+                Ok(length) => ArraySize::Fixed(length),
+                Err(_) => ArraySize::Field(size.to_owned()),
+            })
+        } else {
+            None
+        };
+        let name = self.word()?.to_owned();
+        // skip index options like `primary`, `auto` or `index[12]`
+        loop {
+            match self.next()? {
+                Token::Punct(';') => break,
+                Token::Word(_) | Token::Punct('[') | Token::Punct(']') => continue,
+                token => return Err(unexpected(&token)),
+            }
+        }
+        Ok(AutoSqlField{field_type, array, name, comment: self.comment()})
+    }
+}
+
+fn unexpected(token: &Token) -> Error {
+    let text = match token {
+        Token::Word(word) => word.to_string(),
+        Token::Quoted(text) => format!("\"{}\"", text),
+        Token::Punct(c) => c.to_string(),
+    };
+    Error::BadField("autoSql", format!("unexpected '{}'", text))
+}
+
+// the autoSql definition of a bed12, whose fields start every standard definition
+static BED_AUTOSQL: &str = r#"table bed
+"Browser Extensible Data"
+    (
+    string chrom;       "Reference sequence chromosome or scaffold"
+    uint   chromStart;  "Start position in chromosome"
+    uint   chromEnd;    "End position in chromosome"
+    string name;        "Name of item"
+    uint   score;       "Score from 0-1000"
+    char[1] strand;     "+ or - or . for unknown"
+    uint   thickStart;  "Start of where display should be thick (start codon)"
+    uint   thickEnd;    "End of where display should be thick (stop codon)"
+    uint   itemRgb;     "RGB value (use R,G,B string in input file)"
+    int    blockCount;  "Number of blocks"
+    int[blockCount] blockSizes;  "Comma separated list of block sizes"
+    int[blockCount] chromStarts; "Start positions relative to chromStart"
+    )
+"#;
+
+impl AutoSql {
+    /// the definition of a plain bedN file, with the first `defined_field_count`
+    /// standard columns (at most 12)
+    pub fn standard_bed(defined_field_count: u16) -> AutoSql {
+        let mut schema = AutoSql::parse(BED_AUTOSQL).expect("the bed definition parses");
+        schema.fields.truncate(usize::from(defined_field_count));
+        schema
+    }
+
+    /// parse an autoSql definition, such as one from BigBed::autosql_text
+    /// (a `table`, `simple` or `object` declaration; any declarations after the first are ignored)
+    pub fn parse(text: &str) -> Result<AutoSql, Error> {
+        let mut parser = Parser{tokens: tokenize(text)?, position: 0};
+        match parser.word()? {
+            "table" | "simple" | "object" => {}
+            word => return Err(Error::BadField("autoSql", format!("unexpected '{}'", word))),
+        }
+        let name = parser.word()?.to_owned();
+        let comment = parser.comment();
+        parser.punct('(')?;
+        let mut fields = Vec::new();
+        while parser.peek() != Some(&Token::Punct(')')) {
+            fields.push(parser.field()?);
+        }
+        Ok(AutoSql{name, comment, fields})
+    }
+
+    /// the names of the fields, in order
+    pub fn field_names(&self) -> Vec<&str> {
+        self.fields.iter().map(|field| field.name.as_str()).collect()
+    }
+
+    /// the position of the field with this name (counting from 0, so 3 is usually the name column)
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field.name == name)
+    }
+
+    /// the field describing each of the extra columns of a record (those after chromEnd),
+    /// with the column's text
+    /// columns without a field, or fields without a column, are left out
+    pub fn columns<'a>(&'a self, line: &'a BedLine) -> Vec<(&'a AutoSqlField, &'a str)> {
+        let values = line.rest().map(|rest| rest.split('\t')).into_iter().flatten();
+        self.fields.iter().skip(3).zip(values).collect()
+    }
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// choose columns by field name (see parse_selection)
+    pub fn select_columns(&mut self, spec: &str) -> Result<Vec<SelectedColumn>, Error> {
+        parse_selection(spec, &self.field_names()?)
+    }
+
+    /// the parsed autoSql definition of the file, or None if it has none
+    pub fn schema(&mut self) -> Result<Option<AutoSql>, Error> {
+        self.autosql_text()?.map(|text| AutoSql::parse(&text)).transpose()
+    }
+
+    /// compare the schema (bed type and field names) of this file with another
+    pub fn schema_diff<U: Read + Seek>(&mut self, other: &mut BigBed<U>) -> Result<SchemaDiff, Error> {
+        Ok(SchemaDiff{
+            bed_types: (self.bed_type(), other.bed_type()),
+            field_counts: (self.field_count, other.field_count),
+            defined_field_counts: (self.defined_field_count, other.defined_field_count),
+            changes: diff_fields(&self.field_names()?, &other.field_names()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_schema {
+    use super::*;
+    use std::fs::File;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| String::from(*name)).collect()
+    }
+
+    #[test]
+    fn test_diff_fields() {
+        let old = names(&["chrom", "chromStart", "chromEnd", "name", "score"]);
+        assert_eq!(diff_fields(&old, &old), vec![]);
+        assert_eq!(diff_fields(&old, &names(&["chrom", "chromStart", "chromEnd", "name", "score", "strand"])), vec![
+            FieldChange::Added{position: 5, name: String::from("strand")},
+        ]);
+        assert_eq!(diff_fields(&old, &names(&["chrom", "chromStart", "chromEnd", "score"])), vec![
+            FieldChange::Removed{position: 3, name: String::from("name")},
+        ]);
+        // a field inserted in the middle is not reported as renaming every later field
+        assert_eq!(diff_fields(&old, &names(&["chrom", "chromStart", "chromEnd", "id", "name", "score"])), vec![
+            FieldChange::Added{position: 3, name: String::from("id")},
+        ]);
+        assert_eq!(diff_fields(&old, &names(&["chrom", "chromStart", "chromEnd", "gene", "score"])), vec![
+            FieldChange::Renamed{position: 3, from: String::from("name"), to: String::from("gene")},
+        ]);
+        assert_eq!(diff_fields(&old, &names(&["chrom", "start", "end", "name", "score"])), vec![
+            FieldChange::Renamed{position: 1, from: String::from("chromStart"), to: String::from("start")},
+            FieldChange::Renamed{position: 2, from: String::from("chromEnd"), to: String::from("end")},
+        ]);
+        assert_eq!(FieldChange::Added{position: 5, name: String::from("strand")}.to_string(), "added field 6: strand");
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let fields = names(&["chrom", "chromStart", "chromEnd", "name", "score", "signalValue"]);
+        assert_eq!(parse_selection("name,score:peakScore, chrom", &fields).unwrap(), vec![
+            SelectedColumn{index: 3, name: String::from("name")},
+            SelectedColumn{index: 4, name: String::from("peakScore")},
+            SelectedColumn{index: 0, name: String::from("chrom")},
+        ]);
+        assert_eq!(parse_selection("name,pValue", &fields), Err(Error::BadField("select", String::from("pValue"))));
+        assert!(parse_selection("name:", &fields).is_err());
+        assert!(parse_selection("", &fields).is_err());
+
+        let mut peaks = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let selected = peaks.select_columns("signalValue,name").unwrap();
+        assert_eq!(selected.iter().map(|column| column.index).collect::<Vec<usize>>(), vec![6, 3]);
+    }
+
+    #[test]
+    fn test_schema_diff() {
+        let mut peaks = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let mut bed12 = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        let mut bed12_again = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        assert!(bed12.schema_diff(&mut bed12_again).unwrap().is_empty());
+        let diff = peaks.schema_diff(&mut bed12).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.bed_types, (String::from("bed6+4"), String::from("bed12")));
+        assert_eq!(diff.field_counts, (10, 12));
+        assert_eq!(diff.changes, vec![
+            FieldChange::Renamed{position: 6, from: String::from("signalValue"), to: String::from("thickStart")},
+            FieldChange::Renamed{position: 7, from: String::from("pValue"), to: String::from("thickEnd")},
+            FieldChange::Renamed{position: 8, from: String::from("qValue"), to: String::from("reserved")},
+            FieldChange::Renamed{position: 9, from: String::from("peak"), to: String::from("blockCount")},
+            FieldChange::Added{position: 10, name: String::from("blockSizes")},
+            FieldChange::Added{position: 11, name: String::from("chromStarts")},
+        ]);
+    }
+
+    #[test]
+    fn test_parse_autosql() {
+        let schema = AutoSql::parse(&std::fs::read_to_string("test/autosql/narrowPeak.as").unwrap()).unwrap();
+        assert_eq!(schema.name, "narrowPeak");
+        assert!(schema.comment.starts_with("BED6+4 Peaks"));
+        assert_eq!(schema.field_names(), vec![
+            "chrom", "chromStart", "chromEnd", "name", "score", "strand", "signalValue", "pValue", "qValue", "peak"
+        ]);
+        assert_eq!(schema.fields[5], AutoSqlField{
+            field_type: FieldType::Char, array: Some(ArraySize::Fixed(1)), name: String::from("strand"), comment: String::from("+ or - or . for unknown")
+        });
+        assert!(schema.fields[6].field_type.is_float() && schema.fields[9].field_type.is_integer());
+        assert_eq!(schema.position("peak"), Some(9));
+
+        let bed = AutoSql::standard_bed(12);
+        assert_eq!(bed.fields.len(), 12);
+        assert_eq!(bed.fields[11].array, Some(ArraySize::Field(String::from("blockCount"))));
+        assert_eq!(AutoSql::standard_bed(6).field_names(), &schema.field_names()[..6]);
+
+        let text = "table t \"enums\" (\n  uint id primary auto; \"key\"\n  enum(a, b,c) kind;\n  set(x,y) flags; int count; int[count] sizes;\n  myObject thing index[12];\n)";
+        let schema = AutoSql::parse(text).unwrap();
+        assert_eq!(schema.fields.iter().map(|field| field.field_type.clone()).collect::<Vec<_>>(), vec![
+            FieldType::Uint, FieldType::Enum(vec![String::from("a"), String::from("b"), String::from("c")]),
+            FieldType::Set(vec![String::from("x"), String::from("y")]), FieldType::Int, FieldType::Int, FieldType::Other(String::from("myObject")),
+        ]);
+        assert_eq!((schema.fields[0].comment.as_str(), schema.fields[1].comment.as_str()), ("key", ""));
+        assert_eq!(schema.fields[4].array, Some(ArraySize::Field(String::from("count"))));
+
+        assert_eq!(AutoSql::parse("table bed"), Err(Error::BadField("autoSql", String::from("unexpected end of definition"))));
+        assert_eq!(AutoSql::parse("bed ( int x; )"), Err(Error::BadField("autoSql", String::from("unexpected 'bed'"))));
+        assert_eq!(AutoSql::parse("table bed ( int x )"), Err(Error::BadField("autoSql", String::from("unexpected ')'"))));
+        assert!(AutoSql::parse("table bed \"oops ( int x; )").is_err());
+    }
+
+    #[test]
+    fn test_schema() {
+        let mut peaks = BigBed::from_file(File::open("test/bigbeds/peaks.bb").unwrap()).unwrap();
+        let schema = peaks.schema().unwrap().unwrap();
+        assert_eq!(schema.field_names(), peaks.field_names().unwrap());
+        let line = &peaks.query("chr1", 0, 20000, 1).unwrap()[0];
+        let columns = schema.columns(line);
+        assert_eq!(columns.iter().map(|(field, value)| (field.name.as_str(), *value)).collect::<Vec<_>>()[..4],
+                   [("name", "peak1"), ("score", "518"), ("strand", "."), ("signalValue", "35.77229")]);
+        assert_eq!(columns.len(), 7);
+        let mut bed12 = BigBed::from_file(File::open("test/bigbeds/bed12.bb").unwrap()).unwrap();
+        assert_eq!(bed12.schema().unwrap().unwrap().fields[11].array, Some(ArraySize::Field(String::from("blockCount"))));
+        // a BED3 line has no extra columns
+        assert!(schema.columns(&BedLine::new(0, 10, 20)).is_empty());
+    }
+}
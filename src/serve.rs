@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+/// serves the files in a directory over HTTP, with the byte-range requests and
+/// CORS headers that genome browsers (e.g. igv.js, JBrowse) need to read BigBed files
+#[derive(Debug, Clone)]
+pub struct StaticServer {
+    root: PathBuf,
+    max_age: Option<u32>,
+}
+
+/// a parsed `Range: bytes=...` header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// the first and last byte to send (inclusive)
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+}
+
+/// parse a Range header for a file of `len` bytes
+/// only single ranges are supported; anything else is ignored (i.e. None, so the whole file is sent)
+pub fn parse_range(header: &str, len: u64) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None
+    }
+    let (first, last) = spec.split_at(spec.find('-')?);
+    let last = &last[1..];
+    let range = match (first.trim(), last.trim()) {
+        // the last N bytes
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(ByteRange::Unsatisfiable)
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (first, "") => (first.parse().ok()?, len.saturating_sub(1)),
+        (first, last) => {
+            let (first, last): (u64, u64) = (first.parse().ok()?, last.parse().ok()?);
+            if last < first {
+                return None
+            }
+            (first, last.min(len.saturating_sub(1)))
+        }
+    };
+    if range.0 >= len {
+        Some(ByteRange::Unsatisfiable)
+    } else {
+        Some(ByteRange::Satisfiable(range.0, range.1))
+    }
+}
+
+// decode %XX escapes in a URL path
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl StaticServer {
+    pub fn new<P: AsRef<Path>>(root: P) -> StaticServer {
+        StaticServer{root: root.as_ref().to_path_buf(), max_age: None}
+    }
+
+    /// let clients cache files for this many seconds (by default, clients must revalidate)
+    pub fn max_age(mut self, seconds: u32) -> StaticServer {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    // find the file for a request path, refusing paths that leave the root
+    fn resolve(&self, target: &str) -> Option<PathBuf> {
+        let path = target.split(['?', '#']).next()?;
+        let path = percent_decode(path)?;
+        let mut resolved = self.root.clone();
+        for component in Path::new(path.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        if resolved.is_file() {
+            Some(resolved)
+        } else {
+            None
+        }
+    }
+
+    fn write_head(&self, output: &mut impl Write, status: &str, headers: &[(&str, String)]) -> io::Result<()> {
+        write!(output, "HTTP/1.1 {}\r\n", status)?;
+        write!(output, "Access-Control-Allow-Origin: *\r\n")?;
+        write!(output, "Access-Control-Allow-Methods: GET, HEAD, OPTIONS\r\n")?;
+        write!(output, "Access-Control-Allow-Headers: Range\r\n")?;
+        write!(output, "Access-Control-Expose-Headers: Accept-Ranges, Content-Length, Content-Range\r\n")?;
+        write!(output, "Connection: close\r\n")?;
+        for (name, value) in headers {
+            write!(output, "{}: {}\r\n", name, value)?;
+        }
+        write!(output, "\r\n")
+    }
+
+    /// answer a single request read from `input`, writing the response to `output`
+    pub fn handle(&self, input: impl Read, mut output: impl Write) -> io::Result<()> {
+        let mut input = BufReader::new(input);
+        let mut request_line = String::new();
+        input.read_line(&mut request_line)?;
+        let mut range_header = None;
+        loop {
+            let mut header = String::new();
+            if input.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break
+            }
+            if let Some(colon) = header.find(':') {
+                if header[..colon].trim().eq_ignore_ascii_case("range") {
+                    range_header = Some(header[colon + 1..].trim().to_owned());
+                }
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => return self.write_head(&mut output, "400 Bad Request", &[("Content-Length", String::from("0"))]),
+        };
+        match method {
+            "OPTIONS" => return self.write_head(&mut output, "204 No Content", &[]),
+            "GET" | "HEAD" => {}
+            _ => return self.write_head(&mut output, "405 Method Not Allowed", &[
+                ("Allow", String::from("GET, HEAD, OPTIONS")), ("Content-Length", String::from("0")),
+            ]),
+        }
+        let mut file = match self.resolve(target).map(File::open) {
+            Some(Ok(file)) => file,
+            _ => return self.write_head(&mut output, "404 Not Found", &[("Content-Length", String::from("0"))]),
+        };
+        let len = file.metadata()?.len();
+        let cache_control = match self.max_age {
+            Some(seconds) => format!("max-age={}", seconds),
+            None => String::from("no-cache"),
+        };
+        let (status, first, last) = match range_header.and_then(|header| parse_range(&header, len)) {
+            Some(ByteRange::Satisfiable(first, last)) => ("206 Partial Content", first, last),
+            Some(ByteRange::Unsatisfiable) => {
+                return self.write_head(&mut output, "416 Range Not Satisfiable", &[
+                    ("Content-Range", format!("bytes */{}", len)), ("Content-Length", String::from("0")),
+                ])
+            }
+            None => ("200 OK", 0, len.saturating_sub(1)),
+        };
+        let body_len = if len == 0 { 0 } else { last - first + 1 };
+        let mut headers = vec![
+            ("Accept-Ranges", String::from("bytes")),
+            ("Content-Type", String::from("application/octet-stream")),
+            ("Content-Length", body_len.to_string()),
+            ("Cache-Control", cache_control),
+        ];
+        if status.starts_with("206") {
+            headers.push(("Content-Range", format!("bytes {}-{}/{}", first, last, len)));
+        }
+        self.write_head(&mut output, status, &headers)?;
+        if method == "GET" {
+            file.seek(SeekFrom::Start(first))?;
+            io::copy(&mut file.take(body_len), &mut output)?;
+        }
+        output.flush()
+    }
+
+    /// listen for connections, answering each on its own thread
+    pub fn serve(self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let input = match stream.try_clone() {
+                    Ok(input) => input,
+                    Err(_) => return,
+                };
+                // errors only affect this connection
+                let _ = server.handle(input, stream);
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_serve {
+    use super::*;
+
+    fn request(server: &StaticServer, request: &str) -> (String, Vec<u8>) {
+        let mut output = Vec::new();
+        server.handle(request.as_bytes(), &mut output).unwrap();
+        let split = output.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+        (String::from_utf8(output[..split].to_vec()).unwrap(), output[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(ByteRange::Satisfiable(0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some(ByteRange::Satisfiable(500, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(ByteRange::Satisfiable(900, 999)));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some(ByteRange::Satisfiable(0, 999)));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some(ByteRange::Satisfiable(900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn test_handle() {
+        let server = StaticServer::new("test/bigbeds").max_age(60);
+        let contents = std::fs::read("test/bigbeds/one.bb").unwrap();
+
+        let (head, body) = request(&server, "GET /one.bb HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Access-Control-Allow-Origin: *"));
+        assert!(head.contains("Cache-Control: max-age=60"));
+        assert_eq!(body, contents);
+
+        let (head, body) = request(&server, "GET /one.bb HTTP/1.1\r\nrange: bytes=4-7\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 206 Partial Content"));
+        assert!(head.contains(&format!("Content-Range: bytes 4-7/{}", contents.len())));
+        assert_eq!(body, &contents[4..8]);
+
+        let (head, body) = request(&server, "HEAD /one.bb HTTP/1.1\r\n\r\n");
+        assert!(head.contains(&format!("Content-Length: {}", contents.len())));
+        assert!(body.is_empty());
+
+        let (head, _) = request(&server, "GET /one.bb HTTP/1.1\r\nRange: bytes=999999-\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 416"));
+        let (head, _) = request(&server, "OPTIONS /one.bb HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 204") && head.contains("Access-Control-Allow-Headers: Range"));
+        let (head, _) = request(&server, "GET /missing.bb HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 404"));
+        // files outside the root are not served
+        let (head, _) = request(&server, "GET /../beds/one.bed HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 404"));
+        let (head, _) = request(&server, "GET /%2e%2e/beds/one.bed HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 404"));
+        let (head, _) = request(&server, "POST /one.bb HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 405"));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{BedFormatter, BedLine, NamedBedLine};
+use crate::error::Error;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+use std::sync::mpsc::{Sender, SyncSender};
+use std::time::{Duration, Instant};
+
+/// a destination for the records of a query, which are pushed to it one at a time
+/// as they are read (see BigBed::query_into and BigBed::export)
+pub trait RecordSink {
+    /// take a record on the chromosome `chrom`
+    /// returns false to stop the query early (e.g. once the sink has enough records)
+    fn push(&mut self, chrom: &str, line: BedLine) -> Result<bool, Error>;
+}
+
+impl RecordSink for Vec<BedLine> {
+    fn push(&mut self, _chrom: &str, line: BedLine) -> Result<bool, Error> {
+        Vec::push(self, line);
+        Ok(true)
+    }
+}
+
+impl RecordSink for Vec<NamedBedLine> {
+    fn push(&mut self, chrom: &str, line: BedLine) -> Result<bool, Error> {
+        // share the name with the previous record if it is on the same chromosome
+        let chrom = match self.last() {
+            Some(last) if &*last.chrom == chrom => Arc::clone(&last.chrom),
+            _ => Arc::from(chrom),
+        };
+        Vec::push(self, NamedBedLine{chrom, line});
+        Ok(true)
+    }
+}
+
+/// sends each record down a channel, stopping once the receiver is dropped
+impl RecordSink for Sender<BedLine> {
+    fn push(&mut self, _chrom: &str, line: BedLine) -> Result<bool, Error> {
+        Ok(self.send(line).is_ok())
+    }
+}
+
+/// sends each record down a bounded channel (waiting while it is full),
+/// stopping once the receiver is dropped
+impl RecordSink for SyncSender<BedLine> {
+    fn push(&mut self, _chrom: &str, line: BedLine) -> Result<bool, Error> {
+        Ok(self.send(line).is_ok())
+    }
+}
+
+impl<S: RecordSink + ?Sized> RecordSink for &mut S {
+    fn push(&mut self, chrom: &str, line: BedLine) -> Result<bool, Error> {
+        (**self).push(chrom, line)
+    }
+}
+
+/// writes each record as a line of BED text
+#[derive(Debug)]
+pub struct BedSink<W: Write> {
+    output: W,
+    formatter: BedFormatter,
+}
+
+impl<W: Write> BedSink<W> {
+    pub fn new(output: W) -> BedSink<W> {
+        BedSink::with_formatter(output, BedFormatter::default())
+    }
+
+    /// write the records using `formatter` (e.g. to choose columns or a delimiter)
+    pub fn with_formatter(output: W, formatter: BedFormatter) -> BedSink<W> {
+        BedSink{output, formatter}
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> RecordSink for BedSink<W> {
+    fn push(&mut self, chrom: &str, line: BedLine) -> Result<bool, Error> {
+        self.formatter.write(&mut self.output, chrom, &line)?;
+        Ok(true)
+    }
+}
+
+/// the default size of the output buffer
+pub const DEFAULT_OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// options for buffering exported text on its way to the output (see OutputOptions::buffered)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputOptions {
+    buffer_size: usize,
+    flush_lines: u64,
+    flush_interval: Option<Duration>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> OutputOptions {
+        OutputOptions{buffer_size: DEFAULT_OUTPUT_BUFFER_SIZE, flush_lines: 0, flush_interval: None}
+    }
+}
+
+impl OutputOptions {
+    pub fn new() -> OutputOptions {
+        OutputOptions::default()
+    }
+
+    /// the size of the output buffer, which bounds how much text is held before it is written
+    pub fn buffer_size(mut self, size: usize) -> OutputOptions {
+        self.buffer_size = size;
+        self
+    }
+
+    /// also flush after every `lines` lines (0, the default, to flush only when the buffer is full)
+    pub fn flush_lines(mut self, lines: u64) -> OutputOptions {
+        self.flush_lines = lines;
+        self
+    }
+
+    /// also flush at the end of a line once `interval` has passed since the last flush,
+    /// so that a slow export to a pipe shows its progress downstream
+    pub fn flush_interval(mut self, interval: Duration) -> OutputOptions {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// wrap an output in a buffer with these options
+    pub fn buffered<W: Write>(&self, output: W) -> BufferedWriter<W> {
+        BufferedWriter{
+            inner: BufWriter::with_capacity(self.buffer_size.max(1), output),
+            options: *self,
+            lines: 0,
+            last_flush: Instant::now(),
+        }
+    }
+}
+
+/// a buffered writer that can also flush every few lines or every so often
+/// (for example, to pass to write_bed_with or BedSink)
+#[derive(Debug)]
+pub struct BufferedWriter<W: Write> {
+    inner: BufWriter<W>,
+    options: OutputOptions,
+    // the lines written since the last flush
+    lines: u64,
+    last_flush: Instant,
+}
+
+impl<W: Write> BufferedWriter<W> {
+    /// the size of the output buffer
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// flush the buffer and return the output
+    pub fn into_inner(self) -> io::Result<W> {
+        self.inner.into_inner().map_err(|err| err.into_error())
+    }
+}
+
+impl<W: Write> Write for BufferedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let ended = buf[..written].iter().filter(|&&byte| byte == b'\n').count() as u64;
+        if ended > 0 {
+            self.lines += ended;
+            let enough_lines = self.options.flush_lines > 0 && self.lines >= self.options.flush_lines;
+            let long_enough = self.options.flush_interval.is_some_and(|interval| self.last_flush.elapsed() >= interval);
+            if enough_lines || long_enough {
+                self.flush()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines = 0;
+        self.last_flush = Instant::now();
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test_sink {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::query::QueryOptions;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_sinks() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let options = QueryOptions::new();
+        let expected = bb.query("chr1", 0, 2_000_000, 0).unwrap();
+        let mut lines: Vec<BedLine> = Vec::new();
+        assert_eq!(bb.query_into("chr1", 0..2_000_000, &options, &mut lines).unwrap(), expected.len() as u64);
+        assert_eq!(lines, expected);
+
+        let mut named: Vec<NamedBedLine> = Vec::new();
+        bb.query_into("chr1", 0..2_000_000, &options, &mut named).unwrap();
+        assert_eq!(named, bb.query_named("chr1", 0, 2_000_000).unwrap());
+
+        // exporting to BED text matches write_bed
+        let mut sink = BedSink::new(Vec::new());
+        bb.export(None, &options, &mut sink).unwrap();
+        let mut text = Vec::new();
+        bb.write_bed(None, None, None, None, &mut text).unwrap();
+        assert_eq!(sink.into_inner(), text);
+
+        let (sender, receiver) = mpsc::channel();
+        let mut sender = sender;
+        bb.query_into("chr1", 0..2_000_000, &options, &mut sender).unwrap();
+        drop(sender);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), expected);
+        // a dropped receiver stops the query
+        let (mut sender, receiver) = mpsc::sync_channel(10);
+        drop(receiver);
+        assert!(bb.query_into("chr1", 0..2_000_000, &options, &mut sender).unwrap() < expected.len() as u64);
+    }
+
+    // a sink that only wants a few records
+    struct FirstN(usize, Vec<BedLine>);
+
+    impl RecordSink for FirstN {
+        fn push(&mut self, _chrom: &str, line: BedLine) -> Result<bool, Error> {
+            self.1.push(line);
+            Ok(self.1.len() < self.0)
+        }
+    }
+
+    // an output that records the size of each write it receives
+    #[derive(Default)]
+    struct Writes(Vec<usize>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_buffered_writer() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let mut text = Vec::new();
+        bb.write_bed(Some("chr1"), None, None, Some(30), &mut text).unwrap();
+        let lines: Vec<usize> = text.split_inclusive(|&byte| byte == b'\n').map(<[u8]>::len).collect();
+
+        // by default, nothing is written until the buffer fills (or at the end)
+        let mut output = OutputOptions::new().buffered(Writes::default());
+        assert_eq!(output.capacity(), DEFAULT_OUTPUT_BUFFER_SIZE);
+        bb.write_bed(Some("chr1"), None, None, Some(30), &mut output).unwrap();
+        assert!(output.get_ref().0.is_empty());
+        assert_eq!(output.into_inner().unwrap().0, vec![text.len()]);
+
+        // every ten lines
+        let mut output = OutputOptions::new().flush_lines(10).buffered(Writes::default());
+        bb.write_bed(Some("chr1"), None, None, Some(30), &mut output).unwrap();
+        let expected: Vec<usize> = lines.chunks(10).map(|chunk| chunk.iter().sum()).collect();
+        assert_eq!(output.get_ref().0, expected);
+
+        // a small buffer bounds how much is held
+        let mut output = OutputOptions::new().buffer_size(64).buffered(Writes::default());
+        bb.write_bed(Some("chr1"), None, None, Some(30), &mut output).unwrap();
+        let writes = output.into_inner().unwrap().0;
+        assert!(writes.len() > 1 && writes.iter().sum::<usize>() == text.len());
+
+        // a zero interval flushes at the end of every line
+        let mut output = OutputOptions::new().flush_interval(Duration::from_secs(0)).buffered(Writes::default());
+        let mut sink = BedSink::new(&mut output);
+        bb.export(Some("chr1"), &QueryOptions::new().max_items(30), &mut sink).unwrap();
+        assert_eq!(output.get_ref().0, lines);
+    }
+
+    #[test]
+    fn test_sink_stops() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let mut sink = FirstN(3, Vec::new());
+        assert_eq!(bb.export(None, &QueryOptions::new(), &mut sink).unwrap(), 3);
+        assert_eq!(sink.1, bb.query("chr1", 0, 248956422, 3).unwrap());
+        // max_items counts records across chromosomes
+        let mut lines: Vec<NamedBedLine> = Vec::new();
+        let options = QueryOptions::new().max_items(5);
+        let first = bb.chrom_list().unwrap()[0].size();
+        let in_first = bb.query("chr1", 0, first, 0).unwrap().len();
+        assert_eq!(bb.export(None, &options, &mut lines).unwrap(), 5);
+        assert_eq!(bb.export(Some("chr10"), &QueryOptions::new(), &mut lines).unwrap() as usize + 5, lines.len());
+        assert!(in_first > 5 && lines[..5].iter().all(|line| &*line.chrom == "chr1"));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::BigBed;
+use crate::error::Error;
+use crate::schema::{AutoSql, AutoSqlField};
+use rusqlite::Connection;
+use rusqlite::types::Value;
+use std::io::{Read, Seek};
+
+/// how a column is stored in SQLite
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqlType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl SqlType {
+    /// the SQLite type for a field of an autoSql definition
+    /// (arrays and anything not numeric are stored as text)
+    pub fn from_field(field: &AutoSqlField) -> SqlType {
+        if field.array.is_some() {
+            SqlType::Text
+        } else if field.field_type.is_integer() {
+            SqlType::Integer
+        } else if field.field_type.is_float() {
+            SqlType::Real
+        } else {
+            SqlType::Text
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SqlType::Integer => "INTEGER",
+            SqlType::Real => "REAL",
+            SqlType::Text => "TEXT",
+        }
+    }
+
+    // convert a BED field, keeping the text if it doesn't parse
+    fn value(self, field: &str) -> Value {
+        let parsed = match self {
+            SqlType::Integer => field.parse::<i64>().ok().map(Value::Integer),
+            SqlType::Real => field.parse::<f64>().ok().map(Value::Real),
+            SqlType::Text => None,
+        };
+        parsed.unwrap_or_else(|| Value::Text(field.to_owned()))
+    }
+}
+
+/// a column of an exported table
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlColumn {
+    pub name: String,
+    pub sql_type: SqlType,
+}
+
+// quote an identifier (e.g. a table or column name)
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    /// the columns used when exporting to SQLite, typed from the autoSql definition
+    /// (or the standard BED types if the file has no usable definition)
+    pub fn sql_columns(&mut self) -> Result<Vec<SqlColumn>, Error> {
+        let names = self.field_names()?;
+        let schema = match self.schema()? {
+            Some(schema) if schema.fields.len() == names.len() => schema,
+            _ => AutoSql::standard_bed(self.defined_field_count),
+        };
+        // custom columns without a definition are stored as text
+        let types = schema.fields.iter().map(SqlType::from_field).chain(std::iter::repeat(SqlType::Text));
+        Ok(names.into_iter().zip(types).map(|(name, sql_type)| SqlColumn{name, sql_type}).collect())
+    }
+
+    /// create `table` in an SQLite database and fill it with the records of this file,
+    /// or only those overlapping `region` (chrom, start, end) if given
+    /// the table is indexed on its first three columns (chrom, start, end)
+    /// returns the number of records written
+    pub fn export_sqlite(&mut self, conn: &mut Connection, table: &str, region: Option<(&str, u32, u32)>) -> Result<u64, Error> {
+        let columns = self.sql_columns()?;
+        if columns.len() < 3 {
+            return Err(Error::BadField("field_count", columns.len().to_string()))
+        }
+        let definitions: Vec<String> = columns.iter()
+            .map(|column| format!("{} {}", quote(&column.name), column.sql_type.name()))
+            .collect();
+        let placeholders: Vec<String> = (1..=columns.len()).map(|index| format!("?{}", index)).collect();
+
+        let tx = conn.transaction()?;
+        tx.execute(&format!("CREATE TABLE {} ({})", quote(table), definitions.join(", ")), [])?;
+        tx.execute(&format!(
+            "CREATE INDEX {} ON {} ({}, {}, {})",
+            quote(&format!("{}_position", table)), quote(table),
+            quote(&columns[0].name), quote(&columns[1].name), quote(&columns[2].name),
+        ), [])?;
+        let mut count = 0;
+        {
+            let mut insert = tx.prepare(&format!("INSERT INTO {} VALUES ({})", quote(table), placeholders.join(", ")))?;
+            let mut row = |chrom: &str, start: u32, end: u32, rest: Option<&str>| -> rusqlite::Result<()> {
+                let mut values = vec![Value::Text(chrom.to_owned()), Value::Integer(start.into()), Value::Integer(end.into())];
+                let mut fields = rest.into_iter().flat_map(|rest| rest.split('\t'));
+                for column in &columns[3..] {
+                    // missing columns are left NULL
+                    values.push(fields.next().map(|field| column.sql_type.value(field)).unwrap_or(Value::Null));
+                }
+                insert.execute(rusqlite::params_from_iter(values))?;
+                count += 1;
+                Ok(())
+            };
+            match region {
+                Some((chrom, start, end)) => {
+                    for named in self.query_named(chrom, start, end)? {
+                        row(&named.chrom, named.line.start(), named.line.end(), named.line.rest())?;
+                    }
+                }
+                None => {
+                    let mut result = Ok(());
+                    self.scan_records(|chrom, record| {
+                        let line = record.to_bed_line();
+                        result = row(chrom.name(), line.start(), line.end(), line.rest());
+                        result.is_ok()
+                    })?;
+                    result?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test_sqlite {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::writer::{BigBedWriter, WriterOptions};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_sql_columns() {
+        let columns = bb_from_file("test/bigbeds/peaks.bb").sql_columns().unwrap();
+        let types: Vec<(&str, SqlType)> = columns.iter().map(|column| (column.name.as_str(), column.sql_type)).collect();
+        assert_eq!(types, vec![
+            ("chrom", SqlType::Text), ("chromStart", SqlType::Integer), ("chromEnd", SqlType::Integer),
+            ("name", SqlType::Text), ("score", SqlType::Integer), ("strand", SqlType::Text),
+            ("signalValue", SqlType::Real), ("pValue", SqlType::Real), ("qValue", SqlType::Real), ("peak", SqlType::Integer),
+        ]);
+
+        // without a definition, the standard columns get the standard types
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(5);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t10\t20\tpeak1\t3\t7").unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert_eq!(bb.schema().unwrap(), None);
+        let types: Vec<SqlType> = bb.sql_columns().unwrap().iter().map(|column| column.sql_type).collect();
+        assert_eq!(types, vec![SqlType::Text, SqlType::Integer, SqlType::Integer, SqlType::Text, SqlType::Integer, SqlType::Text]);
+    }
+
+    #[test]
+    fn test_export_sqlite() {
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        let mut conn = Connection::open_in_memory().unwrap();
+        let expected = std::fs::read_to_string("test/beds/peaks.bed").unwrap();
+        let count = bb.export_sqlite(&mut conn, "peaks", None).unwrap();
+        assert_eq!(count as usize, expected.lines().count());
+
+        let total: i64 = conn.query_row("SELECT COUNT(*) FROM peaks", [], |row| row.get(0)).unwrap();
+        assert_eq!(total as u64, count);
+        // the first record comes back with typed columns
+        let first: Vec<&str> = expected.lines().next().unwrap().split('\t').collect();
+        let (chrom, start, signal): (String, i64, f64) = conn.query_row(
+            "SELECT chrom, chromStart, signalValue FROM peaks ORDER BY rowid LIMIT 1", [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).unwrap();
+        assert_eq!((chrom.as_str(), start.to_string()), (first[0], first[1].to_owned()));
+        assert_eq!(signal, first[6].parse::<f64>().unwrap());
+        let indexes: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND tbl_name = 'peaks'", [], |row| row.get(0)).unwrap();
+        assert_eq!(indexes, 1);
+
+        // a region only exports the overlapping records
+        let in_region = expected.lines()
+            .map(|line| line.split('\t').collect::<Vec<&str>>())
+            .filter(|fields| fields[0] == first[0] && fields[1].parse::<u32>().unwrap() < 1_000_000)
+            .count();
+        assert_eq!(bb.export_sqlite(&mut conn, "region", Some((first[0], 0, 1_000_000))).unwrap() as usize, in_region);
+        // the table must not already exist
+        assert!(bb.export_sqlite(&mut conn, "peaks", None).is_err());
+    }
+}
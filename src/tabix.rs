@@ -0,0 +1,344 @@
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+// the most uncompressed data put in a single BGZF block (as bgzip does)
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+// the empty block that marks the end of a BGZF file
+static BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+    0x02, 0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// writes BGZF, the blocked gzip format used by htslib (i.e. bgzip)
+/// the output is a valid gzip file, but can also be read at random via virtual offsets
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    // the number of compressed bytes written so far
+    compressed_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> BgzfWriter<W> {
+        BgzfWriter{inner, buffer: Vec::with_capacity(BGZF_BLOCK_SIZE), compressed_offset: 0}
+    }
+
+    /// the virtual offset of the next byte written:
+    /// the offset of its block in the file (upper 48 bits) and its position in the block (lower 16 bits)
+    pub fn virtual_offset(&self) -> u64 {
+        (self.compressed_offset << 16) | self.buffer.len() as u64
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(())
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let data = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+        // a gzip header with the 'BC' extra field, which holds the block size (minus one)
+        let block_size = data.len() + 26;
+        let mut header = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0x00, b'B', b'C', 0x02, 0x00];
+        header.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&data)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.compressed_offset += block_size as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// write any buffered data and the end-of-file marker, returning the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..count]);
+        // blocks are written as soon as they are full, so the virtual offset never points past a block
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+/// the bin holding the interval [start, end), in the UCSC binning scheme used by tabix
+pub fn reg2bin(start: u32, end: u32) -> u32 {
+    let end = end.max(start.saturating_add(1)) - 1;
+    if start >> 14 == end >> 14 {
+        4681 + (start >> 14)
+    } else if start >> 17 == end >> 17 {
+        585 + (start >> 17)
+    } else if start >> 20 == end >> 20 {
+        73 + (start >> 20)
+    } else if start >> 23 == end >> 23 {
+        9 + (start >> 23)
+    } else if start >> 26 == end >> 26 {
+        1 + (start >> 26)
+    } else {
+        0
+    }
+}
+
+// the index of a single sequence
+#[derive(Debug, Clone, Default)]
+struct SequenceIndex {
+    // the (start, end) virtual offsets of the records in each bin
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    // the smallest virtual offset of a record overlapping each 16kb window
+    linear: Vec<Option<u64>>,
+}
+
+/// a tabix (.tbi) index of a BGZF-compressed BED file
+#[derive(Debug, Clone, Default)]
+pub struct TabixIndex {
+    names: Vec<String>,
+    sequences: Vec<SequenceIndex>,
+    ids: HashMap<String, usize>,
+    // the last record added: (sequence, start)
+    last: Option<(usize, u32)>,
+}
+
+impl TabixIndex {
+    pub fn new() -> TabixIndex {
+        TabixIndex::default()
+    }
+
+    /// add a record, stored between the virtual offsets `begin` and `finish`
+    /// records must be grouped by chromosome and sorted by start
+    pub fn add(&mut self, chrom: &str, start: u32, end: u32, begin: u64, finish: u64) -> io::Result<()> {
+        let id = match self.ids.get(chrom) {
+            Some(&id) => id,
+            None => {
+                self.ids.insert(chrom.to_owned(), self.names.len());
+                self.names.push(chrom.to_owned());
+                self.sequences.push(SequenceIndex::default());
+                self.names.len() - 1
+            }
+        };
+        match self.last {
+            Some((last_id, _)) if id < last_id => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("records on {} are not contiguous", chrom)))
+            }
+            Some((last_id, last_start)) if last_id == id && start < last_start => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("records on {} are not sorted", chrom)))
+            }
+            _ => {}
+        }
+        self.last = Some((id, start));
+
+        let sequence = &mut self.sequences[id];
+        let chunks = sequence.bins.entry(reg2bin(start, end)).or_default();
+        match chunks.last_mut() {
+            // extend the last chunk if this record follows straight on from it
+            Some(chunk) if chunk.1 == begin => chunk.1 = finish,
+            _ => chunks.push((begin, finish)),
+        }
+        let last_window = (end.max(start.saturating_add(1)) - 1) >> 14;
+        if sequence.linear.len() <= last_window as usize {
+            sequence.linear.resize(last_window as usize + 1, None);
+        }
+        for window in (start >> 14)..=last_window {
+            sequence.linear[window as usize].get_or_insert(begin);
+        }
+        Ok(())
+    }
+
+    /// write the index (BGZF-compressed, like tabix does)
+    pub fn write(&self, output: impl Write) -> io::Result<()> {
+        let mut output = BgzfWriter::new(output);
+        let write_i32 = |output: &mut BgzfWriter<_>, value: i32| output.write_all(&value.to_le_bytes());
+        output.write_all(b"TBI\x01")?;
+        write_i32(&mut output, self.names.len() as i32)?;
+        // the preset for BED: generic format with 0-based starts (TI_FLAG_UCSC)
+        write_i32(&mut output, 0x10000)?;
+        // the chrom, start and end columns, then the comment character and lines to skip
+        write_i32(&mut output, 1)?;
+        write_i32(&mut output, 2)?;
+        write_i32(&mut output, 3)?;
+        write_i32(&mut output, i32::from(b'#'))?;
+        write_i32(&mut output, 0)?;
+        let names: Vec<u8> = self.names.iter().flat_map(|name| name.bytes().chain(Some(0))).collect();
+        write_i32(&mut output, names.len() as i32)?;
+        output.write_all(&names)?;
+        for sequence in &self.sequences {
+            write_i32(&mut output, sequence.bins.len() as i32)?;
+            for (bin, chunks) in &sequence.bins {
+                output.write_all(&bin.to_le_bytes())?;
+                write_i32(&mut output, chunks.len() as i32)?;
+                for (begin, end) in chunks {
+                    output.write_all(&begin.to_le_bytes())?;
+                    output.write_all(&end.to_le_bytes())?;
+                }
+            }
+            // windows without records point at the previous window's records
+            write_i32(&mut output, sequence.linear.len() as i32)?;
+            let mut previous = 0;
+            for offset in &sequence.linear {
+                previous = offset.unwrap_or(previous);
+                output.write_all(&previous.to_le_bytes())?;
+            }
+        }
+        output.finish().map(|_| ())
+    }
+}
+
+/// writes BED text as BGZF, indexing each line as it goes
+/// (lines starting with '#' are written but not indexed)
+pub struct TabixWriter<W: Write> {
+    output: BgzfWriter<W>,
+    index: TabixIndex,
+    // the current (incomplete) line and its starting virtual offset
+    line: Vec<u8>,
+    line_start: u64,
+}
+
+impl<W: Write> TabixWriter<W> {
+    pub fn new(output: W) -> TabixWriter<W> {
+        TabixWriter{output: BgzfWriter::new(output), index: TabixIndex::new(), line: Vec::new(), line_start: 0}
+    }
+
+    fn index_line(&mut self) -> io::Result<()> {
+        let text = String::from_utf8_lossy(&self.line);
+        let text = text.trim_end_matches(&['\n', '\r'][..]);
+        if text.is_empty() || text.starts_with('#') {
+            return Ok(())
+        }
+        let mut fields = text.split('\t');
+        let bad_line = || io::Error::new(io::ErrorKind::InvalidData, format!("Could not index line: {}", text));
+        let chrom = fields.next().ok_or_else(bad_line)?;
+        let start: u32 = fields.next().and_then(|field| field.parse().ok()).ok_or_else(bad_line)?;
+        let end: u32 = fields.next().and_then(|field| field.parse().ok()).ok_or_else(bad_line)?;
+        self.index.add(chrom, start, end, self.line_start, self.output.virtual_offset())
+    }
+
+    /// finish the BGZF output, returning the underlying writer and the index of what was written
+    pub fn finish(mut self) -> io::Result<(W, TabixIndex)> {
+        // index a final line without a newline
+        if !self.line.is_empty() {
+            self.index_line()?;
+        }
+        Ok((self.output.finish()?, self.index))
+    }
+}
+
+impl<W: Write> Write for TabixWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // only write up to the end of the current line, so its end offset is known
+        let count = match buf.iter().position(|&byte| byte == b'\n') {
+            Some(newline) => newline + 1,
+            None => buf.len(),
+        };
+        if self.line.is_empty() {
+            self.line_start = self.output.virtual_offset();
+        }
+        self.output.write_all(&buf[..count])?;
+        self.line.extend_from_slice(&buf[..count]);
+        if self.line.last() == Some(&b'\n') {
+            self.index_line()?;
+            self.line.clear();
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod test_tabix {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::convert::TryInto;
+    use std::io::Read;
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut text = Vec::new();
+        MultiGzDecoder::new(data).read_to_end(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_reg2bin() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(0, 16384), 4681);
+        assert_eq!(reg2bin(16383, 16385), 585);
+        assert_eq!(reg2bin(20000, 20000), 4682);
+        assert_eq!(reg2bin(0, 1 << 26), 1);
+        assert_eq!(reg2bin(0, (1 << 26) + 1), 0);
+    }
+
+    #[test]
+    fn test_bgzf() {
+        let text: Vec<u8> = (0..50_000).flat_map(|n| format!("chr1\t{}\t{}\n", n, n + 10).into_bytes()).collect();
+        let mut writer = BgzfWriter::new(Vec::new());
+        writer.write_all(&text).unwrap();
+        let data = writer.finish().unwrap();
+        assert_eq!(gunzip(&data), text);
+        assert!(data.ends_with(&BGZF_EOF));
+
+        // every block records its own size
+        let mut offset = 0;
+        let mut blocks = 0;
+        while offset < data.len() {
+            assert_eq!(&data[offset..offset + 4], &[0x1f, 0x8b, 0x08, 0x04]);
+            assert_eq!(&data[offset + 12..offset + 14], b"BC");
+            offset += usize::from(u16::from_le_bytes(data[offset + 16..offset + 18].try_into().unwrap())) + 1;
+            blocks += 1;
+        }
+        assert_eq!(offset, data.len());
+        assert_eq!(blocks, text.len() / BGZF_BLOCK_SIZE + 2);
+    }
+
+    #[test]
+    fn test_tabix_writer() {
+        let mut writer = TabixWriter::new(Vec::new());
+        let mut text = String::from("#header\n");
+        for n in 0..20_000u32 {
+            text.push_str(&format!("chr1\t{}\t{}\tname{}\n", n * 10, n * 10 + 5, n));
+        }
+        text.push_str("chr2\t100\t40000\tlong\n");
+        writer.write_all(text.as_bytes()).unwrap();
+        let (data, index) = writer.finish().unwrap();
+        assert_eq!(gunzip(&data), text.as_bytes());
+        assert_eq!(index.names, vec!["chr1", "chr2"]);
+        assert_eq!(index.sequences[0].linear.len(), (199_995 >> 14) + 1);
+        assert_eq!(index.sequences[1].bins.keys().collect::<Vec<_>>(), vec![&585]);
+        assert_eq!(index.sequences[1].linear.len(), 3);
+        // the first record starts right after the header
+        assert_eq!(index.sequences[0].linear[0], Some(8));
+
+        let mut tbi = Vec::new();
+        index.write(&mut tbi).unwrap();
+        let tbi = gunzip(&tbi);
+        assert_eq!(&tbi[0..4], b"TBI\x01");
+        assert_eq!(i32::from_le_bytes(tbi[4..8].try_into().unwrap()), 2);
+        assert_eq!(&tbi[36..46], b"chr1\0chr2\0");
+
+        // records out of order can't be indexed
+        let mut writer = TabixWriter::new(Vec::new());
+        assert!(writer.write_all(b"chr1\t100\t200\nchr1\t50\t60\n").is_err());
+        let mut writer = TabixWriter::new(Vec::new());
+        assert!(writer.write_all(b"chr1\t100\t200\nchr2\t50\t60\nchr1\t300\t400\n").is_err());
+    }
+}
@@ -0,0 +1,770 @@
+use crate::{Chrom, ZoomLevel, BIGBED_SIG, BPT_SIG, CIRTREE_SIG};
+use crate::error::Error;
+use crate::region::{is_bed_header, parse_bed_region};
+use crate::rtree::CIRChild;
+use crate::zoom::{Summary, ZoomRecord};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::convert::TryInto;
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
+
+/// the version of the format written (the same as current versions of bedToBigBed)
+pub const BIGBED_VERSION: u16 = 4;
+/// the most zoom levels written (space for their headers is kept before the data)
+pub const MAX_ZOOM_LEVELS: usize = 10;
+/// the default number of children per node of the trees (as in bedToBigBed)
+pub const DEFAULT_BLOCK_SIZE: u32 = 256;
+/// the default number of records per data block (as in bedToBigBed)
+pub const DEFAULT_ITEMS_PER_SLOT: u32 = 512;
+// how much coarser each zoom level is than the last
+const ZOOM_INCREMENT: u32 = 4;
+// the size of the header and of each zoom level header
+pub(crate) const HEADER_SIZE: u64 = 64;
+const ZOOM_HEADER_SIZE: u64 = 24;
+// the sizes of a node's header, and of the entries of R-tree leaves and internal nodes
+const NODE_HEADER_SIZE: u64 = 4;
+const CIR_LEAF_ENTRY_SIZE: u64 = 32;
+const CIR_INTERNAL_ENTRY_SIZE: u64 = 24;
+
+/// a collection of methods for writing little-endian values (the counterpart of ByteReader)
+pub(crate) trait ByteWriter: Write {
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_f64(&mut self, value: f64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+}
+
+impl<T: Write> ByteWriter for T {}
+
+/// the fields of the header of a file being written
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Header {
+    pub(crate) chrom_tree_offset: u64,
+    pub(crate) data_offset: u64,
+    pub(crate) index_offset: u64,
+    pub(crate) field_count: u16,
+    pub(crate) defined_field_count: u16,
+    pub(crate) as_offset: u64,
+    pub(crate) total_summary_offset: u64,
+    pub(crate) uncompress_buf_size: u32,
+    pub(crate) extension_offset: u64,
+    pub(crate) level_list: Vec<ZoomLevel>,
+}
+
+impl Header {
+    // write the header and zoom level headers, leaving room for MAX_ZOOM_LEVELS of them
+    pub(crate) fn write(&self, output: &mut impl Write) -> io::Result<()> {
+        // the signature is stored as a little-endian number
+        let mut sig = BIGBED_SIG;
+        sig.reverse();
+        output.write_all(&sig)?;
+        output.write_u16(BIGBED_VERSION)?;
+        output.write_u16(self.level_list.len() as u16)?;
+        output.write_u64(self.chrom_tree_offset)?;
+        output.write_u64(self.data_offset)?;
+        output.write_u64(self.index_offset)?;
+        output.write_u16(self.field_count)?;
+        output.write_u16(self.defined_field_count)?;
+        output.write_u64(self.as_offset)?;
+        output.write_u64(self.total_summary_offset)?;
+        output.write_u32(self.uncompress_buf_size)?;
+        output.write_u64(self.extension_offset)?;
+        for level in &self.level_list {
+            output.write_u32(level.reduction_level)?;
+            output.write_u32(level.reserved)?;
+            output.write_u64(level.data_offset)?;
+            output.write_u64(level.index_offset)?;
+        }
+        let unused = MAX_ZOOM_LEVELS.saturating_sub(self.level_list.len()) as u64;
+        output.write_all(&vec![0; (unused * ZOOM_HEADER_SIZE) as usize])
+    }
+
+    // the space to leave for the header at the start of the file
+    pub(crate) fn reserved_size() -> u64 {
+        HEADER_SIZE + MAX_ZOOM_LEVELS as u64 * ZOOM_HEADER_SIZE
+    }
+}
+
+// the signature of a tree, as a little-endian number
+fn le_sig(sig: [u8; 4]) -> [u8; 4] {
+    let mut sig = sig;
+    sig.reverse();
+    sig
+}
+
+// the number of levels a tree needs to hold `count` items, `block_size` to a node
+fn tree_levels(count: usize, block_size: usize) -> u32 {
+    let mut levels = 1;
+    let mut span = block_size;
+    while span < count {
+        span = span.saturating_mul(block_size);
+        levels += 1;
+    }
+    levels
+}
+
+// the number of entries (each pointing to a child node) at a level of a tree above the leaves
+fn level_entries(count: usize, block_size: usize, level: u32) -> usize {
+    count.div_ceil(block_size.pow(level))
+}
+
+/// write the chromosome tree (a B+ tree from names to ids and sizes) at the current position
+/// nodes are padded to `block_size` entries, as bedToBigBed does
+pub(crate) fn write_chrom_tree<W: Write + Seek>(output: &mut W, chroms: &[Chrom], block_size: u32) -> io::Result<()> {
+    let mut chroms: Vec<&Chrom> = chroms.iter().collect();
+    chroms.sort_by(|a, b| a.name().as_bytes().cmp(b.name().as_bytes()));
+    let key_size = chroms.iter().map(|chrom| chrom.name().len()).max().unwrap_or(0).max(1);
+    let block_size = (block_size as usize).min(chroms.len()).max(1);
+    output.write_all(&le_sig(BPT_SIG))?;
+    output.write_u32(block_size as u32)?;
+    output.write_u32(key_size as u32)?;
+    output.write_u32(8)?;
+    output.write_u64(chroms.len() as u64)?;
+    output.write_u64(0)?;
+
+    let key = |chrom: &Chrom| {
+        let mut key = chrom.name().as_bytes().to_vec();
+        key.resize(key_size, 0);
+        key
+    };
+    // every entry (a key and either a value or a child offset) takes up the same space
+    let entry_size = key_size + 8;
+    let node_size = (NODE_HEADER_SIZE as usize + block_size * entry_size) as u64;
+    let levels = tree_levels(chroms.len(), block_size);
+    // write the levels from the root down, so each node knows where its children will be
+    let mut level_offset = output.stream_position()?;
+    for level in (0..levels).rev() {
+        let entries = if level == 0 { chroms.len() } else { level_entries(chroms.len(), block_size, level) };
+        let nodes = entries.div_ceil(block_size).max(1);
+        let child_level_offset = level_offset + nodes as u64 * node_size;
+        for node in 0..nodes {
+            let first = node * block_size;
+            let count = entries.saturating_sub(first).min(block_size);
+            output.write_all(&[(level == 0) as u8, 0])?;
+            output.write_u16(count as u16)?;
+            for entry in first..first + count {
+                if level == 0 {
+                    output.write_all(&key(chroms[entry]))?;
+                    output.write_u32(chroms[entry].id)?;
+                    output.write_u32(chroms[entry].size)?;
+                } else {
+                    // the key of a child is the first key below it
+                    output.write_all(&key(chroms[entry * block_size.pow(level)]))?;
+                    output.write_u64(child_level_offset + entry as u64 * node_size)?;
+                }
+            }
+            output.write_all(&vec![0; (block_size - count) * entry_size])?;
+        }
+        level_offset = child_level_offset;
+    }
+    Ok(())
+}
+
+// the bounds of a run of leaves: from the first start to the last end
+fn bounds(leaves: &[CIRChild]) -> (u32, u32, u32, u32) {
+    let first = &leaves[0];
+    let (end_chrom, end_base) = leaves.iter().map(|leaf| (leaf.end_chrom, leaf.end_base)).max().unwrap();
+    (first.start_chrom, first.start_base, end_chrom, end_base)
+}
+
+/// write an R-tree indexing data blocks (`leaves`, in file order) at the current position
+/// `data_end` is the offset of the end of the indexed data
+pub(crate) fn write_cir_tree<W: Write + Seek>(output: &mut W, leaves: &[CIRChild], block_size: u32, data_end: u64) -> io::Result<()> {
+    let block_size = (block_size as usize).max(2);
+    let (start_chrom, start_base, end_chrom, end_base) = if leaves.is_empty() { (0, 0, 0, 0) } else { bounds(leaves) };
+    output.write_all(&le_sig(CIRTREE_SIG))?;
+    output.write_u32(block_size as u32)?;
+    output.write_u64(leaves.len() as u64)?;
+    output.write_u32(start_chrom)?;
+    output.write_u32(start_base)?;
+    output.write_u32(end_chrom)?;
+    output.write_u32(end_base)?;
+    output.write_u64(data_end)?;
+    // each leaf entry indexes a single block
+    output.write_u32(1)?;
+    output.write_u32(0)?;
+
+    let internal_size = NODE_HEADER_SIZE + block_size as u64 * CIR_INTERNAL_ENTRY_SIZE;
+    let leaf_size = NODE_HEADER_SIZE + block_size as u64 * CIR_LEAF_ENTRY_SIZE;
+    let levels = tree_levels(leaves.len(), block_size);
+    // write the levels from the root down, so each node knows where its children will be
+    let mut level_offset = output.stream_position()?;
+    for level in (0..levels).rev() {
+        let entries = if level == 0 { leaves.len() } else { level_entries(leaves.len(), block_size, level) };
+        let nodes = entries.div_ceil(block_size).max(1);
+        let node_size = if level == 0 { leaf_size } else { internal_size };
+        let child_size = if level == 1 { leaf_size } else { internal_size };
+        let child_level_offset = level_offset + nodes as u64 * node_size;
+        for node in 0..nodes {
+            let first = node * block_size;
+            let count = entries.saturating_sub(first).min(block_size);
+            output.write_all(&[(level == 0) as u8, 0])?;
+            output.write_u16(count as u16)?;
+            for entry in first..first + count {
+                let span = block_size.pow(level);
+                let covered = &leaves[entry * span..((entry + 1) * span).min(leaves.len())];
+                let (start_chrom, start_base, end_chrom, end_base) = bounds(covered);
+                output.write_u32(start_chrom)?;
+                output.write_u32(start_base)?;
+                output.write_u32(end_chrom)?;
+                output.write_u32(end_base)?;
+                if level == 0 {
+                    output.write_u64(covered[0].offset)?;
+                    output.write_u64(covered[0].size.unwrap_or(0))?;
+                } else {
+                    output.write_u64(child_level_offset + entry as u64 * child_size)?;
+                }
+            }
+            let entry_size = if level == 0 { CIR_LEAF_ENTRY_SIZE } else { CIR_INTERNAL_ENTRY_SIZE };
+            output.write_all(&vec![0; (block_size - count) * entry_size as usize])?;
+        }
+        level_offset = child_level_offset;
+    }
+    Ok(())
+}
+
+/// collects records into data blocks of up to `items_per_slot` records (never spanning
+/// chromosomes), writing out each block as it fills up
+#[derive(Debug)]
+pub(crate) struct BlockWriter {
+    items_per_slot: usize,
+    compress: bool,
+    buffer: Vec<u8>,
+    items: usize,
+    // the chromosome, first start and largest end of the records in the buffer
+    bounds: Option<(u32, u32, u32)>,
+    /// the R-tree entries of the blocks written so far
+    pub(crate) leaves: Vec<CIRChild>,
+    /// the size of the largest block before compression
+    pub(crate) max_block_size: usize,
+}
+
+impl BlockWriter {
+    pub(crate) fn new(items_per_slot: u32, compress: bool) -> BlockWriter {
+        BlockWriter{
+            items_per_slot: (items_per_slot as usize).max(1), compress, buffer: Vec::new(), items: 0,
+            bounds: None, leaves: Vec::new(), max_block_size: 0,
+        }
+    }
+
+    /// add a record (which must not sort before the last one added)
+    pub(crate) fn add<W: Write + Seek>(&mut self, output: &mut W, chrom_id: u32, start: u32, end: u32, rest: &[u8]) -> io::Result<()> {
+        if let Some((chrom, _, _)) = self.bounds {
+            if chrom != chrom_id || self.items >= self.items_per_slot {
+                self.flush(output)?;
+            }
+        }
+        let bounds = self.bounds.get_or_insert((chrom_id, start, end));
+        bounds.2 = bounds.2.max(end);
+        self.buffer.write_u32(chrom_id)?;
+        self.buffer.write_u32(start)?;
+        self.buffer.write_u32(end)?;
+        self.buffer.extend_from_slice(rest);
+        self.buffer.push(0);
+        self.items += 1;
+        Ok(())
+    }
+
+    /// add a block of zoom records (all on one chromosome)
+    pub(crate) fn add_zoom_records<W: Write + Seek>(&mut self, output: &mut W, records: &[ZoomRecord]) -> io::Result<()> {
+        for record in records {
+            if let Some((chrom, _, _)) = self.bounds {
+                if chrom != record.chrom_id || self.items >= self.items_per_slot {
+                    self.flush(output)?;
+                }
+            }
+            let bounds = self.bounds.get_or_insert((record.chrom_id, record.start, record.end));
+            bounds.2 = bounds.2.max(record.end);
+            self.buffer.write_u32(record.chrom_id)?;
+            self.buffer.write_u32(record.start)?;
+            self.buffer.write_u32(record.end)?;
+            self.buffer.write_u32(record.valid_count)?;
+            for value in &[record.min, record.max, record.sum, record.sum_squares] {
+                self.buffer.write_all(&value.to_le_bytes())?;
+            }
+            self.items += 1;
+        }
+        Ok(())
+    }
+
+    /// write out the records waiting in the buffer as a block
+    pub(crate) fn flush<W: Write + Seek>(&mut self, output: &mut W) -> io::Result<()> {
+        let (chrom_id, start, end) = match self.bounds.take() {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        let offset = output.stream_position()?;
+        self.max_block_size = self.max_block_size.max(self.buffer.len());
+        if self.compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&self.buffer)?;
+            output.write_all(&encoder.finish()?)?;
+        } else {
+            output.write_all(&self.buffer)?;
+        }
+        let size = output.stream_position()? - offset;
+        self.leaves.push(CIRChild{
+            start_chrom: chrom_id, start_base: start, end_chrom: chrom_id, end_base: end, offset, size: Some(size),
+        });
+        self.buffer.clear();
+        self.items = 0;
+        Ok(())
+    }
+}
+
+// split intervals (sorted by start) into runs of constant coverage depth
+fn depth_segments(intervals: &[(u32, u32)]) -> Vec<(u32, u32, u32)> {
+    let mut events: Vec<(u32, i64)> = Vec::with_capacity(intervals.len() * 2);
+    for &(start, end) in intervals {
+        if start < end {
+            events.push((start, 1));
+            events.push((end, -1));
+        }
+    }
+    events.sort_unstable();
+    let mut segments = Vec::new();
+    let mut depth: i64 = 0;
+    let mut prev = 0;
+    for (position, change) in events {
+        if position > prev && depth > 0 {
+            segments.push((prev, position, depth as u32));
+        }
+        depth += change;
+        prev = position;
+    }
+    segments
+}
+
+/// computes the zoom levels (summaries of the coverage depth, as verify_zoom_levels expects)
+/// and the total summary, one chromosome at a time
+pub(crate) struct ZoomBuilder {
+    reductions: Vec<u32>,
+    levels: Vec<Vec<ZoomRecord>>,
+    pub(crate) total: Summary,
+}
+
+impl ZoomBuilder {
+    /// a builder for up to MAX_ZOOM_LEVELS levels, the first summarizing `initial_reduction` bases per record
+    pub(crate) fn new(initial_reduction: u32) -> ZoomBuilder {
+        let mut reductions = Vec::new();
+        let mut reduction = Some(initial_reduction.max(1));
+        while let Some(current) = reduction {
+            if reductions.len() >= MAX_ZOOM_LEVELS {
+                break;
+            }
+            reductions.push(current);
+            reduction = current.checked_mul(ZOOM_INCREMENT);
+        }
+        let levels = vec![Vec::new(); reductions.len()];
+        ZoomBuilder{reductions, levels, total: Summary::empty()}
+    }
+
+    /// summarize the records (intervals sorted by start) of a chromosome
+    pub(crate) fn add_chrom(&mut self, chrom_id: u32, chrom_size: u32, intervals: &[(u32, u32)]) {
+        let segments = depth_segments(intervals);
+        for &(start, end, depth) in &segments {
+            self.total.add_value(f64::from(depth), u64::from(end - start));
+        }
+        for (reduction, records) in self.reductions.iter().zip(self.levels.iter_mut()) {
+            // like bedToBigBed, each summary starts at the first base not yet summarized
+            let mut current: Option<(u32, u32, Summary)> = None;
+            for &(mut start, end, depth) in &segments {
+                while start < end {
+                    // finish a summary that ends before this segment
+                    if let Some((record_start, record_end, summary)) = &current {
+                        if start >= *record_end {
+                            records.push(zoom_record(chrom_id, *record_start, *record_end, summary));
+                            current = None;
+                        }
+                    }
+                    let (record_start, record_end, summary) = current.get_or_insert_with(|| {
+                        let record_end = start.saturating_add(*reduction).min(chrom_size.max(end));
+                        (start, record_end, Summary::empty())
+                    });
+                    let piece_end = end.min(*record_end);
+                    summary.add_value(f64::from(depth), u64::from(piece_end - start));
+                    start = piece_end;
+                    if start >= *record_end {
+                        records.push(zoom_record(chrom_id, *record_start, *record_end, summary));
+                        current = None;
+                    }
+                }
+            }
+            if let Some((record_start, record_end, summary)) = current {
+                records.push(zoom_record(chrom_id, record_start, record_end, &summary));
+            }
+        }
+    }
+
+    /// the levels worth keeping: each must have at most half as many records as the level
+    /// below it (or as `record_count`, for the first)
+    pub(crate) fn finish(self, record_count: u64) -> Vec<(u32, Vec<ZoomRecord>)> {
+        let mut previous = record_count;
+        let mut levels = Vec::new();
+        for (reduction, records) in self.reductions.into_iter().zip(self.levels) {
+            if records.is_empty() || records.len() as u64 * 2 > previous {
+                break;
+            }
+            previous = records.len() as u64;
+            levels.push((reduction, records));
+        }
+        levels
+    }
+}
+
+fn zoom_record(chrom_id: u32, start: u32, end: u32, summary: &Summary) -> ZoomRecord {
+    ZoomRecord{
+        chrom_id, start, end,
+        valid_count: summary.valid_count.try_into().unwrap_or(u32::MAX),
+        min: summary.min as f32,
+        max: summary.max as f32,
+        sum: summary.sum as f32,
+        sum_squares: summary.sum_squares as f32,
+    }
+}
+
+/// write a zoom level (its records and their index) at the current position
+/// returns the level's header and the size of its largest block before compression
+pub(crate) fn write_zoom_level<W: Write + Seek>(output: &mut W, reduction_level: u32, records: &[ZoomRecord],
+                                                items_per_slot: u32, block_size: u32, compress: bool) -> io::Result<(ZoomLevel, usize)> {
+    let data_offset = output.stream_position()?;
+    output.write_u32(records.len() as u32)?;
+    let mut blocks = BlockWriter::new(items_per_slot, compress);
+    blocks.add_zoom_records(output, records)?;
+    blocks.flush(output)?;
+    let index_offset = output.stream_position()?;
+    write_cir_tree(output, &blocks.leaves, block_size, index_offset)?;
+    Ok((ZoomLevel{reduction_level, reserved: 0, data_offset, index_offset}, blocks.max_block_size))
+}
+
+/// write the total summary (of the coverage depth) at the current position
+pub(crate) fn write_total_summary(output: &mut impl Write, total: &Summary) -> io::Result<()> {
+    output.write_u64(total.valid_count)?;
+    output.write_f64(total.min)?;
+    output.write_f64(total.max)?;
+    output.write_f64(total.sum)?;
+    output.write_f64(total.sum_squares)
+}
+
+/// write an extension header (without extra indexes) at the current position,
+/// carrying over the reserved bytes of an existing file's extension header
+pub(crate) fn write_extension(output: &mut impl Write, reserved: &[u8]) -> io::Result<()> {
+    let size = crate::EXTENSION_HEADER_SIZE;
+    let mut reserved = reserved.to_vec();
+    reserved.resize(size - crate::EXTENSION_FIELDS_SIZE, 0);
+    output.write_u16(size as u16)?;
+    output.write_u16(0)?;
+    output.write_u64(0)?;
+    output.write_all(&reserved)
+}
+
+/// options for BigBedWriter
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriterOptions {
+    block_size: u32,
+    items_per_slot: u32,
+    compress: bool,
+    autosql: Option<String>,
+    defined_field_count: Option<u16>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> WriterOptions {
+        WriterOptions{
+            block_size: DEFAULT_BLOCK_SIZE, items_per_slot: DEFAULT_ITEMS_PER_SLOT, compress: true,
+            autosql: None, defined_field_count: None,
+        }
+    }
+}
+
+impl WriterOptions {
+    pub fn new() -> WriterOptions {
+        WriterOptions::default()
+    }
+
+    /// the number of children per node of the indexes
+    pub fn block_size(mut self, block_size: u32) -> WriterOptions {
+        self.block_size = block_size;
+        self
+    }
+
+    /// the number of records per data block
+    pub fn items_per_slot(mut self, items_per_slot: u32) -> WriterOptions {
+        self.items_per_slot = items_per_slot;
+        self
+    }
+
+    /// whether to compress the data blocks (like bedToBigBed without -unc)
+    pub fn compress(mut self, compress: bool) -> WriterOptions {
+        self.compress = compress;
+        self
+    }
+
+    /// the autoSql definition of the columns to store in the file
+    pub fn autosql(mut self, text: &str) -> WriterOptions {
+        self.autosql = Some(text.to_owned());
+        self
+    }
+
+    /// the number of standard BED columns (the N of bedN+M); by default, every column
+    /// up to the 12 of BED12 is taken to be standard
+    pub fn defined_field_count(mut self, count: u16) -> WriterOptions {
+        self.defined_field_count = Some(count);
+        self
+    }
+}
+
+/// writes a BigBed file from BED records (the counterpart of bedToBigBed)
+/// records must be added sorted by chromosome name (in byte order) and then by start,
+/// as `sort -k1,1 -k2,2n` leaves them; the data blocks are written as the records come,
+/// while the zoom levels and indexes are written by finish
+#[derive(Debug)]
+pub struct BigBedWriter<W: Write + Seek> {
+    output: W,
+    options: WriterOptions,
+    header: Header,
+    chroms: Vec<Chrom>,
+    blocks: BlockWriter,
+    // the (chromosome, start) of the last record, to check the order
+    last: Option<(u32, u32)>,
+    // the records of each chromosome, for the zoom levels
+    intervals: Vec<(u32, Vec<(u32, u32)>)>,
+    records: u64,
+    total_length: u64,
+}
+
+impl<W: Write + Seek> BigBedWriter<W> {
+    /// start a BigBed holding records on the chromosomes of `chrom_sizes` ((name, size) pairs,
+    /// as read_chrom_sizes returns them)
+    pub fn new(output: W, chrom_sizes: &[(String, u32)]) -> Result<BigBedWriter<W>, Error> {
+        BigBedWriter::with_options(output, chrom_sizes, WriterOptions::new())
+    }
+
+    pub fn with_options(mut output: W, chrom_sizes: &[(String, u32)], options: WriterOptions) -> Result<BigBedWriter<W>, Error> {
+        // the ids follow the order of the names, which is the order of the records
+        let mut names: Vec<&(String, u32)> = chrom_sizes.iter().collect();
+        names.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        names.dedup_by(|a, b| a.0 == b.0);
+        let chroms: Vec<Chrom> = names.iter().enumerate()
+            .map(|(id, (name, size))| Ok(Chrom{name: name.clone(), id: id.try_into()?, size: *size}))
+            .collect::<Result<_, Error>>()?;
+
+        // leave space for the header, which is only known at the end
+        let mut header = Header::default();
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(&vec![0; Header::reserved_size() as usize])?;
+        if let Some(text) = &options.autosql {
+            header.as_offset = output.stream_position()?;
+            output.write_all(text.as_bytes())?;
+            output.write_all(&[0])?;
+        }
+        header.total_summary_offset = output.stream_position()?;
+        write_total_summary(&mut output, &Summary::empty())?;
+        header.extension_offset = output.stream_position()?;
+        write_extension(&mut output, &[])?;
+        header.chrom_tree_offset = output.stream_position()?;
+        write_chrom_tree(&mut output, &chroms, options.block_size)?;
+        // the record count comes before the data
+        header.data_offset = output.stream_position()?;
+        output.write_u64(0)?;
+
+        let blocks = BlockWriter::new(options.items_per_slot, options.compress);
+        Ok(BigBedWriter{
+            output, options, header, chroms, blocks, last: None, intervals: Vec::new(), records: 0, total_length: 0,
+        })
+    }
+
+    /// add a record, with its columns after the third (tab-separated) in `rest`
+    pub fn add(&mut self, chrom: &str, start: u32, end: u32, rest: Option<&str>) -> Result<(), Error> {
+        let chrom_data = match self.chroms.binary_search_by(|chrom_data| chrom_data.name.as_bytes().cmp(chrom.as_bytes())) {
+            Ok(index) => &self.chroms[index],
+            Err(_) => return Err(Error::BadChrom(chrom.to_owned())),
+        };
+        if start > end {
+            return Err(Error::BadField("chromStart", format!("{}:{}-{} (the start is after the end)", chrom, start, end)))
+        }
+        if end > chrom_data.size {
+            return Err(Error::BadField("chromEnd", format!("{}:{}-{} (past the end of the chromosome, {})", chrom, start, end, chrom_data.size)))
+        }
+        if self.last > Some((chrom_data.id, start)) {
+            return Err(Error::BadField("chromStart", format!("{}:{}-{} (out of order; sort the records with sort -k1,1 -k2,2n)", chrom, start, end)))
+        }
+        // every record must have the same number of columns
+        let field_count = rest.map(|rest| rest.split('\t').count() + 3).unwrap_or(3);
+        if self.records == 0 {
+            self.header.field_count = field_count.try_into()?;
+        } else if usize::from(self.header.field_count) != field_count {
+            return Err(Error::BadField("field count", format!("{}:{}-{} has {} columns, not {}", chrom, start, end, field_count, self.header.field_count)))
+        }
+        self.last = Some((chrom_data.id, start));
+        let chrom_id = chrom_data.id;
+        match self.intervals.last_mut() {
+            Some((id, intervals)) if *id == chrom_id => intervals.push((start, end)),
+            _ => self.intervals.push((chrom_id, vec![(start, end)])),
+        }
+        self.records += 1;
+        self.total_length += u64::from(end - start);
+        self.blocks.add(&mut self.output, chrom_id, start, end, rest.unwrap_or("").as_bytes())?;
+        Ok(())
+    }
+
+    /// add a line of BED text (skipping headers, comments and blank lines; see is_bed_header)
+    pub fn add_line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if is_bed_header(line) {
+            return Ok(())
+        }
+        let (chrom, start, end) = parse_bed_region(line).ok_or_else(|| Error::BadField("BED line", line.to_owned()))?;
+        let rest = line.splitn(4, '\t').nth(3);
+        self.add(chrom, start, end, rest)
+    }
+
+    /// add every line of a BED file, returning the number of records added
+    pub fn add_bed<R: BufRead>(&mut self, reader: R) -> Result<u64, Error> {
+        let before = self.records;
+        for line in reader.lines() {
+            self.add_line(&line?)?;
+        }
+        Ok(self.records - before)
+    }
+
+    /// write the indexes, zoom levels and header, returning the output
+    pub fn finish(mut self) -> Result<W, Error> {
+        let output = &mut self.output;
+        let options = &self.options;
+        let header = &mut self.header;
+        if self.records == 0 {
+            header.field_count = 3;
+        }
+        header.defined_field_count = options.defined_field_count.unwrap_or(header.field_count.min(12)).min(header.field_count);
+        self.blocks.flush(output)?;
+        header.index_offset = output.stream_position()?;
+        write_cir_tree(output, &self.blocks.leaves, options.block_size, header.index_offset)?;
+
+        // like bedToBigBed, the first zoom level summarizes ten times the mean record length
+        let mean_length = self.total_length.checked_div(self.records).unwrap_or(0);
+        let mut zooms = ZoomBuilder::new((mean_length * 10).max(10).try_into().unwrap_or(u32::MAX));
+        for (chrom_id, intervals) in &self.intervals {
+            zooms.add_chrom(*chrom_id, self.chroms[*chrom_id as usize].size, intervals);
+        }
+        let total = zooms.total;
+        let mut max_block_size = self.blocks.max_block_size;
+        for (reduction, level_records) in zooms.finish(self.records) {
+            let (level, block_size) = write_zoom_level(output, reduction, &level_records, options.items_per_slot, options.block_size, options.compress)?;
+            header.level_list.push(level);
+            max_block_size = max_block_size.max(block_size);
+        }
+        header.uncompress_buf_size = if options.compress { max_block_size.try_into()? } else { 0 };
+
+        // go back to fill in the header, total summary and record count
+        output.seek(SeekFrom::Start(0))?;
+        header.write(output)?;
+        output.seek(SeekFrom::Start(header.total_summary_offset))?;
+        write_total_summary(output, &total)?;
+        output.seek(SeekFrom::Start(header.data_offset))?;
+        output.write_u64(self.records)?;
+        output.seek(SeekFrom::End(0))?;
+        output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod test_writer {
+    use super::*;
+    use crate::BigBed;
+    use crate::assembly::read_chrom_sizes;
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+
+    fn sizes_from_file(filename: &str) -> Vec<(String, u32)> {
+        read_chrom_sizes(BufReader::new(File::open(filename).unwrap())).unwrap()
+    }
+
+    // write a BED file as a BigBed and read it back
+    fn round_trip(bed: &str, sizes: &str, options: WriterOptions) -> BigBed<Cursor<Vec<u8>>> {
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes_from_file(sizes), options).unwrap();
+        writer.add_bed(BufReader::new(File::open(bed).unwrap())).unwrap();
+        BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    fn bed_text<T: std::io::Read + Seek>(bb: &mut BigBed<T>) -> String {
+        let mut output = Vec::new();
+        bb.write_bed(None, None, None, None, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let files = [
+            ("long", "hg38"), ("bed12", "bed12"), ("tair10", "tair10"), ("tair10-nochr", "tair10-nochr"),
+        ];
+        // small nodes and blocks give trees several levels deep
+        for options in &[WriterOptions::new(), WriterOptions::new().block_size(2).items_per_slot(3).compress(false)] {
+            for (bed, sizes) in &files {
+                let bed_name = format!("test/beds/{}.bed", bed);
+                let mut bb = round_trip(&bed_name, &format!("test/chrom.sizes/{}.chrom.sizes", sizes), options.clone());
+                assert_eq!(bed_text(&mut bb), std::fs::read_to_string(&bed_name).unwrap(), "{}", bed);
+                assert_eq!(bb.record_count().unwrap(), std::fs::read_to_string(&bed_name).unwrap().lines().count() as u64);
+                assert_eq!(bb.verify_zoom_levels(20, 1).unwrap(), vec![], "{}", bed);
+                assert_eq!(bb.verify_sorted().unwrap(), None);
+                assert_eq!(bb.uncompress_buf_size > 0, options.compress);
+                // the same files made by bedToBigBed cover the same bases (except for BED12 files,
+                // where bedToBigBed only counts the bases of the blocks), though bedToBigBed
+                // sums the depth from its first zoom level, so only roughly
+                let mut original = BigBed::from_file(File::open(format!("test/bigbeds/{}.bb", bed)).unwrap()).unwrap();
+                let (written, expected) = (bb.total_summary().unwrap().unwrap(), original.total_summary().unwrap().unwrap());
+                if original.field_count < 12 {
+                    assert_eq!((written.valid_count, written.min, written.max), (expected.valid_count, expected.min, expected.max), "{}", bed);
+                }
+            }
+        }
+        let bb = round_trip("test/beds/bed12.bed", "test/chrom.sizes/bed12.chrom.sizes", WriterOptions::new());
+        assert_eq!((bb.field_count, bb.defined_field_count), (12, 12));
+    }
+
+    #[test]
+    fn test_autosql() {
+        let text = std::fs::read_to_string("test/autosql/narrowPeak.as").unwrap();
+        let options = WriterOptions::new().autosql(&text).defined_field_count(6);
+        let mut bb = round_trip("test/beds/peaks.bed", "test/chrom.sizes/hg38.chrom.sizes", options);
+        assert_eq!(bb.autosql_text().unwrap().as_deref(), Some(text.as_str()));
+        assert_eq!((bb.field_count, bb.defined_field_count), (10, 6));
+        assert_eq!(bed_text(&mut bb), std::fs::read_to_string("test/beds/peaks.bed").unwrap());
+    }
+
+    #[test]
+    fn test_bad_records() {
+        let sizes = vec![(String::from("chr1"), 1000), (String::from("chr2"), 500)];
+        let mut writer = BigBedWriter::new(Cursor::new(Vec::new()), &sizes).unwrap();
+        writer.add_line("# a comment").unwrap();
+        writer.add_line("chr1\t10\t20\tname").unwrap();
+        assert_eq!(writer.add_line("chrZ\t10\t20\tname"), Err(Error::BadChrom(String::from("chrZ"))));
+        assert!(writer.add_line("chr1\t30\t20\tname").is_err());
+        assert!(writer.add_line("chr2\t10\t600\tname").is_err());
+        assert!(writer.add_line("chr1\t10\t20").is_err());
+        assert!(writer.add_line("chr1\tten\t20\tname").is_err());
+        writer.add_line("chr2\t10\t20\tname").unwrap();
+        // chr1 sorts before chr2
+        assert!(writer.add_line("chr1\t50\t60\tname").is_err());
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert_eq!(bed_text(&mut bb), "chr1\t10\t20\tname\nchr2\t10\t20\tname\n");
+
+        // an empty file can still be read
+        let writer = BigBedWriter::new(Cursor::new(Vec::new()), &sizes).unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert_eq!(bed_text(&mut bb), "");
+        assert_eq!(bb.chrom_list().unwrap().len(), 2);
+    }
+}
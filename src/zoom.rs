@@ -0,0 +1,610 @@
+use crate::{BigBed, BlockRecords, FileOffsetSize, ZoomLevel};
+use crate::rtree::CIRTreeFile;
+use crate::error::Error;
+use crate::scan::{ScoreStats, SplitMix64};
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// a single summary record from a zoom level
+/// for BigBed files, the summarized value is the coverage depth at each base
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoomRecord {
+    pub chrom_id: u32,
+    pub start: u32,
+    pub end: u32,
+    /// the number of bases with data
+    pub valid_count: u32,
+    pub min: f32,
+    pub max: f32,
+    pub sum: f32,
+    pub sum_squares: f32,
+}
+
+// each zoom record is stored as 8 32-bit values
+const ZOOM_RECORD_SIZE: usize = 32;
+
+// parse the records in an uncompressed zoom data block
+fn parse_zoom_block(buff: &[u8], big_endian: bool) -> Vec<ZoomRecord> {
+    let word = |bytes: &[u8]| -> [u8; 4] { bytes.try_into().expect("Failed to convert bytes") };
+    let read_u32 = |bytes: &[u8]| if big_endian {u32::from_be_bytes(word(bytes))} else {u32::from_le_bytes(word(bytes))};
+    let read_f32 = |bytes: &[u8]| if big_endian {f32::from_be_bytes(word(bytes))} else {f32::from_le_bytes(word(bytes))};
+    buff.chunks_exact(ZOOM_RECORD_SIZE).map(|record| {
+        ZoomRecord{
+            chrom_id: read_u32(&record[0..4]),
+            start: read_u32(&record[4..8]),
+            end: read_u32(&record[8..12]),
+            valid_count: read_u32(&record[12..16]),
+            min: read_f32(&record[16..20]),
+            max: read_f32(&record[20..24]),
+            sum: read_f32(&record[24..28]),
+            sum_squares: read_f32(&record[28..32]),
+        }
+    }).collect()
+}
+
+/// summary statistics of the coverage over a region
+/// (e.g. the mean coverage of the covered bases is sum / valid_count)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// the number of bases with data
+    pub valid_count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub sum_squares: f64,
+}
+
+impl Summary {
+    /// the mean value over the bases with data
+    pub fn mean(&self) -> f64 {
+        if self.valid_count == 0 {
+            0.0
+        } else {
+            self.sum / self.valid_count as f64
+        }
+    }
+
+    /// add `bases` bases with value `value`
+    pub(crate) fn add_value(&mut self, value: f64, bases: u64) {
+        self.add(&Summary{
+            valid_count: bases,
+            min: value,
+            max: value,
+            sum: value * bases as f64,
+            sum_squares: value * value * bases as f64,
+        });
+    }
+
+    /// combine another summary into this one
+    pub(crate) fn add(&mut self, other: &Summary) {
+        if self.valid_count == 0 {
+            *self = *other;
+            return
+        }
+        self.valid_count += other.valid_count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+    }
+
+    pub(crate) fn empty() -> Summary {
+        Summary{valid_count: 0, min: 0.0, max: 0.0, sum: 0.0, sum_squares: 0.0}
+    }
+}
+
+// the bin boundaries used to split [start, end) into n_bins
+pub(crate) fn bin_bounds(start: u32, end: u32, n_bins: usize, bin: usize) -> (u32, u32) {
+    let span = u64::from(end - start);
+    let bin_start = u64::from(start) + span * bin as u64 / n_bins as u64;
+    let bin_end = u64::from(start) + span * (bin as u64 + 1) / n_bins as u64;
+    (bin_start as u32, bin_end as u32)
+}
+
+// summarize the coverage of a set of intervals over [start, end)
+// each interval adds 1 to the depth of the bases it covers
+pub(crate) fn coverage_summary(intervals: &[(u32, u32)], start: u32, end: u32) -> Option<Summary> {
+    // find every point where the depth changes
+    let mut events: Vec<(u32, i64)> = Vec::with_capacity(intervals.len() * 2);
+    for &(s, e) in intervals {
+        let (s, e) = (s.max(start), e.min(end));
+        if s < e {
+            events.push((s, 1));
+            events.push((e, -1));
+        }
+    }
+    if events.is_empty() {
+        return None
+    }
+    events.sort_unstable();
+    let mut summary = Summary::empty();
+    let mut depth: i64 = 0;
+    let mut prev = events[0].0;
+    for (position, change) in events {
+        if position > prev && depth > 0 {
+            summary.add_value(depth as f64, u64::from(position - prev));
+        }
+        depth += change;
+        prev = position;
+    }
+    Some(summary)
+}
+
+// fold a zoom record into a bin summary, scaling by how much of the record overlaps the bin
+fn add_zoom_record(summary: &mut Option<Summary>, record: &ZoomRecord, start: u32, end: u32) {
+    let overlap = record.end.min(end).saturating_sub(record.start.max(start));
+    if overlap == 0 || record.valid_count == 0 {
+        return
+    }
+    let factor = f64::from(overlap) / f64::from(record.end - record.start);
+    let part = Summary{
+        valid_count: (f64::from(record.valid_count) * factor).round() as u64,
+        min: f64::from(record.min),
+        max: f64::from(record.max),
+        sum: f64::from(record.sum) * factor,
+        sum_squares: f64::from(record.sum_squares) * factor,
+    };
+    summary.get_or_insert_with(Summary::empty).add(&part);
+}
+
+/// binned coverage statistics for a single chromosome
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromSummary {
+    pub chrom: String,
+    pub size: u32,
+    /// the summary of each bin (of `bin_size` bases) along the chromosome, None where there is no data
+    pub bins: Vec<Option<Summary>>,
+}
+
+impl ChromSummary {
+    /// the summary of the whole chromosome, if it has any data
+    pub fn total(&self) -> Option<Summary> {
+        self.bins.iter().flatten().fold(None, |total, summary| {
+            let mut total = total.unwrap_or_else(Summary::empty);
+            total.add(summary);
+            Some(total)
+        })
+    }
+}
+
+/// a zoom record that does not match the summary of the records it covers
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoomMismatch {
+    pub reduction_level: u32,
+    pub stored: ZoomRecord,
+    /// the summary recomputed from the records (None if no record overlaps the zoom record)
+    pub computed: Option<Summary>,
+}
+
+// zoom records store f32 values, so allow for rounding in the sums
+fn close_enough(stored: f32, computed: f64) -> bool {
+    (f64::from(stored) - computed).abs() <= computed.abs().max(1.0) * 1e-4
+}
+
+fn zoom_record_matches(stored: &ZoomRecord, computed: &Option<Summary>) -> bool {
+    match computed {
+        None => stored.valid_count == 0,
+        Some(computed) => {
+            u64::from(stored.valid_count) == computed.valid_count
+            && close_enough(stored.min, computed.min)
+            && close_enough(stored.max, computed.max)
+            && close_enough(stored.sum, computed.sum)
+            && close_enough(stored.sum_squares, computed.sum_squares)
+        }
+    }
+}
+
+/// statistics of the records overlapping a region
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionSummary {
+    /// the number of overlapping records
+    pub count: u64,
+    /// the number of bases in the region covered by at least one record
+    pub covered: u64,
+    /// the mean score of the overlapping records (None if there are no scores)
+    pub mean_score: Option<f64>,
+}
+
+impl<T: Read + Seek> BigBed<T> {
+    // choose the coarsest zoom level that still has at least two summaries per bin
+    // (this mirrors bbiBestZoom in the kent source)
+    pub(crate) fn best_zoom_level(&self, bases_per_bin: u32) -> Option<&ZoomLevel> {
+        let desired = bases_per_bin / 2;
+        self.level_list.iter()
+            .filter(|level| level.reduction_level <= desired)
+            .max_by_key(|level| level.reduction_level)
+    }
+
+    // read the zoom records of a level overlapping a region
+    pub(crate) fn zoom_records(&mut self, level: &ZoomLevel, chrom_id: u32, start: u32, end: u32) -> Result<Vec<ZoomRecord>, Error> {
+        self.reader.seek(SeekFrom::Start(level.index_offset))?;
+        let index = CIRTreeFile::with_reader(&mut self.reader)?;
+        let blocks: Vec<FileOffsetSize> = index.find_blocks(chrom_id, start, end, &mut self.reader)?;
+        let big_endian = self.big_endian;
+        let mut records = Vec::new();
+        self.read_blocks(&blocks, |_, buff| {
+            records.extend(parse_zoom_block(buff, big_endian).into_iter()
+                .filter(|record| record.chrom_id == chrom_id && record.start < end && record.end > start));
+            true
+        })?;
+        Ok(records)
+    }
+
+    /// the zoom level to draw the region from `start` to `end` at `width` pixels
+    /// (the coarsest level with at least two summaries per pixel), or None if the
+    /// region is too small for any level, so the records should be read instead
+    pub fn zoom_level_for_width(&self, start: u32, end: u32, width: u32) -> Option<ZoomLevel> {
+        let bases_per_pixel = end.saturating_sub(start) / width.max(1);
+        self.best_zoom_level(bases_per_pixel).copied()
+    }
+
+    /// the summary records of the zoom level with the given reduction level
+    /// (see level_list) overlapping a region, in order
+    pub fn query_zoom(&mut self, chrom: &str, start: u32, end: u32, reduction_level: u32) -> Result<Vec<ZoomRecord>, Error> {
+        let level = match self.level_list.iter().find(|level| level.reduction_level == reduction_level) {
+            Some(level) => *level,
+            None => return Err(Error::BadField("reduction level", reduction_level.to_string())),
+        };
+        let chrom_data = self.resolve_chrom(chrom)?;
+        let mut records = self.zoom_records(&level, chrom_data.id, start, end)?;
+        records.sort_by_key(|record| record.start);
+        Ok(records)
+    }
+
+    // summarize the coverage of a region directly from the records
+    pub(crate) fn raw_coverage(&mut self, chrom_id: u32, start: u32, end: u32) -> Result<Option<Summary>, Error> {
+        let blocks = self.overlapping_blocks(chrom_id, start, end)?;
+        let big_endian = self.big_endian;
+        let mut intervals = Vec::new();
+        self.read_blocks(&blocks, |_, buff| {
+            intervals.extend(BlockRecords::new(buff, big_endian)
+                .filter(|record| record.chrom_id == chrom_id && record.start < end && record.end > start)
+                .map(|record| (record.start, record.end)));
+            true
+        })?;
+        Ok(coverage_summary(&intervals, start, end))
+    }
+
+    /// check the zoom levels against the records, by recomputing the summaries of
+    /// up to `samples_per_level` randomly chosen zoom records in each level
+    /// returns every zoom record that does not match
+    pub fn verify_zoom_levels(&mut self, samples_per_level: usize, seed: u64) -> Result<Vec<ZoomMismatch>, Error> {
+        let mut rng = SplitMix64::new(seed);
+        let mut mismatches = Vec::new();
+        for level in self.level_list.clone() {
+            self.reader.seek(SeekFrom::Start(level.index_offset))?;
+            let index = CIRTreeFile::with_reader(&mut self.reader)?;
+            let blocks = index.all_blocks(&mut self.reader)?;
+            if blocks.is_empty() {
+                continue
+            }
+            // sample blocks, then a record from each block
+            let mut sampled: Vec<FileOffsetSize> = (0..samples_per_level)
+                .map(|_| blocks[rng.below(blocks.len() as u64) as usize])
+                .collect();
+            sampled.sort_by_key(|block| block.offset);
+            sampled.dedup();
+            let big_endian = self.big_endian;
+            let mut records = Vec::new();
+            self.read_blocks(&sampled, |_, buff| {
+                let mut block_records = parse_zoom_block(buff, big_endian);
+                if !block_records.is_empty() {
+                    let index = rng.below(block_records.len() as u64) as usize;
+                    records.push(block_records.swap_remove(index));
+                }
+                true
+            })?;
+            for stored in records {
+                let computed = self.raw_coverage(stored.chrom_id, stored.start, stored.end)?;
+                if !zoom_record_matches(&stored, &computed) {
+                    mismatches.push(ZoomMismatch{reduction_level: level.reduction_level, stored, computed});
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// the summary of the coverage depth over the whole file, as stored in its header
+    /// (None if the file has none)
+    pub fn total_summary(&mut self) -> Result<Option<Summary>, Error> {
+        self.header().total_summary(&mut self.reader)
+    }
+
+    /// write every record of a zoom level (an index into level_list) as bedGraph,
+    /// with the mean coverage of each summary interval as its value
+    pub fn zoom_to_bedgraph(&mut self, level: usize, mut output: impl Write) -> Result<(), Error> {
+        let level = match self.level_list.get(level) {
+            Some(level) => *level,
+            None => return Err(Error::BadField("level", level.to_string())),
+        };
+        let mut chroms = self.chrom_list()?;
+        chroms.sort_by_key(|chrom| chrom.id);
+        self.reader.seek(SeekFrom::Start(level.index_offset))?;
+        let index = CIRTreeFile::with_reader(&mut self.reader)?;
+        let mut blocks = index.all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let big_endian = self.big_endian;
+        let mut result = Ok(());
+        self.read_blocks(&blocks, |_, buff| {
+            for record in parse_zoom_block(buff, big_endian) {
+                if record.valid_count == 0 {
+                    continue
+                }
+                let chrom = match chroms.binary_search_by_key(&record.chrom_id, |chrom| chrom.id) {
+                    Ok(index) => chroms[index].name(),
+                    Err(_) => continue,
+                };
+                let mean = record.sum / record.valid_count as f32;
+                result = writeln!(output, "{}\t{}\t{}\t{}", chrom, record.start, record.end, mean);
+                if result.is_err() {
+                    return false
+                }
+            }
+            true
+        })?;
+        Ok(result?)
+    }
+
+    /// summarize the records overlapping a region, like bigWigAverageOverBed does for BigWigs
+    pub fn region_summary(&mut self, chrom: &str, start: u32, end: u32) -> Result<RegionSummary, Error> {
+        let lines = self.query(chrom, start, end, 0)?;
+        let intervals: Vec<(u32, u32)> = lines.iter().map(|line| (line.start, line.end)).collect();
+        let covered = coverage_summary(&intervals, start, end).map(|summary| summary.valid_count).unwrap_or(0);
+        let mut scores = Vec::new();
+        for line in &lines {
+            // only files with at least 5 standard columns have a score (a bed3+2 does not)
+            if let Some(score) = line.defined_score(self.defined_field_count)? {
+                scores.push(score);
+            }
+        }
+        let mean_score = ScoreStats::from_scores(scores).map(|stats| stats.mean);
+        Ok(RegionSummary{count: lines.len() as u64, covered, mean_score})
+    }
+
+    /// summarize the coverage of every chromosome in bins of `bin_size` bases,
+    /// using a single pass over the coarsest zoom level that fits the bin size
+    /// (or the finest zoom level, for bins smaller than any zoom level)
+    pub fn genome_summary(&mut self, bin_size: u32) -> Result<Vec<ChromSummary>, Error> {
+        if bin_size == 0 {
+            return Err(Error::BadField("bin_size", bin_size.to_string()))
+        }
+        let level = match self.best_zoom_level(bin_size.saturating_mul(2)).or_else(|| self.level_list.first()) {
+            Some(level) => *level,
+            None => return Err(Error::Misc("File has no zoom levels")),
+        };
+        let mut chroms = self.chrom_list()?;
+        chroms.sort_by_key(|chrom| chrom.id);
+        let mut summaries: Vec<ChromSummary> = chroms.iter().map(|chrom| ChromSummary{
+            chrom: chrom.name().to_owned(),
+            size: chrom.size,
+            bins: vec![None; chrom.size.div_ceil(bin_size) as usize],
+        }).collect();
+        // chromosome ids are usually 0..n, but look them up to be safe
+        let index_of = |chrom_id: u32| chroms.binary_search_by_key(&chrom_id, |chrom| chrom.id).ok();
+
+        self.reader.seek(SeekFrom::Start(level.index_offset))?;
+        let index = CIRTreeFile::with_reader(&mut self.reader)?;
+        let mut blocks = index.all_blocks(&mut self.reader)?;
+        blocks.sort_by_key(|block| block.offset);
+        let big_endian = self.big_endian;
+        self.read_blocks(&blocks, |_, buff| {
+            for record in parse_zoom_block(buff, big_endian) {
+                let summary = match index_of(record.chrom_id) {
+                    Some(index) => &mut summaries[index],
+                    None => continue,
+                };
+                let end = record.end.min(summary.size);
+                let (first, last) = (record.start / bin_size, end.saturating_sub(1) / bin_size);
+                for bin in first..=last {
+                    let bin_start = bin * bin_size;
+                    let bin_end = bin_start.saturating_add(bin_size).min(summary.size);
+                    if let Some(slot) = summary.bins.get_mut(bin as usize) {
+                        add_zoom_record(slot, &record, bin_start, bin_end);
+                    }
+                }
+            }
+            true
+        })?;
+        Ok(summaries)
+    }
+
+    /// summarize the coverage of a region split into `n_bins` equal bins
+    /// the best zoom level for the bin size is used, or the records themselves
+    /// if no zoom level is fine enough; bins without data are None
+    pub fn summarize_adaptive(&mut self, chrom: &str, start: u32, end: u32, n_bins: usize) -> Result<Vec<Option<Summary>>, Error> {
+        if n_bins == 0 || end <= start {
+            return Ok(Vec::new())
+        }
+        let chrom_data = self.resolve_chrom(chrom)?;
+        let end = end.min(chrom_data.size);
+        if end <= start {
+            return Ok(vec![None; n_bins])
+        }
+        let bases_per_bin = (end - start) / n_bins as u32;
+        let mut summaries = vec![None; n_bins];
+        match self.best_zoom_level(bases_per_bin) {
+            Some(level) => {
+                let level = *level;
+                let records = self.zoom_records(&level, chrom_data.id, start, end)?;
+                for (bin, summary) in summaries.iter_mut().enumerate() {
+                    let (bin_start, bin_end) = bin_bounds(start, end, n_bins, bin);
+                    for record in records.iter().filter(|record| record.start < bin_end && record.end > bin_start) {
+                        add_zoom_record(summary, record, bin_start, bin_end);
+                    }
+                }
+            }
+            None => {
+                let intervals: Vec<(u32, u32)> = self.query(&chrom_data.name, start, end, 0)?
+                    .iter()
+                    .map(|line| (line.start, line.end))
+                    .collect();
+                for (bin, summary) in summaries.iter_mut().enumerate() {
+                    let (bin_start, bin_end) = bin_bounds(start, end, n_bins, bin);
+                    *summary = coverage_summary(&intervals, bin_start, bin_end);
+                }
+            }
+        }
+        Ok(summaries)
+    }
+}
+
+#[cfg(test)]
+mod test_zoom {
+    use super::*;
+    use crate::bb_from_file;
+    use crate::writer::{BigBedWriter, WriterOptions};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_coverage_summary() {
+        assert_eq!(coverage_summary(&[], 0, 100), None);
+        assert_eq!(coverage_summary(&[(200, 300)], 0, 100), None);
+        let summary = coverage_summary(&[(0, 50), (25, 75), (90, 200)], 10, 100).unwrap();
+        // depth 1 over [10, 25), [50, 75), [90, 100); depth 2 over [25, 50)
+        assert_eq!(summary, Summary{valid_count: 75, min: 1.0, max: 2.0, sum: 100.0, sum_squares: 150.0});
+        assert!((summary.mean() - 4.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_best_zoom_level() {
+        let bb = bb_from_file("test/bigbeds/long.bb");
+        assert_eq!(bb.best_zoom_level(1000), None);
+        assert_eq!(bb.best_zoom_level(5000000).map(|level| level.reduction_level), Some(2440976));
+        assert_eq!(bb.best_zoom_level(u32::MAX).map(|level| level.reduction_level), Some(624889856));
+    }
+
+    #[test]
+    fn test_zoom_records() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let level = bb.level_list[0];
+        let records = bb.zoom_records(&level, 0, 0, 248956422).unwrap();
+        assert!(!records.is_empty());
+        // zoom records cover the same bases as the raw data
+        let covered: u64 = records.iter().map(|record| u64::from(record.valid_count)).sum();
+        let intervals: Vec<(u32, u32)> = bb.query("chr1", 0, 248956422, 0).unwrap()
+            .iter().map(|line| (line.start, line.end)).collect();
+        assert_eq!(covered, coverage_summary(&intervals, 0, 248956422).unwrap().valid_count);
+    }
+
+    #[test]
+    fn test_query_zoom() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let level = bb.zoom_level_for_width(0, 248956422, 20).unwrap();
+        assert_eq!(level.reduction_level, 2440976);
+        // too few bases per pixel for any zoom level
+        assert_eq!(bb.zoom_level_for_width(0, 10000, 1000), None);
+        assert_eq!(bb.zoom_level_for_width(0, 10000, 0), None);
+
+        let records = bb.query_zoom("chr1", 0, 248956422, level.reduction_level).unwrap();
+        assert_eq!(records, bb.zoom_records(&level, 0, 0, 248956422).unwrap());
+        assert!(records.windows(2).all(|pair| pair[0].start <= pair[1].start));
+        let part = bb.query_zoom("1", 50_000_000, 60_000_000, level.reduction_level).unwrap();
+        assert!(!part.is_empty() && part.iter().all(|record| record.start < 60_000_000 && record.end > 50_000_000));
+        assert_eq!(bb.query_zoom("chr1", 0, 1000, 3), Err(Error::BadField("reduction level", String::from("3"))));
+        assert!(bb.query_zoom("chrNope", 0, 1000, level.reduction_level).is_err());
+    }
+
+    #[test]
+    fn test_summarize_adaptive() {
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        // small regions are computed from the records
+        let summaries = bb.summarize_adaptive("chr1", 0, 100000, 10).unwrap();
+        assert_eq!(summaries.len(), 10);
+        let intervals: Vec<(u32, u32)> = bb.query("chr1", 0, 100000, 0).unwrap()
+            .iter().map(|line| (line.start, line.end)).collect();
+        assert_eq!(summaries[3], coverage_summary(&intervals, 30000, 40000));
+        // large regions use a zoom level, which splits its summaries between bins
+        // in proportion to their overlap, so the total coverage is unchanged
+        let summaries = bb.summarize_adaptive("chr1", 0, 248956422, 10).unwrap();
+        let intervals: Vec<(u32, u32)> = bb.query("chr1", 0, 248956422, 0).unwrap()
+            .iter().map(|line| (line.start, line.end)).collect();
+        let expected = coverage_summary(&intervals, 0, 248956422).unwrap();
+        let received: u64 = summaries.iter().flatten().map(|summary| summary.valid_count).sum();
+        assert!((expected.valid_count as f64 - received as f64).abs() <= 10.0);
+        let max = summaries.iter().flatten().map(|summary| summary.max).fold(0.0, f64::max);
+        assert_eq!(max, expected.max);
+        assert_eq!(bb.summarize_adaptive("chr1", 100, 100, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_genome_summary() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        assert!(bb.genome_summary(0).is_err());
+        let summaries = bb.genome_summary(1000000).unwrap();
+        let chroms = bb.chrom_list().unwrap();
+        assert_eq!(summaries.len(), chroms.len());
+        for summary in summaries {
+            let chrom = chroms.iter().find(|chrom| chrom.name() == summary.chrom).unwrap();
+            assert_eq!(summary.size, chrom.size());
+            assert_eq!(summary.bins.len() as u64, u64::from(chrom.size().div_ceil(1000000)));
+            // the zoom levels cover the same bases as the records
+            let intervals: Vec<(u32, u32)> = bb.query(chrom.name(), 0, chrom.size(), 0).unwrap()
+                .iter().map(|line| (line.start, line.end)).collect();
+            let expected = coverage_summary(&intervals, 0, chrom.size()).map(|summary| summary.valid_count);
+            let received = summary.total().map(|summary| summary.valid_count);
+            match (expected, received) {
+                (Some(expected), Some(received)) => assert!((expected as f64 - received as f64).abs() <= summary.bins.len() as f64),
+                (expected, received) => assert_eq!(expected, received),
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_zoom_levels() {
+        for filename in &["test/bigbeds/long.bb", "test/bigbeds/bed12.bb", "test/bigbeds/peaks.bb", "test/bigbeds/tair10.bb"] {
+            let mut bb = bb_from_file(filename);
+            assert_eq!(bb.verify_zoom_levels(20, 1).unwrap(), vec![], "{}", filename);
+        }
+        let stored = ZoomRecord{chrom_id: 0, start: 0, end: 100, valid_count: 50, min: 1.0, max: 2.0, sum: 60.0, sum_squares: 80.0};
+        let computed = Summary{valid_count: 50, min: 1.0, max: 2.0, sum: 60.0, sum_squares: 80.0};
+        assert!(zoom_record_matches(&stored, &Some(computed)));
+        assert!(!zoom_record_matches(&stored, &Some(Summary{valid_count: 49, ..computed})));
+        assert!(!zoom_record_matches(&stored, &Some(Summary{sum: 61.0, ..computed})));
+        assert!(!zoom_record_matches(&stored, &None));
+    }
+
+    #[test]
+    fn test_zoom_to_bedgraph() {
+        let mut bb = bb_from_file("test/bigbeds/bed12.bb");
+        let mut output = Vec::new();
+        bb.zoom_to_bedgraph(0, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let level = bb.level_list[0];
+        let chroms = bb.chrom_list().unwrap();
+        let mut expected = Vec::new();
+        for chrom in &chroms {
+            for record in bb.zoom_records(&level, chrom.id(), 0, chrom.size()).unwrap() {
+                expected.push(format!("{}\t{}\t{}\t{}", chrom.name(), record.start, record.end, record.sum / record.valid_count as f32));
+            }
+        }
+        let mut received: Vec<&str> = output.lines().collect();
+        received.sort_unstable();
+        expected.sort_unstable();
+        assert!(!expected.is_empty());
+        assert_eq!(received, expected);
+        assert_eq!(bb.zoom_to_bedgraph(100, Vec::new()), Err(Error::BadField("level", String::from("100"))));
+    }
+
+    #[test]
+    fn test_region_summary() {
+        let mut bb = bb_from_file("test/bigbeds/peaks.bb");
+        let summary = bb.region_summary("chr1", 1000000, 5000000).unwrap();
+        assert_eq!(summary.count, 46);
+        assert!((summary.mean_score.unwrap() - 21415.0 / 46.0).abs() < 1e-9);
+        let summary = bb.region_summary("chr1", 17000, 17107).unwrap();
+        assert_eq!(summary, RegionSummary{count: 1, covered: 100, mean_score: Some(518.0)});
+        assert_eq!(bb.region_summary("chr1", 0, 1).unwrap(), RegionSummary{count: 0, covered: 0, mean_score: None});
+        // BED3 files have no scores
+        let mut bb = bb_from_file("test/bigbeds/long.bb");
+        let summary = bb.region_summary("chr1", 0, 10000000).unwrap();
+        assert!(summary.count > 0 && summary.covered > 0);
+        assert_eq!(summary.mean_score, None);
+        // nor do bed3+2 files, whose fifth column need not even be a number
+        let sizes = vec![(String::from("chr1"), 1000)];
+        let options = WriterOptions::new().defined_field_count(3);
+        let mut writer = BigBedWriter::with_options(Cursor::new(Vec::new()), &sizes, options).unwrap();
+        writer.add_line("chr1\t10\t20\tpeak1\thigh").unwrap();
+        let mut bb = BigBed::from_file(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        assert_eq!(bb.region_summary("chr1", 0, 1000).unwrap(), RegionSummary{count: 1, covered: 10, mean_score: None});
+    }
+}